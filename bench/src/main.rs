@@ -5,6 +5,7 @@ use p2p::{
     builder::{MetaBuilder, ServiceBuilder},
     context::{ProtocolContext, ProtocolContextMutRef},
     multiaddr::Multiaddr,
+    muxer::Muxer,
     secio::SecioKeyPair,
     service::{
         ProtocolHandle, ProtocolMeta, Service, ServiceControl, TargetProtocol, TargetSession,
@@ -17,12 +18,15 @@ use tokio_util::codec::length_delimited::Builder;
 
 static START_SECIO: Once = Once::new();
 static START_NO_SECIO: Once = Once::new();
+static START_MPLEX: Once = Once::new();
 
 static mut SECIO_CONTROL: Option<ServiceControl> = None;
 static mut NO_SECIO_CONTROL: Option<ServiceControl> = None;
+static mut MPLEX_CONTROL: Option<ServiceControl> = None;
 
 static mut SECIO_RECV: Option<crossbeam_channel::Receiver<Notify>> = None;
 static mut NO_SECIO_RECV: Option<crossbeam_channel::Receiver<Notify>> = None;
+static mut MPLEX_RECV: Option<crossbeam_channel::Receiver<Notify>> = None;
 
 #[derive(Debug, PartialEq)]
 enum Notify {
@@ -30,12 +34,13 @@ enum Notify {
     Message(bytes::Bytes),
 }
 
-pub fn create<F>(secio: bool, meta: ProtocolMeta, shandle: F) -> Service<F>
+pub fn create<F>(secio: bool, muxers: Vec<Muxer>, meta: ProtocolMeta, shandle: F) -> Service<F>
 where
     F: ServiceHandle + Unpin,
 {
     let builder = ServiceBuilder::default()
         .insert_protocol(meta)
+        .muxers(muxers)
         .forever(true);
 
     if secio {
@@ -97,49 +102,58 @@ fn create_meta(id: ProtocolId) -> (ProtocolMeta, crossbeam_channel::Receiver<Not
     (meta, receiver)
 }
 
-pub fn init() {
-    // init secio two peers
-    START_SECIO.call_once(|| {
-        let (meta, _receiver) = create_meta(ProtocolId::new(1));
-        let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
-        let mut service = create(true, meta, ());
-        let control = service.control().clone();
-        thread::spawn(move || {
-            let mut rt = tokio::runtime::Runtime::new().unwrap();
-            rt.block_on(async move {
-                let listen_addr = service
-                    .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
-                    .await
-                    .unwrap();
-                let _res = addr_sender.send(listen_addr);
-                loop {
-                    if service.next().await.is_none() {
-                        break;
-                    }
+/// Start two peers, return the control of listener and the receiver of dialer
+fn start_peers(
+    secio: bool,
+    muxers: Vec<Muxer>,
+) -> (ServiceControl, crossbeam_channel::Receiver<Notify>) {
+    let (meta, _receiver) = create_meta(ProtocolId::new(1));
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+    let mut service = create(secio, muxers.clone(), meta, ());
+    let control = service.control().clone();
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
                 }
-            });
+            }
         });
+    });
 
-        let (meta, client_receiver) = create_meta(1.into());
-
-        thread::spawn(|| {
-            let mut rt = tokio::runtime::Runtime::new().unwrap();
-            let mut service = create(true, meta, ());
-            rt.block_on(async move {
-                let listen_addr = addr_receiver.await.unwrap();
-                service
-                    .dial(listen_addr, TargetProtocol::All)
-                    .await
-                    .unwrap();
-                loop {
-                    if service.next().await.is_none() {
-                        break;
-                    }
+    let (meta, client_receiver) = create_meta(ProtocolId::new(1));
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, muxers, meta, ());
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
                 }
-            });
+            }
         });
+    });
+
+    assert_eq!(client_receiver.recv(), Ok(Notify::Connected));
+    (control, client_receiver)
+}
 
-        assert_eq!(client_receiver.recv(), Ok(Notify::Connected));
+pub fn init() {
+    // init secio two peers
+    START_SECIO.call_once(|| {
+        let (control, client_receiver) = start_peers(true, vec![Muxer::Yamux]);
         unsafe {
             SECIO_CONTROL = Some(control);
             SECIO_RECV = Some(client_receiver);
@@ -148,51 +162,21 @@ pub fn init() {
 
     // init no secio two peers
     START_NO_SECIO.call_once(|| {
-        let (meta, _receiver) = create_meta(ProtocolId::new(1));
-        let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
-        let mut service = create(false, meta, ());
-        let control = service.control().clone();
-        thread::spawn(move || {
-            let mut rt = tokio::runtime::Runtime::new().unwrap();
-            rt.block_on(async move {
-                let listen_addr = service
-                    .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
-                    .await
-                    .unwrap();
-                let _res = addr_sender.send(listen_addr);
-                loop {
-                    if service.next().await.is_none() {
-                        break;
-                    }
-                }
-            });
-        });
-
-        let (meta, client_receiver) = create_meta(ProtocolId::new(1));
-
-        thread::spawn(move || {
-            let mut rt = tokio::runtime::Runtime::new().unwrap();
-            let mut service = create(false, meta, ());
-            rt.block_on(async move {
-                let listen_addr = addr_receiver.await.unwrap();
-                service
-                    .dial(listen_addr, TargetProtocol::All)
-                    .await
-                    .unwrap();
-                loop {
-                    if service.next().await.is_none() {
-                        break;
-                    }
-                }
-            });
-        });
-
-        assert_eq!(client_receiver.recv(), Ok(Notify::Connected));
+        let (control, client_receiver) = start_peers(false, vec![Muxer::Yamux]);
         unsafe {
             NO_SECIO_CONTROL = Some(control);
             NO_SECIO_RECV = Some(client_receiver);
         }
     });
+
+    // init no secio two peers with mplex
+    START_MPLEX.call_once(|| {
+        let (control, client_receiver) = start_peers(false, vec![Muxer::Mplex]);
+        unsafe {
+            MPLEX_CONTROL = Some(control);
+            MPLEX_RECV = Some(client_receiver);
+        }
+    });
 }
 
 fn secio_and_send_data(data: &[u8]) {
@@ -228,6 +212,21 @@ fn no_secio_and_send_data(data: &[u8]) {
     }
 }
 
+fn mplex_and_send_data(data: &[u8]) {
+    unsafe {
+        MPLEX_CONTROL.as_mut().map(|control| {
+            control.filter_broadcast(TargetSession::All, 1.into(), Bytes::from(data.to_owned()))
+        });
+
+        if let Some(rev) = MPLEX_RECV.as_ref() {
+            assert_eq!(
+                rev.recv(),
+                Ok(Notify::Message(bytes::Bytes::from(data.to_owned())))
+            )
+        }
+    }
+}
+
 fn main() {
     init();
 
@@ -256,10 +255,16 @@ fn main() {
     bench.bench_function_with_init("10kb_benchmark_with_no_secio", &kb, move |data| {
         no_secio_and_send_data(&data)
     });
+    bench.bench_function_with_init("10kb_benchmark_with_mplex", &kb, move |data| {
+        mplex_and_send_data(&data)
+    });
     bench.bench_function_with_init("10mb_benchmark_with_secio", &mb, move |data| {
         secio_and_send_data(&data)
     });
     bench.bench_function_with_init("10mb_benchmark_with_no_secio", &mb, move |data| {
         no_secio_and_send_data(&data)
     });
+    bench.bench_function_with_init("10mb_benchmark_with_mplex", &mb, move |data| {
+        mplex_and_send_data(&data)
+    });
 }
@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::convert::TryFrom;
+use tentacle_multiaddr::Multiaddr;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(addr) = Multiaddr::try_from(data.to_vec()) {
+        let _ = addr.to_string();
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use bytes::BytesMut;
+use tentacle_discovery::protocol::{decode, DiscoveryMessage};
+
+fuzz_target!(|data: &[u8]| {
+    // with the length delimited frame
+    let _ = decode(&mut BytesMut::from(data));
+    let _ = DiscoveryMessage::decode(data);
+});
@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tentacle_identify::protocol::IdentifyMessage;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = IdentifyMessage::decode(data);
+});
@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tentacle_ping::PingMessage;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = PingMessage::decode(data);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tentacle_secio::{handshake::handshake_struct::Exchange, PublicKey};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Exchange::decode(data);
+    let _ = PublicKey::decode(data);
+});
@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tentacle_secio::handshake::handshake_struct::Propose;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Propose::decode(data);
+});
@@ -0,0 +1,140 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use futures::{executor::block_on, join};
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+use tentacle_secio::{handshake::Config, SecioKeyPair};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+#[derive(Default)]
+struct PipeState {
+    data: VecDeque<u8>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+/// One direction of an in-memory connection
+#[derive(Clone, Default)]
+struct Pipe(Arc<Mutex<PipeState>>);
+
+impl Pipe {
+    fn push(&self, data: &[u8]) {
+        let mut state = self.0.lock().unwrap();
+        state.data.extend(data);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Socket {
+    read: Pipe,
+    write: Pipe,
+}
+
+impl AsyncRead for Socket {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.read.0.lock().unwrap();
+        if state.data.is_empty() {
+            if state.closed {
+                return Poll::Ready(Ok(0));
+            }
+            state.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = buf.len().min(state.data.len());
+        for (dst, src) in buf.iter_mut().zip(state.data.drain(..n)) {
+            *dst = src;
+        }
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for Socket {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.write.push(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        self.write.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Two connected sockets, and the pipe read by the second one
+fn pair() -> (Socket, Socket, Pipe) {
+    let (outbound, inbound) = (Pipe::default(), Pipe::default());
+    (
+        Socket {
+            read: outbound.clone(),
+            write: inbound.clone(),
+        },
+        Socket {
+            read: inbound.clone(),
+            write: outbound,
+        },
+        inbound,
+    )
+}
+
+/// The first byte is the size of the written chunks, when the second byte is odd the rest
+/// is put on the wire as is, otherwise it is sent through the secure stream of the local
+fuzz_target!(|data: &[u8]| {
+    let (chunk, raw, data) = match data {
+        [chunk, raw, data @ ..] => (*chunk as usize + 1, raw & 1 == 1, data),
+        _ => return,
+    };
+
+    block_on(async move {
+        let (local, remote, inbound) = pair();
+        let local_config = Config::new(SecioKeyPair::secp256k1_raw_key([1; 32]).unwrap());
+        let remote_config = Config::new(SecioKeyPair::secp256k1_raw_key([2; 32]).unwrap());
+
+        // a real handshake, the remote decodes the frames with the negotiated cipher
+        let (mut local, mut remote) =
+            match join!(local_config.handshake(local), remote_config.handshake(remote)) {
+                (Ok((local, _, _)), Ok((remote, _, _))) => (local, remote),
+                _ => return,
+            };
+
+        if raw {
+            inbound.push(data);
+        } else {
+            for chunk in data.chunks(chunk) {
+                if local.write_all(chunk).await.is_err() {
+                    return;
+                }
+            }
+        }
+        inbound.close();
+
+        let mut buf = [0; 1024];
+        while let Ok(n) = remote.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+        }
+    });
+});
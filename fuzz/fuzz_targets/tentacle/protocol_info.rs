@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tentacle::protocol_select::{decode_protocol_list, ProtocolInfo};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = ProtocolInfo::decode(data);
+    let _ = decode_protocol_list(data);
+});
@@ -16,6 +16,8 @@ pub enum Misbehavior {
     TooManyAddresses(usize),
     // Decode message error
     InvalidData,
+    // Peer record can't be verified or doesn't belong to remote peer
+    InvalidPeerRecord,
 }
 
 /// Misbehavior report result
@@ -1,12 +1,14 @@
 use std::{
     collections::HashMap,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use log::{debug, trace, warn};
 use p2p::{
     bytes::BytesMut,
     context::{ProtocolContext, ProtocolContextMutRef},
+    multiaddr::Multiaddr,
+    secio::{peer_record::PeerRecord, PeerId, SecioKeyPair},
     traits::ServiceProtocol,
     SessionId,
 };
@@ -17,7 +19,8 @@ use protocol::{decode, encode, DiscoveryMessage, Node, Nodes};
 use state::{RemoteAddress, SessionState};
 
 mod addr;
-mod protocol;
+#[doc(hidden)]
+pub mod protocol;
 mod state;
 
 #[cfg(feature = "flatc")]
@@ -140,7 +143,25 @@ impl<M: AddressManager> ServiceProtocol for DiscoveryProtocol<M> {
                                 }
                             }
 
-                            let max = ::std::cmp::max(MAX_ADDR_TO_SEND, count as usize);
+                            // the listen addresses of self, signed by self, the sessions
+                            // established before a key rotation know the old peer id,
+                            // so there is no record after it
+                            let addr_mgr = &self.addr_mgr;
+                            let addresses = context
+                                .listens()
+                                .iter()
+                                .filter(|addr| addr_mgr.is_valid_addr(addr))
+                                .take(MAX_ADDRS)
+                                .cloned()
+                                .collect();
+                            let self_node = context
+                                .key_pair()
+                                .filter(|_| context.key_rotation().is_none())
+                                .and_then(|key_pair| signed_node(key_pair, addresses));
+
+                            // leave a place for the node of self
+                            let max = ::std::cmp::max(MAX_ADDR_TO_SEND, count as usize)
+                                - self_node.is_some() as usize;
                             if items.len() > max {
                                 items = items
                                     .choose_multiple(&mut rand::thread_rng(), max)
@@ -154,7 +175,9 @@ impl<M: AddressManager> ServiceProtocol for DiscoveryProtocol<M> {
                                 .into_iter()
                                 .map(|addr| Node {
                                     addresses: vec![addr],
+                                    peer_record: None,
                                 })
+                                .chain(self_node)
                                 .collect::<Vec<_>>();
 
                             let nodes = Nodes {
@@ -175,15 +198,28 @@ impl<M: AddressManager> ServiceProtocol for DiscoveryProtocol<M> {
                             }
                         }
 
+                        // drop the nodes whose peer record is invalid
+                        let remote_peer_id =
+                            session.remote_pubkey.as_ref().map(PeerId::from_public_key);
+                        let Nodes { announce, items } = nodes;
+                        let (items, invalid): (Vec<_>, Vec<_>) = items
+                            .into_iter()
+                            .partition(|node| verify_peer_record(node, remote_peer_id.as_ref()));
+                        for _ in invalid {
+                            debug!("{:?} send invalid peer record", session.id);
+                            if check(Misbehavior::InvalidPeerRecord) {
+                                return;
+                            }
+                        }
+
                         if let Some(state) = self.sessions.get_mut(&session.id) {
-                            if !nodes.announce && state.received_nodes {
+                            if !announce && state.received_nodes {
                                 warn!("Nodes items more than {}", ANNOUNCE_THRESHOLD);
                                 if check(Misbehavior::DuplicateFirstNodes) {
                                     return;
                                 }
                             } else {
-                                let addrs = nodes
-                                    .items
+                                let addrs = items
                                     .into_iter()
                                     .flat_map(|node| node.addresses.into_iter())
                                     .collect::<Vec<_>>();
@@ -193,7 +229,7 @@ impl<M: AddressManager> ServiceProtocol for DiscoveryProtocol<M> {
                                 // Due to the uncertainty of the other party’s state,
                                 // the announce node may be sent out first, and it must be
                                 // determined to be Non-announce before the state can be changed
-                                if !nodes.announce {
+                                if !announce {
                                     state.received_nodes = true;
                                 }
                                 self.addr_mgr.add_new_addrs(session.id, addrs);
@@ -262,6 +298,45 @@ impl<M: AddressManager> ServiceProtocol for DiscoveryProtocol<M> {
     }
 }
 
+/// A node with peer record must be signed by the peer of the session,
+/// and all of its addresses must be in the record
+fn verify_peer_record(node: &Node, peer_id: Option<&PeerId>) -> bool {
+    let data = match node.peer_record {
+        Some(ref data) => data,
+        None => return true,
+    };
+    peer_id
+        .and_then(|peer_id| {
+            PeerRecord::decode_signed(data)
+                .ok()
+                .filter(|record| record.peer_id() == peer_id)
+        })
+        .map(|record| {
+            node.addresses
+                .iter()
+                .all(|addr| record.addresses().contains(addr))
+        })
+        .unwrap_or(false)
+}
+
+/// The node of self with the addresses signed by the key pair
+fn signed_node(key_pair: &SecioKeyPair, addresses: Vec<Multiaddr>) -> Option<Node> {
+    if addresses.is_empty() {
+        return None;
+    }
+    let seq = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default();
+    let envelope = PeerRecord::new(key_pair.peer_id(), addresses.clone(), seq)
+        .into_signed_envelope(key_pair)
+        .ok()?;
+    Some(Node {
+        addresses,
+        peer_record: Some(envelope.encode().into()),
+    })
+}
+
 fn verify_nodes_message(nodes: &Nodes) -> Option<Misbehavior> {
     let mut misbehavior = None;
     if nodes.announce {
@@ -294,3 +369,62 @@ fn verify_nodes_message(nodes: &Nodes) -> Option<Misbehavior> {
 
     misbehavior
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{signed_node, verify_peer_record, Node};
+    use p2p::{
+        multiaddr::Multiaddr,
+        secio::{
+            envelope::SignedEnvelope,
+            peer_record::{PeerRecord, DOMAIN, PAYLOAD_TYPE},
+            SecioKeyPair,
+        },
+    };
+
+    fn addresses() -> Vec<Multiaddr> {
+        vec!["/ip4/1.1.1.1/tcp/1337".parse().unwrap()]
+    }
+
+    #[test]
+    fn test_verify_peer_record() {
+        let key_pair = SecioKeyPair::secp256k1_generated();
+        let node = signed_node(&key_pair, addresses()).unwrap();
+        assert!(verify_peer_record(&node, Some(&key_pair.peer_id())));
+
+        // the node without record is not checked
+        let unsigned = Node {
+            addresses: addresses(),
+            peer_record: None,
+        };
+        assert!(verify_peer_record(&unsigned, Some(&key_pair.peer_id())));
+
+        // the record of other peer
+        let other = SecioKeyPair::secp256k1_generated();
+        assert!(!verify_peer_record(&node, Some(&other.peer_id())));
+        assert!(!verify_peer_record(&node, None));
+
+        // forged, the record claims to be of the peer but is signed by other
+        let forged = Node {
+            addresses: addresses(),
+            peer_record: Some(
+                SignedEnvelope::new(
+                    &other,
+                    DOMAIN,
+                    PAYLOAD_TYPE.to_vec(),
+                    PeerRecord::new(key_pair.peer_id(), addresses(), 1).encode(),
+                    1,
+                )
+                .encode()
+                .into(),
+            ),
+        };
+        assert!(!verify_peer_record(&forged, Some(&key_pair.peer_id())));
+
+        // the address is not signed
+        let mut node = node;
+        node.addresses
+            .push("/ip4/2.2.2.2/tcp/1337".parse().unwrap());
+        assert!(!verify_peer_record(&node, Some(&key_pair.peer_id())));
+    }
+}
@@ -21,6 +21,8 @@ use crate::protocol_generated::p2p::discovery::{
 use crate::protocol_mol;
 #[cfg(feature = "molc")]
 use molecule::prelude::{Builder, Entity, Reader};
+#[cfg(feature = "molc")]
+use p2p::secio::molecule_ext::{append_table_field, extra_table_field};
 
 pub(crate) fn encode(data: DiscoveryMessage) -> Bytes {
     // Length Delimited Codec is not a mandatory requirement.
@@ -33,7 +35,7 @@ pub(crate) fn encode(data: DiscoveryMessage) -> Bytes {
     bytes.freeze()
 }
 
-pub(crate) fn decode(data: &mut BytesMut) -> Option<DiscoveryMessage> {
+pub fn decode(data: &mut BytesMut) -> Option<DiscoveryMessage> {
     // Length Delimited Codec is not a mandatory requirement.
     // For historical reasons, this must exist as compatibility
     let mut codec = LengthDelimitedCodec::new();
@@ -90,8 +92,15 @@ impl DiscoveryMessage {
                         vec_addrs.push(bytes_builder.finish());
                     }
                     let fbs_addrs = fbb.create_vector(&vec_addrs);
+                    let peer_record = item
+                        .peer_record
+                        .as_ref()
+                        .map(|peer_record| fbb.create_vector(peer_record));
                     let mut node_builder = NodeBuilder::new(&mut fbb);
                     node_builder.add_addresses(fbs_addrs);
+                    if let Some(peer_record) = peer_record {
+                        node_builder.add_peer_record(peer_record);
+                    }
                     vec_items.push(node_builder.finish());
                 }
                 let fbs_items = fbb.create_vector(&vec_items);
@@ -141,7 +150,10 @@ impl DiscoveryMessage {
                         let multiaddr = Multiaddr::try_from(address.seq()?.to_vec()).ok()?;
                         addresses.push(multiaddr);
                     }
-                    items.push(Node { addresses });
+                    items.push(Node {
+                        addresses,
+                        peer_record: fbs_node.peer_record().map(Bytes::copy_from_slice),
+                    });
                 }
                 Some(DiscoveryMessage::Nodes(Nodes {
                     announce: fbs_nodes.announce(),
@@ -211,6 +223,20 @@ impl DiscoveryMessage {
                     let node = protocol_mol::Node::new_builder()
                         .addresses(bytes_vec)
                         .build();
+                    // peer record is appended as an extra field, the peers decoding with
+                    // the old schema ignore it in compatible mode
+                    let node = match item.peer_record {
+                        Some(peer_record) => {
+                            let peer_record = protocol_mol::Bytes::new_builder()
+                                .set(peer_record.iter().copied().map(Into::into).collect())
+                                .build();
+                            let data = append_table_field(node.as_slice(), peer_record.as_slice())
+                                .expect("node is a valid table");
+                            protocol_mol::Node::from_compatible_slice(&data)
+                                .expect("node with peer record is a valid table")
+                        }
+                        None => node,
+                    };
                     item_vec.push(node)
                 }
                 let items = protocol_mol::NodeVec::new_builder().set(item_vec).build();
@@ -263,7 +289,21 @@ impl DiscoveryMessage {
                         addresses
                             .push(Multiaddr::try_from(address_reader.raw_data().to_vec()).ok()?)
                     }
-                    items.push(Node { addresses })
+                    let peer_record = match extra_table_field(
+                        node_reader.as_slice(),
+                        protocol_mol::NodeReader::FIELD_COUNT,
+                    ) {
+                        Some(field) => Some(Bytes::copy_from_slice(
+                            protocol_mol::BytesReader::from_slice(field)
+                                .ok()?
+                                .raw_data(),
+                        )),
+                        None => None,
+                    };
+                    items.push(Node {
+                        addresses,
+                        peer_record,
+                    })
                 }
                 Some(DiscoveryMessage::Nodes(Nodes { announce, items }))
             }
@@ -280,6 +320,8 @@ pub struct Nodes {
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Node {
     pub(crate) addresses: Vec<Multiaddr>,
+    /// The encoded `SignedEnvelope` of peer record
+    pub(crate) peer_record: Option<Bytes>,
 }
 
 impl std::fmt::Display for DiscoveryMessage {
@@ -304,3 +346,23 @@ impl std::fmt::Display for DiscoveryMessage {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DiscoveryMessage, Node, Nodes};
+    use bytes::Bytes;
+
+    #[test]
+    fn test_nodes_codec() {
+        let node = |peer_record: Option<Bytes>| Node {
+            addresses: vec!["/ip4/1.1.1.1/tcp/1337".parse().unwrap()],
+            peer_record,
+        };
+        let message = DiscoveryMessage::Nodes(Nodes {
+            announce: false,
+            items: vec![node(None), node(Some(Bytes::from_static(b"peer record")))],
+        });
+        let data = message.clone().encode();
+        assert_eq!(DiscoveryMessage::decode(&data), Some(message));
+    }
+}
@@ -409,26 +409,34 @@ impl<'a> Node<'a> {
         _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
         args: &'args NodeArgs<'args>) -> flatbuffers::WIPOffset<Node<'bldr>> {
       let mut builder = NodeBuilder::new(_fbb);
+      if let Some(x) = args.peer_record { builder.add_peer_record(x); }
       if let Some(x) = args.addresses { builder.add_addresses(x); }
       builder.finish()
     }
 
     pub const VT_ADDRESSES: flatbuffers::VOffsetT = 4;
+    pub const VT_PEER_RECORD: flatbuffers::VOffsetT = 6;
 
   #[inline]
   pub fn addresses(&self) -> Option<flatbuffers::Vector<flatbuffers::ForwardsUOffset<Bytes<'a>>>> {
     self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<flatbuffers::ForwardsUOffset<Bytes<'a>>>>>(Node::VT_ADDRESSES, None)
   }
+  #[inline]
+  pub fn peer_record(&self) -> Option<&'a [u8]> {
+    self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(Node::VT_PEER_RECORD, None).map(|v| v.safe_slice())
+  }
 }
 
 pub struct NodeArgs<'a> {
     pub addresses: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a , flatbuffers::ForwardsUOffset<Bytes<'a >>>>>,
+    pub peer_record: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a ,  u8>>>,
 }
 impl<'a> Default for NodeArgs<'a> {
     #[inline]
     fn default() -> Self {
         NodeArgs {
             addresses: None,
+            peer_record: None,
         }
     }
 }
@@ -442,6 +450,10 @@ impl<'a: 'b, 'b> NodeBuilder<'a, 'b> {
     self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Node::VT_ADDRESSES, addresses);
   }
   #[inline]
+  pub fn add_peer_record(&mut self, peer_record: flatbuffers::WIPOffset<flatbuffers::Vector<'b , u8>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Node::VT_PEER_RECORD, peer_record);
+  }
+  #[inline]
   pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> NodeBuilder<'a, 'b> {
     let start = _fbb.start_table();
     NodeBuilder {
@@ -91,6 +91,7 @@ impl SessionState {
                 .drain(..)
                 .map(|addr| Node {
                     addresses: vec![addr],
+                    peer_record: None,
                 })
                 .collect::<Vec<_>>();
             let nodes = Nodes {
@@ -15,16 +15,17 @@ mod protocol_generated_verifier;
 #[allow(dead_code)]
 mod protocol_mol;
 
-mod protocol;
+#[doc(hidden)]
+pub mod protocol;
 
 use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use log::{debug, error, trace, warn};
 use p2p::{
     context::{ProtocolContext, ProtocolContextMutRef, SessionContext},
     multiaddr::{Multiaddr, Protocol},
-    secio::PeerId,
+    secio::{key_rotation::KeyRotation, peer_record::PeerRecord, PeerId, SecioKeyPair},
     service::SessionType,
     traits::ServiceProtocol,
     utils::{is_reachable, multiaddr_to_socketaddr},
@@ -49,6 +50,8 @@ pub enum Misbehavior {
     InvalidData,
     /// Send too many addresses in listen addresses
     TooManyAddresses(usize),
+    /// Peer record can't be verified or doesn't belong to remote peer
+    InvalidPeerRecord,
 }
 
 /// Misbehavior report result
@@ -97,6 +100,15 @@ pub trait Callback: Clone + Send {
     ) -> MisbehaveResult;
     /// Report misbehavior
     fn misbehave(&mut self, peer: &PeerId, kind: Misbehavior) -> MisbehaveResult;
+    /// Remote peer has replaced its key pair, the link from the old peer id to the current one
+    /// has been verified
+    fn received_key_rotation(&mut self, _old: &PeerId, _new: &PeerId) -> MisbehaveResult {
+        MisbehaveResult::Continue
+    }
+    /// Received remote peer's listen addresses signed by itself, the record has been verified
+    fn received_peer_record(&mut self, _peer: &PeerId, _record: PeerRecord) -> MisbehaveResult {
+        MisbehaveResult::Continue
+    }
 }
 
 /// Identify protocol
@@ -171,6 +183,68 @@ impl<T: Callback> IdentifyProtocol<T> {
         }
     }
 
+    fn process_key_rotation(
+        &mut self,
+        context: &mut ProtocolContextMutRef,
+        key_rotation: Option<&[u8]>,
+    ) -> MisbehaveResult {
+        let data = match key_rotation {
+            Some(data) => data,
+            None => return MisbehaveResult::Continue,
+        };
+        let session = context.session;
+        let info = self
+            .remote_infos
+            .get_mut(&session.id)
+            .expect("RemoteInfo must exists");
+
+        match linked_key_rotation(data, &info.peer_id) {
+            Some(rotation) => {
+                trace!(
+                    "remote key rotated from {:?} to {:?}",
+                    rotation.old_peer_id(),
+                    rotation.new_peer_id()
+                );
+                self.callback
+                    .received_key_rotation(rotation.old_peer_id(), rotation.new_peer_id())
+            }
+            None => {
+                debug!("remote({:?}) send invalid key rotation", info.peer_id);
+                self.callback
+                    .misbehave(&info.peer_id, Misbehavior::InvalidData)
+            }
+        }
+    }
+
+    fn process_peer_record(
+        &mut self,
+        context: &mut ProtocolContextMutRef,
+        peer_record: Option<&[u8]>,
+        key_rotation: Option<&[u8]>,
+    ) -> MisbehaveResult {
+        let data = match peer_record {
+            Some(data) => data,
+            None => return MisbehaveResult::Continue,
+        };
+        let session = context.session;
+        let info = self
+            .remote_infos
+            .get_mut(&session.id)
+            .expect("RemoteInfo must exists");
+
+        match verified_peer_record(data, &info.peer_id, key_rotation) {
+            Some(record) => {
+                trace!("received peer record: {:?}", record);
+                self.callback.received_peer_record(&info.peer_id, record)
+            }
+            None => {
+                debug!("remote({:?}) send invalid peer record", info.peer_id);
+                self.callback
+                    .misbehave(&info.peer_id, Misbehavior::InvalidPeerRecord)
+            }
+        }
+    }
+
     fn process_observed(
         &mut self,
         context: &mut ProtocolContextMutRef,
@@ -200,6 +274,46 @@ impl<T: Callback> IdentifyProtocol<T> {
     }
 }
 
+/// Verify the encoded key rotation, the link must point to the peer id of the session,
+/// the session established before the rotation still carries the old one
+fn linked_key_rotation(data: &[u8], peer_id: &PeerId) -> Option<KeyRotation> {
+    KeyRotation::decode_signed(data)
+        .ok()
+        .filter(|rotation| rotation.new_peer_id() == peer_id || rotation.old_peer_id() == peer_id)
+}
+
+/// Verify the encoded peer record, it must be signed by the peer of the session, or by
+/// the current one if the key rotation in the same message links it to the session
+fn verified_peer_record(
+    data: &[u8],
+    peer_id: &PeerId,
+    key_rotation: Option<&[u8]>,
+) -> Option<PeerRecord> {
+    let record = PeerRecord::decode_signed(data).ok()?;
+    if record.peer_id() == peer_id
+        || key_rotation
+            .and_then(|data| linked_key_rotation(data, peer_id))
+            .filter(|rotation| rotation.new_peer_id() == record.peer_id())
+            .is_some()
+    {
+        Some(record)
+    } else {
+        None
+    }
+}
+
+/// Sign the listen addresses with the key pair of self
+fn signed_peer_record(key_pair: &SecioKeyPair, listen_addrs: Vec<Multiaddr>) -> Option<Vec<u8>> {
+    let seq = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default();
+    PeerRecord::new(key_pair.peer_id(), listen_addrs, seq)
+        .into_signed_envelope(key_pair)
+        .ok()
+        .map(|envelope| envelope.encode())
+}
+
 pub(crate) struct RemoteInfo {
     peer_id: PeerId,
     session: SessionContext,
@@ -275,8 +389,19 @@ impl<T: Callback> ServiceProtocol for IdentifyProtocol<T> {
             })
             .collect::<Multiaddr>();
 
+        let key_rotation = context.key_rotation().map(|envelope| envelope.encode());
+        let peer_record = context
+            .key_pair()
+            .and_then(|key_pair| signed_peer_record(key_pair, listen_addrs.clone()));
         let identify = self.callback.identify();
-        let data = IdentifyMessage::new(listen_addrs, observed_addr, identify).encode();
+        let data = IdentifyMessage::new(
+            listen_addrs,
+            observed_addr,
+            identify,
+            key_rotation.as_deref(),
+            peer_record.as_deref(),
+        )
+        .encode();
         let _ = context.quick_send_message(data);
     }
 
@@ -311,6 +436,16 @@ impl<T: Callback> ServiceProtocol for IdentifyProtocol<T> {
                     || self
                         .process_observed(&mut context, message.observed_addr)
                         .is_disconnect()
+                    || self
+                        .process_key_rotation(&mut context, message.key_rotation)
+                        .is_disconnect()
+                    || self
+                        .process_peer_record(
+                            &mut context,
+                            message.peer_record,
+                            message.key_rotation,
+                        )
+                        .is_disconnect()
                 {
                     let _ = context.disconnect(session.id);
                 }
@@ -354,3 +489,91 @@ impl<T: Callback> ServiceProtocol for IdentifyProtocol<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{linked_key_rotation, signed_peer_record, verified_peer_record};
+    use p2p::{
+        multiaddr::Multiaddr,
+        secio::{
+            envelope::SignedEnvelope,
+            key_rotation::KeyRotation,
+            peer_record::{PeerRecord, DOMAIN, PAYLOAD_TYPE},
+            SecioKeyPair,
+        },
+    };
+
+    #[test]
+    fn test_linked_key_rotation() {
+        let old = SecioKeyPair::secp256k1_generated();
+        let new = SecioKeyPair::secp256k1_generated();
+        let rotation = KeyRotation::new(old.peer_id(), new.peer_id(), 1);
+        let data = rotation
+            .clone()
+            .into_signed_envelope(&old)
+            .unwrap()
+            .encode();
+
+        // sessions established after the rotation
+        assert_eq!(
+            linked_key_rotation(&data, &new.peer_id()),
+            Some(rotation.clone())
+        );
+        // sessions established before the rotation
+        assert_eq!(linked_key_rotation(&data, &old.peer_id()), Some(rotation));
+        // the link doesn't belong to this session
+        let other = SecioKeyPair::secp256k1_generated();
+        assert_eq!(linked_key_rotation(&data, &other.peer_id()), None);
+        // not signed
+        assert_eq!(linked_key_rotation(&data[1..], &new.peer_id()), None);
+    }
+
+    #[test]
+    fn test_verified_peer_record() {
+        let key_pair = SecioKeyPair::secp256k1_generated();
+        let addrs: Vec<Multiaddr> = vec!["/ip4/127.0.0.1/tcp/1337".parse().unwrap()];
+        let data = signed_peer_record(&key_pair, addrs.clone()).unwrap();
+
+        let record = verified_peer_record(&data, &key_pair.peer_id(), None).unwrap();
+        assert_eq!(record.peer_id(), &key_pair.peer_id());
+        assert_eq!(record.addresses(), &addrs[..]);
+
+        // the record of other peer
+        let other = SecioKeyPair::secp256k1_generated();
+        assert_eq!(verified_peer_record(&data, &other.peer_id(), None), None);
+
+        // forged, the record claims to be of the peer but is signed by other
+        let forged = SignedEnvelope::new(
+            &other,
+            DOMAIN,
+            PAYLOAD_TYPE.to_vec(),
+            PeerRecord::new(key_pair.peer_id(), addrs, 1).encode(),
+            1,
+        )
+        .encode();
+        assert_eq!(
+            verified_peer_record(&forged, &key_pair.peer_id(), None),
+            None
+        );
+        // not signed
+        assert_eq!(
+            verified_peer_record(&data[1..], &key_pair.peer_id(), None),
+            None
+        );
+
+        // the session established before the rotation of remote
+        let rotation = KeyRotation::new(other.peer_id(), key_pair.peer_id(), 1)
+            .into_signed_envelope(&other)
+            .unwrap()
+            .encode();
+        assert_eq!(
+            verified_peer_record(&data, &other.peer_id(), Some(&rotation)),
+            Some(record)
+        );
+        let third = SecioKeyPair::secp256k1_generated();
+        assert_eq!(
+            verified_peer_record(&data, &third.peer_id(), Some(&rotation)),
+            None
+        );
+    }
+}
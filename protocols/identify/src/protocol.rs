@@ -12,6 +12,8 @@ use crate::protocol_generated::p2p::identify::{
 use crate::protocol_mol;
 #[cfg(feature = "molc")]
 use molecule::prelude::{Builder, Entity, Reader};
+#[cfg(feature = "molc")]
+use p2p::secio::molecule_ext::{append_table_field, extra_table_field};
 
 use bytes::Bytes;
 use p2p::multiaddr::Multiaddr;
@@ -23,6 +25,10 @@ pub struct IdentifyMessage<'a> {
     pub(crate) listen_addrs: Vec<Multiaddr>,
     pub(crate) observed_addr: Multiaddr,
     pub(crate) identify: &'a [u8],
+    /// The encoded `SignedEnvelope` of key rotation
+    pub(crate) key_rotation: Option<&'a [u8]>,
+    /// The encoded `SignedEnvelope` of peer record
+    pub(crate) peer_record: Option<&'a [u8]>,
 }
 
 impl<'a> IdentifyMessage<'a> {
@@ -30,11 +36,15 @@ impl<'a> IdentifyMessage<'a> {
         listen_addrs: Vec<Multiaddr>,
         observed_addr: Multiaddr,
         identify: &'a [u8],
+        key_rotation: Option<&'a [u8]>,
+        peer_record: Option<&'a [u8]>,
     ) -> Self {
         IdentifyMessage {
             listen_addrs,
             observed_addr,
             identify,
+            key_rotation,
+            peer_record,
         }
     }
 
@@ -52,11 +62,25 @@ impl<'a> IdentifyMessage<'a> {
 
         let identify = fbb.create_vector(self.identify);
 
+        let key_rotation = self
+            .key_rotation
+            .map(|key_rotation| fbb.create_vector(key_rotation));
+
+        let peer_record = self
+            .peer_record
+            .map(|peer_record| fbb.create_vector(peer_record));
+
         let mut builder = IdentifyMessageBuilder::new(&mut fbb);
 
         builder.add_listen_addrs(listens_vec);
         builder.add_observed_addr(observed);
         builder.add_identify(identify);
+        if let Some(key_rotation) = key_rotation {
+            builder.add_key_rotation(key_rotation);
+        }
+        if let Some(peer_record) = peer_record {
+            builder.add_peer_record(peer_record);
+        }
 
         let data = builder.finish();
 
@@ -65,7 +89,7 @@ impl<'a> IdentifyMessage<'a> {
     }
 
     #[cfg(feature = "flatc")]
-    pub(crate) fn decode(data: &'a [u8]) -> Option<Self> {
+    pub fn decode(data: &'a [u8]) -> Option<Self> {
         let fbs_message = flatbuffers_verifier::get_root::<FbsIdentifyMessage>(data).ok()?;
 
         match (
@@ -85,6 +109,8 @@ impl<'a> IdentifyMessage<'a> {
                     listen_addrs,
                     observed_addr,
                     identify,
+                    key_rotation: fbs_message.key_rotation(),
+                    peer_record: fbs_message.peer_record(),
                 })
             }
             _ => None,
@@ -125,19 +151,42 @@ impl<'a> IdentifyMessage<'a> {
             .set(listen_addrs)
             .build();
 
-        protocol_mol::IdentifyMessage::new_builder()
+        let message = protocol_mol::IdentifyMessage::new_builder()
             .listen_addrs(listen_addrs)
             .observed_addr(observed_addr)
             .identify(identify)
-            .build()
-            .as_bytes()
+            .build();
+
+        // key rotation and peer record are appended as extra fields in order, the peers
+        // decoding with the old schema ignore them in compatible mode
+        let mut data = message.as_bytes();
+        if self.key_rotation.is_some() || self.peer_record.is_some() {
+            // an empty key rotation takes the place when there is only peer record
+            let key_rotation = mol_bytes(self.key_rotation.unwrap_or_default());
+            data = append_table_field(&data, key_rotation.as_slice())
+                .expect("identify message is a valid table");
+        }
+        if let Some(peer_record) = self.peer_record {
+            data = append_table_field(&data, mol_bytes(peer_record).as_slice())
+                .expect("identify message is a valid table");
+        }
+        data
     }
 
     #[cfg(feature = "molc")]
-    pub(crate) fn decode(data: &'a [u8]) -> Option<Self> {
+    pub fn decode(data: &'a [u8]) -> Option<Self> {
         let reader = protocol_mol::IdentifyMessageReader::from_compatible_slice(data).ok()?;
 
         let identify = reader.identify().raw_data();
+        let extra_field = |index| match extra_table_field(reader.as_slice(), index) {
+            Some(field) => protocol_mol::BytesReader::from_slice(field)
+                .ok()
+                .map(|field| Some(field.raw_data())),
+            None => Some(None),
+        };
+        let field_count = protocol_mol::IdentifyMessageReader::FIELD_COUNT;
+        let key_rotation = extra_field(field_count)?.filter(|data| !data.is_empty());
+        let peer_record = extra_field(field_count + 1)?;
         let observed_addr =
             Multiaddr::try_from(reader.observed_addr().bytes().raw_data().to_vec()).ok()?;
         let mut listen_addrs = Vec::with_capacity(reader.listen_addrs().len());
@@ -149,10 +198,19 @@ impl<'a> IdentifyMessage<'a> {
             identify,
             observed_addr,
             listen_addrs,
+            key_rotation,
+            peer_record,
         })
     }
 }
 
+#[cfg(feature = "molc")]
+fn mol_bytes(data: &[u8]) -> protocol_mol::Bytes {
+    protocol_mol::Bytes::new_builder()
+        .set(data.iter().copied().map(Into::into).collect())
+        .build()
+}
+
 #[cfg(feature = "flatc")]
 fn addr_to_offset<'b>(
     fbb: &mut flatbuffers::FlatBufferBuilder<'b>,
@@ -168,3 +226,73 @@ fn addr_to_offset<'b>(
 fn fbs_to_addr(addr: &FbsAddress) -> Option<Multiaddr> {
     Multiaddr::try_from(addr.bytes()?.to_vec()).ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::IdentifyMessage;
+
+    fn message<'a>(
+        key_rotation: Option<&'a [u8]>,
+        peer_record: Option<&'a [u8]>,
+    ) -> IdentifyMessage<'a> {
+        IdentifyMessage::new(
+            vec![
+                "/ip4/127.0.0.1/tcp/1337".parse().unwrap(),
+                "/ip4/10.0.0.1/tcp/1338".parse().unwrap(),
+            ],
+            "/ip4/192.168.0.1/tcp/1339".parse().unwrap(),
+            b"identify",
+            key_rotation,
+            peer_record,
+        )
+    }
+
+    #[test]
+    fn test_codec() {
+        for key_rotation in &[None, Some(&b"key rotation"[..])] {
+            for peer_record in &[None, Some(&b"peer record"[..])] {
+                let message = message(*key_rotation, *peer_record);
+                let data = message.clone().encode();
+                assert_eq!(IdentifyMessage::decode(&data), Some(message));
+            }
+        }
+    }
+
+    #[cfg(feature = "molc")]
+    #[test]
+    fn test_decode_invalid_key_rotation() {
+        let data = message(Some(b"key rotation"), None).encode();
+        // the truncated key rotation field can't be decoded
+        assert_eq!(IdentifyMessage::decode(&data[..data.len() - 1]), None);
+
+        let data = message(None, Some(b"peer record")).encode();
+        // the truncated peer record field can't be decoded
+        assert_eq!(IdentifyMessage::decode(&data[..data.len() - 1]), None);
+    }
+
+    #[cfg(feature = "molc")]
+    #[test]
+    fn test_old_schema_compatibility() {
+        use crate::protocol_mol;
+        use molecule::prelude::Reader;
+
+        let message = message(Some(b"key rotation"), Some(b"peer record"));
+        let data = message.clone().encode();
+
+        // the peers with the old schema decode the declared fields in compatible mode
+        assert!(protocol_mol::IdentifyMessageReader::verify(&data, false).is_err());
+        let reader = protocol_mol::IdentifyMessageReader::from_compatible_slice(&data).unwrap();
+        assert_eq!(reader.identify().raw_data(), message.identify);
+        assert_eq!(reader.listen_addrs().len(), message.listen_addrs.len());
+
+        // the message from the peers with the old schema has no key rotation or peer record
+        let old = IdentifyMessage {
+            key_rotation: None,
+            peer_record: None,
+            ..message
+        };
+        let data = old.clone().encode();
+        assert!(protocol_mol::IdentifyMessageReader::verify(&data, false).is_ok());
+        assert_eq!(IdentifyMessage::decode(&data), Some(old));
+    }
+}
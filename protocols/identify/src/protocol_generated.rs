@@ -130,6 +130,8 @@ impl<'a> IdentifyMessage<'a> {
         _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
         args: &'args IdentifyMessageArgs<'args>) -> flatbuffers::WIPOffset<IdentifyMessage<'bldr>> {
       let mut builder = IdentifyMessageBuilder::new(_fbb);
+      if let Some(x) = args.peer_record { builder.add_peer_record(x); }
+      if let Some(x) = args.key_rotation { builder.add_key_rotation(x); }
       if let Some(x) = args.identify { builder.add_identify(x); }
       if let Some(x) = args.observed_addr { builder.add_observed_addr(x); }
       if let Some(x) = args.listen_addrs { builder.add_listen_addrs(x); }
@@ -139,6 +141,8 @@ impl<'a> IdentifyMessage<'a> {
     pub const VT_LISTEN_ADDRS: flatbuffers::VOffsetT = 4;
     pub const VT_OBSERVED_ADDR: flatbuffers::VOffsetT = 6;
     pub const VT_IDENTIFY: flatbuffers::VOffsetT = 8;
+    pub const VT_KEY_ROTATION: flatbuffers::VOffsetT = 10;
+    pub const VT_PEER_RECORD: flatbuffers::VOffsetT = 12;
 
   #[inline]
   pub fn listen_addrs(&self) -> Option<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<Address<'a>>>> {
@@ -152,12 +156,22 @@ impl<'a> IdentifyMessage<'a> {
   pub fn identify(&self) -> Option<&'a [u8]> {
     self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(IdentifyMessage::VT_IDENTIFY, None).map(|v| v.safe_slice())
   }
+  #[inline]
+  pub fn key_rotation(&self) -> Option<&'a [u8]> {
+    self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(IdentifyMessage::VT_KEY_ROTATION, None).map(|v| v.safe_slice())
+  }
+  #[inline]
+  pub fn peer_record(&self) -> Option<&'a [u8]> {
+    self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(IdentifyMessage::VT_PEER_RECORD, None).map(|v| v.safe_slice())
+  }
 }
 
 pub struct IdentifyMessageArgs<'a> {
     pub listen_addrs: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a , flatbuffers::ForwardsUOffset<Address<'a >>>>>,
     pub observed_addr: Option<flatbuffers::WIPOffset<Address<'a >>>,
     pub identify: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a ,  u8>>>,
+    pub key_rotation: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a ,  u8>>>,
+    pub peer_record: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a ,  u8>>>,
 }
 impl<'a> Default for IdentifyMessageArgs<'a> {
     #[inline]
@@ -166,6 +180,8 @@ impl<'a> Default for IdentifyMessageArgs<'a> {
             listen_addrs: None,
             observed_addr: None,
             identify: None,
+            key_rotation: None,
+            peer_record: None,
         }
     }
 }
@@ -187,6 +203,14 @@ impl<'a: 'b, 'b> IdentifyMessageBuilder<'a, 'b> {
     self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(IdentifyMessage::VT_IDENTIFY, identify);
   }
   #[inline]
+  pub fn add_key_rotation(&mut self, key_rotation: flatbuffers::WIPOffset<flatbuffers::Vector<'b , u8>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(IdentifyMessage::VT_KEY_ROTATION, key_rotation);
+  }
+  #[inline]
+  pub fn add_peer_record(&mut self, peer_record: flatbuffers::WIPOffset<flatbuffers::Vector<'b , u8>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(IdentifyMessage::VT_PEER_RECORD, peer_record);
+  }
+  #[inline]
   pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> IdentifyMessageBuilder<'a, 'b> {
     let start = _fbb.start_table();
     IdentifyMessageBuilder {
@@ -217,6 +217,40 @@ pub mod p2p {
                     }
                 }
 
+                if Self::VT_KEY_ROTATION as usize + flatbuffers::SIZE_VOFFSET
+                    <= vtab_num_bytes
+                {
+                    let voffset = vtab.get(Self::VT_KEY_ROTATION) as usize;
+                    if voffset > 0 {
+                        if voffset + 4 > object_inline_num_bytes {
+                            return Err(Error::OutOfBounds);
+                        }
+
+                        let key_rotation_verifier = VectorVerifier::follow(
+                            buf,
+                            try_follow_uoffset(buf, tab.loc + voffset)?,
+                        );
+                        key_rotation_verifier.verify_scalar_elements(1)?;
+                    }
+                }
+
+                if Self::VT_PEER_RECORD as usize + flatbuffers::SIZE_VOFFSET
+                    <= vtab_num_bytes
+                {
+                    let voffset = vtab.get(Self::VT_PEER_RECORD) as usize;
+                    if voffset > 0 {
+                        if voffset + 4 > object_inline_num_bytes {
+                            return Err(Error::OutOfBounds);
+                        }
+
+                        let peer_record_verifier = VectorVerifier::follow(
+                            buf,
+                            try_follow_uoffset(buf, tab.loc + voffset)?,
+                        );
+                        peer_record_verifier.verify_scalar_elements(1)?;
+                    }
+                }
+
                 Ok(())
             }
         }
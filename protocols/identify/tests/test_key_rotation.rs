@@ -0,0 +1,162 @@
+use futures::{channel, StreamExt};
+use std::{sync::mpsc, thread};
+
+use p2p::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContextMutRef, ServiceContext},
+    multiaddr::Multiaddr,
+    secio::{PeerId, SecioKeyPair},
+    service::{ProtocolHandle, ServiceControl, ServiceEvent, SessionType, TargetProtocol},
+    traits::ServiceHandle,
+};
+use tentacle_identify::{Callback, IdentifyProtocol, MisbehaveResult, Misbehavior};
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Identify,
+    Close,
+    /// Old and new peer id of remote
+    KeyRotation(PeerId, PeerId),
+    Misbehave,
+}
+
+#[derive(Clone)]
+struct IdentifyCallback {
+    sender: mpsc::Sender<Event>,
+}
+
+impl Callback for IdentifyCallback {
+    fn identify(&mut self) -> &[u8] {
+        b"Identify message"
+    }
+
+    fn received_identify(
+        &mut self,
+        _context: &mut ProtocolContextMutRef,
+        _identify: &[u8],
+    ) -> MisbehaveResult {
+        let _res = self.sender.send(Event::Identify);
+        MisbehaveResult::Continue
+    }
+
+    fn local_listen_addrs(&mut self) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn add_remote_listen_addrs(&mut self, _peer: &PeerId, _addrs: Vec<Multiaddr>) {}
+
+    fn add_observed_addr(
+        &mut self,
+        _peer: &PeerId,
+        _addr: Multiaddr,
+        _ty: SessionType,
+    ) -> MisbehaveResult {
+        MisbehaveResult::Continue
+    }
+
+    fn misbehave(&mut self, _peer: &PeerId, _kind: Misbehavior) -> MisbehaveResult {
+        let _res = self.sender.send(Event::Misbehave);
+        MisbehaveResult::Disconnect
+    }
+
+    fn received_key_rotation(&mut self, old: &PeerId, new: &PeerId) -> MisbehaveResult {
+        let _res = self
+            .sender
+            .send(Event::KeyRotation(old.clone(), new.clone()));
+        MisbehaveResult::Continue
+    }
+}
+
+struct SHandle {
+    sender: mpsc::Sender<Event>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionClose { .. } = event {
+            let _res = self.sender.send(Event::Close);
+        }
+    }
+}
+
+struct Node {
+    control: ServiceControl,
+    events: mpsc::Receiver<Event>,
+}
+
+fn start_service(key_pair: SecioKeyPair, dial: Option<Multiaddr>) -> (Node, Multiaddr) {
+    let (sender, events) = mpsc::channel();
+    let (control_sender, control_receiver) = channel::oneshot::channel();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let callback = IdentifyCallback {
+            sender: sender.clone(),
+        };
+        let protocol = MetaBuilder::default()
+            .id(1.into())
+            .service_handle(move || {
+                ProtocolHandle::Callback(Box::new(
+                    IdentifyProtocol::new(callback).global_ip_only(false),
+                ))
+            })
+            .build();
+        let mut service = ServiceBuilder::default()
+            .insert_protocol(protocol)
+            .key_pair(key_pair)
+            .forever(true)
+            .build(SHandle { sender });
+        rt.block_on(async move {
+            let address = match dial {
+                Some(address) => {
+                    service
+                        .dial(address.clone(), TargetProtocol::All)
+                        .await
+                        .unwrap();
+                    address
+                }
+                None => service
+                    .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                    .await
+                    .unwrap(),
+            };
+            let _res = control_sender.send((service.control().clone(), address));
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let (control, address) = futures::executor::block_on(control_receiver).unwrap();
+    (Node { control, events }, address)
+}
+
+#[test]
+fn test_identify_key_rotation() {
+    let old_key_pair = SecioKeyPair::secp256k1_generated();
+    let new_key_pair = SecioKeyPair::secp256k1_generated();
+
+    let (listener, address) = start_service(old_key_pair.clone(), None);
+    let (dialer, _) = start_service(SecioKeyPair::secp256k1_generated(), Some(address.clone()));
+    // the session is established before the rotation, no link in it
+    assert_eq!(dialer.events.recv(), Ok(Event::Identify));
+
+    listener
+        .control
+        .rotate_key_pair(new_key_pair.clone(), false)
+        .unwrap();
+    assert_eq!(dialer.events.recv(), Ok(Event::Close));
+
+    // the identify message on the new session carries the link to the new identity
+    dialer.control.dial(address, TargetProtocol::All).unwrap();
+    assert_eq!(dialer.events.recv(), Ok(Event::Identify));
+    assert_eq!(
+        dialer.events.recv(),
+        Ok(Event::KeyRotation(
+            old_key_pair.peer_id(),
+            new_key_pair.peer_id()
+        ))
+    );
+}
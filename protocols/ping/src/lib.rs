@@ -261,12 +261,14 @@ where
     }
 }
 
-enum PingPayload {
+#[doc(hidden)]
+pub enum PingPayload {
     Ping(u32),
     Pong(u32),
 }
 
-struct PingMessage;
+#[doc(hidden)]
+pub struct PingMessage;
 
 impl PingMessage {
     #[cfg(feature = "flatc")]
@@ -303,7 +305,7 @@ impl PingMessage {
     }
 
     #[cfg(feature = "flatc")]
-    fn decode(data: &[u8]) -> Option<PingPayload> {
+    pub fn decode(data: &[u8]) -> Option<PingPayload> {
         let msg =
             flatbuffers_verifier::get_root::<protocol_generated::p2p::ping::PingMessage>(data)
                 .ok()?;
@@ -356,7 +358,7 @@ impl PingMessage {
 
     #[cfg(feature = "molc")]
     #[allow(clippy::cast_ptr_alignment)]
-    fn decode(data: &[u8]) -> Option<PingPayload> {
+    pub fn decode(data: &[u8]) -> Option<PingPayload> {
         let reader = protocol_mol::PingMessageReader::from_compatible_slice(data).ok()?;
         match reader.payload().to_enum() {
             protocol_mol::PingPayloadUnionReader::Ping(reader) => {
@@ -1,3 +1,4 @@
+use bytes::BytesMut;
 use criterion::{criterion_group, criterion_main, Bencher, Criterion};
 use tentacle_secio::crypto::{cipher::CipherType, new_stream, CryptoMode};
 
@@ -22,6 +23,31 @@ fn bench_test(bench: &mut Bencher, cipher: CipherType, data: &[u8]) {
     })
 }
 
+/// Encrypt frames to a reused write buffer, as `SecureStream` does
+fn encrypt_frames(data: &[u8], cipher: CipherType, in_place: bool) {
+    let cipher_key = [0u8; 32];
+    let mut encode_cipher = new_stream(
+        cipher,
+        &cipher_key[..cipher.key_size()],
+        CryptoMode::Encrypt,
+    );
+    let mut buf = BytesMut::with_capacity(data.len() + cipher.tag_size());
+
+    for _ in 0..16 {
+        buf.clear();
+        if in_place {
+            encode_cipher.encrypt_to(data, &mut buf).unwrap();
+        } else {
+            let frame = encode_cipher.encrypt(data).unwrap();
+            buf.extend_from_slice(&frame);
+        }
+    }
+}
+
+fn bench_encrypt(bench: &mut Bencher, cipher: CipherType, data: &[u8], in_place: bool) {
+    bench.iter(|| encrypt_frames(data, cipher, in_place))
+}
+
 fn criterion_benchmark(bench: &mut Criterion) {
     let data = (0..1024 * 256)
         .map(|_| rand::random::<u8>())
@@ -52,6 +78,23 @@ fn criterion_benchmark(bench: &mut Criterion) {
     bench.bench_function("1mb_chacha20poly1305", move |b| {
         bench_test(b, CipherType::ChaCha20Poly1305, &data)
     });
+
+    let data = (0..1024 * 64)
+        .map(|_| rand::random::<u8>())
+        .collect::<Vec<_>>();
+    for &(name, cipher) in &[
+        ("aes128gcm", CipherType::Aes128Gcm),
+        ("chacha20poly1305", CipherType::ChaCha20Poly1305),
+    ] {
+        bench.bench_function(&format!("64kb_{}_encrypt", name), {
+            let data = data.clone();
+            move |b| bench_encrypt(b, cipher, &data, false)
+        });
+        bench.bench_function(&format!("64kb_{}_encrypt_to", name), {
+            let data = data.clone();
+            move |b| bench_encrypt(b, cipher, &data, true)
+        });
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
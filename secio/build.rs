@@ -9,4 +9,11 @@ fn main() {
             println!("cargo:rustc-cfg=ossl110");
         }
     }
+
+    // wasm can only use the pure rust crypto backend
+    if env::var("CARGO_CFG_TARGET_ARCH").ok().as_deref() == Some("wasm32")
+        || env::var("CARGO_FEATURE_PURE_RUST_CRYPTO").is_ok()
+    {
+        println!("cargo:rustc-cfg=pure_rust_crypto");
+    }
 }
@@ -1,16 +1,16 @@
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(not(pure_rust_crypto))]
 mod native;
 
-#[cfg(any(target_arch = "wasm32", test))]
+#[cfg(any(pure_rust_crypto, test))]
 mod wasm_compat;
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(not(pure_rust_crypto))]
 pub use native::*;
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(pure_rust_crypto)]
 pub use wasm_compat::*;
 
-#[cfg(test)]
+#[cfg(all(test, not(pure_rust_crypto)))]
 mod test {
     use super::*;
     use crate::Digest;
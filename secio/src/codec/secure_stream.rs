@@ -1,11 +1,13 @@
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::{SinkExt, StreamExt};
 use log::{debug, trace};
 use tokio::{
     io::AsyncReadExt,
     prelude::{AsyncRead, AsyncWrite},
 };
-use tokio_util::codec::{length_delimited::LengthDelimitedCodec, Framed};
+use tokio_util::codec::{
+    length_delimited::LengthDelimitedCodec, Decoder, Encoder, Framed, FramedParts,
+};
 
 use std::{
     cmp::min,
@@ -14,7 +16,7 @@ use std::{
     task::{Context, Poll},
 };
 
-use crate::{crypto::BoxStreamCipher, error::SecioError};
+use crate::{crypto::BoxStreamCipher, error::SecioError, handshake::NegotiatedAlgorithms};
 
 enum RecvBuf {
     Vec(Vec<u8>),
@@ -55,11 +57,60 @@ impl AsRef<[u8]> for RecvBuf {
     }
 }
 
+/// The size of frame length prefix
+const LENGTH_FIELD_SIZE: usize = 4;
+/// The max tag size of supported ciphers
+const MAX_TAG_SIZE: usize = 16;
+
+/// Length delimited codec which encrypts the data directly into the write buffer
+struct SecureCodec {
+    inner: LengthDelimitedCodec,
+    encode_cipher: BoxStreamCipher,
+}
+
+impl Decoder for SecureCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    #[inline]
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.inner.decode(src)
+    }
+}
+
+impl<'a> Encoder<&'a [u8]> for SecureCodec {
+    type Error = io::Error;
+
+    /// Same layout as the length delimited codec used by handshake: a 4 bytes big endian
+    /// length prefix, followed by the encrypted data with tag
+    fn encode(&mut self, item: &'a [u8], dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let start = dst.len();
+        dst.reserve(LENGTH_FIELD_SIZE + item.len() + MAX_TAG_SIZE);
+        // Length is unknown until encrypted
+        dst.put_u32(0);
+
+        if let Err(err) = self.encode_cipher.encrypt_to(item, dst) {
+            dst.truncate(start);
+            return Err(err.into());
+        }
+
+        let n = dst.len() - start - LENGTH_FIELD_SIZE;
+        if n > self.inner.max_frame_length() {
+            dst.truncate(start);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame size too big",
+            ));
+        }
+        dst[start..start + LENGTH_FIELD_SIZE].copy_from_slice(&(n as u32).to_be_bytes());
+        Ok(())
+    }
+}
+
 /// Encrypted stream
 pub struct SecureStream<T> {
-    socket: Framed<T, LengthDelimitedCodec>,
+    socket: Framed<T, SecureCodec>,
     decode_cipher: BoxStreamCipher,
-    encode_cipher: BoxStreamCipher,
     /// denotes a sequence of bytes which are expected to be
     /// found at the beginning of the stream and are checked for equality
     nonce: Vec<u8>,
@@ -71,6 +122,10 @@ pub struct SecureStream<T> {
     /// into this buffer so that multiple following 'read' will eventually
     /// get the message correctly
     recv_buf: RecvBuf,
+    /// algorithms negotiated by secio handshake
+    negotiated: Option<NegotiatedAlgorithms>,
+    /// early data sent by remote with its proposition
+    remote_early_data: Option<Bytes>,
 }
 
 impl<T> SecureStream<T>
@@ -89,15 +144,49 @@ where
         } else {
             RecvBuf::Vec(Vec::default())
         };
+        let parts = socket.into_parts();
+        let mut secure_parts = FramedParts::new(
+            parts.io,
+            SecureCodec {
+                inner: parts.codec,
+                encode_cipher,
+            },
+        );
+        secure_parts.read_buf = parts.read_buf;
+        secure_parts.write_buf = parts.write_buf;
         SecureStream {
-            socket,
+            socket: Framed::from_parts(secure_parts),
             decode_cipher,
-            encode_cipher,
             nonce,
             recv_buf,
+            negotiated: None,
+            remote_early_data: None,
         }
     }
 
+    /// Record the algorithms negotiated by handshake
+    pub(crate) fn with_negotiated(mut self, negotiated: NegotiatedAlgorithms) -> Self {
+        self.negotiated = Some(negotiated);
+        self
+    }
+
+    /// Algorithms negotiated by secio handshake, None if the stream
+    /// is not created by secio handshake, such as a private network stream
+    pub fn negotiated_algorithms(&self) -> Option<NegotiatedAlgorithms> {
+        self.negotiated
+    }
+
+    /// Record the early data of remote
+    pub(crate) fn with_remote_early_data(mut self, early_data: Option<Bytes>) -> Self {
+        self.remote_early_data = early_data;
+        self
+    }
+
+    /// Early data sent by remote during secio handshake, None if the remote doesn't send it
+    pub fn remote_early_data(&self) -> Option<&Bytes> {
+        self.remote_early_data.as_ref()
+    }
+
     /// Decoding data
     #[inline]
     fn decode_buffer(&mut self, mut frame: BytesMut) -> Result<RecvBuf, SecioError> {
@@ -146,12 +235,6 @@ where
 
         n
     }
-
-    #[inline]
-    fn encode_buffer(&mut self, buf: &[u8]) -> Bytes {
-        let out = self.encode_cipher.encrypt(buf).unwrap();
-        Bytes::from(out)
-    }
 }
 
 impl<T> AsyncRead for SecureStream<T>
@@ -216,8 +299,7 @@ where
         match self.socket.poll_ready_unpin(cx) {
             Poll::Ready(Ok(_)) => {
                 trace!("poll_write buf.len={}", buf.len());
-                let frame = self.encode_buffer(buf);
-                self.socket.start_send_unpin(frame)?;
+                self.socket.start_send_unpin(buf)?;
                 let _ignore = self.socket.poll_flush_unpin(cx)?;
                 Poll::Ready(Ok(buf.len()))
             }
@@ -316,11 +398,13 @@ mod tests {
         });
     }
 
+    #[cfg(not(pure_rust_crypto))]
     #[test]
     fn test_encode_decode_aes128gcm() {
         test_decode_encode(CipherType::Aes128Gcm);
     }
 
+    #[cfg(not(pure_rust_crypto))]
     #[test]
     fn test_encode_decode_aes256gcm() {
         test_decode_encode(CipherType::Aes256Gcm);
@@ -331,11 +415,13 @@ mod tests {
         test_decode_encode(CipherType::ChaCha20Poly1305);
     }
 
+    #[cfg(not(pure_rust_crypto))]
     #[test]
     fn secure_codec_encode_then_decode_aes128gcm() {
         secure_codec_encode_then_decode(CipherType::Aes128Gcm);
     }
 
+    #[cfg(not(pure_rust_crypto))]
     #[test]
     fn secure_codec_encode_then_decode_aes256gcm() {
         secure_codec_encode_then_decode(CipherType::Aes256Gcm);
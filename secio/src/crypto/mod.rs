@@ -3,12 +3,12 @@ use bytes::BytesMut;
 
 /// Define cipher
 pub mod cipher;
-#[cfg(unix)]
+#[cfg(all(unix, not(pure_rust_crypto)))]
 mod openssl_impl;
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(not(pure_rust_crypto))]
 #[cfg(any(not(ossl110), test, not(unix)))]
 mod ring_impl;
-#[cfg(any(target_arch = "wasm32", test))]
+#[cfg(any(pure_rust_crypto, test))]
 mod wasm_compat;
 
 /// Variant cipher which contains all possible stream ciphers
@@ -20,6 +20,12 @@ pub type BoxStreamCipher = Box<dyn StreamCipher + Send>;
 pub trait StreamCipher {
     /// Feeds data from input through the cipher, return encrypted bytes.
     fn encrypt(&mut self, input: &[u8]) -> Result<Vec<u8>, SecioError>;
+    /// Feeds data from input through the cipher, append encrypted bytes and tag to output,
+    /// without allocating an intermediate buffer if the cipher supports it.
+    fn encrypt_to(&mut self, input: &[u8], output: &mut BytesMut) -> Result<(), SecioError> {
+        output.extend_from_slice(&self.encrypt(input)?);
+        Ok(())
+    }
     /// Feeds data from input through the cipher, return decrypted bytes.
     fn decrypt(&mut self, input: &[u8]) -> Result<Vec<u8>, SecioError>;
     /// Whether support in place decrypt
@@ -47,14 +53,14 @@ pub enum CryptoMode {
 
 /// Generate a specific Cipher with key and initialize vector
 #[doc(hidden)]
-#[cfg(all(ossl110, unix))]
+#[cfg(all(ossl110, unix, not(pure_rust_crypto)))]
 pub fn new_stream(t: cipher::CipherType, key: &[u8], _mode: CryptoMode) -> BoxStreamCipher {
     Box::new(openssl_impl::OpenSSLCrypt::new(t, key))
 }
 
 /// Generate a specific Cipher with key and initialize vector
 #[doc(hidden)]
-#[cfg(all(not(ossl110), unix))]
+#[cfg(all(not(ossl110), unix, not(pure_rust_crypto)))]
 pub fn new_stream(t: cipher::CipherType, key: &[u8], mode: CryptoMode) -> BoxStreamCipher {
     use cipher::CipherType::*;
 
@@ -66,15 +72,14 @@ pub fn new_stream(t: cipher::CipherType, key: &[u8], mode: CryptoMode) -> BoxStr
 
 /// Generate a specific Cipher with key and initialize vector
 #[doc(hidden)]
-#[cfg(not(target_arch = "wasm32"))]
-#[cfg(not(unix))]
+#[cfg(all(not(unix), not(pure_rust_crypto)))]
 pub fn new_stream(t: cipher::CipherType, key: &[u8], mode: CryptoMode) -> BoxStreamCipher {
     Box::new(ring_impl::RingAeadCipher::new(t, key, mode))
 }
 
 /// Generate a specific Cipher with key and initialize vector
 #[doc(hidden)]
-#[cfg(target_arch = "wasm32")]
+#[cfg(pure_rust_crypto)]
 pub fn new_stream(t: cipher::CipherType, key: &[u8], _mode: CryptoMode) -> BoxStreamCipher {
     Box::new(wasm_compat::WasmCrypt::new(t, key))
 }
@@ -97,12 +102,13 @@ fn nonce_advance(nonce: &mut [u8]) {
     }
 }
 
-#[cfg(all(test, unix))]
+#[cfg(all(test, unix, not(pure_rust_crypto)))]
 mod test {
     use super::{
         cipher::CipherType, openssl_impl::OpenSSLCrypt, ring_impl::RingAeadCipher,
         wasm_compat::WasmCrypt, CryptoMode,
     };
+    use bytes::BytesMut;
 
     fn test_openssl_encrypt_ring_decrypt(cipher: CipherType) {
         let key = (0..cipher.key_size())
@@ -204,16 +210,78 @@ mod test {
         assert_eq!(message, &decrypted_msg[..]);
     }
 
+    fn test_wasm_encrypt_ring_decrypt(cipher: CipherType) {
+        let key = (0..cipher.key_size())
+            .map(|_| rand::random::<u8>())
+            .collect::<Vec<_>>();
+
+        let mut wasm_encrypt = WasmCrypt::new(cipher, &key);
+        let mut ring_decrypt = RingAeadCipher::new(cipher, &key, CryptoMode::Decrypt);
+
+        // first time
+        let message = b"HELLO WORLD";
+
+        let encrypted_msg = wasm_encrypt.encrypt(message).unwrap();
+        let decrypted_msg = ring_decrypt.decrypt(&encrypted_msg).unwrap();
+
+        assert_eq!(message, &decrypted_msg[..]);
+
+        // second time, in place
+        let message = b"hello, world";
+
+        let mut encrypted_msg = BytesMut::new();
+        wasm_encrypt
+            .encrypt_to(message, &mut encrypted_msg)
+            .unwrap();
+        ring_decrypt.decrypt_in_place(&mut encrypted_msg).unwrap();
+
+        assert_eq!(message, &encrypted_msg[..]);
+    }
+
+    fn test_ring_encrypt_wasm_decrypt_in_place(cipher: CipherType) {
+        let key = (0..cipher.key_size())
+            .map(|_| rand::random::<u8>())
+            .collect::<Vec<_>>();
+
+        let mut ring_encrypt = RingAeadCipher::new(cipher, &key, CryptoMode::Encrypt);
+        let mut wasm_decrypt = WasmCrypt::new(cipher, &key);
+
+        for message in &[&b"HELLO WORLD"[..], &b"hello, world"[..]] {
+            let mut encrypted_msg = BytesMut::new();
+            ring_encrypt
+                .encrypt_to(message, &mut encrypted_msg)
+                .unwrap();
+            wasm_decrypt.decrypt_in_place(&mut encrypted_msg).unwrap();
+
+            assert_eq!(message, &&encrypted_msg[..]);
+        }
+    }
+
+    #[test]
+    fn test_chacha20_poly1305_ring_and_pure_rust() {
+        test_ring_encrypt_wasm_decrypt(CipherType::ChaCha20Poly1305);
+        test_wasm_encrypt_ring_decrypt(CipherType::ChaCha20Poly1305);
+        test_ring_encrypt_wasm_decrypt_in_place(CipherType::ChaCha20Poly1305)
+    }
+
     #[test]
     fn test_aes_128_gcm() {
         test_ring_encrypt_openssl_decrypt(CipherType::Aes128Gcm);
-        test_openssl_encrypt_ring_decrypt(CipherType::Aes128Gcm)
+        test_openssl_encrypt_ring_decrypt(CipherType::Aes128Gcm);
+        test_ring_encrypt_wasm_decrypt(CipherType::Aes128Gcm);
+        test_wasm_encrypt_openssl_decrypt(CipherType::Aes128Gcm);
+        test_wasm_encrypt_ring_decrypt(CipherType::Aes128Gcm);
+        test_ring_encrypt_wasm_decrypt_in_place(CipherType::Aes128Gcm)
     }
 
     #[test]
     fn test_aes_256_gcm() {
         test_ring_encrypt_openssl_decrypt(CipherType::Aes256Gcm);
-        test_openssl_encrypt_ring_decrypt(CipherType::Aes256Gcm)
+        test_openssl_encrypt_ring_decrypt(CipherType::Aes256Gcm);
+        test_ring_encrypt_wasm_decrypt(CipherType::Aes256Gcm);
+        test_wasm_encrypt_openssl_decrypt(CipherType::Aes256Gcm);
+        test_wasm_encrypt_ring_decrypt(CipherType::Aes256Gcm);
+        test_ring_encrypt_wasm_decrypt_in_place(CipherType::Aes256Gcm)
     }
 
     #[cfg(any(ossl110))]
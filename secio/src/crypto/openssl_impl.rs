@@ -62,6 +62,39 @@ impl OpenSSLCrypt {
         Ok(output)
     }
 
+    /// Encrypt `input` and append the result with `tag` to `output` directly.
+    pub fn encrypt_to(&mut self, input: &[u8], output: &mut BytesMut) -> Result<(), SecioError> {
+        nonce_advance(self.iv.as_mut());
+        let tag_size = self.cipher_type.tag_size();
+        let start = output.len();
+        let len = input.len() + self.cipher.block_size() + tag_size;
+        output.reserve(len);
+        unsafe {
+            output.set_len(start + len);
+        }
+
+        let res = (|| -> Result<usize, openssl::error::ErrorStack> {
+            let mut crypter =
+                symm::Crypter::new(self.cipher, symm::Mode::Encrypt, &self.key, Some(&self.iv))?;
+            crypter.aad_update(&[])?;
+            let count = crypter.update(input, &mut output[start..])?;
+            let end = start + count + crypter.finalize(&mut output[start + count..])?;
+            crypter.get_tag(&mut output[end..end + tag_size])?;
+            Ok(end + tag_size)
+        })();
+
+        match res {
+            Ok(end) => {
+                output.truncate(end);
+                Ok(())
+            }
+            Err(err) => {
+                output.truncate(start);
+                Err(SecioError::Openssl(err))
+            }
+        }
+    }
+
     /// Decrypt `input` to `output` with `tag`. `output.len()` should equals to `input.len() - tag.len()`.
     /// ```plain
     /// +----------------------------------------+-----------------------+
@@ -91,6 +124,10 @@ impl StreamCipher for OpenSSLCrypt {
         self.encrypt(input)
     }
 
+    fn encrypt_to(&mut self, input: &[u8], output: &mut BytesMut) -> Result<(), SecioError> {
+        self.encrypt_to(input, output)
+    }
+
     fn decrypt(&mut self, input: &[u8]) -> Result<Vec<u8>, SecioError> {
         self.decrypt(input)
     }
@@ -99,6 +136,7 @@ impl StreamCipher for OpenSSLCrypt {
 #[cfg(test)]
 mod test {
     use super::{CipherType, OpenSSLCrypt};
+    use bytes::BytesMut;
 
     fn test_openssl(mode: CipherType) {
         let key = (0..mode.key_size())
@@ -123,6 +161,16 @@ mod test {
         let decrypted_msg = decryptor.decrypt(&encrypted_msg[..]).unwrap();
 
         assert_eq!(message, &decrypted_msg[..]);
+
+        // encrypt to the tail of a buffer
+        let mut output = BytesMut::from(&b"head"[..]);
+        encryptor.encrypt_to(message, &mut output).unwrap();
+        assert_eq!(&output[..4], b"head");
+        assert_eq!(output.len(), 4 + message.len() + mode.tag_size());
+
+        let decrypted_msg = decryptor.decrypt(&output[4..]).unwrap();
+
+        assert_eq!(message, &decrypted_msg[..]);
     }
 
     #[test]
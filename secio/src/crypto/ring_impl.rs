@@ -112,6 +112,27 @@ impl RingAeadCipher {
         Ok(buf)
     }
 
+    /// Encrypt `input` and append the result with `tag` to `output`, the data is sealed in place
+    /// in the spare capacity of `output`.
+    pub fn encrypt_to(&mut self, input: &[u8], output: &mut BytesMut) -> Result<(), SecioError> {
+        let start = output.len();
+        output.reserve(input.len() + self.cipher_type.tag_size());
+        output.extend_from_slice(input);
+        let mut buf = output.split_off(start);
+
+        let res = if let RingAeadCryptoVariant::Seal(ref mut key) = self.cipher {
+            key.seal_in_place_append_tag(Aad::empty(), &mut buf)
+        } else {
+            unreachable!("encrypt is called on a non-seal cipher")
+        };
+        // `buf` is split from the tail of `output`, so unsplit doesn't copy
+        output.unsplit(buf);
+        if res.is_err() {
+            output.truncate(start);
+        }
+        res.map_err(Into::into)
+    }
+
     pub fn decrypt_in_place(&mut self, input: &mut BytesMut) -> Result<(), SecioError> {
         let output_len = input
             .len()
@@ -133,6 +154,10 @@ impl StreamCipher for RingAeadCipher {
         self.encrypt(input)
     }
 
+    fn encrypt_to(&mut self, input: &[u8], output: &mut BytesMut) -> Result<(), SecioError> {
+        self.encrypt_to(input, output)
+    }
+
     fn decrypt(&mut self, input: &[u8]) -> Result<Vec<u8>, SecioError> {
         self.decrypt(input)
     }
@@ -150,6 +175,7 @@ impl StreamCipher for RingAeadCipher {
 #[cfg(test)]
 mod test {
     use super::{CipherType, CryptoMode, RingAeadCipher};
+    use bytes::BytesMut;
 
     fn test_ring_aead(cipher: CipherType) {
         let key = (0..cipher.key_size())
@@ -180,6 +206,16 @@ mod test {
         let decrypted_msg = dec.decrypt(&encrypted_msg[..]).unwrap();
 
         assert_eq!(&decrypted_msg[..], message);
+
+        // encrypt to the tail of a buffer
+        let mut output = BytesMut::from(&b"head"[..]);
+        enc.encrypt_to(message, &mut output).unwrap();
+        assert_eq!(&output[..4], b"head");
+        assert_eq!(output.len(), 4 + message.len() + cipher.tag_size());
+
+        let decrypted_msg = dec.decrypt(&output[4..]).unwrap();
+
+        assert_eq!(&decrypted_msg[..], message);
     }
 
     #[test]
@@ -1,8 +1,13 @@
 #![allow(dead_code)]
 
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
 use chacha20poly1305::{
-    aead::{Aead, NewAead, Nonce},
-    ChaCha20Poly1305, Key,
+    aead::{
+        consts::{U0, U12, U16},
+        generic_array::GenericArray,
+        AeadInPlace, NewAead, Nonce,
+    },
+    ChaCha20Poly1305, Tag,
 };
 
 use crate::{
@@ -12,20 +17,22 @@ use crate::{
 
 use bytes::BytesMut;
 
+type Aead = dyn AeadInPlace<NonceSize = U12, TagSize = U16, CiphertextOverhead = U0> + Send;
+
 pub(crate) struct WasmCrypt {
-    cipher: ChaCha20Poly1305,
-    _cipher_type: CipherType,
+    cipher: Box<Aead>,
+    cipher_type: CipherType,
     iv: BytesMut,
 }
 
 impl WasmCrypt {
     pub fn new(cipher_type: CipherType, key: &[u8]) -> Self {
-        let cipher = match cipher_type {
-            CipherType::ChaCha20Poly1305 => ChaCha20Poly1305::new(Key::from_slice(key)),
-            _ => panic!(
-                "Cipher type {:?} does not supported by WasmCrypt yet",
-                cipher_type
-            ),
+        let cipher: Box<Aead> = match cipher_type {
+            CipherType::Aes128Gcm => Box::new(Aes128Gcm::new(GenericArray::from_slice(key))),
+            CipherType::Aes256Gcm => Box::new(Aes256Gcm::new(GenericArray::from_slice(key))),
+            CipherType::ChaCha20Poly1305 => {
+                Box::new(ChaCha20Poly1305::new(GenericArray::from_slice(key)))
+            }
         };
 
         // aead use self-increase iv
@@ -38,7 +45,7 @@ impl WasmCrypt {
 
         WasmCrypt {
             cipher,
-            _cipher_type: cipher_type,
+            cipher_type,
             iv: nonce,
         }
     }
@@ -51,9 +58,37 @@ impl WasmCrypt {
     /// ```
     pub fn encrypt(&mut self, input: &[u8]) -> Result<Vec<u8>, SecioError> {
         nonce_advance(self.iv.as_mut());
-        self.cipher
-            .encrypt(Nonce::from_slice(self.iv.as_ref()), input)
-            .map_err(|_| SecioError::RingCryptoError)
+        let mut output = Vec::with_capacity(input.len() + self.cipher_type.tag_size());
+        output.extend_from_slice(input);
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(Nonce::from_slice(self.iv.as_ref()), &[], &mut output)
+            .map_err(|_| SecioError::RingCryptoError)?;
+        output.extend_from_slice(&tag);
+        Ok(output)
+    }
+
+    /// Encrypt `input` and append the encrypted text and tag to `output`, in place.
+    pub fn encrypt_to(&mut self, input: &[u8], output: &mut BytesMut) -> Result<(), SecioError> {
+        nonce_advance(self.iv.as_mut());
+        let start = output.len();
+        output.reserve(input.len() + self.cipher_type.tag_size());
+        output.extend_from_slice(input);
+
+        match self.cipher.encrypt_in_place_detached(
+            Nonce::from_slice(self.iv.as_ref()),
+            &[],
+            &mut output[start..],
+        ) {
+            Ok(tag) => {
+                output.extend_from_slice(&tag);
+                Ok(())
+            }
+            Err(_) => {
+                output.truncate(start);
+                Err(SecioError::RingCryptoError)
+            }
+        }
     }
 
     /// Decrypt `input` to `output` with `tag`. `output.len()` should equals to `input.len() - tag.len()`.
@@ -63,10 +98,44 @@ impl WasmCrypt {
     /// +----------------------------------------+-----------------------+
     /// ```
     pub fn decrypt(&mut self, input: &[u8]) -> Result<Vec<u8>, SecioError> {
+        let output_len = input
+            .len()
+            .checked_sub(self.cipher_type.tag_size())
+            .ok_or(SecioError::FrameTooShort)?;
+
         nonce_advance(self.iv.as_mut());
+        let (text, tag) = input.split_at(output_len);
+        let mut output = text.to_vec();
         self.cipher
-            .decrypt(Nonce::from_slice(self.iv.as_ref()), input)
-            .map_err(|_| SecioError::RingCryptoError)
+            .decrypt_in_place_detached(
+                Nonce::from_slice(self.iv.as_ref()),
+                &[],
+                &mut output,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| SecioError::RingCryptoError)?;
+        Ok(output)
+    }
+
+    /// Decrypt `input` in place, the tag is truncated
+    pub fn decrypt_in_place(&mut self, input: &mut BytesMut) -> Result<(), SecioError> {
+        let output_len = input
+            .len()
+            .checked_sub(self.cipher_type.tag_size())
+            .ok_or(SecioError::FrameTooShort)?;
+
+        nonce_advance(self.iv.as_mut());
+        let (text, tag) = input.split_at_mut(output_len);
+        self.cipher
+            .decrypt_in_place_detached(
+                Nonce::from_slice(self.iv.as_ref()),
+                &[],
+                text,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| SecioError::RingCryptoError)?;
+        input.truncate(output_len);
+        Ok(())
     }
 }
 
@@ -75,14 +144,28 @@ impl StreamCipher for WasmCrypt {
         self.encrypt(input)
     }
 
+    fn encrypt_to(&mut self, input: &[u8], output: &mut BytesMut) -> Result<(), SecioError> {
+        self.encrypt_to(input, output)
+    }
+
     fn decrypt(&mut self, input: &[u8]) -> Result<Vec<u8>, SecioError> {
         self.decrypt(input)
     }
+
+    #[inline]
+    fn is_in_place(&self) -> bool {
+        true
+    }
+
+    fn decrypt_in_place(&mut self, input: &mut BytesMut) -> Result<(), SecioError> {
+        self.decrypt_in_place(input)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::{CipherType, WasmCrypt};
+    use bytes::BytesMut;
 
     fn test_wasm(mode: CipherType) {
         let key = (0..mode.key_size())
@@ -107,6 +190,25 @@ mod test {
         let decrypted_msg = decryptor.decrypt(&encrypted_msg[..]).unwrap();
 
         assert_eq!(message, &decrypted_msg[..]);
+
+        // in place
+        let mut buf = BytesMut::from(&b"head"[..]);
+        encryptor.encrypt_to(message, &mut buf).unwrap();
+        let mut encrypted_msg = buf.split_off(4);
+        decryptor.decrypt_in_place(&mut encrypted_msg).unwrap();
+
+        assert_eq!(&buf[..], b"head");
+        assert_eq!(message, &encrypted_msg[..]);
+    }
+
+    #[test]
+    fn test_aes_128_gcm() {
+        test_wasm(CipherType::Aes128Gcm)
+    }
+
+    #[test]
+    fn test_aes_256_gcm() {
+        test_wasm(CipherType::Aes256Gcm)
     }
 
     #[test]
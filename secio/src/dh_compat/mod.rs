@@ -1,23 +1,26 @@
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(not(pure_rust_crypto))]
 mod native;
-#[cfg(any(target_arch = "wasm32", test))]
+#[cfg(any(pure_rust_crypto, test))]
 mod wasm_compat;
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(not(pure_rust_crypto))]
 pub use native::*;
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(pure_rust_crypto)]
 pub use wasm_compat::*;
 
 /// Possible key agreement algorithms.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum KeyAgreement {
+    /// ECDH on the NIST P-256 curve
     EcdhP256,
+    /// ECDH on the NIST P-384 curve
     EcdhP384,
+    /// ECDH on Curve25519
     X25519,
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(pure_rust_crypto)))]
 mod test {
     use super::*;
 
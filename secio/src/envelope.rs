@@ -0,0 +1,254 @@
+//! Signed envelope, a payload signed by the key pair of a peer, so that it can be forwarded
+//! by any other peer and still be verified.
+//!
+//! The signature covers the domain, payload type, sequence number and payload, the domain
+//! isn't transmitted, so an envelope of one domain can't be reused in another.
+//!
+//! Encoding:
+//!
+//! ```protobuf
+//! message Envelope {
+//!     PublicKey public_key = 1;
+//!     bytes payload_type = 2;
+//!     bytes payload = 3;
+//!     uint64 seq = 4;
+//!     bytes signature = 5;
+//! }
+//! ```
+use unsigned_varint::encode;
+
+use crate::{
+    error::SecioError,
+    handshake::handshake_struct::PublicKey,
+    key_encoding::protobuf::{self, Value},
+    peer_id::PeerId,
+    KeyPairInner, SecioKeyPair,
+};
+
+const FIELD_PUBLIC_KEY: u64 = 1;
+const FIELD_PAYLOAD_TYPE: u64 = 2;
+const FIELD_PAYLOAD: u64 = 3;
+const FIELD_SEQ: u64 = 4;
+const FIELD_SIGNATURE: u64 = 5;
+
+/// A payload signed by a peer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedEnvelope {
+    public_key: PublicKey,
+    payload_type: Vec<u8>,
+    payload: Vec<u8>,
+    seq: u64,
+    signature: Vec<u8>,
+}
+
+impl SignedEnvelope {
+    /// Sign the payload with the key pair.
+    ///
+    /// The sequence number should increase on every new envelope of the same payload type,
+    /// e.g. unix time in milliseconds, so that the receivers can drop the stale ones.
+    pub fn new(
+        key_pair: &SecioKeyPair,
+        domain: &str,
+        payload_type: Vec<u8>,
+        payload: Vec<u8>,
+        seq: u64,
+    ) -> Self {
+        let message = signing_message(domain, &payload_type, seq, &payload);
+        let signature = match key_pair.inner {
+            KeyPairInner::Secp256k1 { ref private } => {
+                let message = crate::secp256k1_compat::message_from_slice(
+                    crate::sha256_compat::sha256(&message).as_ref(),
+                )
+                .expect("sha256 is a valid message");
+                crate::secp256k1_compat::signature_to_vec(crate::secp256k1_compat::sign(
+                    &message, private,
+                ))
+            }
+        };
+
+        SignedEnvelope {
+            public_key: key_pair.public_key(),
+            payload_type,
+            payload,
+            seq,
+            signature,
+        }
+    }
+
+    /// Check the signature with the domain
+    pub fn verify(&self, domain: &str) -> Result<(), SecioError> {
+        let message = signing_message(domain, &self.payload_type, self.seq, &self.payload);
+        let message = crate::secp256k1_compat::message_from_slice(
+            crate::sha256_compat::sha256(&message).as_ref(),
+        )
+        .map_err(|_| SecioError::InvalidMessage)?;
+
+        let verified = match self.public_key {
+            PublicKey::Secp256k1(ref key) => {
+                match (
+                    crate::secp256k1_compat::pubkey_from_slice(key),
+                    crate::secp256k1_compat::signature_from_der(&self.signature),
+                ) {
+                    (Ok(key), Ok(signature)) => {
+                        crate::secp256k1_compat::verify(&message, &signature, &key)
+                    }
+                    _ => false,
+                }
+            }
+        };
+
+        if verified {
+            Ok(())
+        } else {
+            Err(SecioError::SignatureVerificationFailed)
+        }
+    }
+
+    /// The public key of signer
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// The peer id of signer
+    pub fn peer_id(&self) -> PeerId {
+        self.public_key.peer_id()
+    }
+
+    /// The type of payload
+    pub fn payload_type(&self) -> &[u8] {
+        &self.payload_type
+    }
+
+    /// The signed payload
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Sequence number
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// The signature in DER
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// Encode to bytes
+    pub fn encode(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        protobuf::write_bytes_field(
+            &mut output,
+            FIELD_PUBLIC_KEY,
+            &self.public_key.to_protobuf_encoding(),
+        );
+        protobuf::write_bytes_field(&mut output, FIELD_PAYLOAD_TYPE, &self.payload_type);
+        protobuf::write_bytes_field(&mut output, FIELD_PAYLOAD, &self.payload);
+        protobuf::write_varint_field(&mut output, FIELD_SEQ, self.seq);
+        protobuf::write_bytes_field(&mut output, FIELD_SIGNATURE, &self.signature);
+        output
+    }
+
+    /// Decode from bytes and verify the signature with the domain
+    pub fn decode(data: &[u8], domain: &str) -> Result<Self, SecioError> {
+        let mut public_key = None;
+        let mut payload_type = Vec::new();
+        let mut payload = Vec::new();
+        let mut seq = 0;
+        let mut signature = Vec::new();
+
+        for (field, value) in protobuf::read_fields(data).ok_or(SecioError::InvalidMessage)? {
+            match (field, value) {
+                (FIELD_PUBLIC_KEY, Value::Bytes(value)) => {
+                    public_key = Some(PublicKey::from_protobuf_encoding(value)?)
+                }
+                (FIELD_PAYLOAD_TYPE, Value::Bytes(value)) => payload_type = value.to_vec(),
+                (FIELD_PAYLOAD, Value::Bytes(value)) => payload = value.to_vec(),
+                (FIELD_SEQ, Value::Varint(value)) => seq = value,
+                (FIELD_SIGNATURE, Value::Bytes(value)) => signature = value.to_vec(),
+                _ => (),
+            }
+        }
+
+        let envelope = SignedEnvelope {
+            public_key: public_key.ok_or(SecioError::InvalidMessage)?,
+            payload_type,
+            payload,
+            seq,
+            signature,
+        };
+        envelope.verify(domain)?;
+        Ok(envelope)
+    }
+}
+
+/// Every part is prefixed with its varint length
+fn signing_message(domain: &str, payload_type: &[u8], seq: u64, payload: &[u8]) -> Vec<u8> {
+    let mut buf = encode::u64_buffer();
+    let seq = encode::u64(seq, &mut buf).to_vec();
+
+    let mut message = Vec::new();
+    for part in &[domain.as_bytes(), payload_type, &seq, payload] {
+        let mut buf = encode::u64_buffer();
+        message.extend_from_slice(encode::u64(part.len() as u64, &mut buf));
+        message.extend_from_slice(part);
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SignedEnvelope;
+    use crate::{error::SecioError, SecioKeyPair};
+
+    const DOMAIN: &str = "test-domain";
+
+    #[test]
+    fn envelope_roundtrip() {
+        let key_pair = SecioKeyPair::secp256k1_generated();
+        let envelope =
+            SignedEnvelope::new(&key_pair, DOMAIN, b"type".to_vec(), b"payload".to_vec(), 7);
+
+        assert!(envelope.verify(DOMAIN).is_ok());
+        assert_eq!(envelope.peer_id(), key_pair.peer_id());
+
+        let decoded = SignedEnvelope::decode(&envelope.encode(), DOMAIN).unwrap();
+        assert_eq!(decoded, envelope);
+        assert_eq!(decoded.payload(), b"payload");
+        assert_eq!(decoded.seq(), 7);
+    }
+
+    #[test]
+    fn envelope_wrong_domain() {
+        let key_pair = SecioKeyPair::secp256k1_generated();
+        let envelope =
+            SignedEnvelope::new(&key_pair, DOMAIN, b"type".to_vec(), b"payload".to_vec(), 7);
+
+        assert_eq!(
+            SignedEnvelope::decode(&envelope.encode(), "other-domain").unwrap_err(),
+            SecioError::SignatureVerificationFailed
+        );
+    }
+
+    #[test]
+    fn envelope_tampered() {
+        let key_pair = SecioKeyPair::secp256k1_generated();
+        let other = SecioKeyPair::secp256k1_generated();
+
+        let mut envelope =
+            SignedEnvelope::new(&key_pair, DOMAIN, b"type".to_vec(), b"payload".to_vec(), 7);
+        envelope.seq = 8;
+        assert!(envelope.verify(DOMAIN).is_err());
+
+        let mut envelope =
+            SignedEnvelope::new(&key_pair, DOMAIN, b"type".to_vec(), b"payload".to_vec(), 7);
+        envelope.payload = b"forged".to_vec();
+        assert!(envelope.verify(DOMAIN).is_err());
+
+        // claim the envelope is signed by other
+        let mut envelope =
+            SignedEnvelope::new(&key_pair, DOMAIN, b"type".to_vec(), b"payload".to_vec(), 7);
+        envelope.public_key = other.public_key();
+        assert!(SignedEnvelope::decode(&envelope.encode(), DOMAIN).is_err());
+    }
+}
@@ -8,7 +8,7 @@ pub enum SecioError {
     IoError(io::Error),
 
     /// Openssl stack error
-    #[cfg(unix)]
+    #[cfg(all(unix, not(pure_rust_crypto)))]
     Openssl(openssl::error::ErrorStack),
 
     /// Ring Crypto error
@@ -46,6 +46,9 @@ pub enum SecioError {
 
     /// We received an invalid proposition from remote.
     InvalidProposition(&'static str),
+
+    /// Failed to import a key from the given encoding.
+    InvalidKeyEncoding(&'static str),
 }
 
 impl PartialEq for SecioError {
@@ -53,6 +56,7 @@ impl PartialEq for SecioError {
         use self::SecioError::*;
         match (self, other) {
             (InvalidProposition(i), InvalidProposition(j)) => i == j,
+            (InvalidKeyEncoding(i), InvalidKeyEncoding(j)) => i == j,
             (EphemeralKeyGenerationFailed, EphemeralKeyGenerationFailed)
             | (SecretGenerationFailed, SecretGenerationFailed)
             | (NoSupportIntersection, NoSupportIntersection)
@@ -84,14 +88,14 @@ impl Into<io::Error> for SecioError {
     }
 }
 
-#[cfg(unix)]
+#[cfg(all(unix, not(pure_rust_crypto)))]
 impl From<openssl::error::ErrorStack> for SecioError {
     fn from(err: openssl::error::ErrorStack) -> SecioError {
         SecioError::Openssl(err)
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(not(pure_rust_crypto))]
 impl From<ring::error::Unspecified> for SecioError {
     fn from(_err: ring::error::Unspecified) -> SecioError {
         SecioError::RingCryptoError
@@ -104,7 +108,7 @@ impl fmt::Display for SecioError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             SecioError::IoError(e) => fmt::Display::fmt(&e, f),
-            #[cfg(unix)]
+            #[cfg(all(unix, not(pure_rust_crypto)))]
             SecioError::Openssl(e) => fmt::Display::fmt(&e, f),
             SecioError::RingCryptoError => write!(f, "Ring Crypto Error"),
             SecioError::EphemeralKeyGenerationFailed => write!(f, "EphemeralKey Generation Failed"),
@@ -118,6 +122,7 @@ impl fmt::Display for SecioError {
             SecioError::InvalidMessage => write!(f, "Invalid Message"),
             SecioError::SignatureVerificationFailed => write!(f, "Signature Verification Failed"),
             SecioError::InvalidProposition(e) => write!(f, "Invalid Proposition: {}", e),
+            SecioError::InvalidKeyEncoding(e) => write!(f, "Invalid Key Encoding: {}", e),
         }
     }
 }
@@ -7,7 +7,7 @@ use crate::{
     error::SecioError,
     handshake::{
         handshake_struct::{Propose, PublicKey},
-        Config,
+        Config, HandshakeProgress, RemotePropositions,
     },
     support, Digest,
 };
@@ -59,6 +59,8 @@ pub struct Remote {
     pub(crate) chosen_exchange: KeyAgreement,
     pub(crate) chosen_cipher: CipherType,
     pub(crate) chosen_hash: Digest,
+    // The remote's early data:
+    pub(crate) early_data: Option<Bytes>,
 }
 
 // HandshakeContext<Remote> --with_ephemeral-> HandshakeContext<Ephemeral>
@@ -114,6 +116,8 @@ impl HandshakeContext<()> {
             .unwrap_or_else(|| support::DEFAULT_DIGESTS_PROPOSITION.into());
         trace!("digests proposition: {}", proposition.hashes);
 
+        proposition.early_data = self.config.early_data.clone();
+
         let proposition_bytes = proposition.encode();
 
         HandshakeContext {
@@ -132,6 +136,7 @@ impl HandshakeContext<Local> {
     pub fn with_remote(
         self,
         remote_bytes: BytesMut,
+        progress: &HandshakeProgress,
     ) -> Result<HandshakeContext<Remote>, SecioError> {
         let propose = match Propose::decode(&remote_bytes) {
             Some(prop) => prop,
@@ -141,6 +146,12 @@ impl HandshakeContext<Local> {
             }
         };
 
+        progress.set_remote_propositions(RemotePropositions {
+            exchanges: propose.exchange.clone(),
+            ciphers: propose.ciphers.clone(),
+            hashes: propose.hashes.clone(),
+        });
+
         // NOTE: Libp2p uses protobuf bytes to calculate order, but here we only use the original pubkey and nonce
         let nonce = propose.rand;
 
@@ -247,6 +258,7 @@ impl HandshakeContext<Local> {
                 chosen_exchange,
                 chosen_cipher,
                 chosen_hash,
+                early_data: propose.early_data,
             },
         })
     }
@@ -194,6 +194,7 @@ impl<'a> Propose<'a> {
         _fbb: &'mut_bldr mut flatbuffers::FlatBufferBuilder<'bldr>,
         args: &'args ProposeArgs<'args>) -> flatbuffers::WIPOffset<Propose<'bldr>> {
       let mut builder = ProposeBuilder::new(_fbb);
+      if let Some(x) = args.early_data { builder.add_early_data(x); }
       if let Some(x) = args.hashes { builder.add_hashes(x); }
       if let Some(x) = args.ciphers { builder.add_ciphers(x); }
       if let Some(x) = args.exchanges { builder.add_exchanges(x); }
@@ -207,6 +208,7 @@ impl<'a> Propose<'a> {
     pub const VT_EXCHANGES: flatbuffers::VOffsetT = 8;
     pub const VT_CIPHERS: flatbuffers::VOffsetT = 10;
     pub const VT_HASHES: flatbuffers::VOffsetT = 12;
+    pub const VT_EARLY_DATA: flatbuffers::VOffsetT = 14;
 
   #[inline]
   pub fn rand(&self) -> Option<&'a [u8]> {
@@ -228,6 +230,10 @@ impl<'a> Propose<'a> {
   pub fn hashes(&self) -> Option<&'a str> {
     self._tab.get::<flatbuffers::ForwardsUOffset<&str>>(Propose::VT_HASHES, None)
   }
+  #[inline]
+  pub fn early_data(&self) -> Option<&'a [u8]> {
+    self._tab.get::<flatbuffers::ForwardsUOffset<flatbuffers::Vector<'a, u8>>>(Propose::VT_EARLY_DATA, None).map(|v| v.safe_slice())
+  }
 }
 
 pub struct ProposeArgs<'a> {
@@ -236,6 +242,7 @@ pub struct ProposeArgs<'a> {
     pub exchanges: Option<flatbuffers::WIPOffset<&'a  str>>,
     pub ciphers: Option<flatbuffers::WIPOffset<&'a  str>>,
     pub hashes: Option<flatbuffers::WIPOffset<&'a  str>>,
+    pub early_data: Option<flatbuffers::WIPOffset<flatbuffers::Vector<'a ,  u8>>>,
 }
 impl<'a> Default for ProposeArgs<'a> {
     #[inline]
@@ -246,6 +253,7 @@ impl<'a> Default for ProposeArgs<'a> {
             exchanges: None,
             ciphers: None,
             hashes: None,
+            early_data: None,
         }
     }
 }
@@ -275,6 +283,10 @@ impl<'a: 'b, 'b> ProposeBuilder<'a, 'b> {
     self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Propose::VT_HASHES, hashes);
   }
   #[inline]
+  pub fn add_early_data(&mut self, early_data: flatbuffers::WIPOffset<flatbuffers::Vector<'b , u8>>) {
+    self.fbb_.push_slot_always::<flatbuffers::WIPOffset<_>>(Propose::VT_EARLY_DATA, early_data);
+  }
+  #[inline]
   pub fn new(_fbb: &'b mut flatbuffers::FlatBufferBuilder<'a>) -> ProposeBuilder<'a, 'b> {
     let start = _fbb.start_table();
     ProposeBuilder {
@@ -4,7 +4,10 @@ use crate::handshake::handshake_generated::p2p::handshake::{
     PublicKey as FBSPublicKey, PublicKeyBuilder, Type,
 };
 #[cfg(feature = "molc")]
-use crate::handshake::handshake_mol;
+use crate::{
+    handshake::handshake_mol,
+    molecule_ext::{append_table_field, extra_table_field},
+};
 #[cfg(feature = "molc")]
 use molecule::prelude::{Builder, Entity, Reader};
 
@@ -21,6 +24,8 @@ pub struct Propose {
     pub(crate) exchange: String,
     pub(crate) ciphers: String,
     pub(crate) hashes: String,
+    /// opaque data of upper layer, authenticated by the signature of exchange
+    pub(crate) early_data: Option<Bytes>,
 }
 
 impl Propose {
@@ -37,6 +42,10 @@ impl Propose {
         let exchange = fbb.create_string(&self.exchange);
         let ciphers = fbb.create_string(&self.ciphers);
         let hashes = fbb.create_string(&self.hashes);
+        let early_data = self
+            .early_data
+            .as_ref()
+            .map(|data| fbb.create_vector(&data[..]));
 
         let mut builder = ProposeBuilder::new(&mut fbb);
         builder.add_rand(rand);
@@ -44,6 +53,9 @@ impl Propose {
         builder.add_exchanges(exchange);
         builder.add_ciphers(ciphers);
         builder.add_hashes(hashes);
+        if let Some(early_data) = early_data {
+            builder.add_early_data(early_data);
+        }
         let data = builder.finish();
 
         fbb.finish(data, None);
@@ -68,6 +80,9 @@ impl Propose {
                     exchange: exchange.to_owned(),
                     ciphers: ciphers.to_owned(),
                     hashes: hashes.to_owned(),
+                    early_data: fbs_propose
+                        .early_data()
+                        .map(|data| Bytes::from(data.to_owned())),
                 })
             }
             _ => None,
@@ -111,26 +126,47 @@ impl Propose {
             )
             .build();
 
-        handshake_mol::Propose::new_builder()
+        let propose = handshake_mol::Propose::new_builder()
             .rand(rand)
             .pubkey(pubkey)
             .exchanges(exchange)
             .ciphers(ciphers)
             .hashes(hashes)
-            .build()
-            .as_bytes()
+            .build();
+
+        match self.early_data {
+            // early data is appended as an extra field, the peers decoding with
+            // the old schema ignore it in compatible mode
+            Some(data) => {
+                let early_data = handshake_mol::Bytes::new_builder()
+                    .set(data.into_iter().map(Into::into).collect())
+                    .build();
+                append_table_field(propose.as_slice(), early_data.as_slice())
+                    .expect("propose is a valid table")
+            }
+            None => propose.as_bytes(),
+        }
     }
 
     /// Decode with molecule
     #[cfg(feature = "molc")]
     pub fn decode(data: &[u8]) -> Option<Self> {
         let reader = handshake_mol::ProposeReader::from_compatible_slice(data).ok()?;
+        let early_data =
+            match extra_table_field(reader.as_slice(), handshake_mol::ProposeReader::FIELD_COUNT) {
+                Some(field) => {
+                    let early_data = handshake_mol::BytesReader::from_slice(field).ok()?;
+                    Some(Bytes::from(early_data.raw_data().to_owned()))
+                }
+                None => None,
+            };
         Some(Propose {
             rand: reader.rand().raw_data().to_owned(),
             pubkey: Bytes::from(reader.pubkey().raw_data().to_owned()),
             exchange: String::from_utf8(reader.exchanges().raw_data().to_owned()).ok()?,
             ciphers: String::from_utf8(reader.ciphers().raw_data().to_owned()).ok()?,
             hashes: String::from_utf8(reader.hashes().raw_data().to_owned()).ok()?,
+            early_data,
         })
     }
 }
@@ -329,6 +365,41 @@ mod tests {
         assert_eq!(raw, Propose::decode(&byte.encode()).unwrap())
     }
 
+    #[test]
+    fn decode_encode_propose_with_early_data() {
+        let mut raw = Propose::new();
+        raw.rand = vec![1u8; 16];
+        raw.pubkey = Bytes::from(vec![25u8; 33]);
+        raw.exchange = "X25519".to_owned();
+        raw.early_data = Some(Bytes::from(vec![7u8; 100]));
+
+        let decoded = Propose::decode(&raw.clone().encode()).unwrap();
+        assert_eq!(raw, decoded);
+
+        // peers without early data still decode
+        let mut old = raw.clone();
+        old.early_data = None;
+        assert_eq!(Propose::decode(&old.clone().encode()).unwrap(), old);
+    }
+
+    #[cfg(feature = "molc")]
+    #[test]
+    fn early_data_ignored_by_old_schema() {
+        use crate::handshake::handshake_mol::ProposeReader;
+        use molecule::prelude::Reader;
+
+        let mut raw = Propose::new();
+        raw.rand = vec![1u8; 16];
+        raw.hashes = "SHA256".to_owned();
+        raw.early_data = Some(Bytes::from(vec![7u8; 10]));
+        let data = raw.encode();
+
+        assert!(ProposeReader::from_slice(&data).is_err());
+        let reader = ProposeReader::from_compatible_slice(&data).unwrap();
+        assert_eq!(reader.rand().raw_data(), &[1u8; 16][..]);
+        assert_eq!(reader.hashes().raw_data(), b"SHA256");
+    }
+
     #[test]
     fn decode_encode_exchange() {
         let mut raw = Exchange::new();
@@ -5,6 +5,7 @@ use crate::{
 };
 
 use crate::codec::secure_stream::SecureStream;
+use bytes::Bytes;
 use tokio::prelude::{AsyncRead, AsyncWrite};
 
 #[cfg(all(feature = "flatc", feature = "molc"))]
@@ -28,11 +29,26 @@ mod handshake_generated_verifier;
 mod handshake_mol;
 
 mod handshake_context;
-pub(crate) mod handshake_struct;
+#[doc(hidden)]
+pub mod handshake_struct;
 mod procedure;
+mod progress;
+
+pub use self::progress::{HandshakeProgress, HandshakeStage, RemotePropositions};
 
 const MAX_FRAME_SIZE: usize = 1024 * 1024 * 8;
 
+/// Algorithms chosen by both sides during the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiatedAlgorithms {
+    /// Key agreement algorithm of the ephemeral key exchange
+    pub key_agreement: KeyAgreement,
+    /// Symmetric cipher of the stream
+    pub cipher: CipherType,
+    /// Digest used to stretch the shared secret
+    pub digest: Digest,
+}
+
 /// Config for Secio
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -41,6 +57,7 @@ pub struct Config {
     pub(crate) ciphers_proposal: Option<String>,
     pub(crate) digests_proposal: Option<String>,
     pub(crate) max_frame_length: usize,
+    pub(crate) early_data: Option<Bytes>,
 }
 
 impl Config {
@@ -52,9 +69,21 @@ impl Config {
             ciphers_proposal: None,
             digests_proposal: None,
             max_frame_length: MAX_FRAME_SIZE,
+            early_data: None,
         }
     }
 
+    /// The key pair of local
+    pub fn key_pair(&self) -> &SecioKeyPair {
+        &self.key
+    }
+
+    /// Replace the key pair of local, other settings are kept
+    pub fn replace_key_pair(mut self, key_pair: SecioKeyPair) -> Self {
+        self.key = key_pair;
+        self
+    }
+
     /// Max frame length
     pub fn max_frame_length(mut self, size: usize) -> Self {
         self.max_frame_length = size;
@@ -88,6 +117,15 @@ impl Config {
         self
     }
 
+    /// Opaque data sent to remote with the proposition, e.g. the supported protocols of upper layer.
+    ///
+    /// It is sent before the key exchange, so it is in plain text, but it is covered by
+    /// the signature of exchange, the remote can trust it after the handshake succeeds.
+    pub fn early_data(mut self, data: Bytes) -> Self {
+        self.early_data = Some(data);
+        self
+    }
+
     /// Attempts to perform a handshake on the given socket.
     ///
     /// On success, produces a `SecureStream` that can then be used to encode/decode
@@ -99,6 +137,20 @@ impl Config {
     where
         T: AsyncRead + AsyncWrite + Send + 'static + Unpin,
     {
-        handshake(socket, self).await
+        handshake(socket, self, HandshakeProgress::new()).await
+    }
+
+    /// Attempts to perform a handshake on the given socket, the same as `handshake`,
+    /// and updates the progress on every stage, so that the caller can tell which stage
+    /// fails or stalls.
+    pub async fn handshake_with_progress<T>(
+        self,
+        socket: T,
+        progress: HandshakeProgress,
+    ) -> Result<(SecureStream<T>, PublicKey, EphemeralPublicKey), SecioError>
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static + Unpin,
+    {
+        handshake(socket, self, progress).await
     }
 }
@@ -12,11 +12,11 @@ use crate::{
     codec::{secure_stream::SecureStream, Hmac},
     crypto::{cipher::CipherType, new_stream, BoxStreamCipher, CryptoMode},
     error::SecioError,
-    handshake::Config,
     handshake::{
         handshake_context::HandshakeContext,
         handshake_struct::{Exchange, PublicKey},
     },
+    handshake::{Config, HandshakeProgress, HandshakeStage, NegotiatedAlgorithms},
     EphemeralPublicKey, KeyPairInner,
 };
 use bytes::{Buf, BytesMut};
@@ -34,6 +34,7 @@ use tokio::io::AsyncWriteExt;
 pub(in crate::handshake) async fn handshake<T>(
     socket: T,
     config: Config,
+    progress: HandshakeProgress,
 ) -> Result<(SecureStream<T>, PublicKey, EphemeralPublicKey), SecioError>
 where
     T: AsyncRead + AsyncWrite + Send + 'static + Unpin,
@@ -59,7 +60,7 @@ where
 
     // Receive the remote's proposition.
     let remote_context = match socket.next().await {
-        Some(p) => local_context.with_remote(p?)?,
+        Some(p) => local_context.with_remote(p?, &progress)?,
         None => {
             let err = io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected eof");
             debug!("unexpected eof while waiting for remote's proposition");
@@ -73,6 +74,8 @@ where
         remote_context.state.nonce
     );
 
+    progress.enter(HandshakeStage::Exchange);
+
     // Generate an ephemeral key for the negotiation.
     let (tmp_priv_key, tmp_pub_key) =
         crate::dh_compat::generate_agreement(remote_context.state.chosen_exchange)?;
@@ -240,7 +243,15 @@ where
         decode_cipher,
         encode_cipher,
         pub_ephemeral_context.state.remote.local.nonce.to_vec(),
-    );
+    )
+    .with_negotiated(NegotiatedAlgorithms {
+        key_agreement: pub_ephemeral_context.state.remote.chosen_exchange,
+        cipher: chosen_cipher,
+        digest: pub_ephemeral_context.state.remote.chosen_hash,
+    })
+    .with_remote_early_data(pub_ephemeral_context.state.remote.early_data.clone());
+
+    progress.enter(HandshakeStage::VerifyNonce);
 
     // We send back their nonce to check if the connection works.
     trace!("checking encryption by sending back remote's nonce");
@@ -300,9 +311,15 @@ fn generate_stream_cipher_and_hmac(
 #[cfg(test)]
 mod tests {
     use super::stretch_key;
-    use crate::{codec::Hmac, handshake::Config, Digest, SecioKeyPair};
+    use crate::{
+        codec::Hmac,
+        crypto::cipher::CipherType,
+        error::SecioError,
+        handshake::{Config, HandshakeProgress, HandshakeStage, NegotiatedAlgorithms},
+        Digest, KeyAgreement, SecioKeyPair,
+    };
 
-    use bytes::BytesMut;
+    use bytes::{Bytes, BytesMut};
     use futures::channel;
     use tokio::{
         io::{AsyncReadExt, AsyncWriteExt},
@@ -348,6 +365,115 @@ mod tests {
         handshake_with_self_success(Config::new(key_1), Config::new(key_2), b"hello world")
     }
 
+    #[test]
+    fn handshake_with_negotiated_algorithms() {
+        let config_1 = Config::new(SecioKeyPair::secp256k1_generated())
+            .key_agreements(&[KeyAgreement::X25519])
+            .ciphers(&[CipherType::ChaCha20Poly1305]);
+        let config_2 = Config::new(SecioKeyPair::secp256k1_generated())
+            .key_agreements(&[KeyAgreement::EcdhP256, KeyAgreement::X25519])
+            .digests(&[Digest::Sha512]);
+        let expected = NegotiatedAlgorithms {
+            key_agreement: KeyAgreement::X25519,
+            cipher: CipherType::ChaCha20Poly1305,
+            digest: Digest::Sha512,
+        };
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let listener_addr = listener.local_addr().unwrap();
+            let server = async move {
+                let (connect, _) = listener.accept().await.unwrap();
+                let (handle, _, _) = config_1.handshake(connect).await.unwrap();
+                handle.negotiated_algorithms()
+            };
+            let client = async move {
+                let connect = TcpStream::connect(&listener_addr).await.unwrap();
+                let (handle, _, _) = config_2.handshake(connect).await.unwrap();
+                handle.negotiated_algorithms()
+            };
+            let (server, client) = futures::join!(server, client);
+            assert_eq!(server, Some(expected));
+            assert_eq!(client, Some(expected));
+        });
+    }
+
+    #[test]
+    fn handshake_with_early_data() {
+        let config_1 = Config::new(SecioKeyPair::secp256k1_generated())
+            .early_data(Bytes::from_static(b"/p2p/ping"));
+        let config_2 = Config::new(SecioKeyPair::secp256k1_generated());
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let listener_addr = listener.local_addr().unwrap();
+            let server = async move {
+                let (connect, _) = listener.accept().await.unwrap();
+                let (handle, _, _) = config_1.handshake(connect).await.unwrap();
+                handle.remote_early_data().cloned()
+            };
+            let client = async move {
+                let connect = TcpStream::connect(&listener_addr).await.unwrap();
+                let (handle, _, _) = config_2.handshake(connect).await.unwrap();
+                handle.remote_early_data().cloned()
+            };
+            let (server, client) = futures::join!(server, client);
+            assert_eq!(server, None);
+            assert_eq!(client, Some(Bytes::from_static(b"/p2p/ping")));
+        });
+    }
+
+    #[test]
+    fn handshake_progress() {
+        let config_1 = Config::new(SecioKeyPair::secp256k1_generated()).digests(&[Digest::Sha256]);
+        let config_2 = Config::new(SecioKeyPair::secp256k1_generated()).digests(&[Digest::Sha512]);
+        let config_3 = Config::new(SecioKeyPair::secp256k1_generated());
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            // no digest in common
+            let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let listener_addr = listener.local_addr().unwrap();
+            let progress = HandshakeProgress::new();
+            let server = async {
+                let (connect, _) = listener.accept().await.unwrap();
+                config_1
+                    .clone()
+                    .handshake_with_progress(connect, progress.clone())
+                    .await
+                    .map(|_| ())
+            };
+            let client = async move {
+                let connect = TcpStream::connect(&listener_addr).await.unwrap();
+                config_2.handshake(connect).await.map(|_| ())
+            };
+            let (server, _) = futures::join!(server, client);
+            assert_eq!(server.unwrap_err(), SecioError::NoSupportIntersection);
+            assert_eq!(progress.stage(), HandshakeStage::Propose);
+            assert_eq!(progress.remote_propositions().unwrap().hashes, "SHA512");
+
+            // success, the last stage is nonce verification
+            let progress = HandshakeProgress::new();
+            let server = async {
+                let (connect, _) = listener.accept().await.unwrap();
+                config_3
+                    .handshake_with_progress(connect, progress.clone())
+                    .await
+                    .map(|_| ())
+            };
+            let client = async move {
+                let connect = TcpStream::connect(&listener_addr).await.unwrap();
+                config_1.handshake(connect).await.map(|_| ())
+            };
+            let (server, client) = futures::join!(server, client);
+            assert!(server.is_ok() && client.is_ok());
+            assert_eq!(progress.stage(), HandshakeStage::VerifyNonce);
+            assert_eq!(progress.remote_propositions().unwrap().hashes, "SHA256");
+        });
+    }
+
     #[test]
     fn stretch() {
         let mut output = [0u8; 32];
@@ -0,0 +1,99 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Stages of secio handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandshakeStage {
+    /// Exchange the propositions and select the algorithms
+    Propose,
+    /// Exchange the ephemeral public keys and verify the signature of remote
+    Exchange,
+    /// Check the nonce sent back by remote with the new ciphers
+    VerifyNonce,
+}
+
+impl fmt::Display for HandshakeStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HandshakeStage::Propose => write!(f, "propose"),
+            HandshakeStage::Exchange => write!(f, "exchange"),
+            HandshakeStage::VerifyNonce => write!(f, "verify nonce"),
+        }
+    }
+}
+
+/// Algorithms proposed by remote, in order of its preference
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemotePropositions {
+    /// Key agreement algorithms
+    pub exchanges: String,
+    /// Ciphers
+    pub ciphers: String,
+    /// Digests
+    pub hashes: String,
+}
+
+impl fmt::Display for RemotePropositions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "exchanges: {}, ciphers: {}, hashes: {}",
+            self.exchanges, self.ciphers, self.hashes
+        )
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    stage: HandshakeStage,
+    remote_propositions: Option<RemotePropositions>,
+}
+
+/// Progress of a handshake, it's shared with the caller and updated as the handshake goes on,
+/// so the stage is still known after the handshake future is dropped, e.g. on timeout.
+#[derive(Debug, Clone)]
+pub struct HandshakeProgress {
+    state: Arc<Mutex<State>>,
+}
+
+impl HandshakeProgress {
+    /// Create a progress at the propose stage
+    pub fn new() -> Self {
+        HandshakeProgress {
+            state: Arc::new(Mutex::new(State {
+                stage: HandshakeStage::Propose,
+                remote_propositions: None,
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The stage in progress, or the stage failed
+    pub fn stage(&self) -> HandshakeStage {
+        self.state().stage
+    }
+
+    /// Propositions of remote, None if it's not received yet
+    pub fn remote_propositions(&self) -> Option<RemotePropositions> {
+        self.state().remote_propositions.clone()
+    }
+
+    pub(crate) fn enter(&self, stage: HandshakeStage) {
+        self.state().stage = stage;
+    }
+
+    pub(crate) fn set_remote_propositions(&self, propositions: RemotePropositions) {
+        self.state().remote_propositions = Some(propositions);
+    }
+}
+
+impl Default for HandshakeProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,134 @@
+//! A minimal DER reader and writer, only the parts needed by key encodings
+use crate::error::SecioError;
+
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const OBJECT_IDENTIFIER: u8 = 0x06;
+pub const SEQUENCE: u8 = 0x30;
+/// Context specific, constructed, tag 0
+pub const CONTEXT_0: u8 = 0xa0;
+/// Context specific, constructed, tag 1
+pub const CONTEXT_1: u8 = 0xa1;
+/// Context specific, primitive, tag 1
+pub const CONTEXT_PRIMITIVE_1: u8 = 0x81;
+
+/// Append a tag-length-value to output
+pub fn write(output: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    output.push(tag);
+    let len = value.len();
+    if len < 0x80 {
+        output.push(len as u8);
+    } else if len <= 0xff {
+        output.extend_from_slice(&[0x81, len as u8]);
+    } else {
+        assert!(len <= 0xffff, "der value too long");
+        output.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]);
+    }
+    output.extend_from_slice(value);
+}
+
+/// Return a tag-length-value
+pub fn encode(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(value.len() + 4);
+    write(&mut output, tag, value);
+    output
+}
+
+/// Read tag-length-values in order
+pub struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Reader { input }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.input.first().cloned()
+    }
+
+    /// Read the value of next tag-length-value, the tag must match
+    pub fn read(&mut self, tag: u8) -> Result<&'a [u8], SecioError> {
+        let invalid = || SecioError::InvalidKeyEncoding("invalid der");
+
+        if self.peek_tag() != Some(tag) {
+            return Err(SecioError::InvalidKeyEncoding("unexpected der tag"));
+        }
+        let first = *self.input.get(1).ok_or_else(invalid)?;
+        let (len, header_len) = match first {
+            0..=0x7f => (first as usize, 2),
+            0x81 => {
+                let len = *self.input.get(2).ok_or_else(invalid)? as usize;
+                if len < 0x80 {
+                    return Err(invalid());
+                }
+                (len, 3)
+            }
+            0x82 => {
+                let high = *self.input.get(2).ok_or_else(invalid)? as usize;
+                let low = *self.input.get(3).ok_or_else(invalid)? as usize;
+                let len = (high << 8) | low;
+                if len <= 0xff {
+                    return Err(invalid());
+                }
+                (len, 4)
+            }
+            _ => return Err(invalid()),
+        };
+
+        if self.input.len() < header_len + len {
+            return Err(invalid());
+        }
+        let value = &self.input[header_len..header_len + len];
+        self.input = &self.input[header_len + len..];
+        Ok(value)
+    }
+
+    /// The reader must be consumed
+    pub fn finish(self) -> Result<(), SecioError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(SecioError::InvalidKeyEncoding(
+                "trailing data after der value",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, Reader, OCTET_STRING, SEQUENCE};
+
+    #[test]
+    fn der_length_roundtrip() {
+        for &len in &[0usize, 1, 0x7f, 0x80, 0xff, 0x100, 0x1234] {
+            let value = vec![7u8; len];
+            let data = encode(SEQUENCE, &encode(OCTET_STRING, &value));
+
+            let mut reader = Reader::new(&data);
+            let mut inner = Reader::new(reader.read(SEQUENCE).unwrap());
+            assert_eq!(inner.read(OCTET_STRING).unwrap(), &value[..]);
+            assert!(inner.finish().is_ok());
+            assert!(reader.finish().is_ok());
+        }
+    }
+
+    #[test]
+    fn der_reject_invalid() {
+        // truncated value
+        assert!(Reader::new(&[0x04, 0x02, 0x00]).read(OCTET_STRING).is_err());
+        // non-minimal length
+        assert!(Reader::new(&[0x04, 0x81, 0x01, 0x00])
+            .read(OCTET_STRING)
+            .is_err());
+        // unexpected tag
+        assert!(Reader::new(&[0x30, 0x00]).read(OCTET_STRING).is_err());
+    }
+}
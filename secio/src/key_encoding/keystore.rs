@@ -0,0 +1,162 @@
+//! Keystore file, the libp2p protobuf encoding of private key, optionally encrypted with a password.
+//!
+//! ```plain
+//! +---------+-----+------------------------------+----------------------------+
+//! | version | kdf | rounds (u32 BE) | salt (16) | payload                    |
+//! +---------+-----+------------------------------+----------------------------+
+//! ```
+//!
+//! - kdf 0: no password, the rounds and salt are absent, payload is the plain key
+//! - kdf 1: the key derived by PBKDF2-HMAC-SHA256, payload is the key encrypted by ChaCha20Poly1305
+use crate::{
+    codec::Hmac,
+    crypto::{cipher::CipherType, new_stream, CryptoMode},
+    error::SecioError,
+    Digest,
+};
+
+/// PEM label of keystore
+pub const LABEL: &str = "TENTACLE KEYSTORE";
+
+const VERSION: u8 = 1;
+const KDF_NONE: u8 = 0;
+const KDF_PBKDF2_SHA256: u8 = 1;
+const SALT_SIZE: usize = 16;
+/// Decode refuses larger rounds to avoid spending unbounded time on a malicious file
+const MAX_ROUNDS: u32 = 10_000_000;
+const CIPHER: CipherType = CipherType::ChaCha20Poly1305;
+
+/// Default PBKDF2 rounds
+pub const DEFAULT_ROUNDS: u32 = 100_000;
+
+/// PBKDF2-HMAC-SHA256, RFC 8018
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], rounds: u32, output: &mut [u8]) {
+    let hmac = Hmac::from_key(Digest::Sha256, password);
+
+    for (index, block) in output.chunks_mut(Digest::Sha256.num_bytes()).enumerate() {
+        let mut context = hmac.context();
+        context.update(salt);
+        context.update(&(index as u32 + 1).to_be_bytes());
+        let mut u = AsRef::<[u8]>::as_ref(&context.sign()).to_vec();
+        let mut t = u.clone();
+
+        for _ in 1..rounds {
+            let mut context = hmac.context();
+            context.update(&u);
+            u = AsRef::<[u8]>::as_ref(&context.sign()).to_vec();
+            t.iter_mut().zip(u.iter()).for_each(|(t, u)| *t ^= u);
+        }
+        block.copy_from_slice(&t[..block.len()]);
+    }
+}
+
+fn derive_key(password: &[u8], salt: &[u8], rounds: u32) -> Vec<u8> {
+    let mut key = vec![0; CIPHER.key_size()];
+    pbkdf2_sha256(password, salt, rounds, &mut key);
+    key
+}
+
+/// Build keystore data from the encoded private key
+pub fn encode(key: &[u8], password: Option<&[u8]>, rounds: u32) -> Result<Vec<u8>, SecioError> {
+    let mut output = vec![VERSION];
+    match password {
+        Some(password) => {
+            let salt: [u8; SALT_SIZE] = rand::random();
+            let mut cipher = new_stream(
+                CIPHER,
+                &derive_key(password, &salt, rounds),
+                CryptoMode::Encrypt,
+            );
+
+            output.push(KDF_PBKDF2_SHA256);
+            output.extend_from_slice(&rounds.to_be_bytes());
+            output.extend_from_slice(&salt);
+            output.extend_from_slice(&cipher.encrypt(key)?);
+        }
+        None => {
+            output.push(KDF_NONE);
+            output.extend_from_slice(key);
+        }
+    }
+    Ok(output)
+}
+
+/// Return the encoded private key from keystore data
+pub fn decode(input: &[u8], password: Option<&[u8]>) -> Result<Vec<u8>, SecioError> {
+    match input {
+        [VERSION, KDF_NONE, key @ ..] => Ok(key.to_vec()),
+        [VERSION, KDF_PBKDF2_SHA256, rest @ ..] if rest.len() > 4 + SALT_SIZE => {
+            let password = password.ok_or(SecioError::InvalidKeyEncoding(
+                "keystore is encrypted, password required",
+            ))?;
+            let mut rounds = [0; 4];
+            rounds.copy_from_slice(&rest[..4]);
+            let rounds = u32::from_be_bytes(rounds);
+            if rounds == 0 || rounds > MAX_ROUNDS {
+                return Err(SecioError::InvalidKeyEncoding(
+                    "invalid keystore kdf rounds",
+                ));
+            }
+            let salt = &rest[4..4 + SALT_SIZE];
+
+            let mut cipher = new_stream(
+                CIPHER,
+                &derive_key(password, salt, rounds),
+                CryptoMode::Decrypt,
+            );
+            cipher
+                .decrypt(&rest[4 + SALT_SIZE..])
+                .map_err(|_| SecioError::InvalidKeyEncoding("wrong keystore password"))
+        }
+        [VERSION, ..] => Err(SecioError::InvalidKeyEncoding("unsupported keystore kdf")),
+        _ => Err(SecioError::InvalidKeyEncoding(
+            "unsupported keystore version",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, pbkdf2_sha256};
+
+    #[test]
+    fn pbkdf2_test_vectors() {
+        // RFC 7914 section 11
+        let mut output = [0; 64];
+        pbkdf2_sha256(b"passwd", b"salt", 1, &mut output);
+        assert_eq!(
+            &output[..],
+            &[
+                0x55, 0xac, 0x04, 0x6e, 0x56, 0xe3, 0x08, 0x9f, 0xec, 0x16, 0x91, 0xc2, 0x25, 0x44,
+                0xb6, 0x05, 0xf9, 0x41, 0x85, 0x21, 0x6d, 0xde, 0x04, 0x65, 0xe6, 0x8b, 0x9d, 0x57,
+                0xc2, 0x0d, 0xac, 0xbc, 0x49, 0xca, 0x9c, 0xcc, 0xf1, 0x79, 0xb6, 0x45, 0x99, 0x16,
+                0x64, 0xb3, 0x9d, 0x77, 0xef, 0x31, 0x7c, 0x71, 0xb8, 0x45, 0xb1, 0xe3, 0x0b, 0xd5,
+                0x09, 0x11, 0x20, 0x41, 0xd3, 0xa1, 0x97, 0x83,
+            ][..]
+        );
+
+        let mut output = [0; 32];
+        pbkdf2_sha256(b"password", b"salt", 4096, &mut output);
+        assert_eq!(
+            &output[..],
+            &[
+                0xc5, 0xe4, 0x78, 0xd5, 0x92, 0x88, 0xc8, 0x41, 0xaa, 0x53, 0x0d, 0xb6, 0x84, 0x5c,
+                0x4c, 0x8d, 0x96, 0x28, 0x93, 0xa0, 0x01, 0xce, 0x4e, 0x11, 0xa4, 0x96, 0x38, 0x73,
+                0xaa, 0x98, 0x13, 0x4a,
+            ][..]
+        );
+    }
+
+    #[test]
+    fn keystore_roundtrip() {
+        let key = b"encoded private key";
+
+        let plain = encode(key, None, 1).unwrap();
+        assert_eq!(decode(&plain, None).unwrap(), key);
+
+        let encrypted = encode(key, Some(b"password"), 16).unwrap();
+        assert_eq!(decode(&encrypted, Some(b"password")).unwrap(), key);
+        assert!(decode(&encrypted, Some(b"wrong")).is_err());
+        assert!(decode(&encrypted, None).is_err());
+    }
+}
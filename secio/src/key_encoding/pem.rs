@@ -0,0 +1,63 @@
+//! PEM armor, RFC 7468
+use data_encoding::BASE64;
+
+use crate::error::SecioError;
+
+const LINE_WIDTH: usize = 64;
+
+/// Wrap the data with the label
+pub fn encode(label: &str, data: &[u8]) -> String {
+    let body = BASE64.encode(data);
+    let mut output =
+        String::with_capacity(body.len() + body.len() / LINE_WIDTH + label.len() * 2 + 40);
+
+    output.push_str("-----BEGIN ");
+    output.push_str(label);
+    output.push_str("-----\n");
+    for line in body.as_bytes().chunks(LINE_WIDTH) {
+        // base64 output is ascii
+        output.push_str(std::str::from_utf8(line).expect("base64 is ascii"));
+        output.push('\n');
+    }
+    output.push_str("-----END ");
+    output.push_str(label);
+    output.push_str("-----\n");
+    output
+}
+
+/// Return the data of the first block with the label
+pub fn decode(label: &str, input: &str) -> Result<Vec<u8>, SecioError> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+
+    let start = input
+        .find(&begin)
+        .map(|index| index + begin.len())
+        .ok_or(SecioError::InvalidKeyEncoding("pem label not found"))?;
+    let len = input[start..]
+        .find(&end)
+        .ok_or(SecioError::InvalidKeyEncoding("pem end not found"))?;
+
+    let body = input[start..start + len]
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect::<String>();
+    BASE64
+        .decode(body.as_bytes())
+        .map_err(|_| SecioError::InvalidKeyEncoding("invalid pem base64"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode};
+
+    #[test]
+    fn pem_roundtrip() {
+        let data = (0..200).map(|i| i as u8).collect::<Vec<_>>();
+        let pem = encode("TEST", &data);
+
+        assert!(pem.lines().all(|line| line.len() <= 64));
+        assert_eq!(decode("TEST", &pem).unwrap(), data);
+        assert!(decode("OTHER", &pem).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//! The key encoding of libp2p:
+//!
+//! ```protobuf
+//! enum KeyType {
+//!     RSA = 0;
+//!     Ed25519 = 1;
+//!     Secp256k1 = 2;
+//!     ECDSA = 3;
+//! }
+//!
+//! message PublicKey {
+//!     required KeyType Type = 1;
+//!     required bytes Data = 2;
+//! }
+//!
+//! message PrivateKey {
+//!     required KeyType Type = 1;
+//!     required bytes Data = 2;
+//! }
+//! ```
+use unsigned_varint::{decode, encode};
+
+use crate::error::SecioError;
+
+pub const KEY_TYPE_SECP256K1: u64 = 2;
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_64BIT: u64 = 1;
+const WIRE_LENGTH_DELIMITED: u64 = 2;
+const WIRE_32BIT: u64 = 5;
+
+const FIELD_TYPE: u64 = 1;
+const FIELD_DATA: u64 = 2;
+
+/// Value of a protobuf field, fixed size fields are skipped on decode
+pub enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+fn write_varint(output: &mut Vec<u8>, value: u64) {
+    let mut buf = encode::u64_buffer();
+    output.extend_from_slice(encode::u64(value, &mut buf));
+}
+
+/// Append a varint field
+pub fn write_varint_field(output: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(output, field << 3 | WIRE_VARINT);
+    write_varint(output, value);
+}
+
+/// Append a length delimited field
+pub fn write_bytes_field(output: &mut Vec<u8>, field: u64, data: &[u8]) {
+    write_varint(output, field << 3 | WIRE_LENGTH_DELIMITED);
+    write_varint(output, data.len() as u64);
+    output.extend_from_slice(data);
+}
+
+/// Return all varint and length delimited fields in order, `None` on malformed input
+pub fn read_fields(mut input: &[u8]) -> Option<Vec<(u64, Value<'_>)>> {
+    let mut fields = Vec::new();
+
+    while !input.is_empty() {
+        let (key, rest) = decode::u64(input).ok()?;
+        input = rest;
+
+        let len = match key & 0x07 {
+            WIRE_VARINT => {
+                let (value, rest) = decode::u64(input).ok()?;
+                fields.push((key >> 3, Value::Varint(value)));
+                input = rest;
+                continue;
+            }
+            WIRE_64BIT => 8,
+            WIRE_32BIT => 4,
+            WIRE_LENGTH_DELIMITED => {
+                let (len, rest) = decode::u64(input).ok()?;
+                input = rest;
+                len as usize
+            }
+            _ => return None,
+        };
+
+        if input.len() < len {
+            return None;
+        }
+        if key & 0x07 == WIRE_LENGTH_DELIMITED {
+            fields.push((key >> 3, Value::Bytes(&input[..len])));
+        }
+        input = &input[len..];
+    }
+
+    Some(fields)
+}
+
+/// Encode a `PublicKey` or `PrivateKey` message
+pub fn encode(key_type: u64, data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() + 4);
+    write_varint_field(&mut output, FIELD_TYPE, key_type);
+    write_bytes_field(&mut output, FIELD_DATA, data);
+    output
+}
+
+/// Decode a `PublicKey` or `PrivateKey` message, unknown fields are skipped
+pub fn decode(input: &[u8]) -> Result<(u64, &[u8]), SecioError> {
+    let mut key_type = None;
+    let mut data = None;
+
+    let fields =
+        read_fields(input).ok_or(SecioError::InvalidKeyEncoding("invalid protobuf message"))?;
+    for (field, value) in fields {
+        match (field, value) {
+            (FIELD_TYPE, Value::Varint(value)) => key_type = Some(value),
+            (FIELD_DATA, Value::Bytes(value)) => data = Some(value),
+            _ => (),
+        }
+    }
+
+    match (key_type, data) {
+        (Some(key_type), Some(data)) => Ok((key_type, data)),
+        _ => Err(SecioError::InvalidKeyEncoding("missing protobuf key field")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, KEY_TYPE_SECP256K1};
+
+    #[test]
+    fn protobuf_roundtrip() {
+        let data = [1u8; 33];
+        let encoded = encode(KEY_TYPE_SECP256K1, &data);
+
+        assert_eq!(&encoded[..4], &[0x08, 0x02, 0x12, 33]);
+        assert_eq!(decode(&encoded).unwrap(), (KEY_TYPE_SECP256K1, &data[..]));
+    }
+
+    #[test]
+    fn protobuf_field_order_and_unknown_field() {
+        // Data first, then an unknown varint field 3, then Type
+        let encoded = [0x12, 0x02, 0xaa, 0xbb, 0x18, 0x01, 0x08, 0x02];
+        assert_eq!(
+            decode(&encoded).unwrap(),
+            (KEY_TYPE_SECP256K1, &[0xaa, 0xbb][..])
+        );
+    }
+
+    #[test]
+    fn protobuf_reject_invalid() {
+        // missing data
+        assert!(decode(&[0x08, 0x02]).is_err());
+        // truncated data
+        assert!(decode(&[0x08, 0x02, 0x12, 0x05, 0x00]).is_err());
+    }
+}
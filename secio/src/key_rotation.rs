@@ -0,0 +1,176 @@
+//! Key rotation, a link from the old peer id to the new one, signed by the old key pair with
+//! `SignedEnvelope`, so that the peers which know the old id can trust the new one.
+//!
+//! Encoding:
+//!
+//! ```protobuf
+//! message KeyRotation {
+//!     bytes old_peer_id = 1;
+//!     bytes new_peer_id = 2;
+//!     uint64 seq = 3;
+//! }
+//! ```
+use crate::{
+    envelope::SignedEnvelope,
+    error::SecioError,
+    key_encoding::protobuf::{self, Value},
+    peer_id::PeerId,
+    SecioKeyPair,
+};
+
+/// Signature domain of key rotation
+pub const DOMAIN: &str = "tentacle-key-rotation";
+/// Payload type of key rotation
+pub const PAYLOAD_TYPE: &[u8] = b"/tentacle/key-rotation";
+
+const FIELD_OLD_PEER_ID: u64 = 1;
+const FIELD_NEW_PEER_ID: u64 = 2;
+const FIELD_SEQ: u64 = 3;
+
+/// A link from the old peer id to the new one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyRotation {
+    old_peer_id: PeerId,
+    new_peer_id: PeerId,
+    seq: u64,
+}
+
+impl KeyRotation {
+    /// Create a link, the sequence number should increase on every rotation
+    pub fn new(old_peer_id: PeerId, new_peer_id: PeerId, seq: u64) -> Self {
+        KeyRotation {
+            old_peer_id,
+            new_peer_id,
+            seq,
+        }
+    }
+
+    /// The replaced peer id
+    pub fn old_peer_id(&self) -> &PeerId {
+        &self.old_peer_id
+    }
+
+    /// The peer id in use
+    pub fn new_peer_id(&self) -> &PeerId {
+        &self.new_peer_id
+    }
+
+    /// Sequence number
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Encode to bytes
+    pub fn encode(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        protobuf::write_bytes_field(&mut output, FIELD_OLD_PEER_ID, self.old_peer_id.as_bytes());
+        protobuf::write_bytes_field(&mut output, FIELD_NEW_PEER_ID, self.new_peer_id.as_bytes());
+        protobuf::write_varint_field(&mut output, FIELD_SEQ, self.seq);
+        output
+    }
+
+    /// Decode from bytes
+    pub fn decode(data: &[u8]) -> Result<Self, SecioError> {
+        let mut old_peer_id = None;
+        let mut new_peer_id = None;
+        let mut seq = 0;
+
+        for (field, value) in protobuf::read_fields(data).ok_or(SecioError::InvalidMessage)? {
+            match (field, value) {
+                (FIELD_OLD_PEER_ID, Value::Bytes(value)) => {
+                    old_peer_id = Some(
+                        PeerId::from_bytes(value.to_vec())
+                            .map_err(|_| SecioError::InvalidMessage)?,
+                    )
+                }
+                (FIELD_NEW_PEER_ID, Value::Bytes(value)) => {
+                    new_peer_id = Some(
+                        PeerId::from_bytes(value.to_vec())
+                            .map_err(|_| SecioError::InvalidMessage)?,
+                    )
+                }
+                (FIELD_SEQ, Value::Varint(value)) => seq = value,
+                _ => (),
+            }
+        }
+
+        Ok(KeyRotation {
+            old_peer_id: old_peer_id.ok_or(SecioError::InvalidMessage)?,
+            new_peer_id: new_peer_id.ok_or(SecioError::InvalidMessage)?,
+            seq,
+        })
+    }
+
+    /// Sign the link with the old key pair
+    pub fn into_signed_envelope(
+        self,
+        old_key_pair: &SecioKeyPair,
+    ) -> Result<SignedEnvelope, SecioError> {
+        if old_key_pair.peer_id() != self.old_peer_id {
+            return Err(SecioError::InvalidMessage);
+        }
+        Ok(SignedEnvelope::new(
+            old_key_pair,
+            DOMAIN,
+            PAYLOAD_TYPE.to_vec(),
+            self.encode(),
+            self.seq,
+        ))
+    }
+
+    /// Verify the envelope and return the link in it, the link must be signed by the old peer
+    pub fn from_signed_envelope(envelope: &SignedEnvelope) -> Result<Self, SecioError> {
+        envelope.verify(DOMAIN)?;
+        if envelope.payload_type() != PAYLOAD_TYPE {
+            return Err(SecioError::InvalidMessage);
+        }
+
+        let rotation = KeyRotation::decode(envelope.payload())?;
+        if rotation.old_peer_id != envelope.peer_id() || rotation.seq != envelope.seq() {
+            return Err(SecioError::InvalidMessage);
+        }
+        Ok(rotation)
+    }
+
+    /// Decode the encoded `SignedEnvelope` and return the verified link in it
+    pub fn decode_signed(data: &[u8]) -> Result<Self, SecioError> {
+        Self::from_signed_envelope(&SignedEnvelope::decode(data, DOMAIN)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyRotation, DOMAIN, PAYLOAD_TYPE};
+    use crate::{envelope::SignedEnvelope, error::SecioError, SecioKeyPair};
+
+    #[test]
+    fn key_rotation_roundtrip() {
+        let old = SecioKeyPair::secp256k1_generated();
+        let new = SecioKeyPair::secp256k1_generated();
+        let rotation = KeyRotation::new(old.peer_id(), new.peer_id(), 1);
+
+        assert_eq!(KeyRotation::decode(&rotation.encode()).unwrap(), rotation);
+
+        let envelope = rotation.clone().into_signed_envelope(&old).unwrap();
+        let decoded = KeyRotation::decode_signed(&envelope.encode()).unwrap();
+        assert_eq!(decoded, rotation);
+        assert_eq!(decoded.new_peer_id(), &new.peer_id());
+    }
+
+    #[test]
+    fn key_rotation_signed_by_new_key() {
+        let old = SecioKeyPair::secp256k1_generated();
+        let new = SecioKeyPair::secp256k1_generated();
+
+        // only the old key can announce its replacement
+        let rotation = KeyRotation::new(old.peer_id(), new.peer_id(), 1);
+        assert!(rotation.clone().into_signed_envelope(&new).is_err());
+
+        let envelope =
+            SignedEnvelope::new(&new, DOMAIN, PAYLOAD_TYPE.to_vec(), rotation.encode(), 1);
+        assert_eq!(
+            KeyRotation::from_signed_envelope(&envelope).unwrap_err(),
+            SecioError::InvalidMessage
+        );
+    }
+}
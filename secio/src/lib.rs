@@ -3,19 +3,36 @@
 #![deny(missing_docs)]
 use rand::RngCore;
 
-pub use crate::{handshake::handshake_struct::PublicKey, peer_id::PeerId};
+#[cfg(all(not(pure_rust_crypto), not(feature = "native-crypto")))]
+compile_error!(
+    "Please choose a crypto backend via feature. Possible choices: native-crypto, pure-rust-crypto"
+);
+
+pub use crate::{dh_compat::KeyAgreement, handshake::handshake_struct::PublicKey, peer_id::PeerId};
 
 /// Encrypted and decrypted codec implementation, and stream handle
 pub mod codec;
 /// Symmetric ciphers algorithms
 pub mod crypto;
 mod dh_compat;
+/// Signed envelope
+pub mod envelope;
 /// Error type
 pub mod error;
 /// Implementation of the handshake process
 pub mod handshake;
+mod key_encoding;
+/// Signed link from the old peer id to the new one
+pub mod key_rotation;
+/// Trailing fields which are not declared in the molecule schema
+#[cfg(feature = "molc")]
+pub mod molecule_ext;
 /// Peer id
 pub mod peer_id;
+/// Signed peer record
+pub mod peer_record;
+/// Private network with a pre-shared key
+pub mod pnet;
 /// A little encapsulation of secp256k1
 mod secp256k1_compat;
 mod sha256_compat;
@@ -0,0 +1,141 @@
+use bytes::Bytes;
+use molecule::{pack_number, unpack_number, Number, NUMBER_SIZE};
+
+/// Append a field to the encoded molecule table, the peers decoding with the old schema
+/// ignore it in compatible mode
+///
+/// Return None if the header of table is invalid
+pub fn append_table_field(table: &[u8], field: &[u8]) -> Option<Bytes> {
+    let number = |index: usize| {
+        table
+            .get(index..index + NUMBER_SIZE)
+            .map(|number| unpack_number(number) as usize)
+    };
+    if number(0)? != table.len() {
+        return None;
+    }
+    // a table without field only has the total size
+    let header_size = if table.len() == NUMBER_SIZE {
+        NUMBER_SIZE
+    } else {
+        number(NUMBER_SIZE)?
+    };
+    if header_size % NUMBER_SIZE != 0 || header_size > table.len() {
+        return None;
+    }
+    let total_size = table.len() + NUMBER_SIZE + field.len();
+
+    let mut output = Vec::with_capacity(total_size);
+    output.extend_from_slice(&pack_number(total_size as Number));
+    // every field moves backward by the size of the new offset
+    for offset in table[NUMBER_SIZE..header_size].chunks(NUMBER_SIZE) {
+        let offset = unpack_number(offset) as usize;
+        if offset < header_size || offset > table.len() {
+            return None;
+        }
+        output.extend_from_slice(&pack_number((offset + NUMBER_SIZE) as Number));
+    }
+    output.extend_from_slice(&pack_number((table.len() + NUMBER_SIZE) as Number));
+    output.extend_from_slice(&table[header_size..]);
+    output.extend_from_slice(field);
+    Some(Bytes::from(output))
+}
+
+/// Return the first field behind the `field_count` fields declared in the schema,
+/// the table must be verified in compatible mode
+pub fn extra_table_field(table: &[u8], field_count: usize) -> Option<&[u8]> {
+    let number = |index: usize| {
+        table
+            .get(index..index + NUMBER_SIZE)
+            .map(|number| unpack_number(number) as usize)
+    };
+    let header_size = number(NUMBER_SIZE)?;
+    let index = NUMBER_SIZE * (field_count + 1);
+    if header_size <= index {
+        return None;
+    }
+    let start = number(index)?;
+    let end = if header_size > index + NUMBER_SIZE {
+        number(index + NUMBER_SIZE)?
+    } else {
+        table.len()
+    };
+    table.get(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append_table_field, extra_table_field};
+    use molecule::{pack_number, Number};
+
+    /// A table with the given fields
+    fn table(fields: &[&[u8]]) -> Vec<u8> {
+        let header_size = 4 * (fields.len() + 1);
+        let total_size = header_size + fields.iter().map(|f| f.len()).sum::<usize>();
+        let mut output = pack_number(total_size as Number).to_vec();
+        let mut offset = header_size;
+        for field in fields {
+            output.extend_from_slice(&pack_number(offset as Number));
+            offset += field.len();
+        }
+        for field in fields {
+            output.extend_from_slice(field);
+        }
+        output
+    }
+
+    #[test]
+    fn append_then_read() {
+        let origin = table(&[b"first", b"second"]);
+        let appended = append_table_field(&origin, b"extra").unwrap();
+        assert_eq!(&appended[..], &table(&[b"first", b"second", b"extra"])[..]);
+        assert_eq!(extra_table_field(&appended, 2), Some(&b"extra"[..]));
+
+        // the field behind the first extra one is ignored
+        let appended = append_table_field(&appended, b"more").unwrap();
+        assert_eq!(extra_table_field(&appended, 2), Some(&b"extra"[..]));
+        assert_eq!(extra_table_field(&appended, 3), Some(&b"more"[..]));
+    }
+
+    #[test]
+    fn append_to_empty_table() {
+        let appended = append_table_field(&table(&[]), b"extra").unwrap();
+        assert_eq!(&appended[..], &table(&[b"extra"])[..]);
+    }
+
+    #[test]
+    fn append_to_invalid_table() {
+        // shorter than the header
+        assert_eq!(append_table_field(&[], b"extra"), None);
+        assert_eq!(append_table_field(&[8, 0, 0], b"extra"), None);
+
+        let origin = table(&[b"first", b"second"]);
+        // total size mismatch
+        assert_eq!(
+            append_table_field(&origin[..origin.len() - 1], b"extra"),
+            None
+        );
+
+        // header size larger than the table
+        let mut corrupt = origin.clone();
+        corrupt[4..8].copy_from_slice(&pack_number(100));
+        assert_eq!(append_table_field(&corrupt, b"extra"), None);
+
+        // header size not aligned to the offsets
+        let mut corrupt = origin.clone();
+        corrupt[4..8].copy_from_slice(&pack_number(10));
+        assert_eq!(append_table_field(&corrupt, b"extra"), None);
+
+        // field offset out of the table
+        let mut corrupt = origin;
+        corrupt[8..12].copy_from_slice(&pack_number(100));
+        assert_eq!(append_table_field(&corrupt, b"extra"), None);
+    }
+
+    #[test]
+    fn no_extra_field() {
+        assert_eq!(extra_table_field(&table(&[b"first", b"second"]), 2), None);
+        assert_eq!(extra_table_field(&table(&[]), 0), None);
+        assert_eq!(extra_table_field(&[], 0), None);
+    }
+}
@@ -0,0 +1,215 @@
+//! Peer record, the listen addresses of a peer, signed by itself with `SignedEnvelope`,
+//! so that addresses forwarded by other peers can be verified.
+//!
+//! The identify protocol sends the record of self with the listen addresses, and the discovery
+//! protocol with the node of self, the receivers check that it's signed by the remote peer.
+//!
+//! Encoding:
+//!
+//! ```protobuf
+//! message PeerRecord {
+//!     message AddressInfo {
+//!         bytes multiaddr = 1;
+//!     }
+//!
+//!     bytes peer_id = 1;
+//!     uint64 seq = 2;
+//!     repeated AddressInfo addresses = 3;
+//! }
+//! ```
+use multiaddr::Multiaddr;
+use std::convert::TryFrom;
+
+use crate::{
+    envelope::SignedEnvelope,
+    error::SecioError,
+    key_encoding::protobuf::{self, Value},
+    peer_id::PeerId,
+    SecioKeyPair,
+};
+
+/// Signature domain of peer record
+pub const DOMAIN: &str = "tentacle-peer-record";
+/// Payload type of peer record, the multicodec of libp2p peer record
+pub const PAYLOAD_TYPE: &[u8] = &[0x03, 0x01];
+
+const FIELD_PEER_ID: u64 = 1;
+const FIELD_SEQ: u64 = 2;
+const FIELD_ADDRESSES: u64 = 3;
+const FIELD_MULTIADDR: u64 = 1;
+
+/// The listen addresses of a peer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerRecord {
+    peer_id: PeerId,
+    addresses: Vec<Multiaddr>,
+    seq: u64,
+}
+
+impl PeerRecord {
+    /// Create a record, the sequence number should increase on every change of addresses,
+    /// e.g. unix time in milliseconds
+    pub fn new(peer_id: PeerId, addresses: Vec<Multiaddr>, seq: u64) -> Self {
+        PeerRecord {
+            peer_id,
+            addresses,
+            seq,
+        }
+    }
+
+    /// Peer id
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    /// Listen addresses
+    pub fn addresses(&self) -> &[Multiaddr] {
+        &self.addresses
+    }
+
+    /// Sequence number
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Encode to bytes
+    pub fn encode(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        protobuf::write_bytes_field(&mut output, FIELD_PEER_ID, self.peer_id.as_bytes());
+        protobuf::write_varint_field(&mut output, FIELD_SEQ, self.seq);
+        for address in &self.addresses {
+            let mut info = Vec::new();
+            protobuf::write_bytes_field(&mut info, FIELD_MULTIADDR, address.as_ref());
+            protobuf::write_bytes_field(&mut output, FIELD_ADDRESSES, &info);
+        }
+        output
+    }
+
+    /// Decode from bytes
+    pub fn decode(data: &[u8]) -> Result<Self, SecioError> {
+        let mut peer_id = None;
+        let mut seq = 0;
+        let mut addresses = Vec::new();
+
+        for (field, value) in protobuf::read_fields(data).ok_or(SecioError::InvalidMessage)? {
+            match (field, value) {
+                (FIELD_PEER_ID, Value::Bytes(value)) => {
+                    peer_id = Some(
+                        PeerId::from_bytes(value.to_vec())
+                            .map_err(|_| SecioError::InvalidMessage)?,
+                    )
+                }
+                (FIELD_SEQ, Value::Varint(value)) => seq = value,
+                (FIELD_ADDRESSES, Value::Bytes(value)) => {
+                    for (field, value) in
+                        protobuf::read_fields(value).ok_or(SecioError::InvalidMessage)?
+                    {
+                        if let (FIELD_MULTIADDR, Value::Bytes(value)) = (field, value) {
+                            addresses.push(
+                                Multiaddr::try_from(value.to_vec())
+                                    .map_err(|_| SecioError::InvalidMessage)?,
+                            );
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(PeerRecord {
+            peer_id: peer_id.ok_or(SecioError::InvalidMessage)?,
+            addresses,
+            seq,
+        })
+    }
+
+    /// Sign the record with the key pair of its peer
+    pub fn into_signed_envelope(
+        self,
+        key_pair: &SecioKeyPair,
+    ) -> Result<SignedEnvelope, SecioError> {
+        if key_pair.peer_id() != self.peer_id {
+            return Err(SecioError::InvalidMessage);
+        }
+        Ok(SignedEnvelope::new(
+            key_pair,
+            DOMAIN,
+            PAYLOAD_TYPE.to_vec(),
+            self.encode(),
+            self.seq,
+        ))
+    }
+
+    /// Verify the envelope and return the record in it, the record must be signed by its peer
+    pub fn from_signed_envelope(envelope: &SignedEnvelope) -> Result<Self, SecioError> {
+        envelope.verify(DOMAIN)?;
+        if envelope.payload_type() != PAYLOAD_TYPE {
+            return Err(SecioError::InvalidMessage);
+        }
+
+        let record = PeerRecord::decode(envelope.payload())?;
+        if record.peer_id != envelope.peer_id() || record.seq != envelope.seq() {
+            return Err(SecioError::InvalidMessage);
+        }
+        Ok(record)
+    }
+
+    /// Decode the encoded `SignedEnvelope` and return the verified record in it
+    pub fn decode_signed(data: &[u8]) -> Result<Self, SecioError> {
+        Self::from_signed_envelope(&SignedEnvelope::decode(data, DOMAIN)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PeerRecord, DOMAIN, PAYLOAD_TYPE};
+    use crate::{envelope::SignedEnvelope, error::SecioError, SecioKeyPair};
+
+    fn addresses() -> Vec<multiaddr::Multiaddr> {
+        vec![
+            "/ip4/127.0.0.1/tcp/1337".parse().unwrap(),
+            "/ip6/::1/tcp/1338".parse().unwrap(),
+        ]
+    }
+
+    #[test]
+    fn peer_record_roundtrip() {
+        let key_pair = SecioKeyPair::secp256k1_generated();
+        let record = PeerRecord::new(key_pair.peer_id(), addresses(), 42);
+
+        assert_eq!(PeerRecord::decode(&record.encode()).unwrap(), record);
+
+        let envelope = record.clone().into_signed_envelope(&key_pair).unwrap();
+        let decoded = PeerRecord::decode_signed(&envelope.encode()).unwrap();
+        assert_eq!(decoded, record);
+        assert_eq!(decoded.addresses(), &addresses()[..]);
+    }
+
+    #[test]
+    fn peer_record_signed_by_other() {
+        let key_pair = SecioKeyPair::secp256k1_generated();
+        let other = SecioKeyPair::secp256k1_generated();
+
+        // can't sign a record of other peer
+        let record = PeerRecord::new(key_pair.peer_id(), addresses(), 42);
+        assert!(record.clone().into_signed_envelope(&other).is_err());
+
+        // a valid envelope, but the record in it claims addresses of other peer
+        let envelope =
+            SignedEnvelope::new(&other, DOMAIN, PAYLOAD_TYPE.to_vec(), record.encode(), 42);
+        assert_eq!(
+            PeerRecord::from_signed_envelope(&envelope).unwrap_err(),
+            SecioError::InvalidMessage
+        );
+    }
+
+    #[test]
+    fn peer_record_wrong_payload_type() {
+        let key_pair = SecioKeyPair::secp256k1_generated();
+        let record = PeerRecord::new(key_pair.peer_id(), addresses(), 42);
+
+        let envelope =
+            SignedEnvelope::new(&key_pair, DOMAIN, b"other".to_vec(), record.encode(), 42);
+        assert!(PeerRecord::from_signed_envelope(&envelope).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Private network, every stream is encrypted by a pre-shared key before any other handshake,
+//! so that only the peers holding the same key can talk to each other.
+//!
+//! The process:
+//!
+//! 1. Both sides send a random nonce in plain text
+//! 2. Both sides derive the encode key with `hmac(psk, local_nonce | remote_nonce)`,
+//!    and the decode key with `hmac(psk, remote_nonce | local_nonce)`
+//! 3. Both sides send back the encrypted nonce of remote, and check the one received,
+//!    a peer without the same key fails here
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use log::{debug, trace};
+use std::{fmt, io};
+use tokio::prelude::{AsyncRead, AsyncWrite};
+use tokio_util::codec::length_delimited::Builder;
+
+use crate::{
+    codec::{secure_stream::SecureStream, Hmac},
+    crypto::{cipher::CipherType, new_stream, CryptoMode},
+    error::SecioError,
+    Digest,
+};
+
+/// The size of pre-shared key
+pub const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 32;
+const CIPHER: CipherType = CipherType::ChaCha20Poly1305;
+
+/// Pre-shared key of a private network
+#[derive(Clone, PartialEq, Eq)]
+pub struct PreSharedKey([u8; KEY_SIZE]);
+
+impl PreSharedKey {
+    /// Create a pre-shared key from raw bytes
+    pub fn new(key: [u8; KEY_SIZE]) -> Self {
+        PreSharedKey(key)
+    }
+
+    /// Build a pre-shared key from a 32 bytes slice
+    pub fn from_slice(key: &[u8]) -> Result<Self, SecioError> {
+        if key.len() != KEY_SIZE {
+            return Err(SecioError::InvalidMessage);
+        }
+        let mut inner = [0; KEY_SIZE];
+        inner.copy_from_slice(key);
+        Ok(PreSharedKey(inner))
+    }
+
+    /// Derive the cipher key of one direction
+    fn derive(&self, first: &[u8], second: &[u8]) -> Vec<u8> {
+        let hmac = Hmac::from_key(Digest::Sha256, &self.0);
+        let mut context = hmac.context();
+        context.update(first);
+        context.update(second);
+        let key = context.sign();
+        AsRef::<[u8]>::as_ref(&key)[..CIPHER.key_size()].to_vec()
+    }
+
+    /// Wrap the stream with this key, the result stream must be used before
+    /// any other handshake.
+    pub async fn handshake<T>(
+        &self,
+        socket: T,
+        max_frame_length: usize,
+    ) -> Result<SecureStream<T>, SecioError>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut socket = Builder::new()
+            .big_endian()
+            .length_field_length(4)
+            .max_frame_length(max_frame_length)
+            .new_framed(socket);
+
+        let local_nonce: [u8; NONCE_SIZE] = rand::random();
+        trace!("sending pnet nonce to remote");
+        socket.send(Bytes::copy_from_slice(&local_nonce)).await?;
+
+        let remote_nonce = match socket.next().await {
+            Some(nonce) => nonce?,
+            None => {
+                let err = io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected eof");
+                debug!("unexpected eof while waiting for remote's pnet nonce");
+                return Err(err.into());
+            }
+        };
+        if remote_nonce.len() != NONCE_SIZE {
+            debug!("invalid pnet nonce length: {}", remote_nonce.len());
+            return Err(SecioError::InvalidMessage);
+        }
+        if remote_nonce[..] == local_nonce[..] {
+            return Err(SecioError::ConnectSelf);
+        }
+
+        let mut encode_cipher = new_stream(
+            CIPHER,
+            &self.derive(&local_nonce, &remote_nonce),
+            CryptoMode::Encrypt,
+        );
+        let mut decode_cipher = new_stream(
+            CIPHER,
+            &self.derive(&remote_nonce, &local_nonce),
+            CryptoMode::Decrypt,
+        );
+
+        // We send back their nonce to check if the remote has the same key.
+        trace!("checking pre-shared key by sending back remote's nonce");
+        let check = encode_cipher.encrypt(&remote_nonce)?;
+        socket.send(Bytes::from(check)).await?;
+
+        let check = match socket.next().await {
+            Some(check) => check?,
+            None => {
+                let err = io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected eof");
+                debug!("unexpected eof while waiting for remote's pnet check");
+                return Err(err.into());
+            }
+        };
+        match decode_cipher.decrypt(&check) {
+            Ok(ref nonce) if nonce[..] == local_nonce[..] => (),
+            _ => {
+                debug!("pnet check failed, remote has a different pre-shared key");
+                return Err(SecioError::NonceVerificationFailed);
+            }
+        }
+
+        Ok(SecureStream::new(
+            socket,
+            decode_cipher,
+            encode_cipher,
+            Vec::new(),
+        ))
+    }
+}
+
+impl From<[u8; KEY_SIZE]> for PreSharedKey {
+    fn from(key: [u8; KEY_SIZE]) -> Self {
+        PreSharedKey::new(key)
+    }
+}
+
+impl fmt::Debug for PreSharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Never print the key itself
+        f.write_str("PreSharedKey(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PreSharedKey;
+    use crate::error::SecioError;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    fn pnet_handshake(
+        key_1: PreSharedKey,
+        key_2: PreSharedKey,
+    ) -> (Result<(), SecioError>, Result<(), SecioError>) {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let listener_addr = listener.local_addr().unwrap();
+
+            let server = async move {
+                let (connect, _) = listener.accept().await.unwrap();
+                let mut handle = key_1.handshake(connect, 1024).await?;
+                let mut data = [0u8; 11];
+                handle.read_exact(&mut data).await?;
+                handle.write_all(&data).await?;
+                Ok(())
+            };
+            let client = async move {
+                let connect = TcpStream::connect(&listener_addr).await.unwrap();
+                let mut handle = key_2.handshake(connect, 1024).await?;
+                handle.write_all(b"hello world").await?;
+                let mut data = [0u8; 11];
+                handle.read_exact(&mut data).await?;
+                assert_eq!(&data, b"hello world");
+                Ok(())
+            };
+            futures::join!(server, client)
+        })
+    }
+
+    #[test]
+    fn pnet_with_same_key() {
+        let key: [u8; 32] = rand::random();
+        let (server, client) = pnet_handshake(key.into(), key.into());
+        assert!(server.is_ok());
+        assert!(client.is_ok());
+    }
+
+    #[test]
+    fn pnet_with_different_key() {
+        let (server, client) = pnet_handshake(
+            PreSharedKey::new(rand::random()),
+            PreSharedKey::new(rand::random()),
+        );
+        assert_eq!(server.unwrap_err(), SecioError::NonceVerificationFailed);
+        assert_eq!(client.unwrap_err(), SecioError::NonceVerificationFailed);
+    }
+
+    #[test]
+    fn invalid_key_length() {
+        assert!(PreSharedKey::from_slice(&[0; 31]).is_err());
+        assert!(PreSharedKey::from_slice(&[0; 32]).is_ok());
+    }
+}
@@ -24,6 +24,10 @@ pub fn verify(message: &Message, signature: &Signature, pubkey: &PublicKey) -> b
     secp256k1.verify(message, signature, pubkey).is_ok()
 }
 
+pub fn secret_key_to_vec(secret: &SecretKey) -> Vec<u8> {
+    secret[..].to_vec()
+}
+
 pub fn secret_key_from_slice(key: &[u8]) -> Result<SecretKey, secp256k1::Error> {
     SecretKey::from_slice(key)
 }
@@ -19,14 +19,11 @@ const CHACHA20_POLY1305: &str = "CHACHA20_POLY1305";
 const SHA_256: &str = "SHA256";
 const SHA_512: &str = "SHA512";
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(not(pure_rust_crypto))]
 pub(crate) const DEFAULT_AGREEMENTS_PROPOSITION: &str = "P-256,P-384,X25519";
-#[cfg(target_arch = "wasm32")]
+#[cfg(pure_rust_crypto)]
 pub(crate) const DEFAULT_AGREEMENTS_PROPOSITION: &str = "X25519";
-#[cfg(not(target_arch = "wasm32"))]
 pub(crate) const DEFAULT_CIPHERS_PROPOSITION: &str = "AES-128-GCM,AES-256-GCM,CHACHA20_POLY1305";
-#[cfg(target_arch = "wasm32")]
-pub(crate) const DEFAULT_CIPHERS_PROPOSITION: &str = "CHACHA20_POLY1305";
 pub(crate) const DEFAULT_DIGESTS_PROPOSITION: &str = "SHA256,SHA512";
 
 /// Return a proposition string from the given sequence of `KeyAgreement` values.
@@ -67,7 +64,10 @@ pub fn select_agreement(r: Ordering, ours: &str, theirs: &str) -> Result<KeyAgre
     for x in a.split(',') {
         if b.split(',').any(|y| x == y) {
             match x {
+                // the pure rust backend can't do ECDH on NIST curves, skip them
+                #[cfg(not(pure_rust_crypto))]
                 ECDH_P256 => return Ok(KeyAgreement::EcdhP256),
+                #[cfg(not(pure_rust_crypto))]
                 ECDH_P384 => return Ok(KeyAgreement::EcdhP384),
                 X25519 => return Ok(KeyAgreement::X25519),
                 _ => continue,
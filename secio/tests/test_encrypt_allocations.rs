@@ -0,0 +1,74 @@
+//! Count the allocations of the encrypt paths with a counting global allocator,
+//! it lives in its own test binary, so the other tests and benches are not affected
+#![cfg(not(pure_rust_crypto))]
+
+use bytes::BytesMut;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tentacle_secio::crypto::{cipher::CipherType, new_stream, CryptoMode};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const FRAMES: usize = 16;
+
+/// Encrypt frames to a reused write buffer, as `SecureStream` does,
+/// return the allocations made by encrypting
+fn encrypt_allocations(cipher: CipherType, in_place: bool) -> usize {
+    let data = vec![1u8; 64 * 1024];
+    let cipher_key = [0u8; 32];
+    let mut encode_cipher = new_stream(
+        cipher,
+        &cipher_key[..cipher.key_size()],
+        CryptoMode::Encrypt,
+    );
+    let mut buf = BytesMut::with_capacity(data.len() + cipher.tag_size());
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..FRAMES {
+        buf.clear();
+        if in_place {
+            encode_cipher.encrypt_to(&data, &mut buf).unwrap();
+        } else {
+            let frame = encode_cipher.encrypt(&data).unwrap();
+            buf.extend_from_slice(&frame);
+        }
+    }
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+#[test]
+fn test_encrypt_to_does_not_allocate_per_frame() {
+    for &cipher in &[
+        CipherType::Aes128Gcm,
+        CipherType::Aes256Gcm,
+        CipherType::ChaCha20Poly1305,
+    ] {
+        // every encrypted frame is a new buffer
+        assert!(encrypt_allocations(cipher, false) >= FRAMES);
+        // the write buffer is reused, no allocation per frame
+        assert!(encrypt_allocations(cipher, true) <= 1);
+    }
+}
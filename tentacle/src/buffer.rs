@@ -1,4 +1,7 @@
-use crate::channel::mpsc::Sender as PrioritySender;
+use crate::channel::{
+    mpsc::{Priority, Sender as PrioritySender},
+    queue_index,
+};
 use futures::channel::mpsc::Sender;
 use std::{
     collections::VecDeque,
@@ -16,88 +19,77 @@ pub enum SendResult {
 
 pub struct PriorityBuffer<T> {
     sender: PrioritySender<T>,
-    high_buffer: VecDeque<T>,
-    normal_buffer: VecDeque<T>,
+    /// Buffers of each priority level, the index is the level
+    buffers: Vec<VecDeque<T>>,
 }
 
 impl<T> PriorityBuffer<T> {
     pub fn new(sender: PrioritySender<T>) -> Self {
-        PriorityBuffer {
-            sender,
-            high_buffer: VecDeque::default(),
-            normal_buffer: VecDeque::default(),
-        }
+        let buffers = (0..sender.priority_levels())
+            .map(|_| VecDeque::default())
+            .collect();
+        PriorityBuffer { sender, buffers }
     }
 
-    pub fn push_high(&mut self, item: T) {
-        self.high_buffer.push_back(item)
+    /// The priority beyond the levels of sender will be treated as the lowest level
+    pub fn push(&mut self, priority: Priority, item: T) {
+        let level = queue_index(priority, self.buffers.len());
+        self.buffers[level].push_back(item)
     }
 
-    pub fn push_normal(&mut self, item: T) {
-        self.normal_buffer.push_back(item)
+    pub fn push_high(&mut self, item: T) {
+        self.push(Priority::HIGH, item)
     }
 
     pub fn len(&self) -> usize {
-        self.high_buffer.len() + self.normal_buffer.len()
+        self.buffers.iter().map(VecDeque::len).sum()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.high_buffer.is_empty() && self.normal_buffer.is_empty()
+        self.buffers.iter().all(VecDeque::is_empty)
     }
 
     fn shrink_to_fit(&mut self) {
-        if self.high_buffer.capacity() - self.high_buffer.len() > BUF_SHRINK_THRESHOLD {
-            self.high_buffer.shrink_to_fit();
-        }
-        if self.normal_buffer.capacity() - self.normal_buffer.len() > BUF_SHRINK_THRESHOLD {
-            self.normal_buffer.shrink_to_fit();
+        for buffer in self.buffers.iter_mut() {
+            if buffer.capacity() - buffer.len() > BUF_SHRINK_THRESHOLD {
+                buffer.shrink_to_fit();
+            }
         }
     }
 
     pub fn try_send(&mut self, cx: &mut Context) -> SendResult {
-        while let Some(event) = self.high_buffer.pop_front() {
-            match self.sender.poll_ready(cx) {
-                Poll::Ready(Ok(())) => {
-                    if let Err(e) = self.sender.try_quick_send(event) {
-                        if e.is_full() {
-                            self.high_buffer.push_front(e.into_inner());
-                            return SendResult::Pending;
-                        } else {
-                            self.clear();
-                            return SendResult::Disconnect;
-                        }
-                    }
-                }
-                Poll::Pending => {
-                    self.high_buffer.push_front(event);
-                    return SendResult::Pending;
-                }
-                Poll::Ready(Err(_)) => {
-                    self.clear();
-                    return SendResult::Disconnect;
-                }
-            }
+        let result = self.try_send_keep_unsent(cx);
+        if let SendResult::Disconnect = result {
+            self.clear();
         }
-        while let Some(event) = self.normal_buffer.pop_front() {
-            match self.sender.poll_ready(cx) {
-                Poll::Ready(Ok(())) => {
-                    if let Err(e) = self.sender.try_send(event) {
-                        if e.is_full() {
-                            self.normal_buffer.push_front(e.into_inner());
-                            return SendResult::Pending;
-                        } else {
-                            self.clear();
-                            return SendResult::Disconnect;
+        result
+    }
+
+    /// Same as `try_send`, but on disconnect, the unsent items are left in the buffer for the owner to drain
+    pub fn try_send_keep_unsent(&mut self, cx: &mut Context) -> SendResult {
+        for level in 0..self.buffers.len() {
+            let priority = Priority::new(level as u8);
+            while let Some(event) = self.buffers[level].pop_front() {
+                match self.sender.poll_ready(cx) {
+                    Poll::Ready(Ok(())) => {
+                        if let Err(e) = self.sender.try_send_with_priority(event, priority) {
+                            let is_full = e.is_full();
+                            self.buffers[level].push_front(e.into_inner());
+                            return if is_full {
+                                SendResult::Pending
+                            } else {
+                                SendResult::Disconnect
+                            };
                         }
                     }
-                }
-                Poll::Pending => {
-                    self.normal_buffer.push_front(event);
-                    return SendResult::Pending;
-                }
-                Poll::Ready(Err(_)) => {
-                    self.clear();
-                    return SendResult::Disconnect;
+                    Poll::Pending => {
+                        self.buffers[level].push_front(event);
+                        return SendResult::Pending;
+                    }
+                    Poll::Ready(Err(_)) => {
+                        self.buffers[level].push_front(event);
+                        return SendResult::Disconnect;
+                    }
                 }
             }
         }
@@ -105,9 +97,15 @@ impl<T> PriorityBuffer<T> {
         SendResult::Ok
     }
 
+    /// Take all the items, from the highest priority to the lowest
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.buffers.iter_mut().flat_map(|buffer| buffer.drain(..))
+    }
+
     pub fn clear(&mut self) {
-        self.high_buffer.clear();
-        self.normal_buffer.clear();
+        for buffer in self.buffers.iter_mut() {
+            buffer.clear();
+        }
     }
 }
 
@@ -194,18 +192,16 @@ impl<T> Clone for Buffer<T> {
 
 impl<T> Clone for PriorityBuffer<T> {
     fn clone(&self) -> Self {
-        Self {
-            sender: self.sender.clone(),
-            high_buffer: Default::default(),
-            normal_buffer: Default::default(),
-        }
+        PriorityBuffer::new(self.sender.clone())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Buffer, PriorityBuffer};
-    use crate::channel::mpsc::channel as priority_channel;
+    use super::{Buffer, PriorityBuffer, SendResult};
+    use crate::channel::mpsc::{
+        channel as priority_channel, channel_with_priority_levels, Priority,
+    };
     use futures::{channel::mpsc::channel, executor::block_on, future::poll_fn, StreamExt};
     use std::{
         collections::VecDeque,
@@ -221,8 +217,8 @@ mod test {
         buffer.push_high(2);
         buffer.push_high(3);
         buffer.push_high(4);
-        buffer.push_normal(5);
-        buffer.push_normal(6);
+        buffer.push(Priority::NORMAL, 5);
+        buffer.push(Priority::NORMAL, 6);
 
         let send_1 = |cx: &mut Context<'_>| -> Poll<()> {
             buffer.try_send(cx);
@@ -230,8 +226,8 @@ mod test {
         };
         block_on(poll_fn(send_1));
 
-        assert_eq!(buffer.high_buffer, VecDeque::from(vec![3, 4]));
-        assert_eq!(buffer.normal_buffer, VecDeque::from(vec![5, 6]));
+        assert_eq!(buffer.buffers[0], VecDeque::from(vec![3, 4]));
+        assert_eq!(buffer.buffers[1], VecDeque::from(vec![5, 6]));
 
         let res: Vec<_> = block_on(async {
             let mut a = Vec::new();
@@ -248,8 +244,8 @@ mod test {
         };
         block_on(poll_fn(send_2));
 
-        assert!(buffer.high_buffer.is_empty());
-        assert_eq!(buffer.normal_buffer, VecDeque::from(vec![5, 6]));
+        assert!(buffer.buffers[0].is_empty());
+        assert_eq!(buffer.buffers[1], VecDeque::from(vec![5, 6]));
 
         let res: Vec<_> = block_on(async {
             let mut a = Vec::new();
@@ -266,8 +262,126 @@ mod test {
         };
         block_on(poll_fn(send_3));
 
-        assert!(buffer.high_buffer.is_empty());
-        assert!(buffer.normal_buffer.is_empty());
+        assert!(buffer.buffers[0].is_empty());
+        assert!(buffer.buffers[1].is_empty());
+    }
+
+    #[test]
+    fn test_priority_buffer_with_levels() {
+        let (tx, mut rx) = channel_with_priority_levels::<u32>(8, 4);
+        let mut buffer = PriorityBuffer::new(tx);
+
+        buffer.push(Priority::new(3), 1);
+        buffer.push(Priority::new(2), 2);
+        buffer.push(Priority::NORMAL, 3);
+        buffer.push(Priority::new(3), 4);
+        buffer.push_high(5);
+        buffer.push(Priority::new(2), 6);
+
+        assert_eq!(buffer.len(), 6);
+
+        let send = |cx: &mut Context<'_>| -> Poll<()> {
+            buffer.try_send(cx);
+            Poll::Ready(())
+        };
+        block_on(poll_fn(send));
+
+        assert!(buffer.is_empty());
+
+        let res: Vec<_> = block_on(async {
+            let mut a = Vec::new();
+            for _ in 0..6 {
+                let (priority, item) = rx.next().await.unwrap();
+                a.push((priority.value(), item));
+            }
+            a
+        });
+
+        assert_eq!(res, vec![(0, 5), (1, 3), (2, 2), (2, 6), (3, 1), (3, 4)]);
+    }
+
+    #[test]
+    fn test_priority_buffer_beyond_levels() {
+        let (tx, mut rx) = channel_with_priority_levels::<u32>(8, 3);
+        let mut buffer = PriorityBuffer::new(tx);
+
+        buffer.push(Priority::new(200), 1);
+        buffer.push(Priority::new(2), 2);
+        buffer.push_high(3);
+
+        // no buffer is created beyond the levels of channel
+        assert_eq!(buffer.buffers.len(), 3);
+        assert_eq!(buffer.len(), 3);
+
+        let send = |cx: &mut Context<'_>| -> Poll<()> {
+            buffer.try_send(cx);
+            Poll::Ready(())
+        };
+        block_on(poll_fn(send));
+
+        let res: Vec<_> = block_on(async {
+            let mut a = Vec::new();
+            for _ in 0..3 {
+                let (priority, item) = rx.next().await.unwrap();
+                a.push((priority.value(), item));
+            }
+            a
+        });
+
+        assert_eq!(res, vec![(0, 3), (2, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_priority_buffer_clone() {
+        let (tx, mut rx) = channel_with_priority_levels::<u32>(8, 3);
+        let buffer = PriorityBuffer::new(tx);
+        buffer.clone().push(Priority::new(2), 0);
+
+        // the clone has the levels of sender and an empty buffer
+        let mut buffer = buffer.clone();
+        assert_eq!(buffer.buffers.len(), 3);
+        assert!(buffer.is_empty());
+
+        buffer.push(Priority::new(2), 1);
+        buffer.push_high(2);
+
+        let send = |cx: &mut Context<'_>| -> Poll<()> {
+            buffer.try_send(cx);
+            Poll::Ready(())
+        };
+        block_on(poll_fn(send));
+
+        let res: Vec<_> = block_on(async {
+            let mut a = Vec::new();
+            for _ in 0..2 {
+                let (priority, item) = rx.next().await.unwrap();
+                a.push((priority.value(), item));
+            }
+            a
+        });
+
+        assert_eq!(res, vec![(0, 2), (2, 1)]);
+    }
+
+    #[test]
+    fn test_priority_buffer_disconnect() {
+        let (tx, rx) = channel_with_priority_levels::<u32>(8, 3);
+        drop(rx);
+        let mut buffer = PriorityBuffer::new(tx);
+
+        buffer.push(Priority::new(2), 1);
+        buffer.push_high(2);
+
+        let send = |cx: &mut Context<'_>| -> Poll<SendResult> {
+            Poll::Ready(buffer.try_send_keep_unsent(cx))
+        };
+        assert!(matches!(block_on(poll_fn(send)), SendResult::Disconnect));
+        assert_eq!(buffer.drain().collect::<Vec<_>>(), vec![2, 1]);
+
+        buffer.push_high(3);
+        let send = |cx: &mut Context<'_>| -> Poll<SendResult> { Poll::Ready(buffer.try_send(cx)) };
+        assert!(matches!(block_on(poll_fn(send)), SendResult::Disconnect));
+        assert!(buffer.is_empty());
     }
 
     #[test]
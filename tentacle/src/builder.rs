@@ -3,8 +3,10 @@ use std::{collections::HashMap, io, sync::Arc, time::Duration};
 use tokio_util::codec::LengthDelimitedCodec;
 
 use crate::{
-    protocol_select::SelectFn,
-    secio::SecioKeyPair,
+    channel::MAX_PRIORITY_LEVELS,
+    muxer::{MplexConfig, Muxer},
+    protocol_select::{encode_protocol_list, ProtocolInfo, SelectFn},
+    secio::{handshake::Config as SecioConfig, pnet::PreSharedKey, PublicKey, SecioKeyPair},
     service::{
         config::{BlockingFlag, Meta, ServiceConfig},
         ProtocolHandle, ProtocolMeta, Service,
@@ -18,7 +20,6 @@ use crate::{
 /// Builder for Service
 pub struct ServiceBuilder {
     inner: HashMap<ProtocolId, ProtocolMeta>,
-    key_pair: Option<SecioKeyPair>,
     forever: bool,
     config: ServiceConfig,
 }
@@ -30,11 +31,28 @@ impl ServiceBuilder {
     }
 
     /// Combine the configuration of this builder with service handle to create a Service.
-    pub fn build<H>(self, handle: H) -> Service<H>
+    pub fn build<H>(mut self, handle: H) -> Service<H>
     where
         H: ServiceHandle + Unpin,
     {
-        Service::new(self.inner, handle, self.key_pair, self.forever, self.config)
+        if self.config.announce_protocols {
+            let proto_infos = self
+                .inner
+                .values()
+                .map(|meta| ProtocolInfo::new(&meta.name(), meta.support_versions()))
+                .collect();
+            self.config.secio_config = self
+                .config
+                .secio_config
+                .take()
+                .map(|config| config.early_data(encode_protocol_list(proto_infos)));
+        }
+        let key_pair = self
+            .config
+            .secio_config
+            .as_ref()
+            .map(|config| config.key_pair().clone());
+        Service::new(self.inner, handle, key_pair, self.forever, self.config)
     }
 
     /// Insert a custom protocol
@@ -51,7 +69,47 @@ impl ServiceBuilder {
     ///
     /// If you do not need encrypted communication, you do not need to call this method
     pub fn key_pair(mut self, key_pair: SecioKeyPair) -> Self {
-        self.key_pair = Some(key_pair);
+        self.config.secio_config = Some(SecioConfig::new(key_pair));
+        self
+    }
+
+    /// Enable encrypted communication mode with a full secio config, which can override
+    /// the key agreements, ciphers and digests proposed during the handshake,
+    /// e.g. ChaCha20 only on the nodes without AES hardware acceleration.
+    ///
+    /// It replaces the one set by `key_pair`, and vice versa
+    pub fn secio_config(mut self, config: SecioConfig) -> Self {
+        self.config.secio_config = Some(config);
+        self
+    }
+
+    /// Enable private network mode, every connection is encrypted by the pre-shared key
+    /// before the secio handshake, peers without the same key are refused
+    /// with `HandshakeErrorKind::PreSharedKeyError`.
+    pub fn pre_shared_key(mut self, key: PreSharedKey) -> Self {
+        self.config.pre_shared_key = Some(key);
+        self
+    }
+
+    /// Send the local protocol list to remote during the secio handshake, default is false
+    ///
+    /// The list of remote is exposed by `SessionContext::remote_protocols`, and the outbound
+    /// sessions only open the protocols that remote supports. It is ignored by the peers
+    /// which don't support it, and it is useless when encryption is disabled.
+    pub fn announce_protocols(mut self, enable: bool) -> Self {
+        self.config.announce_protocols = enable;
+        self
+    }
+
+    /// Check the protocol list of remote before the session is established,
+    /// the connection is refused with `HandshakeErrorKind::RemoteProtocolsRejected` when it returns false
+    ///
+    /// The list is None if remote doesn't announce it
+    pub fn verify_remote_protocols<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&PublicKey, Option<&[ProtocolInfo]>) -> bool + Send + Sync + 'static,
+    {
+        self.config.verify_remote_protocols = Some(Arc::new(f));
         self
     }
 
@@ -79,6 +137,12 @@ impl ServiceBuilder {
         self
     }
 
+    /// Mplex config for service, only used when mplex is negotiated
+    pub fn mplex_config(mut self, config: MplexConfig) -> Self {
+        self.config.session_config.mplex_config = config;
+        self
+    }
+
     /// Secio max frame length
     ///
     /// Panic when max_frame_length < yamux_max_window_size
@@ -95,6 +159,23 @@ impl ServiceBuilder {
         self
     }
 
+    /// Multiplexers supported by service, in order of preference, default is yamux only
+    ///
+    /// The multiplexer is negotiated after the secio handshake unless only yamux is used,
+    /// so both sides must be configured with the same setting: either both yamux only,
+    /// or both with a multiplexer list
+    ///
+    /// Note: any list other than `[Muxer::Yamux]` breaks compatibility with older nodes and
+    /// nodes with the default setting, they speak bare yamux without negotiation, there is
+    /// no fallback and the handshake fails even if yamux is in the list
+    ///
+    /// Panic when muxers is empty
+    pub fn muxers(mut self, muxers: Vec<Muxer>) -> Self {
+        assert!(!muxers.is_empty());
+        self.config.muxers = muxers;
+        self
+    }
+
     /// Set send buffer size, default is 24Mb
     pub fn set_send_buffer_size(mut self, size: usize) -> Self {
         self.config.session_config.send_buffer_size = size;
@@ -107,6 +188,19 @@ impl ServiceBuilder {
         self
     }
 
+    /// Set the number of message priority levels, default is 2
+    ///
+    /// Level 0 is used by `quick_send_message` series and level 1 by `send_message` series,
+    /// the other levels can be used by `send_message_with_priority` series, messages whose
+    /// priority beyond the levels are treated as the lowest level
+    ///
+    /// Panic when levels is 0 or greater than 16
+    pub fn priority_levels(mut self, levels: u8) -> Self {
+        assert!(levels > 0 && levels <= MAX_PRIORITY_LEVELS);
+        self.config.session_config.priority_levels = levels;
+        self
+    }
+
     /// If session is close by remote, did you want to keep unreceived message as more as possible
     /// default is false
     pub fn keep_buffer(mut self, keep: bool) -> Self {
@@ -170,7 +264,6 @@ impl Default for ServiceBuilder {
     fn default() -> Self {
         ServiceBuilder {
             inner: HashMap::new(),
-            key_pair: None,
             forever: false,
             config: ServiceConfig::default(),
         }
@@ -182,6 +275,8 @@ pub(crate) type CodecFn = Box<dyn Fn() -> Box<dyn Codec + Send + 'static> + Send
 pub(crate) type SessionHandleFn =
     Box<dyn FnMut() -> ProtocolHandle<Box<dyn SessionProtocol + Send + 'static + Unpin>> + Send>;
 pub(crate) type SelectVersionFn = Box<dyn Fn() -> Option<SelectFn<String>> + Send + Sync + 'static>;
+pub(crate) type VerifyProtocolsFn =
+    Arc<dyn Fn(&PublicKey, Option<&[ProtocolInfo]>) -> bool + Send + Sync + 'static>;
 pub(crate) type BeforeReceiveFn = Box<dyn Fn() -> Option<BeforeReceive> + Send + Sync + 'static>;
 pub(crate) type BeforeReceive =
     Box<dyn Fn(bytes::BytesMut) -> Result<bytes::Bytes, io::Error> + Send + 'static>;
@@ -197,6 +292,7 @@ pub struct MetaBuilder {
     select_version: SelectVersionFn,
     before_send: Option<Box<dyn Fn(bytes::Bytes) -> bytes::Bytes + Send + 'static>>,
     before_receive: BeforeReceiveFn,
+    stream_priority: Option<u8>,
     flag: BlockingFlag,
     spawn: Option<Box<dyn ProtocolSpawn + Send + Sync + 'static>>,
 }
@@ -308,6 +404,16 @@ impl MetaBuilder {
         self
     }
 
+    /// Write priority of the protocol sub stream, lower value is higher priority,
+    /// default is None, which uses the default priority of multiplexer
+    ///
+    /// The sub streams of a session with the same priority are written in turn,
+    /// the ones with higher priority are always written first. Only yamux supports it.
+    pub fn stream_priority(mut self, priority: u8) -> Self {
+        self.stream_priority = Some(priority);
+        self
+    }
+
     /// Set a flag to control function behavior
     pub fn flag(mut self, flag: BlockingFlag) -> Self {
         self.flag = flag;
@@ -327,6 +433,7 @@ impl MetaBuilder {
             codec: self.codec,
             select_version: self.select_version,
             before_receive: self.before_receive,
+            stream_priority: self.stream_priority,
             spawn: self.spawn,
         };
         ProtocolMeta {
@@ -351,6 +458,7 @@ impl Default for MetaBuilder {
             select_version: Box::new(|| None),
             before_send: None,
             before_receive: Box::new(|| None),
+            stream_priority: None,
             flag: BlockingFlag::default(),
             spawn: None,
         }
@@ -1,6 +1,7 @@
 use crate::channel::{
-    decode_state, encode_state, queue::Queue, Priority, SendError, SendErrorKind, TryRecvError,
-    TrySendError, INIT_STATE, MAX_BUFFER, MAX_CAPACITY, OPEN_MASK,
+    decode_state, encode_state, priority_queues, queue::Queue, queue_index, Priority, SendError,
+    SendErrorKind, TryRecvError, TrySendError, DEFAULT_PRIORITY_LEVELS, INIT_STATE, MAX_BUFFER,
+    MAX_CAPACITY, OPEN_MASK,
 };
 
 use futures::{
@@ -31,6 +32,14 @@ use std::{
 /// [`Stream`](futures_core::stream::Stream) trait, while [`Sender`](Sender) implements
 /// `Sink`.
 pub fn channel<T>(buffer: usize) -> (Sender<T>, Receiver<T>) {
+    channel_with_priority_levels(buffer, DEFAULT_PRIORITY_LEVELS)
+}
+
+/// Creates a bounded mpsc channel with the specified number of priority levels.
+///
+/// Messages of higher priority are always received first, the priority beyond
+/// the levels is treated as the lowest level.
+pub fn channel_with_priority_levels<T>(buffer: usize, levels: u8) -> (Sender<T>, Receiver<T>) {
     // Check that the requested buffer size does not exceed the maximum buffer
     // size permitted by the system.
     assert!(buffer < MAX_BUFFER, "requested buffer size too large");
@@ -38,8 +47,7 @@ pub fn channel<T>(buffer: usize) -> (Sender<T>, Receiver<T>) {
     let inner = Arc::new(BoundedInner {
         buffer,
         state: AtomicUsize::new(INIT_STATE),
-        message_queue: Queue::new(),
-        quick_message_queue: Queue::new(),
+        message_queues: priority_queues(levels),
         parked_queue: Queue::new(),
         num_senders: AtomicUsize::new(1),
         recv_task: AtomicWaker::new(),
@@ -65,11 +73,8 @@ struct BoundedInner<T> {
     // channel as well as a flag signalling that the channel is closed.
     state: AtomicUsize,
 
-    // Atomic, FIFO queue used to send messages to the receiver
-    message_queue: Queue<T>,
-
-    // Atomic, FIFO queue used to send messages to the receiver
-    quick_message_queue: Queue<T>,
+    // Atomic, FIFO queues used to send messages to the receiver, one per priority level
+    message_queues: Vec<Queue<T>>,
 
     // Atomic, FIFO queue used to send parked task handles to the receiver.
     parked_queue: Queue<Arc<Mutex<SenderTask>>>,
@@ -209,10 +214,8 @@ impl<T> BoundedSenderInner<T> {
     // Push message to the queue and signal to the receiver
     fn queue_push_and_signal(&self, msg: T, priority: Priority) {
         // Push the message onto the message queue
-        match priority {
-            Priority::High => self.inner.quick_message_queue.push(msg),
-            Priority::Normal => self.inner.message_queue.push(msg),
-        }
+        let index = queue_index(priority, self.inner.message_queues.len());
+        self.inner.message_queues[index].push(msg);
 
         // Signal to the receiver that a message has been enqueued. If the
         // receiver is parked, this will unpark the task.
@@ -402,23 +405,24 @@ impl<T> Sender<T> {
     /// Attempts to send a message on this `Sender`, returning the message
     /// if there was an error.
     pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>> {
-        if let Some(inner) = &self.0 {
-            inner.try_send(msg, Priority::Normal)
-        } else {
-            Err(TrySendError {
-                err: SendError {
-                    kind: SendErrorKind::Disconnected,
-                },
-                val: msg,
-            })
-        }
+        self.try_send_with_priority(msg, Priority::NORMAL)
     }
 
     /// Attempts to send a message on this `Sender`, returning the message
     /// if there was an error.
     pub fn try_quick_send(&self, msg: T) -> Result<(), TrySendError<T>> {
+        self.try_send_with_priority(msg, Priority::HIGH)
+    }
+
+    /// Attempts to send a message with the specified priority on this `Sender`,
+    /// returning the message if there was an error.
+    pub fn try_send_with_priority(
+        &self,
+        msg: T,
+        priority: Priority,
+    ) -> Result<(), TrySendError<T>> {
         if let Some(inner) = &self.0 {
-            inner.try_send(msg, Priority::High)
+            inner.try_send(msg, priority)
         } else {
             Err(TrySendError {
                 err: SendError {
@@ -447,6 +451,16 @@ impl<T> Sender<T> {
         self.try_quick_send(msg).map_err(|e| e.err)
     }
 
+    /// Send a message with the specified priority on the channel.
+    ///
+    /// This function should only be called after
+    /// [`poll_ready`](Sender::poll_ready) has reported that the channel is
+    /// ready to receive a message.
+    pub fn start_send_with_priority(&self, msg: T, priority: Priority) -> Result<(), SendError> {
+        self.try_send_with_priority(msg, priority)
+            .map_err(|e| e.err)
+    }
+
     /// Polls the channel to determine if there is guaranteed capacity to send
     /// at least one item without waiting.
     ///
@@ -474,6 +488,14 @@ impl<T> Sender<T> {
             .unwrap_or(true)
     }
 
+    /// The number of priority levels of this channel
+    pub fn priority_levels(&self) -> usize {
+        self.0
+            .as_ref()
+            .map(|inner| inner.inner.message_queues.len())
+            .unwrap_or(1)
+    }
+
     /// Closes this channel from the sender side, preventing any new messages.
     pub fn close_channel(&mut self) {
         if let Some(inner) = &mut self.0 {
@@ -553,13 +575,16 @@ impl<T> Receiver<T> {
     }
 
     fn next_message(&mut self) -> Poll<Option<(Priority, T)>> {
-        let inner = self
+        let levels = self
             .inner
-            .as_mut()
-            .expect("Receiver::next_message called after `None`");
-        // Pop off a message
-        match unsafe { inner.quick_message_queue.pop_spin() } {
-            Some(msg) => {
+            .as_ref()
+            .expect("Receiver::next_message called after `None`")
+            .message_queues
+            .len();
+        // Pop off a message, from the highest priority to the lowest
+        for level in 0..levels {
+            let msg = unsafe { self.inner.as_ref().unwrap().message_queues[level].pop_spin() };
+            if let Some(msg) = msg {
                 // If there are any parked task handles in the parked queue,
                 // pop one and unpark it.
                 self.unpark_one();
@@ -567,40 +592,25 @@ impl<T> Receiver<T> {
                 // Decrement number of messages
                 self.dec_num_messages();
 
-                Poll::Ready(Some((Priority::High, msg)))
+                return Poll::Ready(Some((Priority::new(level as u8), msg)));
             }
-            None => {
-                match unsafe { inner.message_queue.pop_spin() } {
-                    Some(msg) => {
-                        // If there are any parked task handles in the parked queue,
-                        // pop one and unpark it.
-                        self.unpark_one();
-
-                        // Decrement number of messages
-                        self.dec_num_messages();
+        }
 
-                        Poll::Ready(Some((Priority::Normal, msg)))
-                    }
-                    None => {
-                        let state = decode_state(inner.state.load(SeqCst));
-                        if state.is_closed() {
-                            // If closed flag is set AND there are no pending messages
-                            // it means end of stream
-                            self.inner = None;
-                            Poll::Ready(None)
-                        } else {
-                            // If queue is open, we need to return Pending
-                            // to be woken up when new messages arrive.
-                            // If queue is closed but num_messages is non-zero,
-                            // it means that senders updated the state,
-                            // but didn't put message to queue yet,
-                            // so we need to park until sender unparks the task
-                            // after queueing the message.
-                            Poll::Pending
-                        }
-                    }
-                }
-            }
+        let state = decode_state(self.inner.as_ref().unwrap().state.load(SeqCst));
+        if state.is_closed() {
+            // If closed flag is set AND there are no pending messages
+            // it means end of stream
+            self.inner = None;
+            Poll::Ready(None)
+        } else {
+            // If queue is open, we need to return Pending
+            // to be woken up when new messages arrive.
+            // If queue is closed but num_messages is non-zero,
+            // it means that senders updated the state,
+            // but didn't put message to queue yet,
+            // so we need to park until sender unparks the task
+            // after queueing the message.
+            Poll::Pending
         }
     }
 
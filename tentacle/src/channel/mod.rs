@@ -12,7 +12,7 @@ mod tests;
 mod unbound;
 
 pub(crate) mod mpsc {
-    pub use super::bound::{channel, Receiver, Sender};
+    pub use super::bound::{channel, channel_with_priority_levels, Receiver, Sender};
     pub use super::unbound::{unbounded, UnboundedReceiver, UnboundedSender};
     pub use super::{Priority, SendError, TrySendError};
 }
@@ -169,19 +169,72 @@ impl fmt::Display for TryRecvError {
     }
 }
 
-/// Priority for send
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
-pub enum Priority {
-    High,
-    Normal,
-}
+/// Priority for send, the smaller the level, the higher the priority
+///
+/// Messages of the same priority keep the order in which they were sent
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Priority(u8);
 
 impl Priority {
+    /// The highest priority, used by `quick_send` series
+    pub const HIGH: Priority = Priority(0);
+    /// The default priority, used by `send` series
+    pub const NORMAL: Priority = Priority(1);
+
+    /// New a priority by level
+    pub const fn new(level: u8) -> Self {
+        Priority(level)
+    }
+
+    /// Get inner value
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    /// Is the highest priority
     #[inline]
     pub fn is_high(self) -> bool {
-        match self {
-            Priority::High => true,
-            Priority::Normal => false,
-        }
+        self == Priority::HIGH
     }
 }
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::NORMAL
+    }
+}
+
+impl From<u8> for Priority {
+    fn from(level: u8) -> Self {
+        Priority(level)
+    }
+}
+
+/// The number of priority levels of a channel created by `channel`/`unbounded`
+pub(crate) const DEFAULT_PRIORITY_LEVELS: u8 = 2;
+
+/// The max number of priority levels of a channel
+pub(crate) const MAX_PRIORITY_LEVELS: u8 = 16;
+
+/// Clamp the number of priority levels to `1..=MAX_PRIORITY_LEVELS`
+#[inline]
+pub(crate) fn bounded_levels(levels: u8) -> u8 {
+    match levels {
+        0 => 1,
+        levels => ::std::cmp::min(levels, MAX_PRIORITY_LEVELS),
+    }
+}
+
+/// Create the message queues of each priority level
+fn priority_queues<T>(levels: u8) -> Vec<queue::Queue<T>> {
+    (0..bounded_levels(levels))
+        .map(|_| queue::Queue::new())
+        .collect()
+}
+
+/// The index of queue which the message of this priority will be pushed to,
+/// the priority beyond the levels of channel will be treated as the lowest level
+#[inline]
+pub(crate) fn queue_index(priority: Priority, levels: usize) -> usize {
+    ::std::cmp::min(priority.value() as usize, levels - 1)
+}
@@ -1,4 +1,4 @@
-use super::{bound::Sender, unbound::UnboundedSender, SendError, TrySendError};
+use super::{bound::Sender, unbound::UnboundedSender, Priority, SendError, TrySendError};
 use futures::{
     ready,
     task::{Context, Poll},
@@ -76,56 +76,72 @@ impl<T> Sink<T> for &UnboundedSender<T> {
     }
 }
 
-/// Since the priority channel comes with two sending methods, the normal sink uses normal level sending,
-/// while the `QuickSinkExt` will use the quick series interface as the sending method.
+/// Since the priority channel comes with multiple priority levels, the normal sink uses normal level sending,
+/// while the `QuickSinkExt` will use the quick series interface or the specified priority as the sending method.
 /// Currently, only simple send async is implemented.
 pub trait QuickSinkExt<Item>: Sink<Item> {
-    fn start_quick_send(&mut self, item: Item) -> Result<(), Self::Error>;
+    fn start_send_with_priority(
+        &mut self,
+        item: Item,
+        priority: Priority,
+    ) -> Result<(), Self::Error>;
 
-    fn quick_send(&mut self, item: Item) -> QuickSend<'_, Self, Item>
+    fn start_quick_send(&mut self, item: Item) -> Result<(), Self::Error> {
+        self.start_send_with_priority(item, Priority::HIGH)
+    }
+
+    fn quick_send(&mut self, item: Item) -> PrioritySend<'_, Self, Item>
+    where
+        Self: Unpin,
+    {
+        PrioritySend::new(self, item, Priority::HIGH)
+    }
+
+    fn send_with_priority(&mut self, item: Item, priority: Priority) -> PrioritySend<'_, Self, Item>
     where
         Self: Unpin,
     {
-        QuickSend::new(self, item)
+        PrioritySend::new(self, item, priority)
     }
 }
 
 impl<T> QuickSinkExt<T> for Sender<T> {
-    fn start_quick_send(&mut self, msg: T) -> Result<(), Self::Error> {
-        (*self).start_quick_send(msg)
+    fn start_send_with_priority(&mut self, msg: T, priority: Priority) -> Result<(), Self::Error> {
+        (*self).start_send_with_priority(msg, priority)
     }
 }
 
 impl<T> QuickSinkExt<T> for UnboundedSender<T> {
-    fn start_quick_send(&mut self, msg: T) -> Result<(), Self::Error> {
-        UnboundedSender::start_quick_send(&self, msg)
+    fn start_send_with_priority(&mut self, msg: T, priority: Priority) -> Result<(), Self::Error> {
+        UnboundedSender::start_send_with_priority(&self, msg, priority)
     }
 }
 
 impl<T> QuickSinkExt<T> for &UnboundedSender<T> {
-    fn start_quick_send(&mut self, msg: T) -> Result<(), Self::Error> {
-        self.unbounded_quick_send(msg)
-            .map_err(TrySendError::into_send_error)
+    fn start_send_with_priority(&mut self, msg: T, priority: Priority) -> Result<(), Self::Error> {
+        UnboundedSender::start_send_with_priority(self, msg, priority)
     }
 }
 
-pub struct QuickSend<'a, Si: ?Sized, Item> {
+pub struct PrioritySend<'a, Si: ?Sized, Item> {
     sink: &'a mut Si,
     item: Option<Item>,
+    priority: Priority,
 }
 
-impl<Si: Unpin + ?Sized, Item> Unpin for QuickSend<'_, Si, Item> {}
+impl<Si: Unpin + ?Sized, Item> Unpin for PrioritySend<'_, Si, Item> {}
 
-impl<'a, Si: QuickSinkExt<Item> + Unpin + ?Sized, Item> QuickSend<'a, Si, Item> {
-    pub(super) fn new(sink: &'a mut Si, item: Item) -> Self {
-        QuickSend {
+impl<'a, Si: QuickSinkExt<Item> + Unpin + ?Sized, Item> PrioritySend<'a, Si, Item> {
+    pub(super) fn new(sink: &'a mut Si, item: Item, priority: Priority) -> Self {
+        PrioritySend {
             sink,
             item: Some(item),
+            priority,
         }
     }
 }
 
-impl<Si: QuickSinkExt<Item> + Unpin + ?Sized, Item> Future for QuickSend<'_, Si, Item> {
+impl<Si: QuickSinkExt<Item> + Unpin + ?Sized, Item> Future for PrioritySend<'_, Si, Item> {
     type Output = Result<(), Si::Error>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
@@ -133,7 +149,9 @@ impl<Si: QuickSinkExt<Item> + Unpin + ?Sized, Item> Future for QuickSend<'_, Si,
         if let Some(item) = this.item.take() {
             let mut sink = Pin::new(&mut this.sink);
             match sink.as_mut().poll_ready(cx)? {
-                Poll::Ready(()) => sink.as_mut().start_quick_send(item)?,
+                Poll::Ready(()) => sink
+                    .as_mut()
+                    .start_send_with_priority(item, this.priority)?,
                 Poll::Pending => {
                     this.item = Some(item);
                     return Poll::Pending;
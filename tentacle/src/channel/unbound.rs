@@ -1,6 +1,7 @@
 use crate::channel::{
-    decode_state, encode_state, queue::Queue, Priority, SendError, SendErrorKind, TryRecvError,
-    TrySendError, INIT_STATE, MAX_BUFFER, MAX_CAPACITY, OPEN_MASK,
+    decode_state, encode_state, priority_queues, queue::Queue, queue_index, Priority, SendError,
+    SendErrorKind, TryRecvError, TrySendError, DEFAULT_PRIORITY_LEVELS, INIT_STATE, MAX_BUFFER,
+    MAX_CAPACITY, OPEN_MASK,
 };
 use futures::{
     stream::{FusedStream, Stream},
@@ -28,8 +29,7 @@ use std::{
 pub fn unbounded<T>() -> (UnboundedSender<T>, UnboundedReceiver<T>) {
     let inner = Arc::new(UnboundedInner {
         state: AtomicUsize::new(INIT_STATE),
-        message_queue: Queue::new(),
-        quick_message_queue: Queue::new(),
+        message_queues: priority_queues(DEFAULT_PRIORITY_LEVELS),
         num_senders: AtomicUsize::new(1),
         recv_task: AtomicWaker::new(),
     });
@@ -49,11 +49,8 @@ struct UnboundedInner<T> {
     // channel as well as a flag signalling that the channel is closed.
     state: AtomicUsize,
 
-    // Atomic, FIFO queue used to send messages to the receiver
-    message_queue: Queue<T>,
-
-    // Atomic, FIFO queue used to send messages to the receiver, quick queue
-    quick_message_queue: Queue<T>,
+    // Atomic, FIFO queues used to send messages to the receiver, one per priority level
+    message_queues: Vec<Queue<T>>,
 
     // Number of senders in existence
     num_senders: AtomicUsize,
@@ -101,10 +98,8 @@ impl<T> UnboundedSenderInner<T> {
     // Push message to the queue and signal to the receiver
     fn queue_push_and_signal(&self, msg: T, priority: Priority) {
         // Push the message onto the message queue
-        match priority {
-            Priority::High => self.inner.quick_message_queue.push(msg),
-            Priority::Normal => self.inner.message_queue.push(msg),
-        }
+        let index = queue_index(priority, self.inner.message_queues.len());
+        self.inner.message_queues[index].push(msg);
 
         // Signal to the receiver that a message has been enqueued. If the
         // receiver is parked, this will unpark the task.
@@ -285,7 +280,7 @@ impl<T> UnboundedSender<T> {
     /// This method should only be called after `poll_ready` has been used to
     /// verify that the channel is ready to receive a message.
     pub fn start_send(&self, msg: T) -> Result<(), SendError> {
-        self.do_send_nb(msg, Priority::Normal).map_err(|e| e.err)
+        self.do_send_nb(msg, Priority::NORMAL).map_err(|e| e.err)
     }
 
     /// Send a message on the channel.
@@ -293,7 +288,15 @@ impl<T> UnboundedSender<T> {
     /// This method should only be called after `poll_ready` has been used to
     /// verify that the channel is ready to receive a message.
     pub fn start_quick_send(&self, msg: T) -> Result<(), SendError> {
-        self.do_send_nb(msg, Priority::High).map_err(|e| e.err)
+        self.do_send_nb(msg, Priority::HIGH).map_err(|e| e.err)
+    }
+
+    /// Send a message with the specified priority on the channel.
+    ///
+    /// This method should only be called after `poll_ready` has been used to
+    /// verify that the channel is ready to receive a message.
+    pub fn start_send_with_priority(&self, msg: T, priority: Priority) -> Result<(), SendError> {
+        self.do_send_nb(msg, priority).map_err(|e| e.err)
     }
 
     /// Sends a message along this channel.
@@ -302,7 +305,7 @@ impl<T> UnboundedSender<T> {
     /// by ensuring the return type reflects that the channel is always ready to
     /// receive messages.
     pub fn unbounded_send(&self, msg: T) -> Result<(), TrySendError<T>> {
-        self.do_send_nb(msg, Priority::Normal)
+        self.do_send_nb(msg, Priority::NORMAL)
     }
 
     /// Sends a message along this channel.
@@ -311,7 +314,7 @@ impl<T> UnboundedSender<T> {
     /// by ensuring the return type reflects that the channel is always ready to
     /// receive messages.
     pub fn unbounded_quick_send(&self, msg: T) -> Result<(), TrySendError<T>> {
-        self.do_send_nb(msg, Priority::High)
+        self.do_send_nb(msg, Priority::HIGH)
     }
 
     /// Returns whether the senders send to the same receiver.
@@ -378,46 +381,38 @@ impl<T> UnboundedReceiver<T> {
     }
 
     fn next_message(&mut self) -> Poll<Option<(Priority, T)>> {
-        let inner = self
+        let levels = self
             .inner
-            .as_mut()
-            .expect("Receiver::next_message called after `None`");
-
-        match unsafe { inner.quick_message_queue.pop_spin() } {
-            Some(msg) => {
+            .as_ref()
+            .expect("Receiver::next_message called after `None`")
+            .message_queues
+            .len();
+        // Pop off a message, from the highest priority to the lowest
+        for level in 0..levels {
+            let msg = unsafe { self.inner.as_ref().unwrap().message_queues[level].pop_spin() };
+            if let Some(msg) = msg {
                 // Decrement number of messages
                 self.dec_num_messages();
 
-                Poll::Ready(Some((Priority::High, msg)))
+                return Poll::Ready(Some((Priority::new(level as u8), msg)));
             }
-            None => {
-                match unsafe { inner.message_queue.pop_spin() } {
-                    Some(msg) => {
-                        // Decrement number of messages
-                        self.dec_num_messages();
+        }
 
-                        Poll::Ready(Some((Priority::Normal, msg)))
-                    }
-                    None => {
-                        let state = decode_state(inner.state.load(SeqCst));
-                        if state.is_closed() {
-                            // If closed flag is set AND there are no pending messages
-                            // it means end of stream
-                            self.inner = None;
-                            Poll::Ready(None)
-                        } else {
-                            // If queue is open, we need to return Pending
-                            // to be woken up when new messages arrive.
-                            // If queue is closed but num_messages is non-zero,
-                            // it means that senders updated the state,
-                            // but didn't put message to queue yet,
-                            // so we need to park until sender unparks the task
-                            // after queueing the message.
-                            Poll::Pending
-                        }
-                    }
-                }
-            }
+        let state = decode_state(self.inner.as_ref().unwrap().state.load(SeqCst));
+        if state.is_closed() {
+            // If closed flag is set AND there are no pending messages
+            // it means end of stream
+            self.inner = None;
+            Poll::Ready(None)
+        } else {
+            // If queue is open, we need to return Pending
+            // to be woken up when new messages arrive.
+            // If queue is closed but num_messages is non-zero,
+            // it means that senders updated the state,
+            // but didn't put message to queue yet,
+            // so we need to park until sender unparks the task
+            // after queueing the message.
+            Poll::Pending
         }
     }
 
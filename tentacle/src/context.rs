@@ -1,5 +1,5 @@
 use bytes::Bytes;
-use futures::prelude::*;
+use futures::{channel::oneshot, prelude::*, task::AtomicWaker};
 use std::{
     collections::HashMap,
     ops::{Deref, DerefMut},
@@ -7,8 +7,8 @@ use std::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
-    task::Context,
-    time::Duration,
+    task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use crate::{
@@ -16,10 +16,15 @@ use crate::{
     channel::{mpsc, mpsc::Priority},
     error::SendErrorKind,
     multiaddr::Multiaddr,
-    protocol_select::ProtocolInfo,
-    secio::{PublicKey, SecioKeyPair},
-    service::{event::ServiceTask, ServiceControl, SessionType, TargetProtocol, TargetSession},
+    muxer::MuxerControl,
+    protocol_select::{decode_protocol_list, ProtocolInfo},
+    secio::{envelope::SignedEnvelope, handshake::NegotiatedAlgorithms, PublicKey, SecioKeyPair},
+    service::{
+        delivery::DeliveryNotify, event::ServiceTask, DeliveryAck, ServiceControl, SessionType,
+        TargetProtocol, TargetSession,
+    },
     session::SessionEvent,
+    yamux::Rtt,
     ProtocolId, SessionId,
 };
 
@@ -40,19 +45,24 @@ impl SessionController {
     }
 
     pub(crate) fn push(&mut self, priority: Priority, event: SessionEvent) {
-        if priority.is_high() {
-            self.buffer.push_high(event)
-        } else {
-            self.buffer.push_normal(event)
-        }
+        self.buffer.push(priority, event)
     }
 
-    pub(crate) fn push_message(&mut self, proto_id: ProtocolId, priority: Priority, data: Bytes) {
+    pub(crate) fn push_message(
+        &mut self,
+        proto_id: ProtocolId,
+        priority: Priority,
+        data: Bytes,
+        ack: Option<DeliveryNotify>,
+        deadline: Option<Instant>,
+    ) {
         self.inner.incr_pending_data_size(data.len());
         let message_event = SessionEvent::ProtocolMessage {
             id: self.inner.id,
             proto_id,
             data,
+            ack,
+            deadline,
         };
         self.push(priority, message_event)
     }
@@ -74,26 +84,46 @@ pub struct SessionContext {
     // TODO: use reference?
     /// Remote public key
     pub remote_pubkey: Option<PublicKey>,
+    /// Key agreement, cipher and digest negotiated by secio, None if encryption is disabled
+    pub negotiated_algorithms: Option<NegotiatedAlgorithms>,
+    /// Early data sent by remote during secio handshake, it's the protocol list of remote
+    /// if remote enables `ServiceBuilder::announce_protocols`
+    pub remote_early_data: Option<Bytes>,
     pub(crate) closed: Arc<AtomicBool>,
     pending_data_size: Arc<AtomicUsize>,
+    unflushed_data_size: Arc<AtomicUsize>,
+    /// Woken when all the pending data is flushed or the session is closed
+    flushed_waker: Arc<AtomicWaker>,
+    expired_messages: Arc<AtomicUsize>,
+    muxer_control: Arc<dyn MuxerControl>,
 }
 
 impl SessionContext {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         id: SessionId,
         address: Multiaddr,
         ty: SessionType,
         remote_pubkey: Option<PublicKey>,
+        negotiated_algorithms: Option<NegotiatedAlgorithms>,
+        remote_early_data: Option<Bytes>,
         closed: Arc<AtomicBool>,
         pending_data_size: Arc<AtomicUsize>,
+        muxer_control: Arc<dyn MuxerControl>,
     ) -> SessionContext {
         SessionContext {
             id,
             address,
             ty,
             remote_pubkey,
+            negotiated_algorithms,
+            remote_early_data,
             closed,
             pending_data_size,
+            unflushed_data_size: Arc::new(AtomicUsize::new(0)),
+            flushed_waker: Arc::new(AtomicWaker::new()),
+            expired_messages: Arc::new(AtomicUsize::new(0)),
+            muxer_control,
         }
     }
 
@@ -107,6 +137,60 @@ impl SessionContext {
     pub(crate) fn decr_pending_data_size(&self, data_size: usize) {
         self.pending_data_size
             .fetch_sub(data_size, Ordering::Release);
+        self.wake_if_flushed();
+    }
+
+    // Increase when data sent to underlying Yamux Stream
+    pub(crate) fn incr_unflushed_data_size(&self, data_size: usize) {
+        self.unflushed_data_size
+            .fetch_add(data_size, Ordering::Release);
+    }
+
+    // Decrease when underlying Yamux Stream flushed or closed
+    pub(crate) fn decr_unflushed_data_size(&self, data_size: usize) {
+        self.unflushed_data_size
+            .fetch_sub(data_size, Ordering::Release);
+        self.wake_if_flushed();
+    }
+
+    fn is_flushed(&self) -> bool {
+        self.pending_data_size() == 0 && self.unflushed_data_size.load(Ordering::Acquire) == 0
+    }
+
+    fn wake_if_flushed(&self) {
+        if self.is_flushed() {
+            self.flushed_waker.wake();
+        }
+    }
+
+    // Mark the session closed, the one waiting for flush is woken
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.flushed_waker.wake();
+    }
+
+    // Ready when all the pending data is flushed to underlying Yamux Stream,
+    // or the session is closed, used by graceful shutdown
+    pub(crate) fn poll_flushed(&self, cx: &mut Context) -> Poll<()> {
+        self.flushed_waker.register(cx.waker());
+        if self.closed() || self.is_flushed() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    // Called when a message reached its deadline before sent to underlying Yamux Stream
+    pub(crate) fn drop_expired_message(&self, data_size: usize) {
+        self.decr_pending_data_size(data_size);
+        self.expired_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Protocol list announced by remote, None if remote doesn't announce it
+    pub fn remote_protocols(&self) -> Option<Vec<ProtocolInfo>> {
+        self.remote_early_data
+            .as_ref()
+            .and_then(|data| decode_protocol_list(data))
     }
 
     /// Session is closed
@@ -117,6 +201,20 @@ impl SessionContext {
     pub fn pending_data_size(&self) -> usize {
         self.pending_data_size.load(Ordering::Acquire)
     }
+    /// The number of messages dropped on this session because they expired before being sent
+    pub fn expired_messages(&self) -> usize {
+        self.expired_messages.load(Ordering::Relaxed)
+    }
+    /// Round trip time measured by yamux keepalive and `ping`,
+    /// None if no ping has been acked yet or the multiplexer can't measure it
+    pub fn rtt(&self) -> Option<Rtt> {
+        self.muxer_control.rtt()
+    }
+    /// Send a ping to remote and return the round trip time,
+    /// return error if the multiplexer doesn't support ping
+    pub fn ping(&self) -> impl Future<Output = std::io::Result<Duration>> + Send + 'static {
+        self.muxer_control.ping()
+    }
 }
 
 type Result = std::result::Result<(), SendErrorKind>;
@@ -127,6 +225,7 @@ type Result = std::result::Result<(), SendErrorKind>;
 pub struct ServiceContext {
     listens: Vec<Multiaddr>,
     key_pair: Option<SecioKeyPair>,
+    key_rotation: Option<SignedEnvelope>,
     inner: ServiceControl,
 }
 
@@ -141,6 +240,7 @@ impl ServiceContext {
         ServiceContext {
             inner: ServiceControl::new(task_sender, proto_infos, closed),
             key_pair,
+            key_rotation: None,
             listens: Vec::new(),
         }
     }
@@ -174,6 +274,32 @@ impl ServiceContext {
         self.inner.send_message_to(session_id, proto_id, data)
     }
 
+    /// Send message, it will be dropped if not sent to the underlying yamux stream within `ttl`
+    #[inline]
+    pub fn send_message_to_with_ttl(
+        &self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        data: Bytes,
+        ttl: Duration,
+    ) -> Result {
+        self.inner
+            .send_message_to_with_ttl(session_id, proto_id, data, ttl)
+    }
+
+    /// Send message, and get a future which resolves when the message has been written to
+    /// the underlying yamux stream
+    #[inline]
+    pub fn send_message_to_with_ack(
+        &self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        data: Bytes,
+    ) -> std::result::Result<DeliveryAck, SendErrorKind> {
+        self.inner
+            .send_message_to_with_ack(session_id, proto_id, data)
+    }
+
     /// Send message on quick channel
     #[inline]
     pub fn quick_send_message_to(
@@ -196,6 +322,40 @@ impl ServiceContext {
         self.inner.filter_broadcast(session_ids, proto_id, data)
     }
 
+    /// Send data to the specified protocol for the specified sessions,
+    /// it will be dropped if not sent to the underlying yamux stream within `ttl`
+    #[inline]
+    pub fn filter_broadcast_with_ttl(
+        &self,
+        session_ids: TargetSession,
+        proto_id: ProtocolId,
+        data: Bytes,
+        ttl: Duration,
+    ) -> Result {
+        self.inner
+            .filter_broadcast_with_ttl(session_ids, proto_id, data, ttl)
+    }
+
+    /// Send data to the specified protocol for the specified sessions, but skip the sessions
+    /// whose pending data size exceeds `max_pending_data_size`
+    ///
+    /// The returned receiver reports the skipped sessions
+    #[inline]
+    pub fn filter_broadcast_with_backpressure(
+        &self,
+        session_ids: TargetSession,
+        proto_id: ProtocolId,
+        data: Bytes,
+        max_pending_data_size: usize,
+    ) -> std::result::Result<oneshot::Receiver<Vec<SessionId>>, SendErrorKind> {
+        self.inner.filter_broadcast_with_backpressure(
+            session_ids,
+            proto_id,
+            data,
+            max_pending_data_size,
+        )
+    }
+
     /// Send data to the specified protocol for the specified sessions on quick channel.
     #[inline]
     pub fn quick_filter_broadcast(
@@ -208,6 +368,32 @@ impl ServiceContext {
             .quick_filter_broadcast(session_ids, proto_id, data)
     }
 
+    /// Send message with the specified priority
+    #[inline]
+    pub fn send_message_to_with_priority(
+        &self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        priority: Priority,
+        data: Bytes,
+    ) -> Result {
+        self.inner
+            .send_message_to_with_priority(session_id, proto_id, priority, data)
+    }
+
+    /// Send data to the specified protocol for the specified sessions with the specified priority.
+    #[inline]
+    pub fn filter_broadcast_with_priority(
+        &self,
+        session_ids: TargetSession,
+        proto_id: ProtocolId,
+        priority: Priority,
+        data: Bytes,
+    ) -> Result {
+        self.inner
+            .filter_broadcast_with_priority(session_ids, proto_id, priority, data)
+    }
+
     /// Send a future task
     #[inline]
     pub fn future_task<T>(&self, task: T) -> Result
@@ -259,6 +445,13 @@ impl ServiceContext {
         self.key_pair.as_ref()
     }
 
+    /// Get the link from the last replaced peer id to the current one, signed by the replaced
+    /// key pair, None if the key pair has never been rotated
+    #[inline]
+    pub fn key_rotation(&self) -> Option<&SignedEnvelope> {
+        self.key_rotation.as_ref()
+    }
+
     /// Get service listen address list
     #[inline]
     pub fn listens(&self) -> &[Multiaddr] {
@@ -271,6 +464,13 @@ impl ServiceContext {
         self.listens = address_list;
     }
 
+    /// Update key pair after rotation
+    #[inline]
+    pub(crate) fn update_key_pair(&mut self, key_pair: SecioKeyPair, key_rotation: SignedEnvelope) {
+        self.key_pair = Some(key_pair);
+        self.key_rotation = Some(key_rotation);
+    }
+
     /// Set a service notify token
     pub fn set_service_notify(
         &self,
@@ -325,10 +525,22 @@ impl ServiceContext {
         self.inner.shutdown()
     }
 
+    /// Shutdown service gracefully, wait for all sessions to flush the pending messages,
+    /// at most `timeout`
+    ///
+    /// The returned receiver will be notified when the service has been shutdown
+    pub fn shutdown_graceful(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<oneshot::Receiver<()>, SendErrorKind> {
+        self.inner.shutdown_graceful(timeout)
+    }
+
     pub(crate) fn clone_self(&self) -> Self {
         ServiceContext {
             inner: self.inner.clone(),
             key_pair: self.key_pair.clone(),
+            key_rotation: self.key_rotation.clone(),
             listens: self.listens.clone(),
         }
     }
@@ -379,6 +591,28 @@ impl<'a> ProtocolContextMutRef<'a> {
         self.inner.send_message_to(self.session.id, proto_id, data)
     }
 
+    /// Send message to current protocol current session, and get a future which resolves
+    /// when the message has been written to the underlying yamux stream
+    ///
+    /// It is useful for request/response code which want to time out precisely
+    #[inline]
+    pub fn send_message_with_ack(
+        &self,
+        data: Bytes,
+    ) -> std::result::Result<DeliveryAck, SendErrorKind> {
+        let proto_id = self.proto_id();
+        self.inner
+            .send_message_to_with_ack(self.session.id, proto_id, data)
+    }
+
+    /// Send message to current protocol current session with the specified priority
+    #[inline]
+    pub fn send_message_with_priority(&self, priority: Priority, data: Bytes) -> Result {
+        let proto_id = self.proto_id();
+        self.inner
+            .send_message_to_with_priority(self.session.id, proto_id, priority, data)
+    }
+
     /// Send message to current protocol current session on quick channel
     #[inline]
     pub fn quick_send_message(&self, data: Bytes) -> Result {
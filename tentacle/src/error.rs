@@ -1,6 +1,12 @@
-use crate::{secio::error::SecioError, SessionId};
+use crate::{
+    secio::{
+        error::SecioError,
+        handshake::{HandshakeStage as SecioStage, RemotePropositions},
+    },
+    SessionId,
+};
 use multiaddr::Multiaddr;
-use std::io::Error as IOError;
+use std::{io::Error as IOError, time::Duration};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -48,15 +54,49 @@ pub enum DialerErrorKind {
     TransportError(TransportErrorKind),
 }
 
+/// Stage of the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeStage {
+    /// Private network handshake with the pre-shared key
+    PreSharedKey,
+    /// Secio handshake
+    Secio(SecioStage),
+    /// Multiplexer negotiation
+    MuxerNegotiation,
+}
+
+/// The stage where the handshake failed or stalled, and the time spent on every stage,
+/// the time is not measured on wasm
+#[derive(Debug, Clone)]
+pub struct HandshakeReport {
+    /// The failed stage
+    pub stage: HandshakeStage,
+    /// Time spent on the failed stage
+    pub elapsed: Duration,
+    /// Time spent on every finished stage, in order
+    pub steps: Vec<(HandshakeStage, Duration)>,
+    /// Algorithms proposed by remote, None if its secio proposition is not received
+    pub remote_propositions: Option<RemotePropositions>,
+}
+
 #[derive(Error, Debug)]
 /// Handshake error
 pub enum HandshakeErrorKind {
     /// Handshake timeout error
     #[error("timeout error: `{0:?}`")]
     Timeout(String),
+    /// Private network error, remote doesn't have the same pre-shared key
+    #[error("pre-shared key error: `{0:?}`")]
+    PreSharedKeyError(SecioError),
     /// Secio error
     #[error("secio error: `{0:?}`")]
     SecioError(SecioError),
+    /// Multiplexer negotiation error, no multiplexer in common or protocol error
+    #[error("muxer negotiation error: `{0:?}`")]
+    MuxerNegotiationError(IOError),
+    /// The protocol list of remote is rejected by `ServiceBuilder::verify_remote_protocols`
+    #[error("remote protocols rejected")]
+    RemoteProtocolsRejected,
 }
 
 #[derive(Error, Debug)]
@@ -71,6 +111,16 @@ pub enum ListenErrorKind {
     /// Transport error
     #[error("transport error: `{0:?}`")]
     TransportError(TransportErrorKind),
+    /// Handshake with the incoming connection failed, it used to be dropped silently
+    #[error("handshake with `{address:?}` error: `{error:?}`")]
+    HandshakeError {
+        /// Remote address
+        address: Multiaddr,
+        /// Handshake error
+        error: HandshakeErrorKind,
+        /// Where the handshake failed or stalled
+        report: HandshakeReport,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -83,3 +133,27 @@ pub enum SendErrorKind {
     #[error("would block")]
     WouldBlock,
 }
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+/// The reason why a message was not written to the underlying stream
+pub enum DeliveryErrorKind {
+    /// The session does not exist or has been closed
+    #[error("session closed")]
+    SessionClosed,
+    /// The protocol is not open on this session
+    #[error("protocol not open")]
+    ProtocolNotOpen,
+    /// The protocol sub stream was closed before the message was written
+    #[error("protocol stream closed")]
+    StreamClosed,
+    /// The message was discarded before being written, such as the session being killed by
+    /// blocked detection
+    #[error("message dropped")]
+    Dropped,
+    /// The message expired before being written to the underlying stream
+    #[error("message expired")]
+    Expired,
+    /// Write to the underlying stream error
+    #[error("io error: `{0:?}`")]
+    Io(std::io::ErrorKind),
+}
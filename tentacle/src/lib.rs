@@ -106,6 +106,8 @@ pub mod builder;
 pub mod context;
 /// Error
 pub mod error;
+/// Stream multiplexer abstraction
+pub mod muxer;
 /// Protocol handle callback stream
 pub(crate) mod protocol_handle_stream;
 /// Protocol select
@@ -0,0 +1,391 @@
+use bytes::Bytes;
+use futures::{
+    future::{self, poll_fn, BoxFuture},
+    Stream,
+};
+use log::debug;
+use std::{fmt, io, pin::Pin, sync::Arc, time::Duration};
+use tokio::prelude::{AsyncRead, AsyncWrite};
+
+use crate::{service::SessionType, yamux::Rtt};
+
+/// Mplex multiplexer
+mod mplex;
+/// Yamux multiplexer
+mod yamux;
+
+pub use self::mplex::Config as MplexConfig;
+pub(crate) use self::{mplex::MplexSession, yamux::YamuxMuxer};
+
+/// Reset code: the protocol of substream is rejected
+pub const RESET_PROTOCOL_REJECTED: u32 = 1;
+/// Reset code: the substream is aborted on error
+pub const RESET_ABORTED: u32 = 2;
+
+/// Substream opened by multiplexer, `poll_shutdown` only closes the write side
+pub trait MuxerSubstream: AsyncRead + AsyncWrite + Send + Unpin + 'static {
+    /// Reset the substream with a code, remote can get it by [`reset_code`] on reading,
+    /// multiplexers that can't carry the code reset without it
+    ///
+    /// [`reset_code`]: fn.reset_code.html
+    fn reset(&mut self, code: u32);
+
+    /// Set the write priority of the substream, lower value is higher priority,
+    /// multiplexers that can't schedule writes by priority ignore it
+    fn set_priority(&mut self, _priority: u8) {}
+}
+
+/// Return the reset code if the error is caused by a yamux RST from remote,
+/// 0 means no code, None for other errors, including the resets of multiplexers
+/// that can't carry the code
+pub fn reset_code(err: &io::Error) -> Option<u32> {
+    match err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<crate::yamux::Error>())
+    {
+        Some(crate::yamux::Error::StreamReset(code)) => Some(*code),
+        _ => None,
+    }
+}
+
+/// Boxed substream of multiplexer
+pub type BoxedSubstream = Box<dyn MuxerSubstream>;
+
+impl fmt::Debug for dyn MuxerSubstream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MuxerSubstream")
+    }
+}
+
+/// Control of a multiplexer session, it can be shared by multiple tasks
+pub trait MuxerControl: Send + Sync {
+    /// Open a new substream to remote
+    fn open_stream(&self) -> BoxFuture<'static, io::Result<BoxedSubstream>>;
+
+    /// Open a new substream to remote and send the data as soon as possible,
+    /// by default the data is written after the substream opened
+    fn open_stream_with_data(&self, data: Bytes) -> BoxFuture<'static, io::Result<BoxedSubstream>> {
+        let open = self.open_stream();
+        Box::pin(async move {
+            let mut stream = open.await?;
+            write_all(&mut stream, &data).await?;
+            Ok(stream)
+        })
+    }
+
+    /// Close the multiplexer session
+    fn close(&self) -> BoxFuture<'static, ()>;
+
+    /// Tell remote not to open new substreams, the opened substreams are not affected
+    fn go_away(&self) -> BoxFuture<'static, ()> {
+        Box::pin(future::ready(()))
+    }
+
+    /// Wait until all the data written by substreams has been sent to the underlying connection
+    fn flush(&self) -> BoxFuture<'static, ()> {
+        Box::pin(future::ready(()))
+    }
+
+    /// The round trip time measured by the multiplexer
+    fn rtt(&self) -> Option<Rtt> {
+        None
+    }
+
+    /// Send a ping to remote and wait for its ack
+    fn ping(&self) -> BoxFuture<'static, io::Result<Duration>> {
+        Box::pin(future::err(io::Error::new(
+            io::ErrorKind::Other,
+            "ping is not supported by the multiplexer",
+        )))
+    }
+}
+
+impl fmt::Debug for dyn MuxerControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MuxerControl")
+    }
+}
+
+/// A multiplexer session, output the substreams opened by remote.
+///
+/// The session must be polled continuously to drive the underlying connection
+pub trait StreamMuxer: Stream<Item = io::Result<BoxedSubstream>> + Send + Unpin {
+    /// Return a control to open substream/close session
+    fn control(&self) -> Arc<dyn MuxerControl>;
+}
+
+/// Boxed multiplexer session
+pub type BoxedMuxer = Box<dyn StreamMuxer>;
+
+/// Multiplexers supported by tentacle
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Muxer {
+    /// Yamux, the default multiplexer
+    Yamux,
+    /// Mplex
+    Mplex,
+}
+
+impl Muxer {
+    /// Protocol name used in negotiation
+    pub fn protocol_name(self) -> &'static str {
+        match self {
+            Muxer::Yamux => "/yamux/1.0.0",
+            Muxer::Mplex => "/mplex/6.7.0",
+        }
+    }
+
+    fn from_protocol_name(name: &str) -> Option<Self> {
+        match name {
+            "/yamux/1.0.0" => Some(Muxer::Yamux),
+            "/mplex/6.7.0" => Some(Muxer::Mplex),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Muxer {
+    fn default() -> Self {
+        Muxer::Yamux
+    }
+}
+
+const MULTISTREAM_HEADER: &str = "/multistream/1.0.0";
+const NOT_AVAILABLE: &str = "na";
+const MAX_MESSAGE_LENGTH: usize = 1024;
+const MAX_ATTEMPTS: usize = 16;
+
+/// Negotiate the multiplexer with multistream-select after the secure handshake,
+/// the outbound side proposes its multiplexers in order, the inbound side accepts
+/// the first one it supports.
+pub(crate) async fn negotiate<T>(
+    socket: &mut T,
+    ty: SessionType,
+    muxers: &[Muxer],
+) -> io::Result<Muxer>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    write_message(socket, MULTISTREAM_HEADER).await?;
+    if read_message(socket).await? != MULTISTREAM_HEADER {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected multistream header",
+        ));
+    }
+
+    if ty.is_outbound() {
+        for muxer in muxers {
+            write_message(socket, muxer.protocol_name()).await?;
+            let response = read_message(socket).await?;
+            if response == muxer.protocol_name() {
+                return Ok(*muxer);
+            } else if response != NOT_AVAILABLE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected multiplexer response: {}", response),
+                ));
+            }
+            debug!("remote doesn't support multiplexer {:?}", muxer);
+        }
+    } else {
+        for _ in 0..MAX_ATTEMPTS {
+            let proposal = read_message(socket).await?;
+            match Muxer::from_protocol_name(&proposal) {
+                Some(muxer) if muxers.contains(&muxer) => {
+                    write_message(socket, &proposal).await?;
+                    return Ok(muxer);
+                }
+                _ => write_message(socket, NOT_AVAILABLE).await?,
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "no multiplexer in common",
+    ))
+}
+
+/// Message of multistream-select: unsigned varint length, then the content ends with '\n'
+async fn write_message<T>(socket: &mut T, message: &str) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin,
+{
+    let mut buf = Vec::with_capacity(message.len() + 2);
+    let mut len = message.len() + 1;
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+    buf.extend_from_slice(message.as_bytes());
+    buf.push(b'\n');
+    write_all(socket, &buf).await
+}
+
+/// Read byte by byte, so no data after the message is consumed
+async fn read_message<T>(socket: &mut T) -> io::Result<String>
+where
+    T: AsyncRead + Unpin,
+{
+    let mut len = 0;
+    let mut byte = [0; 1];
+    for i in 0..2 {
+        read_exact(socket, &mut byte).await?;
+        len |= ((byte[0] & 0x7f) as usize) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    if len == 0 || len > MAX_MESSAGE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid multistream message length",
+        ));
+    }
+
+    let mut buf = vec![0; len];
+    read_exact(socket, &mut buf).await?;
+    if buf.pop() != Some(b'\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "multistream message must end with newline",
+        ));
+    }
+    String::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+async fn write_all<T>(socket: &mut T, buf: &[u8]) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + ?Sized,
+{
+    let mut written = 0;
+    while written < buf.len() {
+        let n = poll_fn(|cx| Pin::new(&mut *socket).poll_write(cx, &buf[written..])).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        written += n;
+    }
+    poll_fn(|cx| Pin::new(&mut *socket).poll_flush(cx)).await
+}
+
+async fn read_exact<T>(socket: &mut T, buf: &mut [u8]) -> io::Result<()>
+where
+    T: AsyncRead + Unpin,
+{
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = poll_fn(|cx| Pin::new(&mut *socket).poll_read(cx, &mut buf[filled..])).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        filled += n;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        negotiate, reset_code, BoxedMuxer, MplexConfig, MplexSession, Muxer, YamuxMuxer,
+        RESET_PROTOCOL_REJECTED,
+    };
+    use crate::{
+        service::SessionType,
+        yamux::{Config, Session},
+    };
+    use futures::StreamExt;
+    use std::io;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    fn negotiate_pair(
+        client: Vec<Muxer>,
+        server: Vec<Muxer>,
+    ) -> (io::Result<Muxer>, io::Result<Muxer>) {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (mut a, (mut b, _)) =
+                futures::try_join!(TcpStream::connect(addr), listener.accept()).unwrap();
+            futures::join!(
+                async move { negotiate(&mut a, SessionType::Outbound, &client).await },
+                async move { negotiate(&mut b, SessionType::Inbound, &server).await },
+            )
+        })
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let (client, server) = negotiate_pair(
+            vec![Muxer::Mplex, Muxer::Yamux],
+            vec![Muxer::Yamux, Muxer::Mplex],
+        );
+        assert_eq!(client.unwrap(), Muxer::Mplex);
+        assert_eq!(server.unwrap(), Muxer::Mplex);
+
+        let (client, server) = negotiate_pair(vec![Muxer::Mplex, Muxer::Yamux], vec![Muxer::Yamux]);
+        assert_eq!(client.unwrap(), Muxer::Yamux);
+        assert_eq!(server.unwrap(), Muxer::Yamux);
+    }
+
+    #[test]
+    fn test_negotiate_fail() {
+        let (client, _server) = negotiate_pair(vec![Muxer::Mplex], vec![Muxer::Yamux]);
+        assert!(client.is_err());
+    }
+
+    fn reset_stream(muxer: Muxer) -> Option<u32> {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (a, (b, _)) =
+                futures::try_join!(TcpStream::connect(addr), listener.accept()).unwrap();
+            let (client, mut server): (BoxedMuxer, BoxedMuxer) = match muxer {
+                Muxer::Yamux => (
+                    Box::new(YamuxMuxer::new(Session::new_client(a, Config::default()))),
+                    Box::new(YamuxMuxer::new(Session::new_server(b, Config::default()))),
+                ),
+                Muxer::Mplex => (
+                    Box::new(MplexSession::new(a, MplexConfig::default())),
+                    Box::new(MplexSession::new(b, MplexConfig::default())),
+                ),
+            };
+            let control = client.control();
+            tokio::spawn(client.for_each(|_| futures::future::ready(())));
+            tokio::spawn(async move {
+                while let Some(Ok(mut stream)) = server.next().await {
+                    tokio::spawn(async move {
+                        let mut buf = [0; 5];
+                        stream.read_exact(&mut buf).await.unwrap();
+                        stream.reset(RESET_PROTOCOL_REJECTED);
+                    });
+                }
+            });
+
+            let mut stream = control.open_stream().await.unwrap();
+            stream.write_all(b"hello").await.unwrap();
+            let mut buf = [0; 5];
+            let err = stream.read(&mut buf).await.err().unwrap();
+            reset_code(&err)
+        })
+    }
+
+    #[test]
+    fn test_reset_code() {
+        assert_eq!(reset_stream(Muxer::Yamux), Some(RESET_PROTOCOL_REJECTED));
+        // mplex can't carry the code
+        assert_eq!(reset_stream(Muxer::Mplex), None);
+        assert_eq!(reset_code(&io::ErrorKind::BrokenPipe.into()), None);
+        assert_eq!(reset_code(&io::ErrorKind::ConnectionReset.into()), None);
+    }
+}
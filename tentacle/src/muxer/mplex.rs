@@ -0,0 +1,985 @@
+//! Mplex, a simple stream multiplexer without flow control.
+//!
+//! Every frame is `header(varint) | length(varint) | data`, the header is
+//! `stream_id << 3 | flag`, and the stream id is only unique together with
+//! the side that opened the stream.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, BoxFuture},
+    Sink, Stream, StreamExt,
+};
+use log::{debug, trace};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tokio::prelude::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+use crate::muxer::{BoxedSubstream, MuxerControl, MuxerSubstream, StreamMuxer};
+
+/// Max length of a message
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+/// Default value for accept_backlog, same as yamux
+const DEFAULT_ACCEPT_BACKLOG: usize = 256;
+/// Default max stream count, same as yamux
+const DEFAULT_MAX_STREAM_COUNT: usize = 65535;
+/// Default max bytes buffered for each stream
+const DEFAULT_MAX_STREAM_BUFFER_SIZE: usize = 16 * 1024 * 1024;
+/// Frames buffered for writing before the streams are blocked
+const MAX_PENDING_FRAMES: usize = 128;
+/// Max polls of session in one turn, same as yamux
+const MAX_POLL_LOOP: usize = 16;
+
+/// Configuration of mplex session
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// AcceptBacklog is used to limit how many streams may be
+    /// waiting an accept, the new streams beyond it are reset.
+    pub accept_backlog: usize,
+
+    /// Max stream count, the new streams from remote beyond it are reset
+    pub max_stream_count: usize,
+
+    /// MaxStreamBufferSize is the max bytes received but not read of a stream.
+    /// Mplex has no flow control, the stream is reset when it's exceeded,
+    /// the other streams are not affected.
+    pub max_stream_buffer_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            accept_backlog: DEFAULT_ACCEPT_BACKLOG,
+            max_stream_count: DEFAULT_MAX_STREAM_COUNT,
+            max_stream_buffer_size: DEFAULT_MAX_STREAM_BUFFER_SIZE,
+        }
+    }
+}
+
+/// Stream id with the side that opened it
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+struct StreamKey {
+    id: u64,
+    /// Opened by local
+    local: bool,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Flag {
+    NewStream,
+    Message,
+    Close,
+    Reset,
+}
+
+#[derive(Debug)]
+struct Frame {
+    key: StreamKey,
+    flag: Flag,
+    data: Bytes,
+}
+
+impl Frame {
+    fn new(key: StreamKey, flag: Flag) -> Self {
+        Frame {
+            key,
+            flag,
+            data: Bytes::new(),
+        }
+    }
+}
+
+/// Codec of mplex frame, the stream key is translated between the view of
+/// local and remote
+struct Codec;
+
+fn put_varint(dst: &mut BytesMut, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            dst.put_u8(byte);
+            break;
+        }
+        dst.put_u8(byte | 0x80);
+    }
+}
+
+/// Return the value and the length of varint, or None if incomplete
+fn get_varint(src: &[u8]) -> io::Result<Option<(u64, usize)>> {
+    let mut value = 0;
+    for (i, byte) in src.iter().enumerate() {
+        if i >= 9 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "mplex varint overflow",
+            ));
+        }
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, i + 1)));
+        }
+    }
+    Ok(None)
+}
+
+impl Encoder<Frame> for Codec {
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let code = match (frame.flag, frame.key.local) {
+            (Flag::NewStream, _) => 0,
+            (Flag::Message, false) => 1,
+            (Flag::Message, true) => 2,
+            (Flag::Close, false) => 3,
+            (Flag::Close, true) => 4,
+            (Flag::Reset, false) => 5,
+            (Flag::Reset, true) => 6,
+        };
+        dst.reserve(frame.data.len() + 20);
+        put_varint(dst, frame.key.id << 3 | code);
+        put_varint(dst, frame.data.len() as u64);
+        dst.put_slice(&frame.data);
+        Ok(())
+    }
+}
+
+impl Decoder for Codec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let (header, header_len) = match get_varint(src)? {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+        let (len, len_len) = match get_varint(&src[header_len..])? {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+        if len as usize > MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("mplex message too large: {}", len),
+            ));
+        }
+        let len = len as usize;
+        if src.len() < header_len + len_len + len {
+            src.reserve(header_len + len_len + len - src.len());
+            return Ok(None);
+        }
+        src.advance(header_len + len_len);
+        let data = src.split_to(len).freeze();
+
+        // The flag is from the view of remote, initiator flags mean the stream is opened by remote
+        let (flag, local) = match header & 0x07 {
+            0 => (Flag::NewStream, false),
+            1 => (Flag::Message, true),
+            2 => (Flag::Message, false),
+            3 => (Flag::Close, true),
+            4 => (Flag::Close, false),
+            5 => (Flag::Reset, true),
+            6 => (Flag::Reset, false),
+            flag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid mplex flag: {}", flag),
+                ))
+            }
+        };
+        Ok(Some(Frame {
+            key: StreamKey {
+                id: header >> 3,
+                local,
+            },
+            flag,
+            data,
+        }))
+    }
+}
+
+enum Command {
+    OpenStream(oneshot::Sender<MplexStream>),
+    Close(oneshot::Sender<()>),
+    GoAway,
+    Flush(oneshot::Sender<()>),
+}
+
+struct StreamState {
+    /// None if remote closed its write side
+    sender: Option<mpsc::UnboundedSender<Bytes>>,
+    /// Bytes sent to the stream but not read
+    buffered: Arc<AtomicUsize>,
+    reset: Arc<AtomicBool>,
+    local_closed: bool,
+}
+
+/// Mplex session
+pub(crate) struct MplexSession<T> {
+    framed: Framed<T, Codec>,
+    config: Config,
+    next_stream_id: u64,
+    streams: HashMap<StreamKey, StreamState>,
+    /// Streams opened by remote and waiting to be accepted
+    pending_streams: VecDeque<MplexStream>,
+
+    /// Frames waiting to be written
+    write_pending: VecDeque<Frame>,
+
+    /// Clone to new stream
+    frame_sender: mpsc::Sender<Frame>,
+    frame_receiver: mpsc::Receiver<Frame>,
+    /// Used when stream dropped
+    unbound_sender: mpsc::UnboundedSender<Frame>,
+    unbound_receiver: mpsc::UnboundedReceiver<Frame>,
+
+    control_sender: mpsc::UnboundedSender<Command>,
+    control_receiver: mpsc::UnboundedReceiver<Command>,
+    close_waiters: Vec<oneshot::Sender<()>>,
+    flush_waiters: Vec<oneshot::Sender<()>>,
+
+    /// Mplex has no go away frame, just reset the streams opened by remote
+    go_away: bool,
+    closing: bool,
+    dead: bool,
+}
+
+impl<T> MplexSession<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    pub(crate) fn new(socket: T, config: Config) -> Self {
+        let (frame_sender, frame_receiver) = mpsc::channel(MAX_PENDING_FRAMES);
+        let (unbound_sender, unbound_receiver) = mpsc::unbounded();
+        let (control_sender, control_receiver) = mpsc::unbounded();
+        MplexSession {
+            framed: Framed::new(socket, Codec),
+            config,
+            next_stream_id: 0,
+            streams: HashMap::default(),
+            pending_streams: VecDeque::default(),
+            write_pending: VecDeque::default(),
+            frame_sender,
+            frame_receiver,
+            unbound_sender,
+            unbound_receiver,
+            control_sender,
+            control_receiver,
+            close_waiters: Vec::new(),
+            flush_waiters: Vec::new(),
+            go_away: false,
+            closing: false,
+            dead: false,
+        }
+    }
+
+    fn create_stream(&mut self, key: StreamKey) -> MplexStream {
+        let (sender, receiver) = mpsc::unbounded();
+        let buffered = Arc::new(AtomicUsize::new(0));
+        let reset = Arc::new(AtomicBool::new(false));
+        self.streams.insert(
+            key,
+            StreamState {
+                sender: Some(sender),
+                buffered: Arc::clone(&buffered),
+                reset: Arc::clone(&reset),
+                local_closed: false,
+            },
+        );
+        MplexStream {
+            key,
+            receiver,
+            buffered,
+            read_buf: Bytes::new(),
+            frame_sender: self.frame_sender.clone(),
+            unbound_sender: self.unbound_sender.clone(),
+            reset,
+            read_closed: false,
+            write_closed: false,
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::OpenStream(sender) => {
+                if self.closing {
+                    return;
+                }
+                let key = StreamKey {
+                    id: self.next_stream_id,
+                    local: true,
+                };
+                self.next_stream_id += 1;
+                let stream = self.create_stream(key);
+                self.write_pending
+                    .push_back(Frame::new(key, Flag::NewStream));
+                if sender.send(stream).is_err() {
+                    debug!("mplex stream {:?} is opened but nobody cares", key);
+                }
+            }
+            Command::Close(sender) => {
+                self.closing = true;
+                self.close_waiters.push(sender);
+            }
+            Command::GoAway => self.go_away = true,
+            Command::Flush(sender) => self.flush_waiters.push(sender),
+        }
+    }
+
+    /// Frames from local streams
+    fn handle_local_frame(&mut self, frame: Frame) {
+        match frame.flag {
+            Flag::Close => match self.streams.get_mut(&frame.key) {
+                Some(state) => {
+                    state.local_closed = true;
+                    if state.sender.is_none() {
+                        self.streams.remove(&frame.key);
+                    }
+                }
+                None => return,
+            },
+            Flag::Reset => {
+                if self.streams.remove(&frame.key).is_none() {
+                    return;
+                }
+            }
+            _ => {
+                if !self.streams.contains_key(&frame.key) {
+                    return;
+                }
+            }
+        }
+        self.write_pending.push_back(frame);
+    }
+
+    /// Reset the stream opened by remote or local, both sides forget it
+    fn reset_stream(&mut self, key: StreamKey) {
+        if let Some(state) = self.streams.remove(&key) {
+            state.reset.store(true, Ordering::SeqCst);
+        }
+        self.write_pending.push_back(Frame::new(key, Flag::Reset));
+    }
+
+    /// Frames from remote
+    fn handle_remote_frame(&mut self, frame: Frame) -> io::Result<()> {
+        trace!("mplex receive frame: {:?}", frame);
+        match frame.flag {
+            Flag::NewStream => {
+                if self.streams.contains_key(&frame.key) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("mplex stream {} is already opened", frame.key.id),
+                    ));
+                }
+                if self.closing
+                    || self.go_away
+                    || self.streams.len() >= self.config.max_stream_count
+                    || self.pending_streams.len() >= self.config.accept_backlog
+                {
+                    debug!("mplex stream {} refused", frame.key.id);
+                    self.write_pending
+                        .push_back(Frame::new(frame.key, Flag::Reset));
+                    return Ok(());
+                }
+                let stream = self.create_stream(frame.key);
+                self.pending_streams.push_back(stream);
+            }
+            Flag::Message => {
+                let overflow = match self.streams.get_mut(&frame.key) {
+                    Some(StreamState {
+                        sender: Some(sender),
+                        buffered,
+                        ..
+                    }) => {
+                        let len = frame.data.len();
+                        if buffered.load(Ordering::SeqCst) + len
+                            > self.config.max_stream_buffer_size
+                        {
+                            true
+                        } else {
+                            buffered.fetch_add(len, Ordering::SeqCst);
+                            let _ignore = sender.unbounded_send(frame.data);
+                            false
+                        }
+                    }
+                    _ => false,
+                };
+                if overflow {
+                    debug!("mplex stream {:?} buffer is full, reset it", frame.key);
+                    self.reset_stream(frame.key);
+                }
+            }
+            Flag::Close => {
+                let remove = match self.streams.get_mut(&frame.key) {
+                    Some(state) => {
+                        state.sender.take();
+                        state.local_closed
+                    }
+                    None => false,
+                };
+                if remove {
+                    self.streams.remove(&frame.key);
+                }
+            }
+            Flag::Reset => {
+                if let Some(state) = self.streams.remove(&frame.key) {
+                    if state.sender.is_some() {
+                        state.reset.store(true, Ordering::SeqCst);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive local frames, return true if anything received
+    fn recv_local_frames(&mut self, cx: &mut Context) -> bool {
+        let mut received = false;
+        while let Poll::Ready(Some(command)) = self.control_receiver.poll_next_unpin(cx) {
+            self.handle_command(command);
+            received = true;
+        }
+        while let Poll::Ready(Some(frame)) = self.unbound_receiver.poll_next_unpin(cx) {
+            self.handle_local_frame(frame);
+            received = true;
+        }
+        while self.write_pending.len() < MAX_PENDING_FRAMES {
+            match self.frame_receiver.poll_next_unpin(cx) {
+                Poll::Ready(Some(frame)) => {
+                    self.handle_local_frame(frame);
+                    received = true;
+                }
+                _ => break,
+            }
+        }
+        received
+    }
+
+    /// Write all pending frames, return true if anything written
+    fn send_frames(&mut self, cx: &mut Context) -> io::Result<bool> {
+        let mut sent = false;
+        while !self.write_pending.is_empty() {
+            match Pin::new(&mut self.framed).poll_ready(cx)? {
+                Poll::Ready(()) => {
+                    let frame = self.write_pending.pop_front().unwrap();
+                    Pin::new(&mut self.framed).start_send(frame)?;
+                    sent = true;
+                }
+                Poll::Pending => break,
+            }
+        }
+        if let Poll::Ready(Err(err)) = Pin::new(&mut self.framed).poll_flush(cx) {
+            return Err(err);
+        }
+        Ok(sent)
+    }
+
+    /// Read frames from remote, the streams are never blocked by each other,
+    /// a stream is reset when its buffer is full
+    fn recv_frames(&mut self, cx: &mut Context) -> Poll<Option<io::Result<()>>> {
+        for _ in 0..MAX_POLL_LOOP {
+            match Pin::new(&mut self.framed).poll_next(cx) {
+                Poll::Ready(Some(Ok(frame))) => {
+                    if let Err(err) = self.handle_remote_frame(frame) {
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Some(Ok(())))
+    }
+
+    fn shutdown(&mut self) {
+        self.dead = true;
+        self.pending_streams.clear();
+        for (_, state) in self.streams.drain() {
+            if state.sender.is_some() {
+                state.reset.store(true, Ordering::SeqCst);
+            }
+        }
+        for waiter in self.close_waiters.drain(..) {
+            let _ignore = waiter.send(());
+        }
+        self.control_receiver.close();
+    }
+
+    /// Get a control of the session
+    pub(crate) fn control(&self) -> MplexControl {
+        MplexControl {
+            sender: self.control_sender.clone(),
+        }
+    }
+}
+
+impl<T> Stream for MplexSession<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = io::Result<BoxedSubstream>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if self.dead {
+            return Poll::Ready(None);
+        }
+
+        for _ in 0..MAX_POLL_LOOP {
+            let received = self.recv_local_frames(cx);
+
+            let sent = match self.send_frames(cx) {
+                Ok(sent) => sent,
+                Err(err) => {
+                    self.shutdown();
+                    return Poll::Ready(Some(Err(err)));
+                }
+            };
+
+            if self.closing {
+                if !self.write_pending.is_empty() {
+                    return Poll::Pending;
+                }
+                return match Pin::new(&mut self.framed).poll_close(cx) {
+                    Poll::Ready(_) => {
+                        self.shutdown();
+                        Poll::Ready(None)
+                    }
+                    Poll::Pending => Poll::Pending,
+                };
+            }
+
+            let queued = self.write_pending.len();
+            let read = match self.recv_frames(cx) {
+                Poll::Ready(Some(Ok(()))) => true,
+                Poll::Ready(Some(Err(err))) => {
+                    self.shutdown();
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Ready(None) => {
+                    self.shutdown();
+                    return Poll::Ready(None);
+                }
+                // Reset frames may be queued for the refused or overflowed streams
+                Poll::Pending => self.write_pending.len() > queued,
+            };
+
+            if let Some(stream) = self.pending_streams.pop_front() {
+                return Poll::Ready(Some(Ok(Box::new(stream) as BoxedSubstream)));
+            }
+
+            if !received && !sent && !read {
+                if !self.flush_waiters.is_empty() && self.write_pending.is_empty() {
+                    match Pin::new(&mut self.framed).poll_flush(cx) {
+                        Poll::Ready(Ok(())) => {
+                            for waiter in self.flush_waiters.drain(..) {
+                                let _ignore = waiter.send(());
+                            }
+                        }
+                        Poll::Ready(Err(err)) => {
+                            self.shutdown();
+                            return Poll::Ready(Some(Err(err)));
+                        }
+                        Poll::Pending => (),
+                    }
+                }
+                return Poll::Pending;
+            }
+        }
+
+        // Give other tasks a chance
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl<T> StreamMuxer for MplexSession<T>
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin,
+{
+    fn control(&self) -> Arc<dyn MuxerControl> {
+        Arc::new(MplexSession::control(self))
+    }
+}
+
+/// Control of mplex session
+#[derive(Clone)]
+pub(crate) struct MplexControl {
+    sender: mpsc::UnboundedSender<Command>,
+}
+
+impl MuxerControl for MplexControl {
+    fn open_stream(&self) -> BoxFuture<'static, io::Result<BoxedSubstream>> {
+        let (sender, receiver) = oneshot::channel();
+        let res = self.sender.unbounded_send(Command::OpenStream(sender));
+        Box::pin(async move {
+            if res.is_err() {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            receiver
+                .await
+                .map(|stream| Box::new(stream) as BoxedSubstream)
+                .map_err(|_| io::ErrorKind::BrokenPipe.into())
+        })
+    }
+
+    fn close(&self) -> BoxFuture<'static, ()> {
+        let (sender, receiver) = oneshot::channel();
+        let res = self.sender.unbounded_send(Command::Close(sender));
+        Box::pin(async move {
+            if res.is_ok() {
+                let _ignore = receiver.await;
+            }
+        })
+    }
+
+    fn go_away(&self) -> BoxFuture<'static, ()> {
+        let _ignore = self.sender.unbounded_send(Command::GoAway);
+        Box::pin(future::ready(()))
+    }
+
+    fn flush(&self) -> BoxFuture<'static, ()> {
+        let (sender, receiver) = oneshot::channel();
+        let res = self.sender.unbounded_send(Command::Flush(sender));
+        Box::pin(async move {
+            if res.is_ok() {
+                let _ignore = receiver.await;
+            }
+        })
+    }
+}
+
+/// Mplex stream
+pub(crate) struct MplexStream {
+    key: StreamKey,
+    receiver: mpsc::UnboundedReceiver<Bytes>,
+    /// Bytes received but not read, shared with session
+    buffered: Arc<AtomicUsize>,
+    read_buf: Bytes,
+    frame_sender: mpsc::Sender<Frame>,
+    unbound_sender: mpsc::UnboundedSender<Frame>,
+    /// Reset by remote or session closed
+    reset: Arc<AtomicBool>,
+    read_closed: bool,
+    write_closed: bool,
+}
+
+impl MplexStream {
+    fn is_reset(&self) -> bool {
+        self.reset.load(Ordering::SeqCst)
+    }
+
+    fn send_frame(&mut self, cx: &mut Context, frame: Frame) -> Poll<io::Result<()>> {
+        match self.frame_sender.poll_ready(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(
+                self.frame_sender
+                    .start_send(frame)
+                    .map_err(|_| io::ErrorKind::BrokenPipe.into()),
+            ),
+            Poll::Ready(Err(_)) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl AsyncRead for MplexStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        while self.read_buf.is_empty() {
+            if self.read_closed {
+                return Poll::Ready(Ok(0));
+            }
+            match self.receiver.poll_next_unpin(cx) {
+                Poll::Ready(Some(data)) => {
+                    self.buffered.fetch_sub(data.len(), Ordering::SeqCst);
+                    self.read_buf = data;
+                }
+                Poll::Ready(None) => {
+                    if self.is_reset() {
+                        return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+                    }
+                    self.read_closed = true;
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let n = ::std::cmp::min(buf.len(), self.read_buf.len());
+        buf[..n].copy_from_slice(&self.read_buf[..n]);
+        self.read_buf.advance(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for MplexStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.is_reset() {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        if self.write_closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let n = ::std::cmp::min(buf.len(), MAX_MESSAGE_SIZE);
+        let frame = Frame {
+            key: self.key,
+            flag: Flag::Message,
+            data: Bytes::copy_from_slice(&buf[..n]),
+        };
+        self.send_frame(cx, frame).map_ok(|_| n)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        if self.write_closed || self.is_reset() {
+            return Poll::Ready(Ok(()));
+        }
+        let frame = Frame::new(self.key, Flag::Close);
+        match self.send_frame(cx, frame) {
+            Poll::Ready(res) => {
+                self.write_closed = true;
+                Poll::Ready(res)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl MuxerSubstream for MplexStream {
+    /// Mplex can't carry the reset code
+    fn reset(&mut self, _code: u32) {
+        if self.is_reset() || (self.read_closed && self.write_closed) {
+            return;
+        }
+        self.reset.store(true, Ordering::SeqCst);
+        let _ignore = self
+            .unbound_sender
+            .unbounded_send(Frame::new(self.key, Flag::Reset));
+    }
+}
+
+impl Drop for MplexStream {
+    fn drop(&mut self) {
+        if self.is_reset() {
+            return;
+        }
+        let flag = if !self.read_closed {
+            Flag::Reset
+        } else if !self.write_closed {
+            Flag::Close
+        } else {
+            return;
+        };
+        let _ignore = self
+            .unbound_sender
+            .unbounded_send(Frame::new(self.key, flag));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Codec, Config, Flag, Frame, MplexControl, MplexSession, StreamKey};
+    use crate::muxer::MuxerControl;
+    use bytes::{Bytes, BytesMut};
+    use futures::StreamExt;
+    use std::io;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_util::codec::{Decoder, Encoder};
+
+    #[test]
+    fn test_codec() {
+        let mut codec = Codec;
+        let mut buf = BytesMut::new();
+        let key = StreamKey {
+            id: 1000,
+            local: true,
+        };
+        let frame = Frame {
+            key,
+            flag: Flag::Message,
+            data: Bytes::from_static(b"hello"),
+        };
+        codec.encode(frame, &mut buf).unwrap();
+        // 1000 << 3 | 2
+        assert_eq!(&buf[..3], &[0xc2, 0x3e, 0x05]);
+
+        // the remote view of the stream
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(
+            frame.key,
+            StreamKey {
+                id: 1000,
+                local: false
+            }
+        );
+        assert_eq!(frame.flag, Flag::Message);
+        assert_eq!(frame.data, Bytes::from_static(b"hello"));
+        assert!(buf.is_empty());
+
+        // incomplete frame
+        buf.extend_from_slice(&[0x08, 0x05, b'h']);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        // invalid flag
+        let mut buf = BytesMut::from(&[0x07, 0x00][..]);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_open_stream() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (a, (b, _)) =
+                futures::try_join!(TcpStream::connect(addr), listener.accept()).unwrap();
+
+            let client = MplexSession::new(a, Config::default());
+            let control = client.control();
+            tokio::spawn(client.for_each(|_| futures::future::ready(())));
+
+            let mut server = MplexSession::new(b, Config::default());
+            tokio::spawn(async move {
+                while let Some(Ok(mut stream)) = server.next().await {
+                    tokio::spawn(async move {
+                        let mut buf = Vec::new();
+                        stream.read_to_end(&mut buf).await.unwrap();
+                        stream.write_all(&buf).await.unwrap();
+                        stream.shutdown().await.unwrap();
+                    });
+                }
+            });
+
+            for _ in 0..3 {
+                let mut stream = control.open_stream().await.unwrap();
+                let data = vec![7; 3 * 1024 * 1024];
+                stream.write_all(&data).await.unwrap();
+                stream.shutdown().await.unwrap();
+                let mut buf = Vec::new();
+                stream.read_to_end(&mut buf).await.unwrap();
+                assert_eq!(buf, data);
+            }
+
+            control.close().await;
+            let err = control.open_stream().await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        });
+    }
+
+    #[test]
+    fn test_reset_stream() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (a, (b, _)) =
+                futures::try_join!(TcpStream::connect(addr), listener.accept()).unwrap();
+
+            let client = MplexSession::new(a, Config::default());
+            let control = client.control();
+            tokio::spawn(client.for_each(|_| futures::future::ready(())));
+
+            let mut server = MplexSession::new(b, Config::default());
+            tokio::spawn(async move {
+                // drop the stream without reading, reset it
+                while let Some(Ok(_stream)) = server.next().await {}
+            });
+
+            let mut stream = control.open_stream().await.unwrap();
+            let _ignore = stream.write_all(b"hello").await;
+            let mut buf = [0; 5];
+            let err = stream.read(&mut buf).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        });
+    }
+
+    /// Accept the streams, hold the first one without reading and echo the others
+    async fn hold_first_stream(config: Config) -> MplexControl {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (a, (b, _)) = futures::try_join!(TcpStream::connect(addr), listener.accept()).unwrap();
+        let client = MplexSession::new(a, Config::default());
+        let control = client.control();
+        tokio::spawn(client.for_each(|_| futures::future::ready(())));
+
+        let mut server = MplexSession::new(b, config);
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Some(Ok(mut stream)) = server.next().await {
+                if held.is_empty() {
+                    held.push(stream);
+                    continue;
+                }
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    stream.read_to_end(&mut buf).await.unwrap();
+                    stream.write_all(&buf).await.unwrap();
+                    stream.shutdown().await.unwrap();
+                });
+            }
+        });
+        control
+    }
+
+    #[test]
+    fn test_max_stream_count() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut config = Config::default();
+            config.max_stream_count = 1;
+            let control = hold_first_stream(config).await;
+
+            let _first = control.open_stream().await.unwrap();
+            let mut second = control.open_stream().await.unwrap();
+            let _ignore = second.write_all(b"hello").await;
+            let mut buf = [0; 5];
+            let err = second.read(&mut buf).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        });
+    }
+
+    #[test]
+    fn test_stream_buffer_full() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut config = Config::default();
+            config.max_stream_buffer_size = 1024;
+            let control = hold_first_stream(config).await;
+
+            // the held stream is reset when its buffer is full
+            let mut first = control.open_stream().await.unwrap();
+            first.write_all(&[1; 2048]).await.unwrap();
+            let mut buf = [0; 1];
+            let err = first.read(&mut buf).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+            // the other streams are not blocked
+            let mut second = control.open_stream().await.unwrap();
+            second.write_all(b"hello").await.unwrap();
+            second.shutdown().await.unwrap();
+            let mut buf = Vec::new();
+            second.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"hello");
+        });
+    }
+}
@@ -0,0 +1,113 @@
+use bytes::Bytes;
+use futures::{future::BoxFuture, Stream};
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::prelude::{AsyncRead, AsyncWrite};
+
+use crate::{
+    muxer::{BoxedSubstream, MuxerControl, MuxerSubstream, StreamMuxer},
+    yamux::{Control, Error as YamuxError, Rtt, Session, StreamHandle},
+};
+
+/// Yamux session as a multiplexer
+pub(crate) struct YamuxMuxer<T> {
+    session: Session<T>,
+}
+
+impl<T> YamuxMuxer<T> {
+    pub(crate) fn new(session: Session<T>) -> Self {
+        YamuxMuxer { session }
+    }
+}
+
+impl<T> Stream for YamuxMuxer<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = io::Result<BoxedSubstream>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.session)
+            .poll_next(cx)
+            .map(|item| item.map(|res| res.map(|stream| Box::new(stream) as BoxedSubstream)))
+    }
+}
+
+impl<T> StreamMuxer for YamuxMuxer<T>
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin,
+{
+    fn control(&self) -> Arc<dyn MuxerControl> {
+        Arc::new(self.session.control())
+    }
+}
+
+impl MuxerSubstream for StreamHandle {
+    fn reset(&mut self, code: u32) {
+        let _ignore = StreamHandle::reset(self, code);
+    }
+
+    fn set_priority(&mut self, priority: u8) {
+        let _ignore = StreamHandle::set_priority(self, priority);
+    }
+}
+
+impl MuxerControl for Control {
+    fn open_stream(&self) -> BoxFuture<'static, io::Result<BoxedSubstream>> {
+        let mut control = self.clone();
+        Box::pin(async move {
+            Control::open_stream(&mut control)
+                .await
+                .map(|stream| Box::new(stream) as BoxedSubstream)
+                .map_err(|err| io::Error::new(io::ErrorKind::BrokenPipe, err))
+        })
+    }
+
+    fn open_stream_with_data(&self, data: Bytes) -> BoxFuture<'static, io::Result<BoxedSubstream>> {
+        let mut control = self.clone();
+        Box::pin(async move {
+            Control::open_stream_with_data(&mut control, data)
+                .await
+                .map(|stream| Box::new(stream) as BoxedSubstream)
+                .map_err(|err| io::Error::new(io::ErrorKind::BrokenPipe, err))
+        })
+    }
+
+    fn close(&self) -> BoxFuture<'static, ()> {
+        let mut control = self.clone();
+        Box::pin(async move { Control::close(&mut control).await })
+    }
+
+    fn go_away(&self) -> BoxFuture<'static, ()> {
+        let mut control = self.clone();
+        Box::pin(async move {
+            let _ignore = Control::go_away(&mut control).await;
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'static, ()> {
+        let mut control = self.clone();
+        Box::pin(async move {
+            let _ignore = Control::flush(&mut control).await;
+        })
+    }
+
+    fn rtt(&self) -> Option<Rtt> {
+        Control::rtt(self)
+    }
+
+    fn ping(&self) -> BoxFuture<'static, io::Result<Duration>> {
+        let mut control = self.clone();
+        Box::pin(async move {
+            Control::ping(&mut control).await.map_err(|err| match err {
+                YamuxError::PingTimeout => io::Error::new(io::ErrorKind::TimedOut, err),
+                err => io::Error::new(io::ErrorKind::Other, err),
+            })
+        })
+    }
+}
@@ -15,6 +15,7 @@ use crate::{
     context::{ProtocolContext, ServiceContext, SessionContext},
     error::ProtocolHandleErrorKind,
     multiaddr::Multiaddr,
+    secio::{envelope::SignedEnvelope, SecioKeyPair},
     service::{config::BlockingFlag, future_task::BoxedFutureTask},
     session::SessionEvent,
     traits::{ServiceProtocol, SessionProtocol},
@@ -65,6 +66,10 @@ pub enum ServiceProtocolEvent {
     Update {
         listen_addrs: Vec<Multiaddr>,
     },
+    UpdateKeyPair {
+        key_pair: SecioKeyPair,
+        key_rotation: SignedEnvelope,
+    },
 }
 
 enum CurrentTask {
@@ -216,6 +221,13 @@ where
                 self.current_task.run();
                 self.handle_context.update_listens(listen_addrs);
             }
+            UpdateKeyPair {
+                key_pair,
+                key_rotation,
+            } => {
+                self.current_task.run();
+                self.handle_context.update_key_pair(key_pair, key_rotation);
+            }
         }
         self.current_task.idle();
     }
@@ -367,6 +379,10 @@ pub enum SessionProtocolEvent {
     Update {
         listen_addrs: Vec<Multiaddr>,
     },
+    UpdateKeyPair {
+        key_pair: SecioKeyPair,
+        key_rotation: SignedEnvelope,
+    },
 }
 
 pub struct SessionProtocolStream<T> {
@@ -472,6 +488,12 @@ where
             Update { listen_addrs } => {
                 self.handle_context.update_listens(listen_addrs);
             }
+            UpdateKeyPair {
+                key_pair,
+                key_rotation,
+            } => {
+                self.handle_context.update_key_pair(key_pair, key_rotation);
+            }
         }
         self.current_task = false;
     }
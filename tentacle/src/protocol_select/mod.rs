@@ -10,13 +10,13 @@ use crate::protocol_select::protocol_select_generated::p2p::protocol_select::{
 #[cfg(feature = "molc")]
 use molecule::prelude::{Builder, Entity, Reader};
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::prelude::*;
 use log::{debug, trace};
 use std::cmp::Ordering;
 use std::{collections::HashMap, io};
 use tokio::prelude::{AsyncRead, AsyncWrite};
-use tokio_util::codec::{length_delimited::LengthDelimitedCodec, Framed};
+use tokio_util::codec::{length_delimited::LengthDelimitedCodec, Encoder, Framed};
 
 #[cfg(feature = "flatc")]
 #[rustfmt::skip]
@@ -142,19 +142,57 @@ impl ProtocolInfo {
     }
 }
 
-/// Performs a handshake on the given socket.
+/// Encode the protocol list, e.g. the early data of secio handshake,
+/// every protocol info is prefixed with its length
+pub fn encode_protocol_list(proto_infos: Vec<ProtocolInfo>) -> Bytes {
+    let mut buf = BytesMut::new();
+    for proto_info in proto_infos {
+        LengthDelimitedCodec::new()
+            .encode(proto_info.encode(), &mut buf)
+            .expect("protocol info must not exceed the max frame length");
+    }
+    buf.freeze()
+}
+
+/// Decode the protocol list encoded by `encode_protocol_list`
+pub fn decode_protocol_list(mut data: &[u8]) -> Option<Vec<ProtocolInfo>> {
+    let mut proto_infos = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 4 {
+            return None;
+        }
+        let mut len = [0; 4];
+        len.copy_from_slice(&data[..4]);
+        let len = u32::from_be_bytes(len) as usize;
+        if data.len() - 4 < len {
+            return None;
+        }
+        proto_infos.push(ProtocolInfo::decode(&data[4..4 + len])?);
+        data = &data[4 + len..];
+    }
+    Some(proto_infos)
+}
+
+/// Encode the request of client select, it's sent along with the opening of substream
+pub(crate) fn client_select_request(proto_info: ProtocolInfo) -> Bytes {
+    let data = proto_info.encode();
+    trace!("client_select send_proto(len={}): {:#x}", data.len(), data);
+    let mut buf = BytesMut::with_capacity(data.len() + 4);
+    LengthDelimitedCodec::new()
+        .encode(data, &mut buf)
+        .expect("protocol info must not exceed the max frame length");
+    buf.freeze()
+}
+
+/// Performs a handshake on the given socket, the request from `client_select_request`
+/// must have been sent.
 ///
 /// Select the protocol version, return a handle that implements the `AsyncWrite` and `AsyncRead` trait,
 /// plus the protocol name, plus the version option.
 pub(crate) async fn client_select<T: AsyncWrite + AsyncRead + Send + Unpin>(
     handle: T,
-    proto_info: ProtocolInfo,
 ) -> Result<(Framed<T, LengthDelimitedCodec>, String, Option<String>), io::Error> {
-    let mut socket = Framed::new(handle, LengthDelimitedCodec::new());
-
-    let data = proto_info.encode();
-    trace!("client_select send_proto(len={}): {:#x}", data.len(), data);
-    socket.send(data).await?;
+    let socket = Framed::new(handle, LengthDelimitedCodec::new());
 
     let (raw_remote_info, socket) = socket.into_future().await;
 
@@ -245,10 +283,16 @@ pub fn select_version<T: Ord + Clone>(local: &[T], remote: &[T]) -> Option<T> {
 
 #[cfg(test)]
 mod tests {
-    use super::{client_select, select_version, server_select, ProtocolInfo};
+    use super::{
+        client_select, client_select_request, decode_protocol_list, encode_protocol_list,
+        select_version, server_select, ProtocolInfo,
+    };
     use futures::channel;
     use std::collections::HashMap;
-    use tokio::net::{TcpListener, TcpStream};
+    use tokio::{
+        io::AsyncWriteExt,
+        net::{TcpListener, TcpStream},
+    };
 
     #[test]
     fn protocol_message_decode_encode() {
@@ -260,6 +304,22 @@ mod tests {
         assert_eq!(message, ProtocolInfo::decode(&byte.encode()).unwrap())
     }
 
+    #[test]
+    fn protocol_list_decode_encode() {
+        let list = vec![
+            ProtocolInfo::new("/p2p/ping", vec!["0.0.1".to_owned()]),
+            ProtocolInfo::new(
+                "/p2p/identify",
+                vec!["0.0.1".to_owned(), "0.0.2".to_owned()],
+            ),
+        ];
+
+        let data = encode_protocol_list(list.clone());
+        assert_eq!(decode_protocol_list(&data), Some(list));
+        assert_eq!(decode_protocol_list(&[]), Some(Vec::new()));
+        assert!(decode_protocol_list(&data[..data.len() - 1]).is_none());
+    }
+
     #[test]
     fn test_select_version() {
         let test_a = vec![
@@ -311,13 +371,17 @@ mod tests {
 
         rt.spawn(async move {
             let listener_addr = addr_receiver.await.unwrap();
-            let connect = TcpStream::connect(&listener_addr).await.unwrap();
+            let mut connect = TcpStream::connect(&listener_addr).await.unwrap();
 
             let mut message = ProtocolInfo::default();
             message.name = "test".to_owned();
             message.support_versions = client;
 
-            let (_, _, a) = client_select(connect, message).await.unwrap();
+            connect
+                .write_all(&client_select_request(message))
+                .await
+                .unwrap();
+            let (_, _, a) = client_select(connect).await.unwrap();
             let _res = sender_2.send(a);
         });
 
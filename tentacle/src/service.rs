@@ -1,5 +1,5 @@
 use futures::{
-    channel::mpsc,
+    channel::{mpsc, oneshot},
     prelude::*,
     stream::{FusedStream, StreamExt},
 };
@@ -13,24 +13,33 @@ use std::{
         Arc,
     },
     task::{Context, Poll},
+    time::Instant,
 };
 use tokio::prelude::{AsyncRead, AsyncWrite};
 
 #[cfg(not(target_arch = "wasm32"))]
-use crate::service::helper::Listener;
+use crate::service::helper::{Listener, SharedSecioConfig};
 use crate::{
     buffer::{Buffer, SendResult},
-    channel::{mpsc as priority_mpsc, mpsc::Priority},
+    channel::mpsc as priority_mpsc,
     context::{ServiceContext, SessionContext, SessionController},
-    error::{DialerErrorKind, ListenErrorKind, ProtocolHandleErrorKind, TransportErrorKind},
+    error::{
+        DeliveryErrorKind, DialerErrorKind, HandshakeErrorKind, ListenErrorKind,
+        ProtocolHandleErrorKind, TransportErrorKind,
+    },
     multiaddr::{Multiaddr, Protocol},
+    muxer::{BoxedMuxer, MplexSession, Muxer, YamuxMuxer},
     protocol_handle_stream::{
         ServiceProtocolEvent, ServiceProtocolStream, SessionProtocolEvent, SessionProtocolStream,
     },
     protocol_select::ProtocolInfo,
-    secio::{PublicKey, SecioKeyPair},
+    secio::{
+        error::SecioError, handshake::NegotiatedAlgorithms, key_rotation::KeyRotation, PublicKey,
+        SecioKeyPair,
+    },
     service::{
         config::{ServiceConfig, State},
+        delivery::{notify, DeliveryNotify},
         event::ServiceTask,
         future_task::{BoxedFutureTask, FutureTaskManager},
         helper::{HandshakeContext, Source},
@@ -39,19 +48,24 @@ use crate::{
     traits::ServiceHandle,
     transports::{MultiIncoming, MultiTransport, Transport},
     utils::extract_peer_id,
-    yamux::Config as YamuxConfig,
+    yamux::{Config as YamuxConfig, Session as YamuxSession},
     ProtocolId, SessionId,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{PoisonError, RwLock};
 
 pub(crate) mod config;
 mod control;
+pub(crate) mod delivery;
 pub(crate) mod event;
 pub(crate) mod future_task;
 mod helper;
 
+pub use crate::channel::Priority;
 pub use crate::service::{
     config::{BlockingFlag, ProtocolHandle, ProtocolMeta, TargetProtocol, TargetSession},
     control::{ServiceAsyncControl, ServiceControl},
+    delivery::DeliveryAck,
     event::{ProtocolEvent, ServiceError, ServiceEvent},
     helper::SessionType,
 };
@@ -81,6 +95,9 @@ pub struct Service<T> {
 
     dial_protocols: HashMap<Multiaddr, TargetProtocol>,
     config: ServiceConfig,
+    /// Secio config of listeners, keep the same as `config.secio_config`
+    #[cfg(not(target_arch = "wasm32"))]
+    listen_secio_config: SharedSecioConfig,
     /// service state
     state: State,
 
@@ -95,6 +112,8 @@ pub struct Service<T> {
     future_task_manager: Option<FutureTaskManager>,
     // To add a future task
     future_task_sender: Buffer<BoxedFutureTask>,
+    /// Stop the listeners, they are aborted when the listens are closed
+    listener_abort_handles: Vec<future::AbortHandle>,
 
     service_proto_handles: HashMap<ProtocolId, Buffer<ServiceProtocolEvent>>,
 
@@ -111,6 +130,8 @@ pub struct Service<T> {
     service_task_receiver: priority_mpsc::Receiver<ServiceTask>,
 
     shutdown: Arc<AtomicBool>,
+    /// Notify when the service has been shutdown
+    shutdown_waiters: Vec<oneshot::Sender<()>>,
 
     wait_handle: Vec<(
         Option<futures::channel::oneshot::Sender<()>>,
@@ -131,7 +152,10 @@ where
         config: ServiceConfig,
     ) -> Self {
         let (session_event_sender, session_event_receiver) = mpsc::channel(RECEIVED_SIZE);
-        let (task_sender, task_receiver) = priority_mpsc::channel(RECEIVED_BUFFER_SIZE);
+        let (task_sender, task_receiver) = priority_mpsc::channel_with_priority_levels(
+            RECEIVED_BUFFER_SIZE,
+            config.session_config.priority_levels,
+        );
         let proto_infos = protocol_configs
             .values()
             .map(|meta| {
@@ -160,6 +184,7 @@ where
                 transport
             },
             future_task_sender: Buffer::new(future_task_sender),
+            listener_abort_handles: Vec::new(),
             future_task_manager: Some(FutureTaskManager::new(
                 future_task_receiver,
                 shutdown.clone(),
@@ -181,9 +206,12 @@ where
                 key_pair,
                 shutdown.clone(),
             ),
+            #[cfg(not(target_arch = "wasm32"))]
+            listen_secio_config: Arc::new(RwLock::new(config.secio_config.clone())),
             config,
             service_task_receiver: task_receiver,
             shutdown,
+            shutdown_waiters: Vec::new(),
             wait_handle: Vec::new(),
         }
     }
@@ -252,18 +280,21 @@ where
     fn spawn_listener(&mut self, incoming: MultiIncoming, listen_address: Multiaddr) {
         let listener = Listener {
             inner: incoming,
-            key_pair: self.service_context.key_pair().cloned(),
+            secio_config: Arc::clone(&self.listen_secio_config),
             event_sender: self.session_event_sender.clone(),
             max_frame_length: self.config.max_frame_length,
             timeout: self.config.timeout,
             listen_addr: listen_address,
+            muxers: self.config.muxers.clone(),
+            pre_shared_key: self.config.pre_shared_key.clone(),
+            verify_remote_protocols: self.config.verify_remote_protocols.clone(),
             future_task_sender: self.future_task_sender.clone_sender(),
         };
+        let (listener, abort_handle) = future::abortable(listener.for_each(|_| future::ready(())));
+        self.listener_abort_handles.push(abort_handle);
         let mut sender = self.future_task_sender.clone_sender();
         crate::runtime::spawn(async move {
-            let res = sender
-                .send(Box::pin(listener.for_each(|_| future::ready(()))))
-                .await;
+            let res = sender.send(Box::pin(listener.map(|_| ()))).await;
             if res.is_err() {
                 trace!("spawn listener fail")
             }
@@ -318,9 +349,12 @@ where
         self.dial_protocols.insert(address.clone(), target);
         let dial_future = self.multi_transport.dial(address.clone())?;
 
-        let key_pair = self.service_context.key_pair().cloned();
+        let secio_config = self.config.secio_config.clone();
         let timeout = self.config.timeout;
         let max_frame_length = self.config.max_frame_length;
+        let muxers = self.config.muxers.clone();
+        let pre_shared_key = self.config.pre_shared_key.clone();
+        let verify_remote_protocols = self.config.verify_remote_protocols.clone();
 
         let mut sender = self.session_event_sender.clone();
         let task = async move {
@@ -332,10 +366,13 @@ where
                         ty: SessionType::Outbound,
                         remote_address: addr,
                         listen_address: None,
-                        key_pair,
+                        secio_config,
                         event_sender: sender,
                         max_frame_length,
                         timeout,
+                        muxers,
+                        pre_shared_key,
+                        verify_remote_protocols,
                     }
                     .handshake(incoming)
                     .await;
@@ -399,7 +436,7 @@ where
                     // clean message and try to close this session
                     control.buffer.clear();
                     let id = control.inner.id;
-                    control.push(Priority::High, SessionEvent::SessionClose { id });
+                    control.push(Priority::HIGH, SessionEvent::SessionClose { id });
                     control.try_send(cx);
                 }
             }
@@ -476,7 +513,7 @@ where
 
         if error {
             // if handle panic, close service
-            self.handle_service_task(cx, ServiceTask::Shutdown(false), Priority::High);
+            self.handle_service_task(cx, ServiceTask::Shutdown(false), Priority::HIGH);
         }
     }
 
@@ -525,6 +562,7 @@ where
         handles
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn handle_message(
         &mut self,
         cx: &mut Context,
@@ -532,6 +570,8 @@ where
         proto_id: ProtocolId,
         priority: Priority,
         data: Bytes,
+        ack: Option<DeliveryNotify>,
+        deadline: Option<Instant>,
     ) {
         let data = match self.before_sends.get(&proto_id) {
             Some(function) => function(data),
@@ -540,12 +580,13 @@ where
 
         match target {
             // Send data to the specified protocol for the specified session.
-            TargetSession::Single(id) => {
-                if let Some(control) = self.sessions.get_mut(&id) {
-                    control.push_message(proto_id, priority, data);
+            TargetSession::Single(id) => match self.sessions.get_mut(&id) {
+                Some(control) => {
+                    control.push_message(proto_id, priority, data, ack, deadline);
                     control.try_send(cx);
                 }
-            }
+                None => notify(ack, Err(DeliveryErrorKind::SessionClosed)),
+            },
             // Send data to the specified protocol for the specified sessions.
             TargetSession::Multi(ids) => {
                 for id in ids {
@@ -556,7 +597,7 @@ where
                         data.len()
                     );
                     if let Some(control) = self.sessions.get_mut(&id) {
-                        control.push_message(proto_id, priority, data.clone());
+                        control.push_message(proto_id, priority, data.clone(), None, deadline);
                         control.try_send(cx);
                     }
                 }
@@ -570,13 +611,59 @@ where
                     data.len()
                 );
                 for control in self.sessions.values_mut() {
-                    control.push_message(proto_id, priority, data.clone());
+                    control.push_message(proto_id, priority, data.clone(), None, deadline);
                     control.try_send(cx);
                 }
             }
         }
     }
 
+    /// Send data to the target sessions whose pending data size does not exceed the threshold,
+    /// return the skipped sessions
+    fn handle_backpressure_broadcast(
+        &mut self,
+        cx: &mut Context,
+        target: TargetSession,
+        proto_id: ProtocolId,
+        priority: Priority,
+        data: Bytes,
+        max_pending_data_size: usize,
+    ) -> Vec<SessionId> {
+        let ids = match target {
+            TargetSession::All => self.sessions.keys().cloned().collect(),
+            TargetSession::Single(id) => vec![id],
+            TargetSession::Multi(ids) => ids,
+        };
+        let mut skipped = Vec::new();
+        let ids = ids
+            .into_iter()
+            .filter(|id| match self.sessions.get(id) {
+                Some(control) if control.inner.pending_data_size() > max_pending_data_size => {
+                    skipped.push(*id);
+                    false
+                }
+                Some(_) => true,
+                None => false,
+            })
+            .collect();
+        if !skipped.is_empty() {
+            debug!(
+                "broadcast proto [{}] skip sessions {:?}, max pending data size: {}",
+                proto_id, skipped, max_pending_data_size
+            );
+        }
+        self.handle_message(
+            cx,
+            TargetSession::Multi(ids),
+            proto_id,
+            priority,
+            data,
+            None,
+            None,
+        );
+        skipped
+    }
+
     /// Handshake
     #[inline]
     fn handshake<H>(
@@ -592,10 +679,13 @@ where
             ty,
             remote_address,
             listen_address,
-            key_pair: self.service_context.key_pair().cloned(),
+            secio_config: self.config.secio_config.clone(),
             event_sender: self.session_event_sender.clone(),
             max_frame_length: self.config.max_frame_length,
             timeout: self.config.timeout,
+            muxers: self.config.muxers.clone(),
+            pre_shared_key: self.config.pre_shared_key.clone(),
+            verify_remote_protocols: self.config.verify_remote_protocols.clone(),
         }
         .handshake(socket);
 
@@ -631,14 +721,18 @@ where
 
     /// Session open
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     fn session_open<H>(
         &mut self,
         cx: &mut Context,
         mut handle: H,
         remote_pubkey: Option<PublicKey>,
+        negotiated: Option<NegotiatedAlgorithms>,
+        early_data: Option<Bytes>,
         mut address: Multiaddr,
         ty: SessionType,
         listen_addr: Option<Multiaddr>,
+        muxer: Muxer,
     ) where
         H: AsyncRead + AsyncWrite + Send + 'static + Unpin,
     {
@@ -665,6 +759,7 @@ where
                             ServiceError::DialerError {
                                 error: DialerErrorKind::RepeatedConnection(context.inner.id),
                                 address,
+                                report: None,
                             },
                         );
                     } else {
@@ -688,6 +783,7 @@ where
                                 ServiceError::DialerError {
                                     error: DialerErrorKind::PeerIdNotMatch,
                                     address,
+                                    report: None,
                                 },
                             );
                             return;
@@ -701,9 +797,24 @@ where
 
         self.generate_next_session();
 
+        let socket: BoxedMuxer = match muxer {
+            Muxer::Yamux => Box::new(YamuxMuxer::new(YamuxSession::new(
+                handle,
+                self.config.session_config.yamux_config,
+                ty.into(),
+            ))),
+            Muxer::Mplex => Box::new(MplexSession::new(
+                handle,
+                self.config.session_config.mplex_config,
+            )),
+        };
         let session_closed = Arc::new(AtomicBool::new(false));
         let pending_data_size = Arc::new(AtomicUsize::new(0));
-        let (service_event_sender, service_event_receiver) = priority_mpsc::channel(SEND_SIZE);
+        let (service_event_sender, service_event_receiver) =
+            priority_mpsc::channel_with_priority_levels(
+                SEND_SIZE,
+                self.config.session_config.priority_levels,
+            );
         let session_control = SessionController::new(
             service_event_sender.clone(),
             Arc::new(SessionContext::new(
@@ -711,8 +822,11 @@ where
                 address,
                 ty,
                 remote_pubkey,
+                negotiated,
+                early_data,
                 session_closed,
                 pending_data_size,
+                socket.control(),
             )),
         );
 
@@ -759,7 +873,7 @@ where
         .event(self.config.event.clone());
 
         let mut session = Session::new(
-            handle,
+            socket,
             self.session_event_sender.clone(),
             service_event_receiver,
             meta,
@@ -767,20 +881,34 @@ where
         );
 
         if ty.is_outbound() {
+            // Skip the protocols that remote doesn't support if it announces its protocol list
+            let remote_protocols = session_context.remote_protocols();
+            let remote_support = |meta: &ProtocolMeta| {
+                remote_protocols
+                    .as_ref()
+                    .map(|infos| infos.iter().any(|info| meta.compatible_with(info)))
+                    .unwrap_or(true)
+            };
+            let mut open = |meta: &ProtocolMeta| {
+                let name = meta.name();
+                if remote_support(meta) {
+                    session.open_proto_stream(&name);
+                } else {
+                    debug!("remote doesn't support protocol {}, skip it", name);
+                }
+            };
             match target {
                 TargetProtocol::All => {
-                    self.protocol_configs
-                        .values()
-                        .for_each(|meta| session.open_proto_stream(&meta.name()));
+                    self.protocol_configs.values().for_each(open);
                 }
                 TargetProtocol::Single(proto_id) => {
                     if let Some(meta) = self.protocol_configs.get(&proto_id) {
-                        session.open_proto_stream(&meta.name());
+                        open(meta);
                     }
                 }
                 TargetProtocol::Multi(proto_ids) => proto_ids.into_iter().for_each(|id| {
                     if let Some(meta) = self.protocol_configs.get(&id) {
-                        session.open_proto_stream(&meta.name());
+                        open(meta);
                     }
                 }),
             }
@@ -799,7 +927,7 @@ where
     fn session_close(&mut self, cx: &mut Context, id: SessionId, source: Source) {
         if source == Source::External {
             if let Some(control) = self.sessions.get_mut(&id) {
-                control.push(Priority::High, SessionEvent::SessionClose { id });
+                control.push(Priority::HIGH, SessionEvent::SessionClose { id });
                 debug!("try close service session [{}] ", id);
                 control.try_send(cx);
             }
@@ -835,7 +963,7 @@ where
         if source == Source::External {
             if let Some(control) = self.sessions.get_mut(&id) {
                 control.push(
-                    Priority::High,
+                    Priority::HIGH,
                     SessionEvent::ProtocolOpen {
                         id,
                         proto_id,
@@ -907,7 +1035,7 @@ where
         if source == Source::External {
             if let Some(control) = self.sessions.get_mut(&session_id) {
                 control.push(
-                    Priority::High,
+                    Priority::HIGH,
                     SessionEvent::ProtocolClose {
                         id: session_id,
                         proto_id,
@@ -1018,20 +1146,49 @@ where
         match event {
             SessionEvent::SessionClose { id } => self.session_close(cx, id, Source::Internal),
             SessionEvent::HandshakeSuccess {
-                handle,
+                mut handle,
                 public_key,
+                negotiated,
+                early_data,
                 address,
                 ty,
                 listen_address,
+                muxer,
             } => {
                 if ty.is_outbound() {
                     self.state.decrease();
                 }
-                if !self.reached_max_connection_limit() {
-                    self.session_open(cx, handle, public_key, address, ty, listen_address);
+                if self.state == State::PreShutdown {
+                    // no new session is opened during shutdown
+                    debug!(
+                        "service is shutting down, close the new session of {}",
+                        address
+                    );
+                    self.dial_protocols.remove(&address);
+                    if let Poll::Ready(Err(e)) = Pin::new(&mut handle).poll_shutdown(cx) {
+                        trace!("handle poll shutdown err {}", e)
+                    }
+                } else if !self.reached_max_connection_limit() {
+                    self.session_open(
+                        cx,
+                        handle,
+                        public_key,
+                        negotiated,
+                        early_data,
+                        address,
+                        ty,
+                        listen_address,
+                        muxer,
+                    );
                 }
             }
-            SessionEvent::HandshakeError { ty, error, address } => {
+            SessionEvent::HandshakeError {
+                ty,
+                listen_address,
+                error,
+                report,
+                address,
+            } => {
                 if ty.is_outbound() {
                     self.state.decrease();
                     self.dial_protocols.remove(&address);
@@ -1040,6 +1197,23 @@ where
                         ServiceError::DialerError {
                             address,
                             error: DialerErrorKind::HandshakeError(error),
+                            report: Some(report),
+                        },
+                    )
+                } else if let Some(listen_address) = listen_address {
+                    // dialing self, the dial side has reported it
+                    if let HandshakeErrorKind::SecioError(SecioError::ConnectSelf) = error {
+                        return;
+                    }
+                    self.handle.handle_error(
+                        &mut self.service_context,
+                        ServiceError::ListenError {
+                            address: listen_address,
+                            error: ListenErrorKind::HandshakeError {
+                                address,
+                                error,
+                                report,
+                            },
                         },
                     )
                 }
@@ -1087,6 +1261,7 @@ where
                     ServiceError::DialerError {
                         address,
                         error: DialerErrorKind::TransportError(error),
+                        report: None,
                     },
                 )
             }
@@ -1161,7 +1336,7 @@ where
                     ServiceError::ProtocolHandleError { error, proto_id },
                 );
                 // if handle panic, close service
-                self.handle_service_task(cx, ServiceTask::Shutdown(false), Priority::High);
+                self.handle_service_task(cx, ServiceTask::Shutdown(false), Priority::HIGH);
             }
             _ => (),
         }
@@ -1174,8 +1349,46 @@ where
                 target,
                 proto_id,
                 data,
+                ack,
+                deadline,
             } => {
-                self.handle_message(cx, target, proto_id, priority, data);
+                self.handle_message(cx, target, proto_id, priority, data, ack, deadline);
+            }
+            ServiceTask::BackpressureBroadcast {
+                target,
+                proto_id,
+                data,
+                max_pending_data_size,
+                skipped,
+            } => {
+                let skipped_sessions = self.handle_backpressure_broadcast(
+                    cx,
+                    target,
+                    proto_id,
+                    priority,
+                    data,
+                    max_pending_data_size,
+                );
+                let _ignore = skipped.send(skipped_sessions);
+            }
+            ServiceTask::Dial { address, .. } if self.state == State::PreShutdown => {
+                self.handle.handle_error(
+                    &mut self.service_context,
+                    ServiceError::DialerError {
+                        address,
+                        error: DialerErrorKind::TransportError(shutting_down_error()),
+                        report: None,
+                    },
+                );
+            }
+            ServiceTask::Listen { address } if self.state == State::PreShutdown => {
+                self.handle.handle_error(
+                    &mut self.service_context,
+                    ServiceError::ListenError {
+                        address,
+                        error: ListenErrorKind::TransportError(shutting_down_error()),
+                    },
+                );
             }
             ServiceTask::Dial { address, target } => {
                 if !self.dial_protocols.contains_key(&address) {
@@ -1185,6 +1398,7 @@ where
                             ServiceError::DialerError {
                                 address,
                                 error: DialerErrorKind::TransportError(e),
+                                report: None,
                             },
                         );
                     }
@@ -1276,20 +1490,13 @@ where
                 session_id,
                 proto_id,
             } => self.protocol_close(cx, session_id, proto_id, Source::External),
+            ServiceTask::RotateKeyPair {
+                key_pair,
+                keep_sessions,
+            } => self.rotate_key_pair(cx, key_pair, keep_sessions),
             ServiceTask::Shutdown(quick) => {
                 self.state.pre_shutdown();
-
-                for address in self.listens.drain() {
-                    self.handle.handle_event(
-                        &mut self.service_context,
-                        ServiceEvent::ListenClose { address },
-                    )
-                }
-                // clear upnp register
-                #[cfg(all(not(target_arch = "wasm32"), feature = "upnp"))]
-                if let Some(client) = self.igd_client.as_mut() {
-                    client.clear()
-                };
+                self.close_listens();
                 self.future_task_sender.clear();
 
                 let sessions = self.sessions.keys().cloned().collect::<Vec<SessionId>>();
@@ -1311,9 +1518,122 @@ where
                         .for_each(|i| self.session_close(cx, i, Source::External));
                 }
             }
+            ServiceTask::GracefulShutdown { timeout, done } => {
+                self.shutdown_waiters.push(done);
+                if self.state == State::PreShutdown {
+                    // already shutting down, just wait for it
+                    return;
+                }
+                self.state.pre_shutdown();
+                self.close_listens();
+
+                // Send go away first, so remote stops opening new streams during the drain
+                let mut flushed = Vec::with_capacity(self.sessions.len());
+                for control in self.sessions.values_mut() {
+                    let (sender, receiver) = oneshot::channel();
+                    control.push(Priority::HIGH, SessionEvent::Drain { flushed: sender });
+                    control.try_send(cx);
+                    flushed.push(receiver);
+                }
+                let control = self.service_context.control().clone();
+                let task = async move {
+                    if crate::runtime::timeout(timeout, future::join_all(flushed))
+                        .await
+                        .is_err()
+                    {
+                        debug!("graceful shutdown drain timeout")
+                    }
+                    if control.close().is_err() {
+                        trace!("graceful shutdown close service err")
+                    }
+                };
+                self.send_future_task(cx, Box::pin(task));
+            }
+        }
+    }
+
+    /// Replace the key pair of secio and sign the link from the old peer id to the new one
+    fn rotate_key_pair(&mut self, cx: &mut Context, key_pair: SecioKeyPair, keep_sessions: bool) {
+        let config = match self.config.secio_config.take() {
+            Some(config) => config,
+            None => {
+                debug!("secio is disabled, ignore key rotation");
+                return;
+            }
+        };
+        let old_key_pair = config.key_pair().clone();
+        if old_key_pair.peer_id() == key_pair.peer_id() {
+            self.config.secio_config = Some(config);
+            return;
+        }
+
+        let seq = self
+            .service_context
+            .key_rotation()
+            .map(|envelope| envelope.seq() + 1)
+            .unwrap_or(1);
+        let key_rotation = KeyRotation::new(old_key_pair.peer_id(), key_pair.peer_id(), seq)
+            .into_signed_envelope(&old_key_pair)
+            .expect("signed by the key pair of old peer id");
+        debug!(
+            "rotate key pair from {:?} to {:?}",
+            old_key_pair.peer_id(),
+            key_pair.peer_id()
+        );
+
+        let config = config.replace_key_pair(key_pair.clone());
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            *self
+                .listen_secio_config
+                .write()
+                .unwrap_or_else(PoisonError::into_inner) = Some(config.clone());
+        }
+        self.config.secio_config = Some(config);
+        self.service_context
+            .update_key_pair(key_pair.clone(), key_rotation.clone());
+
+        for buffer in self.service_proto_handles.values_mut() {
+            buffer.push(ServiceProtocolEvent::UpdateKeyPair {
+                key_pair: key_pair.clone(),
+                key_rotation: key_rotation.clone(),
+            });
+        }
+        for buffer in self.session_proto_handles.values_mut() {
+            buffer.push(SessionProtocolEvent::UpdateKeyPair {
+                key_pair: key_pair.clone(),
+                key_rotation: key_rotation.clone(),
+            });
+        }
+        self.distribute_to_user_level(cx);
+
+        if !keep_sessions {
+            let sessions = self.sessions.keys().cloned().collect::<Vec<SessionId>>();
+            sessions
+                .into_iter()
+                .for_each(|id| self.session_close(cx, id, Source::External));
         }
     }
 
+    /// Close all listens and clear upnp register
+    fn close_listens(&mut self) {
+        // stop accepting new connections
+        for handle in self.listener_abort_handles.drain(..) {
+            handle.abort();
+        }
+        for address in self.listens.drain() {
+            self.handle.handle_event(
+                &mut self.service_context,
+                ServiceEvent::ListenClose { address },
+            )
+        }
+        // clear upnp register
+        #[cfg(all(not(target_arch = "wasm32"), feature = "upnp"))]
+        if let Some(client) = self.igd_client.as_mut() {
+            client.clear()
+        };
+    }
+
     #[inline]
     fn user_task_poll(&mut self, cx: &mut Context) -> Poll<Option<()>> {
         if self.service_task_receiver.is_terminated() {
@@ -1373,6 +1693,10 @@ where
         }
 
         if self.wait_handle.is_empty() {
+            for done in self.shutdown_waiters.drain(..) {
+                // don't care about it
+                let _ignore = done.send(());
+            }
             Poll::Ready(None)
         } else {
             Poll::Pending
@@ -1460,3 +1784,11 @@ where
         }
     }
 }
+
+/// Error reported for dial/listen tasks received after graceful shutdown started
+fn shutting_down_error() -> TransportErrorKind {
+    TransportErrorKind::Io(::std::io::Error::new(
+        ::std::io::ErrorKind::Other,
+        "service is shutting down",
+    ))
+}
@@ -1,5 +1,11 @@
 use crate::{
-    builder::{BeforeReceiveFn, CodecFn, NameFn, SelectVersionFn, SessionHandleFn},
+    builder::{
+        BeforeReceiveFn, CodecFn, NameFn, SelectVersionFn, SessionHandleFn, VerifyProtocolsFn,
+    },
+    channel::DEFAULT_PRIORITY_LEVELS,
+    muxer::{MplexConfig, Muxer},
+    protocol_select::{select_version, ProtocolInfo},
+    secio::{handshake::Config as SecioConfig, pnet::PreSharedKey},
     traits::{Codec, ProtocolSpawn, ServiceProtocol, SessionProtocol},
     yamux::config::Config as YamuxConfig,
     ProtocolId, SessionId,
@@ -13,6 +19,16 @@ pub(crate) struct ServiceConfig {
     pub timeout: Duration,
     pub session_config: SessionConfig,
     pub max_frame_length: usize,
+    /// Multiplexers in order of preference
+    pub muxers: Vec<Muxer>,
+    /// Secio config, None means encryption is disabled
+    pub secio_config: Option<SecioConfig>,
+    /// Private network key
+    pub pre_shared_key: Option<PreSharedKey>,
+    /// Send the local protocol list with the secio handshake
+    pub announce_protocols: bool,
+    /// Check the protocol list of remote
+    pub verify_remote_protocols: Option<VerifyProtocolsFn>,
     /// event output or callback output
     pub event: HashSet<ProtocolId>,
     pub keep_buffer: bool,
@@ -30,6 +46,11 @@ impl Default for ServiceConfig {
             timeout: Duration::from_secs(10),
             session_config: SessionConfig::default(),
             max_frame_length: 1024 * 1024 * 8,
+            muxers: vec![Muxer::Yamux],
+            secio_config: None,
+            pre_shared_key: None,
+            announce_protocols: false,
+            verify_remote_protocols: None,
             event: HashSet::default(),
             keep_buffer: false,
             #[cfg(all(not(target_arch = "wasm32"), feature = "upnp"))]
@@ -45,10 +66,13 @@ impl Default for ServiceConfig {
 #[derive(Clone, Copy)]
 pub(crate) struct SessionConfig {
     pub yamux_config: YamuxConfig,
+    pub mplex_config: MplexConfig,
     /// default is 24Mb
     pub send_buffer_size: usize,
     /// default is 24Mb
     pub recv_buffer_size: usize,
+    /// The number of message priority levels, default is 2
+    pub priority_levels: u8,
 }
 
 impl SessionConfig {
@@ -71,6 +95,8 @@ impl Default for SessionConfig {
             recv_buffer_size: MAX_BUF_SIZE,
             send_buffer_size: MAX_BUF_SIZE,
             yamux_config: YamuxConfig::default(),
+            mplex_config: MplexConfig::default(),
+            priority_levels: DEFAULT_PRIORITY_LEVELS,
         }
     }
 }
@@ -195,6 +221,18 @@ impl ProtocolMeta {
     pub fn blocking_flag(&self) -> BlockingFlag {
         self.flag
     }
+
+    /// Whether the remote protocol has the same name and a version that can be selected
+    pub(crate) fn compatible_with(&self, remote: &ProtocolInfo) -> bool {
+        if remote.name != self.name() {
+            return false;
+        }
+        let local = &self.inner.support_versions;
+        (self.inner.select_version)()
+            .map(|f| f(local, &remote.support_versions))
+            .unwrap_or_else(|| select_version(local, &remote.support_versions))
+            .is_some()
+    }
 }
 
 pub(crate) struct Meta {
@@ -204,6 +242,7 @@ pub(crate) struct Meta {
     pub(crate) codec: CodecFn,
     pub(crate) select_version: SelectVersionFn,
     pub(crate) before_receive: BeforeReceiveFn,
+    pub(crate) stream_priority: Option<u8>,
     pub(crate) spawn: Option<Box<dyn ProtocolSpawn + Send + Sync + 'static>>,
 }
 
@@ -414,6 +453,7 @@ impl State {
 #[cfg(test)]
 mod test {
     use super::{BlockingFlag, State};
+    use crate::{builder::MetaBuilder, protocol_select::ProtocolInfo};
 
     #[test]
     fn test_state_no_forever() {
@@ -474,4 +514,29 @@ mod test {
         assert_eq!(p.received(), false);
         assert_eq!(p.notify(), false);
     }
+
+    #[test]
+    fn test_compatible_with() {
+        let meta = MetaBuilder::new()
+            .id(1.into())
+            .support_versions(vec!["0.1".to_owned(), "0.2".to_owned()])
+            .build();
+
+        let info = |name: &str, versions: &[&str]| {
+            ProtocolInfo::new(name, versions.iter().map(|v| (*v).to_owned()).collect())
+        };
+        assert!(meta.compatible_with(&info("/p2p/1", &["0.2", "0.3"])));
+        assert!(!meta.compatible_with(&info("/p2p/1", &["0.3"])));
+        assert!(!meta.compatible_with(&info("/p2p/2", &["0.1"])));
+
+        let meta = MetaBuilder::new()
+            .id(1.into())
+            .select_version(|| {
+                Some(Box::new(|_: &[String], _: &[String]| {
+                    Some("0.1".to_owned())
+                }))
+            })
+            .build();
+        assert!(meta.compatible_with(&info("/p2p/1", &["0.3"])));
+    }
 }
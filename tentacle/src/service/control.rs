@@ -1,17 +1,22 @@
-use futures::prelude::*;
+use futures::{channel::oneshot, prelude::*};
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{
     collections::HashMap,
     sync::{atomic::Ordering, Arc},
 };
 
 use crate::{
-    channel::{mpsc, QuickSinkExt},
+    channel::{mpsc, Priority, QuickSinkExt},
     error::SendErrorKind,
     multiaddr::Multiaddr,
     protocol_select::ProtocolInfo,
-    service::{event::ServiceTask, TargetProtocol, TargetSession},
+    secio::SecioKeyPair,
+    service::{
+        delivery::{delivery_channel, DeliveryAck},
+        event::ServiceTask,
+        TargetProtocol, TargetSession,
+    },
     ProtocolId, SessionId,
 };
 use bytes::Bytes;
@@ -42,32 +47,31 @@ impl ServiceControl {
     }
 
     /// Send raw event
+    #[inline]
     pub(crate) fn send(&self, event: ServiceTask) -> Result {
-        if self.closed.load(Ordering::SeqCst) {
-            return Err(SendErrorKind::BrokenPipe);
-        }
-        self.task_sender.try_send(event).map_err(|err| {
-            if err.is_full() {
-                SendErrorKind::WouldBlock
-            } else {
-                SendErrorKind::BrokenPipe
-            }
-        })
+        self.send_with_priority(event, Priority::NORMAL)
     }
 
     /// Send raw event on quick channel
     #[inline]
     fn quick_send(&self, event: ServiceTask) -> Result {
+        self.send_with_priority(event, Priority::HIGH)
+    }
+
+    /// Send raw event with the specified priority
+    fn send_with_priority(&self, event: ServiceTask, priority: Priority) -> Result {
         if self.closed.load(Ordering::SeqCst) {
             return Err(SendErrorKind::BrokenPipe);
         }
-        self.task_sender.try_quick_send(event).map_err(|err| {
-            if err.is_full() {
-                SendErrorKind::WouldBlock
-            } else {
-                SendErrorKind::BrokenPipe
-            }
-        })
+        self.task_sender
+            .try_send_with_priority(event, priority)
+            .map_err(|err| {
+                if err.is_full() {
+                    SendErrorKind::WouldBlock
+                } else {
+                    SendErrorKind::BrokenPipe
+                }
+            })
     }
 
     /// Get service protocol message, Map(ID, Name), but can't modify
@@ -94,6 +98,20 @@ impl ServiceControl {
         self.quick_send(ServiceTask::Disconnect { session_id })
     }
 
+    /// Replace the key pair of secio, the new handshakes use the new identity.
+    ///
+    /// The sessions established with the old key pair are closed unless `keep_sessions` is true,
+    /// the link from the old peer id to the new one signed by the old key pair can be got from
+    /// `ServiceContext::key_rotation` after that.
+    ///
+    /// Do nothing if secio is disabled
+    pub fn rotate_key_pair(&self, key_pair: SecioKeyPair, keep_sessions: bool) -> Result {
+        self.send(ServiceTask::RotateKeyPair {
+            key_pair,
+            keep_sessions,
+        })
+    }
+
     /// Send message
     #[inline]
     pub fn send_message_to(
@@ -105,6 +123,38 @@ impl ServiceControl {
         self.filter_broadcast(TargetSession::Single(session_id), proto_id, data)
     }
 
+    /// Send message, it will be dropped if not sent to the underlying yamux stream within `ttl`
+    #[inline]
+    pub fn send_message_to_with_ttl(
+        &self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        data: Bytes,
+        ttl: Duration,
+    ) -> Result {
+        self.filter_broadcast_with_ttl(TargetSession::Single(session_id), proto_id, data, ttl)
+    }
+
+    /// Send message, and get a future which resolves when the message has been written to
+    /// the underlying yamux stream, or fails with the reason why it can't be written
+    #[inline]
+    pub fn send_message_to_with_ack(
+        &self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        data: Bytes,
+    ) -> std::result::Result<DeliveryAck, SendErrorKind> {
+        let (ack, receiver) = delivery_channel();
+        self.send(ServiceTask::ProtocolMessage {
+            target: TargetSession::Single(session_id),
+            proto_id,
+            data,
+            ack: Some(ack),
+            deadline: None,
+        })?;
+        Ok(receiver)
+    }
+
     /// Send message on quick channel
     #[inline]
     pub fn quick_send_message_to(
@@ -128,9 +178,54 @@ impl ServiceControl {
             target,
             proto_id,
             data,
+            ack: None,
+            deadline: None,
+        })
+    }
+
+    /// Send data to the specified protocol for the specified sessions,
+    /// it will be dropped if not sent to the underlying yamux stream within `ttl`
+    #[inline]
+    pub fn filter_broadcast_with_ttl(
+        &self,
+        target: TargetSession,
+        proto_id: ProtocolId,
+        data: Bytes,
+        ttl: Duration,
+    ) -> Result {
+        self.send(ServiceTask::ProtocolMessage {
+            target,
+            proto_id,
+            data,
+            ack: None,
+            deadline: Some(Instant::now() + ttl),
         })
     }
 
+    /// Send data to the specified protocol for the specified sessions, but skip the sessions
+    /// whose pending data size exceeds `max_pending_data_size`, so that slow peers will not
+    /// be killed by `SessionBlocked`
+    ///
+    /// The returned receiver reports the skipped sessions
+    #[inline]
+    pub fn filter_broadcast_with_backpressure(
+        &self,
+        target: TargetSession,
+        proto_id: ProtocolId,
+        data: Bytes,
+        max_pending_data_size: usize,
+    ) -> std::result::Result<oneshot::Receiver<Vec<SessionId>>, SendErrorKind> {
+        let (skipped, receiver) = oneshot::channel();
+        self.send(ServiceTask::BackpressureBroadcast {
+            target,
+            proto_id,
+            data,
+            max_pending_data_size,
+            skipped,
+        })?;
+        Ok(receiver)
+    }
+
     /// Send data to the specified protocol for the specified sessions on quick channel.
     #[inline]
     pub fn quick_filter_broadcast(
@@ -143,9 +238,50 @@ impl ServiceControl {
             target,
             proto_id,
             data,
+            ack: None,
+            deadline: None,
         })
     }
 
+    /// Send message with the specified priority
+    #[inline]
+    pub fn send_message_to_with_priority(
+        &self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        priority: Priority,
+        data: Bytes,
+    ) -> Result {
+        self.filter_broadcast_with_priority(
+            TargetSession::Single(session_id),
+            proto_id,
+            priority,
+            data,
+        )
+    }
+
+    /// Send data to the specified protocol for the specified sessions with the specified priority,
+    /// the smaller the priority level, the earlier the data is sent
+    #[inline]
+    pub fn filter_broadcast_with_priority(
+        &self,
+        target: TargetSession,
+        proto_id: ProtocolId,
+        priority: Priority,
+        data: Bytes,
+    ) -> Result {
+        self.send_with_priority(
+            ServiceTask::ProtocolMessage {
+                target,
+                proto_id,
+                data,
+                ack: None,
+                deadline: None,
+            },
+            priority,
+        )
+    }
+
     /// Send a future task
     #[inline]
     pub fn future_task<T>(&self, task: T) -> Result
@@ -251,6 +387,24 @@ impl ServiceControl {
     pub fn shutdown(&self) -> Result {
         self.quick_send(ServiceTask::Shutdown(true))
     }
+
+    /// Shutdown service gracefully
+    ///
+    /// Order:
+    /// 1. close all listens and refuse new dials
+    /// 2. wait for all sessions to flush the pending messages, at most `timeout`
+    /// 3. close all session's protocol stream and all session
+    /// 4. close service
+    ///
+    /// The returned receiver will be notified when the service has been shutdown
+    pub fn shutdown_graceful(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<oneshot::Receiver<()>, SendErrorKind> {
+        let (done, receiver) = oneshot::channel();
+        self.send(ServiceTask::GracefulShutdown { timeout, done })?;
+        Ok(receiver)
+    }
 }
 
 impl From<ServiceControl> for ServiceAsyncControl {
@@ -283,26 +437,29 @@ pub struct ServiceAsyncControl {
 
 impl ServiceAsyncControl {
     /// Send raw event
+    #[inline]
     async fn send(&mut self, event: ServiceTask) -> Result {
-        if self.closed.load(Ordering::SeqCst) {
-            return Err(SendErrorKind::BrokenPipe);
-        }
-        self.task_sender.send(event).await.map_err(|_err| {
-            // await only return err when channel close
-            SendErrorKind::BrokenPipe
-        })
+        self.send_with_priority(event, Priority::NORMAL).await
     }
 
     /// Send raw event on quick channel
     #[inline]
     async fn quick_send(&mut self, event: ServiceTask) -> Result {
+        self.send_with_priority(event, Priority::HIGH).await
+    }
+
+    /// Send raw event with the specified priority
+    async fn send_with_priority(&mut self, event: ServiceTask, priority: Priority) -> Result {
         if self.closed.load(Ordering::SeqCst) {
             return Err(SendErrorKind::BrokenPipe);
         }
-        self.task_sender.quick_send(event).await.map_err(|_err| {
-            // await only return err when channel close
-            SendErrorKind::BrokenPipe
-        })
+        self.task_sender
+            .send_with_priority(event, priority)
+            .await
+            .map_err(|_err| {
+                // await only return err when channel close
+                SendErrorKind::BrokenPipe
+            })
     }
 
     /// Get service protocol message, Map(ID, Name), but can't modify
@@ -330,6 +487,21 @@ impl ServiceAsyncControl {
             .await
     }
 
+    /// Replace the key pair of secio, the new handshakes use the new identity.
+    ///
+    /// The sessions established with the old key pair are closed unless `keep_sessions` is true,
+    /// the link from the old peer id to the new one signed by the old key pair can be got from
+    /// `ServiceContext::key_rotation` after that.
+    ///
+    /// Do nothing if secio is disabled
+    pub async fn rotate_key_pair(&mut self, key_pair: SecioKeyPair, keep_sessions: bool) -> Result {
+        self.send(ServiceTask::RotateKeyPair {
+            key_pair,
+            keep_sessions,
+        })
+        .await
+    }
+
     /// Send message
     #[inline]
     pub async fn send_message_to(
@@ -342,6 +514,40 @@ impl ServiceAsyncControl {
             .await
     }
 
+    /// Send message, it will be dropped if not sent to the underlying yamux stream within `ttl`
+    #[inline]
+    pub async fn send_message_to_with_ttl(
+        &mut self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        data: Bytes,
+        ttl: Duration,
+    ) -> Result {
+        self.filter_broadcast_with_ttl(TargetSession::Single(session_id), proto_id, data, ttl)
+            .await
+    }
+
+    /// Send message, and get a future which resolves when the message has been written to
+    /// the underlying yamux stream, or fails with the reason why it can't be written
+    #[inline]
+    pub async fn send_message_to_with_ack(
+        &mut self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        data: Bytes,
+    ) -> std::result::Result<DeliveryAck, SendErrorKind> {
+        let (ack, receiver) = delivery_channel();
+        self.send(ServiceTask::ProtocolMessage {
+            target: TargetSession::Single(session_id),
+            proto_id,
+            data,
+            ack: Some(ack),
+            deadline: None,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
     /// Send message on quick channel
     #[inline]
     pub async fn quick_send_message_to(
@@ -366,10 +572,57 @@ impl ServiceAsyncControl {
             target,
             proto_id,
             data,
+            ack: None,
+            deadline: None,
         })
         .await
     }
 
+    /// Send data to the specified protocol for the specified sessions,
+    /// it will be dropped if not sent to the underlying yamux stream within `ttl`
+    #[inline]
+    pub async fn filter_broadcast_with_ttl(
+        &mut self,
+        target: TargetSession,
+        proto_id: ProtocolId,
+        data: Bytes,
+        ttl: Duration,
+    ) -> Result {
+        self.send(ServiceTask::ProtocolMessage {
+            target,
+            proto_id,
+            data,
+            ack: None,
+            deadline: Some(Instant::now() + ttl),
+        })
+        .await
+    }
+
+    /// Send data to the specified protocol for the specified sessions, but skip the sessions
+    /// whose pending data size exceeds `max_pending_data_size`, so that slow peers will not
+    /// be killed by `SessionBlocked`
+    ///
+    /// The returned receiver reports the skipped sessions
+    #[inline]
+    pub async fn filter_broadcast_with_backpressure(
+        &mut self,
+        target: TargetSession,
+        proto_id: ProtocolId,
+        data: Bytes,
+        max_pending_data_size: usize,
+    ) -> std::result::Result<oneshot::Receiver<Vec<SessionId>>, SendErrorKind> {
+        let (skipped, receiver) = oneshot::channel();
+        self.send(ServiceTask::BackpressureBroadcast {
+            target,
+            proto_id,
+            data,
+            max_pending_data_size,
+            skipped,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
     /// Send data to the specified protocol for the specified sessions on quick channel.
     #[inline]
     pub async fn quick_filter_broadcast(
@@ -382,10 +635,53 @@ impl ServiceAsyncControl {
             target,
             proto_id,
             data,
+            ack: None,
+            deadline: None,
         })
         .await
     }
 
+    /// Send message with the specified priority
+    #[inline]
+    pub async fn send_message_to_with_priority(
+        &mut self,
+        session_id: SessionId,
+        proto_id: ProtocolId,
+        priority: Priority,
+        data: Bytes,
+    ) -> Result {
+        self.filter_broadcast_with_priority(
+            TargetSession::Single(session_id),
+            proto_id,
+            priority,
+            data,
+        )
+        .await
+    }
+
+    /// Send data to the specified protocol for the specified sessions with the specified priority,
+    /// the smaller the priority level, the earlier the data is sent
+    #[inline]
+    pub async fn filter_broadcast_with_priority(
+        &mut self,
+        target: TargetSession,
+        proto_id: ProtocolId,
+        priority: Priority,
+        data: Bytes,
+    ) -> Result {
+        self.send_with_priority(
+            ServiceTask::ProtocolMessage {
+                target,
+                proto_id,
+                data,
+                ack: None,
+                deadline: None,
+            },
+            priority,
+        )
+        .await
+    }
+
     /// Send a future task
     #[inline]
     pub async fn future_task<T>(&mut self, task: T) -> Result
@@ -503,4 +799,23 @@ impl ServiceAsyncControl {
     pub async fn shutdown(&mut self) -> Result {
         self.quick_send(ServiceTask::Shutdown(true)).await
     }
+
+    /// Shutdown service gracefully
+    ///
+    /// Order:
+    /// 1. close all listens and refuse new dials
+    /// 2. wait for all sessions to flush the pending messages, at most `timeout`
+    /// 3. close all session's protocol stream and all session
+    /// 4. close service
+    ///
+    /// The returned receiver will be notified when the service has been shutdown
+    pub async fn shutdown_graceful(
+        &mut self,
+        timeout: Duration,
+    ) -> std::result::Result<oneshot::Receiver<()>, SendErrorKind> {
+        let (done, receiver) = oneshot::channel();
+        self.send(ServiceTask::GracefulShutdown { timeout, done })
+            .await?;
+        Ok(receiver)
+    }
 }
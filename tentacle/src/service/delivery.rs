@@ -0,0 +1,88 @@
+use futures::{channel::oneshot, prelude::*};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use crate::error::DeliveryErrorKind;
+
+pub(crate) type DeliveryResult = Result<(), DeliveryErrorKind>;
+
+/// The sender side of a delivery acknowledgement, travel with the message down to the sub stream
+pub(crate) type DeliveryNotify = oneshot::Sender<DeliveryResult>;
+
+/// Create a delivery acknowledgement pair
+pub(crate) fn delivery_channel() -> (DeliveryNotify, DeliveryAck) {
+    let (sender, receiver) = oneshot::channel();
+    (sender, DeliveryAck { inner: receiver })
+}
+
+/// Notify the sender of the message, if it cares about the result
+#[inline]
+pub(crate) fn notify(ack: Option<DeliveryNotify>, result: DeliveryResult) {
+    if let Some(sender) = ack {
+        // the receiver may have been dropped, don't care about it
+        let _ignore = sender.send(result);
+    }
+}
+
+/// Whether the message deadline has been reached
+#[inline]
+pub(crate) fn is_expired(deadline: Option<Instant>) -> bool {
+    deadline
+        .map(|deadline| deadline <= Instant::now())
+        .unwrap_or(false)
+}
+
+/// A future that resolves when the message has been written to the underlying yamux stream,
+/// or fails with the reason why it can't be written
+///
+/// If the message is discarded anywhere on the way, such as the session being killed,
+/// it will resolve with `DeliveryErrorKind::Dropped`
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct DeliveryAck {
+    inner: oneshot::Receiver<DeliveryResult>,
+}
+
+impl Future for DeliveryAck {
+    type Output = DeliveryResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.inner.poll_unpin(cx) {
+            Poll::Ready(Ok(res)) => Poll::Ready(res),
+            Poll::Ready(Err(_)) => Poll::Ready(Err(DeliveryErrorKind::Dropped)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{delivery_channel, is_expired, notify};
+    use crate::error::DeliveryErrorKind;
+    use futures::executor::block_on;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_delivery_ack() {
+        let (sender, ack) = delivery_channel();
+        notify(Some(sender), Ok(()));
+        assert_eq!(block_on(ack), Ok(()));
+
+        let (sender, ack) = delivery_channel();
+        notify(Some(sender), Err(DeliveryErrorKind::StreamClosed));
+        assert_eq!(block_on(ack), Err(DeliveryErrorKind::StreamClosed));
+
+        let (sender, ack) = delivery_channel();
+        drop(sender);
+        assert_eq!(block_on(ack), Err(DeliveryErrorKind::Dropped));
+    }
+
+    #[test]
+    fn test_is_expired() {
+        assert!(!is_expired(None));
+        assert!(is_expired(Some(Instant::now())));
+        assert!(!is_expired(Some(Instant::now() + Duration::from_secs(60))));
+    }
+}
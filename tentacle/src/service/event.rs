@@ -1,15 +1,19 @@
 use std::fmt;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::{
     context::SessionContext,
-    error::{DialerErrorKind, ListenErrorKind, ProtocolHandleErrorKind},
+    error::{DialerErrorKind, HandshakeReport, ListenErrorKind, ProtocolHandleErrorKind},
     multiaddr::Multiaddr,
-    service::{future_task::BoxedFutureTask, TargetProtocol, TargetSession},
+    secio::SecioKeyPair,
+    service::{
+        delivery::DeliveryNotify, future_task::BoxedFutureTask, TargetProtocol, TargetSession,
+    },
     ProtocolId, SessionId,
 };
 use bytes::Bytes;
+use futures::channel::oneshot;
 
 /// Error generated by the Service
 #[derive(Debug)]
@@ -20,6 +24,8 @@ pub enum ServiceError {
         address: Multiaddr,
         /// error
         error: DialerErrorKind,
+        /// Where the handshake failed or stalled, only on `DialerErrorKind::HandshakeError`
+        report: Option<HandshakeReport>,
     },
     /// When listen error
     ListenError {
@@ -56,6 +62,9 @@ pub enum ServiceError {
         /// Session context
         session_context: Arc<SessionContext>,
         /// error, such as `InvalidData`
+        ///
+        /// If the remote closes the session with an error code or violates the protocol,
+        /// the inner error is a `yamux::Error`, such as `RemoteGoAwayWithError`
         error: std::io::Error,
     },
     /// Protocol handle error, will cause memory leaks/abnormal CPU usage
@@ -145,6 +154,24 @@ pub(crate) enum ServiceTask {
         proto_id: ProtocolId,
         /// data
         data: Bytes,
+        /// Notify when the data has been written to the sub stream,
+        /// only available on single target
+        ack: Option<DeliveryNotify>,
+        /// The message will be dropped if not sent before the deadline
+        deadline: Option<Instant>,
+    },
+    /// Send protocol data task, skip the sessions whose pending data size exceeds the threshold
+    BackpressureBroadcast {
+        /// Specify which sessions to send to
+        target: TargetSession,
+        /// protocol id
+        proto_id: ProtocolId,
+        /// data
+        data: Bytes,
+        /// The max pending data size of session which can be sent to
+        max_pending_data_size: usize,
+        /// Report the skipped sessions
+        skipped: oneshot::Sender<Vec<SessionId>>,
     },
     /// Open specify protocol
     ProtocolOpen {
@@ -218,8 +245,22 @@ pub(crate) enum ServiceTask {
         /// Listen address
         address: Multiaddr,
     },
+    /// Replace the key pair of secio
+    RotateKeyPair {
+        /// The new key pair
+        key_pair: SecioKeyPair,
+        /// Keep the sessions established with the old key pair
+        keep_sessions: bool,
+    },
     /// Shutdown service
     Shutdown(bool),
+    /// Shutdown service after all sessions flush the pending messages
+    GracefulShutdown {
+        /// The max time to wait for flushing
+        timeout: Duration,
+        /// Notify when the service has been shutdown
+        done: oneshot::Sender<()>,
+    },
 }
 
 impl fmt::Debug for ServiceTask {
@@ -231,11 +272,23 @@ impl fmt::Debug for ServiceTask {
                 target,
                 proto_id,
                 data,
+                ..
             } => write!(
                 f,
                 "id: {:?}, proto_id: {}, message: {:?}",
                 target, proto_id, data
             ),
+            BackpressureBroadcast {
+                target,
+                proto_id,
+                data,
+                max_pending_data_size,
+                ..
+            } => write!(
+                f,
+                "id: {:?}, proto_id: {}, max pending data size: {}, message: {:?}",
+                target, proto_id, max_pending_data_size, data
+            ),
             SetProtocolNotify {
                 proto_id, token, ..
             } => write!(f, "set protocol({}) notify({})", proto_id, token),
@@ -272,7 +325,13 @@ impl fmt::Debug for ServiceTask {
                 session_id,
                 proto_id,
             } => write!(f, "Close session [{}] proto [{}]", session_id, proto_id),
+            RotateKeyPair { key_pair, .. } => {
+                write!(f, "Rotate key pair to: {:?}", key_pair.peer_id())
+            }
             Shutdown(_) => write!(f, "Try close service"),
+            GracefulShutdown { timeout, .. } => {
+                write!(f, "Try close service gracefully, timeout: {:?}", timeout)
+            }
         }
     }
 }
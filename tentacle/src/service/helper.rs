@@ -1,7 +1,14 @@
 use futures::{channel::mpsc, prelude::*};
 use log::{debug, error, trace};
 use multiaddr::Multiaddr;
-use secio::handshake::Config;
+use secio::{
+    handshake::{Config, HandshakeProgress, HandshakeStage as SecioStage, RemotePropositions},
+    pnet::PreSharedKey,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{Arc, PoisonError, RwLock};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 use std::{
     io,
     pin::Pin,
@@ -12,9 +19,12 @@ use tokio::prelude::{AsyncRead, AsyncWrite};
 use yamux::session::SessionType as YamuxType;
 
 use crate::{
-    error::{HandshakeErrorKind, TransportErrorKind},
+    builder::VerifyProtocolsFn,
+    error::{HandshakeErrorKind, HandshakeReport, HandshakeStage, TransportErrorKind},
+    muxer::{negotiate, Muxer},
+    protocol_select::decode_protocol_list,
     service::future_task::BoxedFutureTask,
-    session::SessionEvent,
+    session::{AsyncRW, SessionEvent},
     transports::MultiIncoming,
 };
 
@@ -72,14 +82,90 @@ impl Into<YamuxType> for SessionType {
     }
 }
 
+/// Time spent on every stage of handshake, it's not measured on wasm
+#[derive(Default)]
+struct StageTimer {
+    stage: Option<HandshakeStage>,
+    #[cfg(not(target_arch = "wasm32"))]
+    start: Option<Instant>,
+    steps: Vec<(HandshakeStage, Duration)>,
+}
+
+impl StageTimer {
+    fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.start.map(|start| start.elapsed()).unwrap_or_default()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            Duration::default()
+        }
+    }
+
+    fn enter(&mut self, stage: HandshakeStage) {
+        if self.stage == Some(stage) {
+            return;
+        }
+        if let Some(prev) = self.stage.replace(stage) {
+            self.steps.push((prev, self.elapsed()));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.start = Some(Instant::now());
+        }
+    }
+
+    fn report(&self, remote_propositions: Option<RemotePropositions>) -> HandshakeReport {
+        HandshakeReport {
+            stage: self.stage.expect("report after entering a stage"),
+            elapsed: self.elapsed(),
+            steps: self.steps.clone(),
+            remote_propositions,
+        }
+    }
+}
+
+/// The whole handshake shares one timeout, every stage only gets the time left,
+/// it's not measured on wasm, every stage gets the whole timeout there
+struct Deadline {
+    timeout: Duration,
+    #[cfg(not(target_arch = "wasm32"))]
+    start: Instant,
+}
+
+impl Deadline {
+    fn new(timeout: Duration) -> Self {
+        Deadline {
+            timeout,
+            #[cfg(not(target_arch = "wasm32"))]
+            start: Instant::now(),
+        }
+    }
+
+    fn remaining(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.timeout.saturating_sub(self.start.elapsed())
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.timeout
+        }
+    }
+}
+
 pub(crate) struct HandshakeContext {
-    pub(crate) key_pair: Option<secio::SecioKeyPair>,
+    pub(crate) secio_config: Option<Config>,
     pub(crate) event_sender: mpsc::Sender<SessionEvent>,
     pub(crate) max_frame_length: usize,
     pub(crate) timeout: Duration,
     pub(crate) ty: SessionType,
     pub(crate) remote_address: Multiaddr,
     pub(crate) listen_address: Option<Multiaddr>,
+    pub(crate) muxers: Vec<Muxer>,
+    pub(crate) pre_shared_key: Option<PreSharedKey>,
+    pub(crate) verify_remote_protocols: Option<VerifyProtocolsFn>,
 }
 
 impl HandshakeContext {
@@ -87,78 +173,196 @@ impl HandshakeContext {
     where
         H: AsyncRead + AsyncWrite + Send + 'static + Unpin,
     {
-        match self.key_pair {
-            Some(key_pair) => {
+        let deadline = Deadline::new(self.timeout);
+        let mut timer = StageTimer::default();
+        match self.pre_shared_key.take() {
+            Some(key) => {
+                timer.enter(HandshakeStage::PreSharedKey);
                 let result = crate::runtime::timeout(
-                    self.timeout,
-                    Config::new(key_pair)
-                        .max_frame_length(self.max_frame_length)
-                        .handshake(socket),
+                    deadline.remaining(),
+                    key.handshake(socket, self.max_frame_length),
                 )
                 .await;
 
-                let event = match result {
-                    Err(error) => {
+                match result {
+                    Ok(Ok(handle)) => self.secure_handshake(handle, deadline, timer).await,
+                    Ok(Err(error)) => {
                         debug!(
-                            "Handshake with {} failed, error: {:?}",
+                            "Private network handshake with {} failed, error: {:?}",
                             self.remote_address, error
                         );
-                        // time out error
-                        SessionEvent::HandshakeError {
-                            ty: self.ty,
-                            error: HandshakeErrorKind::Timeout(error.to_string()),
-                            address: self.remote_address,
-                        }
+                        self.send_error(
+                            HandshakeErrorKind::PreSharedKeyError(error),
+                            timer.report(None),
+                        )
+                        .await;
                     }
-                    Ok(res) => match res {
-                        Ok((handle, public_key, _)) => SessionEvent::HandshakeSuccess {
-                            handle: Box::new(handle),
-                            public_key: Some(public_key),
-                            address: self.remote_address,
-                            ty: self.ty,
-                            listen_address: self.listen_address,
-                        },
+                    Err(error) => {
+                        self.send_error(
+                            HandshakeErrorKind::Timeout(error.to_string()),
+                            timer.report(None),
+                        )
+                        .await;
+                    }
+                }
+            }
+            None => self.secure_handshake(socket, deadline, timer).await,
+        }
+    }
+
+    async fn secure_handshake<H>(mut self, socket: H, deadline: Deadline, mut timer: StageTimer)
+    where
+        H: AsyncRead + AsyncWrite + Send + 'static + Unpin,
+    {
+        let mut early_data = None;
+        let (mut handle, public_key, negotiated): (Box<dyn AsyncRW + Send + Unpin>, _, _) =
+            match self.secio_config.take() {
+                Some(config) => {
+                    let progress = HandshakeProgress::new();
+                    let handshake = config
+                        .max_frame_length(self.max_frame_length)
+                        .handshake_with_progress(socket, progress.clone());
+                    futures::pin_mut!(handshake);
+                    timer.enter(HandshakeStage::Secio(SecioStage::Propose));
+                    // record the time of every secio stage as soon as it's entered
+                    let result = crate::runtime::timeout(
+                        deadline.remaining(),
+                        future::poll_fn(|cx| {
+                            let poll = handshake.as_mut().poll(cx);
+                            timer.enter(HandshakeStage::Secio(progress.stage()));
+                            poll
+                        }),
+                    )
+                    .await;
+
+                    match result {
                         Err(error) => {
                             debug!(
                                 "Handshake with {} failed, error: {:?}",
                                 self.remote_address, error
                             );
-                            SessionEvent::HandshakeError {
-                                ty: self.ty,
-                                error: HandshakeErrorKind::SecioError(error),
-                                address: self.remote_address,
-                            }
+                            // time out error
+                            let report = timer.report(progress.remote_propositions());
+                            self.send_error(HandshakeErrorKind::Timeout(error.to_string()), report)
+                                .await;
+                            return;
                         }
-                    },
-                };
-                if let Err(err) = self.event_sender.send(event).await {
-                    error!("handshake result send back error: {:?}", err);
+                        Ok(res) => match res {
+                            Ok((handle, public_key, _)) => {
+                                let negotiated = handle.negotiated_algorithms();
+                                early_data = handle.remote_early_data().cloned();
+                                (Box::new(handle), Some(public_key), negotiated)
+                            }
+                            Err(error) => {
+                                debug!(
+                                    "Handshake with {} failed, error: {:?}",
+                                    self.remote_address, error
+                                );
+                                let report = timer.report(progress.remote_propositions());
+                                self.send_error(HandshakeErrorKind::SecioError(error), report)
+                                    .await;
+                                return;
+                            }
+                        },
+                    }
                 }
+                None => (Box::new(socket), None, None),
+            };
+
+        if let (Some(verify), Some(ref key)) = (self.verify_remote_protocols.take(), &public_key) {
+            let remote_protocols = early_data
+                .as_ref()
+                .and_then(|data| decode_protocol_list(data));
+            if !verify(key, remote_protocols.as_deref()) {
+                debug!("Protocols of {} are rejected", self.remote_address);
+                self.send_error(
+                    HandshakeErrorKind::RemoteProtocolsRejected,
+                    timer.report(None),
+                )
+                .await;
+                return;
             }
-            None => {
-                let event = SessionEvent::HandshakeSuccess {
-                    handle: Box::new(socket),
-                    public_key: None,
-                    address: self.remote_address,
-                    ty: self.ty,
-                    listen_address: self.listen_address,
-                };
-                if let Err(err) = self.event_sender.send(event).await {
-                    error!("handshake result send back error: {:?}", err);
+        }
+
+        // Only yamux is supported by default, keep compatible with the peers that don't negotiate
+        let muxer = if self.muxers == [Muxer::Yamux] {
+            Muxer::Yamux
+        } else {
+            timer.enter(HandshakeStage::MuxerNegotiation);
+            match crate::runtime::timeout(
+                deadline.remaining(),
+                negotiate(&mut handle, self.ty, &self.muxers),
+            )
+            .await
+            {
+                Ok(Ok(muxer)) => muxer,
+                Ok(Err(error)) => {
+                    debug!(
+                        "Negotiate multiplexer with {} failed, error: {:?}",
+                        self.remote_address, error
+                    );
+                    self.send_error(
+                        HandshakeErrorKind::MuxerNegotiationError(error),
+                        timer.report(None),
+                    )
+                    .await;
+                    return;
+                }
+                Err(error) => {
+                    self.send_error(
+                        HandshakeErrorKind::Timeout(error.to_string()),
+                        timer.report(None),
+                    )
+                    .await;
+                    return;
                 }
             }
+        };
+
+        let event = SessionEvent::HandshakeSuccess {
+            handle,
+            public_key,
+            negotiated,
+            early_data,
+            address: self.remote_address,
+            ty: self.ty,
+            listen_address: self.listen_address,
+            muxer,
+        };
+        if let Err(err) = self.event_sender.send(event).await {
+            error!("handshake result send back error: {:?}", err);
+        }
+    }
+
+    async fn send_error(mut self, error: HandshakeErrorKind, report: HandshakeReport) {
+        let event = SessionEvent::HandshakeError {
+            ty: self.ty,
+            listen_address: self.listen_address,
+            error,
+            report,
+            address: self.remote_address,
+        };
+        if let Err(err) = self.event_sender.send(event).await {
+            error!("handshake result send back error: {:?}", err);
         }
     }
 }
 
+/// Secio config shared by the listeners, it's replaced on key rotation
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type SharedSecioConfig = Arc<RwLock<Option<Config>>>;
+
 #[cfg(not(target_arch = "wasm32"))]
 pub struct Listener {
     pub(crate) inner: MultiIncoming,
-    pub(crate) key_pair: Option<secio::SecioKeyPair>,
+    pub(crate) secio_config: SharedSecioConfig,
     pub(crate) event_sender: mpsc::Sender<SessionEvent>,
     pub(crate) max_frame_length: usize,
     pub(crate) timeout: Duration,
     pub(crate) listen_addr: Multiaddr,
+    pub(crate) muxers: Vec<Muxer>,
+    pub(crate) pre_shared_key: Option<PreSharedKey>,
+    pub(crate) verify_remote_protocols: Option<VerifyProtocolsFn>,
     pub(crate) future_task_sender: mpsc::Sender<BoxedFutureTask>,
 }
 
@@ -194,10 +398,17 @@ impl Listener {
             ty: SessionType::Inbound,
             remote_address,
             listen_address: Some(self.listen_addr.clone()),
-            key_pair: self.key_pair.clone(),
+            secio_config: self
+                .secio_config
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
             event_sender: self.event_sender.clone(),
             max_frame_length: self.max_frame_length,
             timeout: self.timeout,
+            muxers: self.muxers.clone(),
+            pre_shared_key: self.pre_shared_key.clone(),
+            verify_remote_protocols: self.verify_remote_protocols.clone(),
         }
         .handshake(socket);
 
@@ -1,4 +1,9 @@
-use futures::{channel::mpsc, prelude::*, stream::iter, SinkExt};
+use futures::{
+    channel::{mpsc, oneshot},
+    prelude::*,
+    stream::iter,
+    SinkExt,
+};
 use log::{debug, error, log_enabled, trace, warn};
 use std::collections::{HashMap, HashSet};
 use std::{
@@ -6,27 +11,34 @@ use std::{
     pin::Pin,
     sync::{atomic::Ordering, Arc},
     task::{Context, Poll},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::prelude::{AsyncRead, AsyncWrite};
 use tokio_util::codec::{Framed, FramedParts, FramedRead, FramedWrite, LengthDelimitedCodec};
-use yamux::{Control, Session as YamuxSession, StreamHandle};
+use yamux::Error as YamuxError;
 
 use crate::{
     buffer::{Buffer, PriorityBuffer, SendResult},
     channel::{mpsc as priority_mpsc, mpsc::Priority, QuickSinkExt},
     context::SessionContext,
-    error::{HandshakeErrorKind, ProtocolHandleErrorKind, TransportErrorKind},
+    error::{
+        DeliveryErrorKind, HandshakeErrorKind, HandshakeReport, ProtocolHandleErrorKind,
+        TransportErrorKind,
+    },
     multiaddr::Multiaddr,
+    muxer::{BoxedMuxer, BoxedSubstream, Muxer, MuxerControl, RESET_PROTOCOL_REJECTED},
     protocol_handle_stream::{ServiceProtocolEvent, SessionProtocolEvent},
-    protocol_select::{client_select, server_select, ProtocolInfo},
-    secio::PublicKey,
+    protocol_select::{client_select, client_select_request, server_select, ProtocolInfo},
+    secio::{handshake::NegotiatedAlgorithms, PublicKey},
     service::{
         config::{Meta, SessionConfig},
+        delivery::{is_expired, notify, DeliveryNotify},
         future_task::BoxedFutureTask,
         ServiceControl, SessionType, RECEIVED_SIZE, SEND_SIZE,
     },
-    substream::{PatchedReadPart, ProtocolEvent, SubstreamBuilder, SubstreamWritePartBuilder},
+    substream::{
+        drop_unsent, PatchedReadPart, ProtocolEvent, SubstreamBuilder, SubstreamWritePartBuilder,
+    },
     transports::MultiIncoming,
     ProtocolId, SessionId, StreamId, SubstreamReadPart,
 };
@@ -42,6 +54,11 @@ pub(crate) enum SessionEvent {
         /// Session id
         id: SessionId,
     },
+    /// Stop remote opening new streams and flush the pending messages, used by graceful shutdown
+    Drain {
+        /// Notify when all the pending messages have been written to the underlying connection
+        flushed: oneshot::Sender<()>,
+    },
     ListenStart {
         listen_address: Multiaddr,
         incoming: MultiIncoming,
@@ -52,20 +69,30 @@ pub(crate) enum SessionEvent {
         handle: Box<dyn AsyncRW + Send + Unpin + 'static>,
         /// Remote Public key
         public_key: Option<PublicKey>,
+        /// Algorithms negotiated by secio
+        negotiated: Option<NegotiatedAlgorithms>,
+        /// Early data of remote's secio handshake
+        early_data: Option<bytes::Bytes>,
         /// Remote address
         address: Multiaddr,
         /// Session type
         ty: SessionType,
         /// listen addr
         listen_address: Option<Multiaddr>,
+        /// Negotiated multiplexer
+        muxer: Muxer,
     },
     HandshakeError {
         /// remote address
         address: Multiaddr,
         /// Session type
         ty: SessionType,
+        /// listen addr
+        listen_address: Option<Multiaddr>,
         /// error
         error: HandshakeErrorKind,
+        /// Where the handshake failed or stalled
+        report: HandshakeReport,
     },
     DialError {
         /// remote address
@@ -87,6 +114,10 @@ pub(crate) enum SessionEvent {
         proto_id: ProtocolId,
         /// Data
         data: bytes::Bytes,
+        /// Notify when the data has been written to the sub stream
+        ack: Option<DeliveryNotify>,
+        /// The message will be dropped if not sent before the deadline
+        deadline: Option<Instant>,
     },
     /// Protocol open event
     ProtocolOpen {
@@ -105,7 +136,7 @@ pub(crate) enum SessionEvent {
         proto_id: ProtocolId,
     },
     StreamStart {
-        stream: StreamHandle,
+        stream: BoxedSubstream,
     },
     ChangeState {
         state: SessionState,
@@ -145,7 +176,7 @@ pub(crate) enum SessionEvent {
 
 /// Wrapper for real data streams, such as TCP stream
 pub(crate) struct Session {
-    control: Control,
+    control: Arc<dyn MuxerControl>,
 
     protocol_configs_by_name: HashMap<String, Arc<Meta>>,
     protocol_configs_by_id: HashMap<ProtocolId, Arc<Meta>>,
@@ -191,14 +222,13 @@ pub(crate) struct Session {
 
 impl Session {
     /// New a session
-    pub fn new<T: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
-        socket: T,
+    pub fn new(
+        socket: BoxedMuxer,
         service_sender: mpsc::Sender<SessionEvent>,
         service_receiver: priority_mpsc::Receiver<SessionEvent>,
         meta: SessionMeta,
         future_task_sender: mpsc::Sender<BoxedFutureTask>,
     ) -> Self {
-        let socket = YamuxSession::new(socket, meta.config.yamux_config, meta.context.ty.into());
         let control = socket.control();
         let (proto_event_sender, proto_event_receiver) = mpsc::channel(RECEIVED_SIZE);
         let mut interval = proto_event_sender.clone();
@@ -255,7 +285,7 @@ impl Session {
         procedure: impl Future<
                 Output = Result<
                     (
-                        Framed<StreamHandle, LengthDelimitedCodec>,
+                        Framed<BoxedSubstream, LengthDelimitedCodec>,
                         String,
                         Option<String>,
                     ),
@@ -272,7 +302,7 @@ impl Session {
         let task = Box::pin(async move {
             let event = match crate::runtime::timeout(timeout, procedure).await {
                 Ok(res) => match res {
-                    Ok((handle, name, version)) => match version {
+                    Ok((mut handle, name, version)) => match version {
                         Some(version) => ProtocolEvent::Open {
                             substream: Box::new(handle),
                             proto_name: name,
@@ -280,6 +310,7 @@ impl Session {
                         },
                         None => {
                             debug!("Negotiation to open the protocol {} failed", name);
+                            handle.get_mut().reset(RESET_PROTOCOL_REJECTED);
                             ProtocolEvent::SelectError {
                                 proto_name: Some(name),
                             }
@@ -315,18 +346,20 @@ impl Session {
             .support_versions
             .clone();
         let proto_info = ProtocolInfo::new(&proto_name, versions);
-        let mut control = self.control.clone();
+        let control = Arc::clone(&self.control);
         let id = self.context.id;
 
         let task = async move {
-            let handle = match control.open_stream().await {
+            // Send the select request along with the opening of substream
+            let request = client_select_request(proto_info);
+            let handle = match control.open_stream_with_data(request).await {
                 Ok(handle) => handle,
                 Err(e) => {
                     debug!("session {} open stream error: {}", id, e);
                     return Err(io::ErrorKind::BrokenPipe.into());
                 }
             };
-            client_select(handle, proto_info).await
+            client_select(handle).await
         };
         self.select_procedure(task);
     }
@@ -354,26 +387,29 @@ impl Session {
             .values_mut()
             .filter(|buffer| !buffer.is_empty())
         {
-            if let SendResult::Pending = buffer.try_send(cx) {
-                if self.context.pending_data_size() > self.config.send_buffer_size {
-                    self.state = SessionState::Abnormal;
-                    warn!(
-                        "session {:?} unable to send message, \
+            match send_to_substream(&self.context, buffer, cx) {
+                SendResult::Pending => {
+                    if self.context.pending_data_size() > self.config.send_buffer_size {
+                        self.state = SessionState::Abnormal;
+                        warn!(
+                            "session {:?} unable to send message, \
                          user allow buffer size: {}, \
                          current buffer size: {}, so kill it",
-                        self.context,
-                        self.config.send_buffer_size,
-                        self.context.pending_data_size()
-                    );
-                    buffer.clear();
+                            self.context,
+                            self.config.send_buffer_size,
+                            self.context.pending_data_size()
+                        );
+                        buffer.clear();
+                    }
+                    break;
                 }
-                break;
+                SendResult::Ok | SendResult::Disconnect => (),
             }
         }
     }
 
     /// Handling client-initiated open protocol sub stream requests
-    fn handle_substream(&mut self, substream: StreamHandle) {
+    fn handle_substream(&mut self, substream: BoxedSubstream) {
         let proto_metas = self
             .protocol_configs_by_name
             .values()
@@ -394,7 +430,7 @@ impl Session {
         cx: &mut Context,
         name: String,
         version: String,
-        substream: Box<Framed<StreamHandle, LengthDelimitedCodec>>,
+        substream: Box<Framed<BoxedSubstream, LengthDelimitedCodec>>,
     ) {
         let proto = match self.protocol_configs_by_name.get(&name) {
             Some(proto) => proto,
@@ -421,14 +457,17 @@ impl Session {
 
         let before_receive_fn = (proto.before_receive)();
         let (session_to_proto_sender, session_to_proto_receiver) =
-            priority_mpsc::channel(SEND_SIZE);
+            priority_mpsc::channel_with_priority_levels(SEND_SIZE, self.config.priority_levels);
 
         self.substreams.insert(
             self.next_stream,
             PriorityBuffer::new(session_to_proto_sender.clone()),
         );
         self.proto_streams.insert(proto_id, self.next_stream);
-        let raw_part = substream.into_parts();
+        let mut raw_part = substream.into_parts();
+        if let Some(priority) = proto.stream_priority {
+            raw_part.io.set_priority(priority);
+        }
 
         match proto.spawn {
             Some(ref spawn) => {
@@ -517,7 +556,10 @@ impl Session {
             }
             ProtocolEvent::Close { id, proto_id } => {
                 debug!("session [{}] proto [{}] closed", self.context.id, proto_id);
-                if self.substreams.remove(&id).is_some() {
+                if let Some(mut buffer) = self.substreams.remove(&id) {
+                    for event in buffer.drain() {
+                        drop_unsent(&self.context, event);
+                    }
                     self.proto_streams.remove(&proto_id);
                     if self.event.contains(&proto_id) {
                         self.event_output(
@@ -541,6 +583,8 @@ impl Session {
                         id: self.context.id,
                         proto_id,
                         data,
+                        ack: None,
+                        deadline: None,
                     },
                 )
             }
@@ -581,23 +625,39 @@ impl Session {
     /// Handling events send by the service
     fn handle_session_event(&mut self, cx: &mut Context, event: SessionEvent, priority: Priority) {
         match event {
-            SessionEvent::ProtocolMessage { proto_id, data, .. } => {
+            SessionEvent::ProtocolMessage {
+                proto_id,
+                data,
+                ack,
+                deadline,
+                ..
+            } => {
+                if is_expired(deadline) {
+                    trace!(
+                        "session [{}] proto [{}] message expired",
+                        self.context.id,
+                        proto_id
+                    );
+                    self.context.drop_expired_message(data.len());
+                    notify(ack, Err(DeliveryErrorKind::Expired));
+                    return;
+                }
                 if let Some(stream_id) = self.proto_streams.get(&proto_id) {
                     if let Some(buffer) = self.substreams.get_mut(stream_id) {
                         let event = ProtocolEvent::Message {
                             id: *stream_id,
                             proto_id,
                             data,
+                            ack,
+                            deadline,
                         };
-                        if priority.is_high() {
-                            buffer.push_high(event)
-                        } else {
-                            buffer.push_normal(event)
-                        }
-                        buffer.try_send(cx);
+                        buffer.push(priority, event);
+                        send_to_substream(&self.context, buffer, cx);
                     }
                 } else {
                     trace!("protocol {} not ready", proto_id);
+                    self.context.decr_pending_data_size(data.len());
+                    notify(ack, Err(DeliveryErrorKind::ProtocolNotOpen));
                 }
             }
             SessionEvent::SessionClose { .. } => {
@@ -609,6 +669,17 @@ impl Session {
                     self.close_all_proto(cx);
                 }
             }
+            SessionEvent::Drain { flushed } => {
+                let control = Arc::clone(&self.control);
+                let context = Arc::clone(&self.context);
+                crate::runtime::spawn(async move {
+                    control.go_away().await;
+                    // The messages buffered by tentacle go first, then the ones buffered by muxer
+                    future::poll_fn(|cx| context.poll_flushed(cx)).await;
+                    control.flush().await;
+                    let _ignore = flushed.send(());
+                });
+            }
             SessionEvent::ProtocolOpen { proto_id, .. } => {
                 if self.proto_streams.contains_key(&proto_id) {
                     debug!("proto [{}] has been open", proto_id);
@@ -629,7 +700,7 @@ impl Session {
                             id: *stream_id,
                             proto_id,
                         });
-                        buffer.try_send(cx);
+                        send_to_substream(&self.context, buffer, cx);
                     }
                 } else {
                     debug!("proto [{}] has been closed", proto_id);
@@ -712,15 +783,15 @@ impl Session {
                     id: *pid,
                     proto_id: 0.into(),
                 });
-                buffer.try_send(cx);
+                send_to_substream(&self.context, buffer, cx);
             }
-            self.context.closed.store(true, Ordering::SeqCst);
+            self.context.close();
         }
     }
 
     /// Close session
     fn close_session(&mut self) {
-        self.context.closed.store(true, Ordering::SeqCst);
+        self.context.close();
 
         let (mut sender, mut events) = self.service_sender.take();
         events.push_back(SessionEvent::SessionClose {
@@ -763,10 +834,7 @@ impl Session {
         self.service_receiver.close();
         self.proto_event_receiver.close();
 
-        let mut control = self.control.clone();
-        crate::runtime::spawn(async move {
-            control.close().await;
-        });
+        crate::runtime::spawn(self.control.close());
     }
 
     #[inline]
@@ -778,6 +846,21 @@ impl Session {
     }
 }
 
+/// Send the buffered events to sub stream, the messages left on disconnect are dropped as unsent
+fn send_to_substream(
+    context: &SessionContext,
+    buffer: &mut PriorityBuffer<ProtocolEvent>,
+    cx: &mut Context,
+) -> SendResult {
+    let result = buffer.try_send_keep_unsent(cx);
+    if let SendResult::Disconnect = result {
+        for event in buffer.drain() {
+            drop_unsent(context, event);
+        }
+    }
+    result
+}
+
 impl Stream for Session {
     type Item = ();
 
@@ -971,24 +1054,18 @@ impl SessionState {
     }
 }
 
-struct InnerSocket<T> {
-    socket: YamuxSession<T>,
+struct InnerSocket {
+    socket: BoxedMuxer,
     sender: priority_mpsc::Sender<SessionEvent>,
 }
 
-impl<T> InnerSocket<T>
-where
-    T: AsyncRead + AsyncWrite + Unpin + Send,
-{
-    fn new(socket: YamuxSession<T>, sender: priority_mpsc::Sender<SessionEvent>) -> Self {
+impl InnerSocket {
+    fn new(socket: BoxedMuxer, sender: priority_mpsc::Sender<SessionEvent>) -> Self {
         InnerSocket { socket, sender }
     }
 }
 
-impl<T> Stream for InnerSocket<T>
-where
-    T: AsyncRead + AsyncWrite + Unpin,
-{
+impl Stream for InnerSocket {
     type Item = ();
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
@@ -1021,15 +1098,24 @@ where
             Poll::Ready(Some(Err(err))) => {
                 debug!("session poll error: {:?}", err);
 
+                // Remote go away with an error code or protocol violations of remote
+                let is_muxer_error = err
+                    .get_ref()
+                    .map(|inner| inner.is::<YamuxError>())
+                    .unwrap_or(false);
                 let event = match err.kind() {
                     ErrorKind::BrokenPipe
                     | ErrorKind::ConnectionAborted
                     | ErrorKind::ConnectionReset
                     | ErrorKind::NotConnected
-                    | ErrorKind::UnexpectedEof => SessionEvent::ChangeState {
-                        state: SessionState::RemoteClose,
-                        error: None,
-                    },
+                    | ErrorKind::UnexpectedEof
+                        if !is_muxer_error =>
+                    {
+                        SessionEvent::ChangeState {
+                            state: SessionState::RemoteClose,
+                            error: None,
+                        }
+                    }
                     _ => {
                         debug!("MuxerError: {:?}", err);
 
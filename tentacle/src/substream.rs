@@ -1,4 +1,9 @@
-use futures::{channel::mpsc, prelude::*, stream::iter, SinkExt, StreamExt};
+use futures::{
+    channel::mpsc,
+    prelude::*,
+    stream::{iter, FusedStream},
+    SinkExt, StreamExt,
+};
 use log::debug;
 use std::{
     collections::VecDeque,
@@ -6,6 +11,7 @@ use std::{
     pin::Pin,
     sync::{atomic::Ordering, Arc},
     task::{Context, Poll},
+    time::Instant,
 };
 use tokio::prelude::{AsyncRead, AsyncWrite};
 use tokio_util::codec::{length_delimited::LengthDelimitedCodec, Framed, FramedRead, FramedWrite};
@@ -13,12 +19,16 @@ use tokio_util::codec::{length_delimited::LengthDelimitedCodec, Framed, FramedRe
 use crate::{
     buffer::{Buffer, SendResult},
     builder::BeforeReceive,
-    channel::{mpsc as priority_mpsc, mpsc::Priority},
+    channel::{bounded_levels, mpsc as priority_mpsc, mpsc::Priority, queue_index},
     context::SessionContext,
+    error::DeliveryErrorKind,
+    muxer::{BoxedSubstream, RESET_ABORTED},
     protocol_handle_stream::{ServiceProtocolEvent, SessionProtocolEvent},
-    service::config::SessionConfig,
+    service::{
+        config::SessionConfig,
+        delivery::{is_expired, notify, DeliveryNotify},
+    },
     traits::Codec,
-    yamux::StreamHandle,
     ProtocolId, StreamId,
 };
 
@@ -30,7 +40,7 @@ pub(crate) enum ProtocolEvent {
         /// Protocol name
         proto_name: String,
         /// Yamux sub stream handle handshake framed
-        substream: Box<Framed<StreamHandle, LengthDelimitedCodec>>,
+        substream: Box<Framed<BoxedSubstream, LengthDelimitedCodec>>,
         /// Protocol version
         version: String,
     },
@@ -49,6 +59,10 @@ pub(crate) enum ProtocolEvent {
         proto_id: ProtocolId,
         /// Data
         data: bytes::Bytes,
+        /// Notify when the data has been written to the sub stream
+        ack: Option<DeliveryNotify>,
+        /// The message will be dropped if not sent before the deadline
+        deadline: Option<Instant>,
     },
     SelectError {
         proto_name: Option<String>,
@@ -65,10 +79,88 @@ pub(crate) enum ProtocolEvent {
     TimeoutCheck,
 }
 
+/// The message will never reach the sub stream, it is no longer pending
+pub(crate) fn drop_unsent(context: &SessionContext, event: ProtocolEvent) {
+    if let ProtocolEvent::Message { data, ack, .. } = event {
+        context.decr_pending_data_size(data.len());
+        notify(ack, Err(DeliveryErrorKind::StreamClosed));
+    }
+}
+
+/// A message waiting in the write buffer of sub stream
+struct WriteFrame {
+    data: bytes::Bytes,
+    ack: Option<DeliveryNotify>,
+    deadline: Option<Instant>,
+}
+
+/// Write buffer of sub stream, one queue per priority level
+struct WriteBuffer {
+    queues: Vec<VecDeque<WriteFrame>>,
+}
+
+impl WriteBuffer {
+    /// The same levels as the event channel
+    fn new(levels: u8) -> Self {
+        WriteBuffer {
+            queues: (0..bounded_levels(levels))
+                .map(|_| VecDeque::new())
+                .collect(),
+        }
+    }
+
+    /// The priority beyond the levels will be treated as the lowest level
+    fn queue(&mut self, priority: Priority) -> &mut VecDeque<WriteFrame> {
+        let index = queue_index(priority, self.queues.len());
+        &mut self.queues[index]
+    }
+
+    fn push_front(&mut self, priority: Priority, frame: WriteFrame) {
+        self.queue(priority).push_front(frame)
+    }
+
+    fn push_back(&mut self, priority: Priority, frame: WriteFrame) {
+        self.queue(priority).push_back(frame)
+    }
+
+    /// Pop the frame of the highest priority
+    fn pop_front(&mut self) -> Option<(Priority, WriteFrame)> {
+        self.queues
+            .iter_mut()
+            .enumerate()
+            .find_map(|(level, queue)| {
+                queue
+                    .pop_front()
+                    .map(|frame| (Priority::new(level as u8), frame))
+            })
+    }
+
+    /// The number of frames of all levels
+    fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    /// Drain the frames which are not high priority
+    fn drain_low(&mut self) -> impl Iterator<Item = WriteFrame> + '_ {
+        self.queues
+            .iter_mut()
+            .skip(1)
+            .flat_map(|queue| queue.drain(..))
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = WriteFrame> + '_ {
+        self.queues.iter_mut().flat_map(|queue| queue.drain(..))
+    }
+}
+
 /// Each custom protocol in a session corresponds to a sub stream
 /// Can be seen as the route of each protocol
 pub(crate) struct Substream<U> {
-    substream: Framed<StreamHandle, U>,
+    substream: Framed<BoxedSubstream, U>,
     id: StreamId,
     proto_id: ProtocolId,
 
@@ -76,10 +168,13 @@ pub(crate) struct Substream<U> {
     event: bool,
 
     config: SessionConfig,
-    /// The buffer will be prioritized for send to underlying network
-    high_write_buf: VecDeque<bytes::Bytes>,
-    // The buffer which will send to underlying network
-    write_buf: VecDeque<bytes::Bytes>,
+    /// The buffer which will send to underlying network, ordered by priority
+    write_buf: WriteBuffer,
+    /// The messages which have been sent to sink, but not yet flushed
+    pending_acks: Vec<DeliveryNotify>,
+    /// The size of messages which have been sent to sink, but not yet flushed,
+    /// graceful shutdown waits for it through `SessionContext`
+    unflushed_size: usize,
     dead: bool,
     keep_buffer: bool,
 
@@ -110,42 +205,47 @@ where
         }
     }
 
-    fn push_front(&mut self, priority: Priority, frame: bytes::Bytes) {
-        if priority.is_high() {
-            self.high_write_buf.push_front(frame);
-        } else {
-            self.write_buf.push_front(frame);
-        }
-    }
-
-    fn push_back(&mut self, priority: Priority, frame: bytes::Bytes) {
-        if priority.is_high() {
-            self.high_write_buf.push_back(frame);
-        } else {
-            self.write_buf.push_back(frame);
-        }
-    }
-
     /// Sink `start_send` Ready -> data send to buffer
     /// Sink `start_send` NotReady -> buffer full need poll complete
     #[inline]
     fn send_inner(
         &mut self,
         cx: &mut Context,
-        frame: bytes::Bytes,
+        frame: WriteFrame,
         priority: Priority,
     ) -> Result<bool, io::Error> {
-        let data_size = frame.len();
+        let data_size = frame.data.len();
+        if is_expired(frame.deadline) {
+            self.context.drop_expired_message(data_size);
+            notify(frame.ack, Err(DeliveryErrorKind::Expired));
+            return Ok(false);
+        }
         let mut sink = Pin::new(&mut self.substream);
 
-        match sink.as_mut().poll_ready(cx)? {
-            Poll::Ready(()) => {
-                sink.as_mut().start_send(frame)?;
+        match sink.as_mut().poll_ready(cx) {
+            Poll::Ready(Ok(())) => {
+                let WriteFrame { data, ack, .. } = frame;
+                if let Err(err) = sink.as_mut().start_send(data) {
+                    self.context.decr_pending_data_size(data_size);
+                    notify(ack, Err(DeliveryErrorKind::Io(err.kind())));
+                    return Err(err);
+                }
+                // unflushed goes first, so that no one sees the data flushed in between
+                self.context.incr_unflushed_data_size(data_size);
                 self.context.decr_pending_data_size(data_size);
+                self.unflushed_size += data_size;
+                if let Some(ack) = ack {
+                    self.pending_acks.push(ack);
+                }
                 Ok(false)
             }
+            Poll::Ready(Err(err)) => {
+                self.context.decr_pending_data_size(data_size);
+                notify(frame.ack, Err(DeliveryErrorKind::Io(err.kind())));
+                Err(err)
+            }
             Poll::Pending => {
-                self.push_front(priority, frame);
+                self.write_buf.push_front(priority, frame);
                 self.poll_complete(cx)?;
                 Ok(true)
             }
@@ -154,14 +254,8 @@ where
 
     /// Send data to the lower `yamux` sub stream
     fn send_data(&mut self, cx: &mut Context) -> Result<(), io::Error> {
-        while let Some(frame) = self.high_write_buf.pop_front() {
-            if self.send_inner(cx, frame, Priority::High)? {
-                return Ok(());
-            }
-        }
-
-        while let Some(frame) = self.write_buf.pop_front() {
-            if self.send_inner(cx, frame, Priority::Normal)? {
+        while let Some((priority, frame)) = self.write_buf.pop_front() {
+            if self.send_inner(cx, frame, priority)? {
                 return Ok(());
             }
         }
@@ -179,7 +273,38 @@ where
     fn poll_complete(&mut self, cx: &mut Context) -> Result<bool, io::Error> {
         match Pin::new(&mut self.substream).poll_flush(cx) {
             Poll::Pending => Ok(true),
-            Poll::Ready(res) => res.map(|_| false),
+            Poll::Ready(res) => {
+                let result = match res {
+                    Ok(()) => Ok(()),
+                    Err(ref err) => Err(DeliveryErrorKind::Io(err.kind())),
+                };
+                for ack in self.pending_acks.drain(..) {
+                    notify(Some(ack), result);
+                }
+                self.context
+                    .decr_unflushed_data_size(::std::mem::replace(&mut self.unflushed_size, 0));
+                res.map(|_| false)
+            }
+        }
+    }
+
+    /// Notify all the messages which can't be written anymore
+    fn notify_unsent(&mut self) {
+        for frame in self.write_buf.drain() {
+            self.context.decr_pending_data_size(frame.data.len());
+            notify(frame.ack, Err(DeliveryErrorKind::StreamClosed));
+        }
+        for ack in self.pending_acks.drain(..) {
+            notify(Some(ack), Err(DeliveryErrorKind::StreamClosed));
+        }
+        self.context
+            .decr_unflushed_data_size(::std::mem::replace(&mut self.unflushed_size, 0));
+        // the receiver is closed, the messages left in it will never be sent
+        while !self.event_receiver.is_terminated() {
+            match self.event_receiver.try_next() {
+                Ok(Some((_, event))) => drop_unsent(&self.context, event),
+                _ => break,
+            }
         }
     }
 
@@ -189,6 +314,7 @@ where
         if let Poll::Ready(Err(e)) = Pin::new(self.substream.get_mut()).poll_shutdown(cx) {
             log::trace!("sub stream poll shutdown err {}", e)
         }
+        self.notify_unsent();
 
         if !self.keep_buffer {
             self.event_sender.clear()
@@ -254,14 +380,27 @@ where
             proto_id: self.proto_id,
             error,
         });
+        self.substream.get_mut().reset(RESET_ABORTED);
         self.close_proto_stream(cx);
     }
 
     /// Handling commands send by session
     fn handle_proto_event(&mut self, cx: &mut Context, event: ProtocolEvent, priority: Priority) {
         match event {
-            ProtocolEvent::Message { data, .. } => {
-                self.push_back(priority, data);
+            ProtocolEvent::Message {
+                data,
+                ack,
+                deadline,
+                ..
+            } => {
+                self.write_buf.push_back(
+                    priority,
+                    WriteFrame {
+                        data,
+                        ack,
+                        deadline,
+                    },
+                );
 
                 if let Err(err) = self.send_data(cx) {
                     // Whether it is a read send error or a flush error,
@@ -283,7 +422,10 @@ where
                 }
             }
             ProtocolEvent::Close { .. } => {
-                self.write_buf.clear();
+                for frame in self.write_buf.drain_low() {
+                    self.context.decr_pending_data_size(frame.data.len());
+                    notify(frame.ack, Err(DeliveryErrorKind::StreamClosed));
+                }
                 self.dead = true;
             }
             _ => (),
@@ -407,6 +549,8 @@ where
                             id: self.id,
                             proto_id: self.proto_id,
                             data,
+                            ack: None,
+                            deadline: None,
                         },
                     )
                 }
@@ -443,10 +587,7 @@ where
             self.distribute_to_user_level(cx);
         }
 
-        if !self.event_sender.is_empty()
-            || !self.write_buf.is_empty()
-            || !self.high_write_buf.is_empty()
-        {
+        if !self.event_sender.is_empty() || !self.write_buf.is_empty() {
             self.output(cx);
 
             match self.send_data(cx) {
@@ -584,7 +725,7 @@ impl SubstreamBuilder {
         self
     }
 
-    pub fn build<U>(self, substream: Framed<StreamHandle, U>) -> Substream<U>
+    pub fn build<U>(self, substream: Framed<BoxedSubstream, U>) -> Substream<U>
     where
         U: Codec,
     {
@@ -596,9 +737,9 @@ impl SubstreamBuilder {
             context: self.context,
             event: self.event,
 
-            high_write_buf: VecDeque::new(),
-
-            write_buf: VecDeque::new(),
+            write_buf: WriteBuffer::new(self.config.priority_levels),
+            pending_acks: Vec::new(),
+            unflushed_size: 0,
             dead: false,
             keep_buffer: self.keep_buffer,
 
@@ -615,17 +756,20 @@ impl SubstreamBuilder {
 /* Code organization under read-write separation */
 
 pub(crate) struct SubstreamWritePart<U> {
-    substream: FramedWrite<crate::runtime::WriteHalf<StreamHandle>, U>,
+    substream: FramedWrite<crate::runtime::WriteHalf<BoxedSubstream>, U>,
     id: StreamId,
     proto_id: ProtocolId,
 
     dead: bool,
     config: SessionConfig,
 
-    /// The buffer will be prioritized for send to underlying network
-    high_write_buf: VecDeque<bytes::Bytes>,
-    // The buffer which will send to underlying network
-    write_buf: VecDeque<bytes::Bytes>,
+    /// The buffer which will send to underlying network, ordered by priority
+    write_buf: WriteBuffer,
+    /// The messages which have been sent to sink, but not yet flushed
+    pending_acks: Vec<DeliveryNotify>,
+    /// The size of messages which have been sent to sink, but not yet flushed,
+    /// graceful shutdown waits for it through `SessionContext`
+    unflushed_size: usize,
 
     /// Send event to session
     event_sender: Buffer<ProtocolEvent>,
@@ -639,42 +783,47 @@ impl<U> SubstreamWritePart<U>
 where
     U: Codec + Unpin,
 {
-    fn push_front(&mut self, priority: Priority, frame: bytes::Bytes) {
-        if priority.is_high() {
-            self.high_write_buf.push_front(frame);
-        } else {
-            self.write_buf.push_front(frame);
-        }
-    }
-
-    fn push_back(&mut self, priority: Priority, frame: bytes::Bytes) {
-        if priority.is_high() {
-            self.high_write_buf.push_back(frame);
-        } else {
-            self.write_buf.push_back(frame);
-        }
-    }
-
     /// Sink `start_send` Ready -> data send to buffer
     /// Sink `start_send` NotReady -> buffer full need poll complete
     #[inline]
     fn send_inner(
         &mut self,
         cx: &mut Context,
-        frame: bytes::Bytes,
+        frame: WriteFrame,
         priority: Priority,
     ) -> Result<bool, io::Error> {
-        let data_size = frame.len();
+        let data_size = frame.data.len();
+        if is_expired(frame.deadline) {
+            self.context.drop_expired_message(data_size);
+            notify(frame.ack, Err(DeliveryErrorKind::Expired));
+            return Ok(false);
+        }
         let mut sink = Pin::new(&mut self.substream);
 
-        match sink.as_mut().poll_ready(cx)? {
-            Poll::Ready(()) => {
-                sink.as_mut().start_send(frame)?;
+        match sink.as_mut().poll_ready(cx) {
+            Poll::Ready(Ok(())) => {
+                let WriteFrame { data, ack, .. } = frame;
+                if let Err(err) = sink.as_mut().start_send(data) {
+                    self.context.decr_pending_data_size(data_size);
+                    notify(ack, Err(DeliveryErrorKind::Io(err.kind())));
+                    return Err(err);
+                }
+                // unflushed goes first, so that no one sees the data flushed in between
+                self.context.incr_unflushed_data_size(data_size);
                 self.context.decr_pending_data_size(data_size);
+                self.unflushed_size += data_size;
+                if let Some(ack) = ack {
+                    self.pending_acks.push(ack);
+                }
                 Ok(false)
             }
+            Poll::Ready(Err(err)) => {
+                self.context.decr_pending_data_size(data_size);
+                notify(frame.ack, Err(DeliveryErrorKind::Io(err.kind())));
+                Err(err)
+            }
             Poll::Pending => {
-                self.push_front(priority, frame);
+                self.write_buf.push_front(priority, frame);
                 self.poll_complete(cx)?;
                 Ok(true)
             }
@@ -684,20 +833,45 @@ where
     fn poll_complete(&mut self, cx: &mut Context) -> Result<bool, io::Error> {
         match Pin::new(&mut self.substream).poll_flush(cx) {
             Poll::Pending => Ok(true),
-            Poll::Ready(res) => res.map(|_| false),
+            Poll::Ready(res) => {
+                let result = match res {
+                    Ok(()) => Ok(()),
+                    Err(ref err) => Err(DeliveryErrorKind::Io(err.kind())),
+                };
+                for ack in self.pending_acks.drain(..) {
+                    notify(Some(ack), result);
+                }
+                self.context
+                    .decr_unflushed_data_size(::std::mem::replace(&mut self.unflushed_size, 0));
+                res.map(|_| false)
+            }
         }
     }
 
-    /// Send data to the lower `yamux` sub stream
-    fn send_data(&mut self, cx: &mut Context) -> Result<(), io::Error> {
-        while let Some(frame) = self.high_write_buf.pop_front() {
-            if self.send_inner(cx, frame, Priority::High)? {
-                return Ok(());
+    /// Notify all the messages which can't be written anymore
+    fn notify_unsent(&mut self) {
+        for frame in self.write_buf.drain() {
+            self.context.decr_pending_data_size(frame.data.len());
+            notify(frame.ack, Err(DeliveryErrorKind::StreamClosed));
+        }
+        for ack in self.pending_acks.drain(..) {
+            notify(Some(ack), Err(DeliveryErrorKind::StreamClosed));
+        }
+        self.context
+            .decr_unflushed_data_size(::std::mem::replace(&mut self.unflushed_size, 0));
+        // the receiver is closed, the messages left in it will never be sent
+        while !self.event_receiver.is_terminated() {
+            match self.event_receiver.try_next() {
+                Ok(Some((_, event))) => drop_unsent(&self.context, event),
+                _ => break,
             }
         }
+    }
 
-        while let Some(frame) = self.write_buf.pop_front() {
-            if self.send_inner(cx, frame, Priority::Normal)? {
+    /// Send data to the lower `yamux` sub stream
+    fn send_data(&mut self, cx: &mut Context) -> Result<(), io::Error> {
+        while let Some((priority, frame)) = self.write_buf.pop_front() {
+            if self.send_inner(cx, frame, priority)? {
                 return Ok(());
             }
         }
@@ -710,10 +884,7 @@ where
     #[inline]
     fn flush(&mut self, cx: &mut Context) -> Result<(), io::Error> {
         self.poll_complete(cx)?;
-        if !self.event_sender.is_empty()
-            || !self.write_buf.is_empty()
-            || !self.high_write_buf.is_empty()
-        {
+        if !self.event_sender.is_empty() || !self.write_buf.is_empty() {
             self.output(cx);
 
             match self.send_data(cx) {
@@ -728,8 +899,20 @@ where
     /// Handling commands send by session
     fn handle_proto_event(&mut self, cx: &mut Context, event: ProtocolEvent, priority: Priority) {
         match event {
-            ProtocolEvent::Message { data, .. } => {
-                self.push_back(priority, data);
+            ProtocolEvent::Message {
+                data,
+                ack,
+                deadline,
+                ..
+            } => {
+                self.write_buf.push_back(
+                    priority,
+                    WriteFrame {
+                        data,
+                        ack,
+                        deadline,
+                    },
+                );
 
                 if let Err(err) = self.send_data(cx) {
                     // Whether it is a read send error or a flush error,
@@ -751,7 +934,10 @@ where
                 }
             }
             ProtocolEvent::Close { .. } => {
-                self.write_buf.clear();
+                for frame in self.write_buf.drain_low() {
+                    self.context.decr_pending_data_size(frame.data.len());
+                    notify(frame.ack, Err(DeliveryErrorKind::StreamClosed));
+                }
                 self.dead = true;
             }
             _ => (),
@@ -808,6 +994,7 @@ where
         if let Poll::Ready(Err(e)) = Pin::new(self.substream.get_mut()).poll_shutdown(cx) {
             log::trace!("sub stream poll shutdown err {}", e)
         }
+        self.notify_unsent();
         if !self.context.closed.load(Ordering::SeqCst) {
             let (mut sender, mut events) = self.event_sender.take();
             events.push_back(ProtocolEvent::Close {
@@ -988,7 +1175,7 @@ impl SubstreamWritePartBuilder {
 
     pub fn build<U>(
         self,
-        substream: FramedWrite<crate::runtime::WriteHalf<StreamHandle>, U>,
+        substream: FramedWrite<crate::runtime::WriteHalf<BoxedSubstream>, U>,
     ) -> SubstreamWritePart<U>
     where
         U: Codec,
@@ -1000,9 +1187,9 @@ impl SubstreamWritePartBuilder {
             config: self.config,
             context: self.context,
 
-            high_write_buf: VecDeque::new(),
-
-            write_buf: VecDeque::new(),
+            write_buf: WriteBuffer::new(self.config.priority_levels),
+            pending_acks: Vec::new(),
+            unflushed_size: 0,
             dead: false,
 
             event_sender: Buffer::new(self.event_sender),
@@ -1014,11 +1201,11 @@ impl SubstreamWritePartBuilder {
 /// Remove after https://github.com/tokio-rs/tokio/pull/3166 merge
 pub(crate) struct PatchedReadPart {
     buffer: bytes::BytesMut,
-    io: crate::runtime::ReadHalf<StreamHandle>,
+    io: crate::runtime::ReadHalf<BoxedSubstream>,
 }
 
 impl PatchedReadPart {
-    pub fn new(io: crate::runtime::ReadHalf<StreamHandle>, buffer: bytes::BytesMut) -> Self {
+    pub fn new(io: crate::runtime::ReadHalf<BoxedSubstream>, buffer: bytes::BytesMut) -> Self {
         Self { io, buffer }
     }
 }
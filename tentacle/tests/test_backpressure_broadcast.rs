@@ -0,0 +1,149 @@
+use bytes::Bytes;
+use futures::{channel, StreamExt};
+use std::thread;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol, TargetSession},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId, SessionId,
+};
+
+pub fn create<F>(secio: bool, meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true);
+
+    if secio {
+        builder
+            .key_pair(SecioKeyPair::secp256k1_generated())
+            .build(shandle)
+    } else {
+        builder.build(shandle)
+    }
+}
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<(SessionId, Vec<SessionId>)>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            // far more than the yamux stream window, can't be flushed immediately
+            for _ in 0..4 {
+                let _res = context.send_message(Bytes::from(vec![0; 512 * 1024]));
+            }
+
+            let skipped = context
+                .filter_broadcast_with_backpressure(
+                    TargetSession::All,
+                    context.proto_id,
+                    Bytes::from("skip"),
+                    1024 * 1024,
+                )
+                .unwrap();
+            let sender = self.sender.clone();
+            let id = context.session.id;
+            tokio::spawn(async move {
+                let _res = sender.send((id, skipped.await.unwrap()));
+            });
+
+            let sent = context
+                .filter_broadcast_with_backpressure(
+                    TargetSession::Single(context.session.id),
+                    context.proto_id,
+                    Bytes::from("sent"),
+                    usize::max_value(),
+                )
+                .unwrap();
+            let sender = self.sender.clone();
+            tokio::spawn(async move {
+                let _res = sender.send((id, sent.await.unwrap()));
+            });
+        }
+    }
+}
+
+fn create_meta(
+    id: ProtocolId,
+) -> (
+    ProtocolMeta,
+    crossbeam_channel::Receiver<(SessionId, Vec<SessionId>)>,
+) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    (
+        MetaBuilder::new()
+            .id(id)
+            .service_handle(move || {
+                let handle = Box::new(PHandle { sender });
+                ProtocolHandle::Callback(handle)
+            })
+            .build(),
+        receiver,
+    )
+}
+
+fn test_backpressure_broadcast(secio: bool) {
+    let (meta, _) = create_meta(1.into());
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, meta, ());
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let (meta, result) = create_meta(1.into());
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, meta, ());
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let mut results = vec![result.recv().unwrap(), result.recv().unwrap()];
+    results.sort_by_key(|(_, skipped)| skipped.len());
+    let id = results[0].0;
+
+    assert_eq!(results, vec![(id, Vec::new()), (id, vec![id])]);
+}
+
+#[test]
+fn test_backpressure_broadcast_with_secio() {
+    test_backpressure_broadcast(true)
+}
+
+#[test]
+fn test_backpressure_broadcast_with_no_secio() {
+    test_backpressure_broadcast(false)
+}
@@ -0,0 +1,132 @@
+use bytes::Bytes;
+use futures::{channel, StreamExt};
+use std::thread;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    error::DeliveryErrorKind,
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId, SessionId,
+};
+
+pub fn create<F>(secio: bool, meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true);
+
+    if secio {
+        builder
+            .key_pair(SecioKeyPair::secp256k1_generated())
+            .build(shandle)
+    } else {
+        builder.build(shandle)
+    }
+}
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<Result<(), DeliveryErrorKind>>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            let ack = context.send_message_with_ack(Bytes::from("hello")).unwrap();
+            let sender = self.sender.clone();
+            tokio::spawn(async move {
+                let _res = sender.send(ack.await);
+            });
+
+            // session 100 does not exist
+            let ack = context
+                .send_message_to_with_ack(SessionId::new(100), context.proto_id, Bytes::new())
+                .unwrap();
+            let sender = self.sender.clone();
+            tokio::spawn(async move {
+                let _res = sender.send(ack.await);
+            });
+        }
+    }
+}
+
+fn create_meta(
+    id: ProtocolId,
+) -> (
+    ProtocolMeta,
+    crossbeam_channel::Receiver<Result<(), DeliveryErrorKind>>,
+) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    (
+        MetaBuilder::new()
+            .id(id)
+            .service_handle(move || {
+                let handle = Box::new(PHandle { sender });
+                ProtocolHandle::Callback(handle)
+            })
+            .build(),
+        receiver,
+    )
+}
+
+fn test_delivery_ack(secio: bool) {
+    let (meta, _) = create_meta(1.into());
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, meta, ());
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let (meta, result) = create_meta(1.into());
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, meta, ());
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let mut results = vec![result.recv().unwrap(), result.recv().unwrap()];
+    results.sort_by_key(|res| res.is_ok());
+
+    assert_eq!(results, vec![Err(DeliveryErrorKind::SessionClosed), Ok(())]);
+}
+
+#[test]
+fn test_delivery_ack_with_secio() {
+    test_delivery_ack(true)
+}
+
+#[test]
+fn test_delivery_ack_with_no_secio() {
+    test_delivery_ack(false)
+}
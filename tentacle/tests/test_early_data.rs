@@ -0,0 +1,177 @@
+use futures::{channel, StreamExt};
+use std::thread;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ServiceContext},
+    error::{DialerErrorKind, HandshakeErrorKind},
+    multiaddr::Multiaddr,
+    protocol_select::ProtocolInfo,
+    secio::{PublicKey, SecioKeyPair},
+    service::{ProtocolHandle, ProtocolMeta, ServiceError, ServiceEvent, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+type VerifyFn = fn(&PublicKey, Option<&[ProtocolInfo]>) -> bool;
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    /// Protocol names announced by remote
+    Open(Option<Vec<String>>),
+    Rejected,
+}
+
+struct PHandle;
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Event>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::DialerError {
+            error: DialerErrorKind::HandshakeError(HandshakeErrorKind::RemoteProtocolsRejected),
+            ..
+        } = error
+        {
+            let _res = self.sender.send(Event::Rejected);
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            let names = session_context.remote_protocols().map(|infos| {
+                let mut names = infos.into_iter().map(|info| info.name).collect::<Vec<_>>();
+                names.sort();
+                names
+            });
+            let _res = self.sender.send(Event::Open(names));
+        }
+    }
+}
+
+fn create_meta(id: ProtocolId) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle)))
+        .build()
+}
+
+fn create_builder(ids: &[usize], announce: bool, verify: Option<VerifyFn>) -> ServiceBuilder {
+    let mut builder = ids
+        .iter()
+        .fold(ServiceBuilder::default(), |builder, id| {
+            builder.insert_protocol(create_meta((*id).into()))
+        })
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .announce_protocols(announce)
+        .forever(true);
+    if let Some(verify) = verify {
+        builder = builder.verify_remote_protocols(verify);
+    }
+    builder
+}
+
+fn test_early_data(listen: ServiceBuilder, dial: ServiceBuilder) -> (Event, Event) {
+    let (listen_sender, listen_receiver) = crossbeam_channel::unbounded();
+    let (dial_sender, dial_receiver) = crossbeam_channel::unbounded();
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = listen.build(SHandle {
+            sender: listen_sender,
+        });
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = dial.build(SHandle {
+            sender: dial_sender,
+        });
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let dial = dial_receiver.recv().unwrap();
+    let listen = if dial == Event::Rejected {
+        Event::Rejected
+    } else {
+        listen_receiver.recv().unwrap()
+    };
+    (listen, dial)
+}
+
+fn names(ids: &[usize]) -> Option<Vec<String>> {
+    Some(ids.iter().map(|id| format!("/p2p/{}", id)).collect())
+}
+
+#[test]
+fn test_announce_protocols() {
+    let (listen, dial) = test_early_data(
+        create_builder(&[1, 2], true, None),
+        create_builder(&[1, 3], true, None),
+    );
+
+    assert_eq!(listen, Event::Open(names(&[1, 3])));
+    assert_eq!(dial, Event::Open(names(&[1, 2])));
+}
+
+#[test]
+fn test_announce_protocols_one_side() {
+    let (listen, dial) = test_early_data(
+        create_builder(&[1], true, None),
+        create_builder(&[1], false, None),
+    );
+
+    assert_eq!(listen, Event::Open(None));
+    assert_eq!(dial, Event::Open(names(&[1])));
+}
+
+#[test]
+fn test_reject_remote_protocols() {
+    fn require_protocol_2(_key: &PublicKey, protocols: Option<&[ProtocolInfo]>) -> bool {
+        protocols
+            .map(|infos| infos.iter().any(|info| info.name == "/p2p/2"))
+            .unwrap_or(false)
+    }
+
+    let (_, dial) = test_early_data(
+        create_builder(&[1], true, None),
+        create_builder(&[1, 2], true, Some(require_protocol_2)),
+    );
+    assert_eq!(dial, Event::Rejected);
+
+    let (listen, dial) = test_early_data(
+        create_builder(&[1, 2], true, None),
+        create_builder(&[1, 2], true, Some(require_protocol_2)),
+    );
+    assert_eq!(listen, Event::Open(names(&[1, 2])));
+    assert_eq!(dial, Event::Open(names(&[1, 2])));
+}
@@ -0,0 +1,360 @@
+use bytes::Bytes;
+use futures::{channel, executor::block_on, StreamExt};
+use std::{
+    io, thread,
+    time::{Duration, Instant},
+};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    error::{DialerErrorKind, TransportErrorKind},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{
+        ProtocolHandle, ProtocolMeta, Service, ServiceControl, ServiceError, ServiceEvent,
+        TargetProtocol,
+    },
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+const MESSAGE_COUNT: usize = 100;
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Connected,
+    Received(usize),
+    Disconnected,
+}
+
+pub fn create<F>(secio: bool, meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    // keeps the session open after the protocol above is closed
+    let idle = MetaBuilder::new().id(2.into()).build();
+    let builder = ServiceBuilder::default()
+        .insert_protocol(meta)
+        .insert_protocol(idle)
+        .forever(true);
+
+    if secio {
+        builder
+            .key_pair(SecioKeyPair::secp256k1_generated())
+            .build(shandle)
+    } else {
+        builder.build(shandle)
+    }
+}
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<Event>,
+    received: usize,
+    /// Close the protocol before the messages are sent
+    close: bool,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            if self.close {
+                let _res = context.close_protocol(context.session.id, context.proto_id());
+            }
+            for _ in 0..MESSAGE_COUNT {
+                let _res = context.send_message(Bytes::from(vec![0; 64 * 1024]));
+            }
+            let _res = self.sender.send(Event::Connected);
+        }
+    }
+
+    fn disconnected(&mut self, context: ProtocolContextMutRef) {
+        if context.session.ty.is_outbound() {
+            let _res = self.sender.send(Event::Disconnected);
+        } else {
+            let _res = self.sender.send(Event::Received(self.received));
+        }
+    }
+
+    fn received(&mut self, _context: ProtocolContextMutRef, _data: bytes::Bytes) {
+        self.received += 1;
+    }
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<ServiceError>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        let _res = self.sender.send(error);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Notice {
+    SessionOpen,
+    ListenClose,
+    DialerError(Multiaddr),
+}
+
+struct NoticeHandle {
+    sender: crossbeam_channel::Sender<Notice>,
+}
+
+impl ServiceHandle for NoticeHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::DialerError { address, .. } = error {
+            let _res = self.sender.send(Notice::DialerError(address));
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        match event {
+            ServiceEvent::SessionOpen { .. } => {
+                let _res = self.sender.send(Notice::SessionOpen);
+            }
+            ServiceEvent::ListenClose { .. } => {
+                let _res = self.sender.send(Notice::ListenClose);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Receive the messages slowly, so that the drain of remote lasts for a while
+struct SlowHandle;
+
+impl ServiceProtocol for SlowHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn received(&mut self, _context: ProtocolContextMutRef, _data: bytes::Bytes) {
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn create_meta(id: ProtocolId, close: bool) -> (ProtocolMeta, crossbeam_channel::Receiver<Event>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    (
+        MetaBuilder::new()
+            .id(id)
+            .service_handle(move || {
+                let handle = Box::new(PHandle {
+                    sender,
+                    received: 0,
+                    close,
+                });
+                ProtocolHandle::Callback(handle)
+            })
+            .build(),
+        receiver,
+    )
+}
+
+fn test_graceful_shutdown(secio: bool, dial_on_shutdown: bool, close_protocol: bool) {
+    let (meta, server_result) = create_meta(1.into(), false);
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, meta, ());
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let (meta, client_result) = create_meta(1.into(), close_protocol);
+    let (control_sender, control_receiver) = crossbeam_channel::bounded::<ServiceControl>(1);
+    let (error_sender, errors) = crossbeam_channel::unbounded();
+
+    let handle = thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(
+            secio,
+            meta,
+            SHandle {
+                sender: error_sender,
+            },
+        );
+        control_sender.send(service.control().clone()).unwrap();
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let control = control_receiver.recv().unwrap();
+    assert_eq!(client_result.recv().unwrap(), Event::Connected);
+    let now = Instant::now();
+    let done = control.shutdown_graceful(Duration::from_secs(10)).unwrap();
+
+    if close_protocol {
+        // the messages dropped with the protocol are not waited for
+        assert_eq!(client_result.recv().unwrap(), Event::Disconnected);
+        assert_eq!(block_on(done), Ok(()));
+        assert!(now.elapsed() < Duration::from_secs(5));
+        handle.join().unwrap();
+        return;
+    }
+
+    if dial_on_shutdown {
+        // no one listens on this address, dial is refused until the shutdown task is handled
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/1".parse().unwrap();
+        loop {
+            control.dial(address.clone(), TargetProtocol::All).unwrap();
+            match errors.recv().unwrap() {
+                ServiceError::DialerError {
+                    error: DialerErrorKind::TransportError(TransportErrorKind::Io(err)),
+                    ..
+                } if err.kind() == io::ErrorKind::ConnectionRefused => (),
+                ServiceError::DialerError {
+                    address: addr,
+                    error: DialerErrorKind::TransportError(TransportErrorKind::Io(err)),
+                    ..
+                } if err.kind() == io::ErrorKind::Other => {
+                    assert_eq!(addr, address);
+                    break;
+                }
+                err => panic!("test fail, expected DialerError, got {:?}", err),
+            }
+        }
+    }
+
+    // all messages are flushed before the session closed
+    assert_eq!(
+        server_result.recv().unwrap(),
+        Event::Received(MESSAGE_COUNT)
+    );
+    // protocol disconnected is called before the shutdown completes
+    assert_eq!(client_result.recv().unwrap(), Event::Disconnected);
+    assert_eq!(block_on(done), Ok(()));
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_graceful_shutdown_with_secio() {
+    test_graceful_shutdown(true, false, false)
+}
+
+#[test]
+fn test_graceful_shutdown_with_no_secio() {
+    test_graceful_shutdown(false, false, false)
+}
+
+#[test]
+fn test_dial_on_graceful_shutdown() {
+    test_graceful_shutdown(false, true, false)
+}
+
+#[test]
+fn test_graceful_shutdown_after_protocol_close() {
+    test_graceful_shutdown(false, false, true)
+}
+
+/// Run the service until it's shutdown, return the listen address if it listens
+fn run_service<F, B>(
+    build: B,
+    listen: bool,
+    dial: Option<Multiaddr>,
+) -> (Option<Multiaddr>, ServiceControl, thread::JoinHandle<()>)
+where
+    F: ServiceHandle + Unpin,
+    B: FnOnce() -> Service<F> + Send + 'static,
+{
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    let handle = thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = build();
+        let control = service.control().clone();
+        rt.block_on(async move {
+            let listen_addr = if listen {
+                Some(
+                    service
+                        .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                        .await
+                        .unwrap(),
+                )
+            } else {
+                None
+            };
+            if let Some(address) = dial {
+                service.dial(address, TargetProtocol::All).await.unwrap();
+            }
+            sender.send((listen_addr, control)).unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+    let (listen_addr, control) = receiver.recv().unwrap();
+    (listen_addr, control, handle)
+}
+
+#[test]
+fn test_dial_draining_node() {
+    // the remote of the draining node reads slowly
+    let build_remote = || {
+        let slow = MetaBuilder::new()
+            .id(1.into())
+            .service_handle(|| ProtocolHandle::Callback(Box::new(SlowHandle)))
+            .build();
+        create(false, slow, ())
+    };
+    let (remote_addr, _, _) = run_service(build_remote, true, None);
+
+    let (meta, result) = create_meta(1.into(), false);
+    let (sender, notices) = crossbeam_channel::unbounded();
+    let (listen_addr, control, handle) = run_service(
+        move || create(false, meta, NoticeHandle { sender }),
+        true,
+        remote_addr,
+    );
+    let listen_addr = listen_addr.unwrap();
+
+    assert_eq!(notices.recv().unwrap(), Notice::SessionOpen);
+    assert_eq!(result.recv().unwrap(), Event::Connected);
+    let done = control.shutdown_graceful(Duration::from_secs(10)).unwrap();
+    assert_eq!(notices.recv().unwrap(), Notice::ListenClose);
+
+    // the listener is stopped during the drain, dial to it fails
+    let (meta, _) = create_meta(1.into(), false);
+    let (sender, dialer_notices) = crossbeam_channel::unbounded();
+    let _dialer = run_service(
+        move || create(false, meta, NoticeHandle { sender }),
+        false,
+        Some(listen_addr.clone()),
+    );
+    assert_eq!(
+        dialer_notices.recv().unwrap(),
+        Notice::DialerError(listen_addr)
+    );
+
+    assert_eq!(result.recv().unwrap(), Event::Disconnected);
+    assert_eq!(block_on(done), Ok(()));
+    handle.join().unwrap();
+    // no session is opened on the draining node
+    assert!(notices
+        .try_iter()
+        .all(|notice| notice != Notice::SessionOpen));
+}
@@ -0,0 +1,237 @@
+use futures::{channel, StreamExt};
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ServiceContext},
+    error::{
+        DialerErrorKind, HandshakeErrorKind, HandshakeReport, HandshakeStage, ListenErrorKind,
+    },
+    multiaddr::Multiaddr,
+    secio::{
+        error::SecioError,
+        handshake::{Config, HandshakeStage as SecioStage},
+        pnet::PreSharedKey,
+        Digest, SecioKeyPair,
+    },
+    service::{ProtocolHandle, ProtocolMeta, ServiceError, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    utils::socketaddr_to_multiaddr,
+    ProtocolId,
+};
+
+#[derive(Debug)]
+enum Event {
+    Dial(HandshakeErrorKind, HandshakeReport),
+    Listen(HandshakeErrorKind, HandshakeReport),
+}
+
+struct PHandle;
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Event>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        match error {
+            ServiceError::DialerError {
+                error: DialerErrorKind::HandshakeError(error),
+                report: Some(report),
+                ..
+            } => {
+                let _res = self.sender.send(Event::Dial(error, report));
+            }
+            ServiceError::ListenError {
+                error: ListenErrorKind::HandshakeError { error, report, .. },
+                ..
+            } => {
+                let _res = self.sender.send(Event::Listen(error, report));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn create_meta(id: ProtocolId) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle)))
+        .build()
+}
+
+fn create_builder(config: Config) -> ServiceBuilder {
+    ServiceBuilder::default()
+        .insert_protocol(create_meta(1.into()))
+        .secio_config(config)
+        .timeout(Duration::from_secs(1))
+        .forever(true)
+}
+
+fn start_listen(
+    builder: ServiceBuilder,
+    sender: crossbeam_channel::Sender<Event>,
+    addr_sender: channel::oneshot::Sender<Multiaddr>,
+) {
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = builder.build(SHandle { sender });
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+}
+
+fn start_dial(
+    builder: ServiceBuilder,
+    sender: crossbeam_channel::Sender<Event>,
+    addr_receiver: channel::oneshot::Receiver<Multiaddr>,
+) {
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = builder.build(SHandle { sender });
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+}
+
+fn secio_error(event: Event) -> (SecioError, HandshakeReport) {
+    match event {
+        Event::Dial(HandshakeErrorKind::SecioError(error), report)
+        | Event::Listen(HandshakeErrorKind::SecioError(error), report) => (error, report),
+        event => panic!("expected secio error, got {:?}", event),
+    }
+}
+
+#[test]
+fn test_no_support_intersection() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    start_listen(
+        create_builder(Config::new(SecioKeyPair::secp256k1_generated()).digests(&[Digest::Sha256])),
+        sender.clone(),
+        addr_sender,
+    );
+    start_dial(
+        create_builder(Config::new(SecioKeyPair::secp256k1_generated()).digests(&[Digest::Sha512])),
+        sender,
+        addr_receiver,
+    );
+
+    let mut events = vec![receiver.recv().unwrap(), receiver.recv().unwrap()];
+    events.sort_by_key(|event| matches!(event, Event::Listen(..)));
+    let (listen_error, listen_report) = secio_error(events.pop().unwrap());
+    let (dial_error, dial_report) = secio_error(events.pop().unwrap());
+
+    assert_eq!(dial_error, SecioError::NoSupportIntersection);
+    assert_eq!(
+        dial_report.stage,
+        HandshakeStage::Secio(SecioStage::Propose)
+    );
+    assert_eq!(dial_report.remote_propositions.unwrap().hashes, "SHA256");
+
+    assert_eq!(listen_error, SecioError::NoSupportIntersection);
+    assert_eq!(
+        listen_report.stage,
+        HandshakeStage::Secio(SecioStage::Propose)
+    );
+    assert_eq!(listen_report.remote_propositions.unwrap().hashes, "SHA512");
+}
+
+#[test]
+fn test_timeout_stage() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    // a peer that accepts the connection and never answers
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let _res = addr_sender.send(socketaddr_to_multiaddr(listener.local_addr().unwrap()));
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::delay_for(Duration::from_secs(10)).await;
+        });
+    });
+    start_dial(
+        create_builder(Config::new(SecioKeyPair::secp256k1_generated())),
+        sender,
+        addr_receiver,
+    );
+
+    match receiver.recv().unwrap() {
+        Event::Dial(HandshakeErrorKind::Timeout(_), report) => {
+            assert_eq!(report.stage, HandshakeStage::Secio(SecioStage::Propose));
+            assert!(report.elapsed >= Duration::from_millis(900));
+            assert!(report.steps.is_empty());
+            assert!(report.remote_propositions.is_none());
+        }
+        event => panic!("expected timeout, got {:?}", event),
+    }
+}
+
+#[test]
+fn test_timeout_shared_by_stages() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    // a peer that answers the pre-shared key nonce late and never answers secio
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            use tokio::io::AsyncWriteExt;
+
+            let mut listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let _res = addr_sender.send(socketaddr_to_multiaddr(listener.local_addr().unwrap()));
+            let (mut socket, _) = listener.accept().await.unwrap();
+            tokio::time::delay_for(Duration::from_millis(600)).await;
+            let mut nonce = 32u32.to_be_bytes().to_vec();
+            nonce.extend_from_slice(&[1; 32]);
+            socket.write_all(&nonce).await.unwrap();
+            tokio::time::delay_for(Duration::from_secs(10)).await;
+        });
+    });
+    start_dial(
+        create_builder(Config::new(SecioKeyPair::secp256k1_generated()))
+            .pre_shared_key(PreSharedKey::new([1; 32])),
+        sender,
+        addr_receiver,
+    );
+
+    match receiver.recv().unwrap() {
+        Event::Dial(HandshakeErrorKind::Timeout(_), report) => {
+            assert_eq!(report.stage, HandshakeStage::Secio(SecioStage::Propose));
+            assert_eq!(report.steps.len(), 1);
+            let (stage, pnet_elapsed) = report.steps[0];
+            assert_eq!(stage, HandshakeStage::PreSharedKey);
+            assert!(pnet_elapsed >= Duration::from_millis(500));
+            // secio only gets the time left of the one second timeout
+            assert!(report.elapsed < Duration::from_millis(800));
+            assert!(pnet_elapsed + report.elapsed < Duration::from_millis(1500));
+        }
+        event => panic!("expected timeout, got {:?}", event),
+    }
+}
@@ -0,0 +1,180 @@
+use futures::{channel, StreamExt};
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ServiceContext},
+    multiaddr::Multiaddr,
+    secio::{envelope::SignedEnvelope, key_rotation::KeyRotation, PeerId, SecioKeyPair},
+    service::{ProtocolHandle, ProtocolMeta, ServiceControl, ServiceEvent, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    /// Peer id of remote
+    Open(PeerId),
+    Close,
+}
+
+struct PHandle {
+    rotated: crossbeam_channel::Sender<PeerId>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn notify(&mut self, context: &mut ProtocolContext, _token: u64) {
+        if let Some(envelope) = context.key_rotation() {
+            let rotation = KeyRotation::from_signed_envelope(envelope).unwrap();
+            let _res = self.rotated.send(rotation.new_peer_id().clone());
+        }
+    }
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Event>,
+    key_rotation: crossbeam_channel::Sender<Option<SignedEnvelope>>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, context: &mut ServiceContext, event: ServiceEvent) {
+        match event {
+            ServiceEvent::SessionOpen { session_context } => {
+                let peer_id = session_context.remote_pubkey.as_ref().unwrap().peer_id();
+                let _res = self.key_rotation.send(context.key_rotation().cloned());
+                let _res = self.sender.send(Event::Open(peer_id));
+            }
+            ServiceEvent::SessionClose { .. } => {
+                let _res = self.sender.send(Event::Close);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn create_meta(id: ProtocolId, rotated: crossbeam_channel::Sender<PeerId>) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle { rotated })))
+        .build()
+}
+
+struct Node {
+    control: ServiceControl,
+    events: crossbeam_channel::Receiver<Event>,
+    key_rotations: crossbeam_channel::Receiver<Option<SignedEnvelope>>,
+    /// New peer id seen by the protocol handle after rotation
+    rotated: crossbeam_channel::Receiver<PeerId>,
+}
+
+fn start_service(key_pair: SecioKeyPair, dial: Option<Multiaddr>) -> (Node, Multiaddr) {
+    let (sender, events) = crossbeam_channel::unbounded();
+    let (key_rotation, key_rotations) = crossbeam_channel::unbounded();
+    let (rotated_sender, rotated) = crossbeam_channel::unbounded();
+    let (control_sender, control_receiver) = channel::oneshot::channel();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = ServiceBuilder::default()
+            .insert_protocol(create_meta(1.into(), rotated_sender))
+            .key_pair(key_pair)
+            .forever(true)
+            .build(SHandle {
+                sender,
+                key_rotation,
+            });
+        rt.block_on(async move {
+            let address = match dial {
+                Some(address) => {
+                    service
+                        .dial(address.clone(), TargetProtocol::All)
+                        .await
+                        .unwrap();
+                    address
+                }
+                None => service
+                    .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                    .await
+                    .unwrap(),
+            };
+            let _res = control_sender.send((service.control().clone(), address));
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let (control, address) = futures::executor::block_on(control_receiver).unwrap();
+    (
+        Node {
+            control,
+            events,
+            key_rotations,
+            rotated,
+        },
+        address,
+    )
+}
+
+fn test_rotate_key_pair(keep_sessions: bool) {
+    let old_key_pair = SecioKeyPair::secp256k1_generated();
+    let new_key_pair = SecioKeyPair::secp256k1_generated();
+
+    let (listener, address) = start_service(old_key_pair.clone(), None);
+    let (dialer, _) = start_service(SecioKeyPair::secp256k1_generated(), Some(address.clone()));
+
+    assert_eq!(
+        dialer.events.recv(),
+        Ok(Event::Open(old_key_pair.peer_id()))
+    );
+    assert_eq!(listener.key_rotations.recv(), Ok(None));
+
+    listener
+        .control
+        .rotate_key_pair(new_key_pair.clone(), keep_sessions)
+        .unwrap();
+    if keep_sessions {
+        // the notify task is handled after the rotation, the protocol handle sees the new
+        // identity on notify
+        listener
+            .control
+            .set_service_notify(1.into(), Duration::from_millis(10), 0)
+            .unwrap();
+        assert_eq!(listener.rotated.recv(), Ok(new_key_pair.peer_id()));
+    } else {
+        assert_eq!(dialer.events.recv(), Ok(Event::Close));
+    }
+
+    // the new handshake uses the new identity
+    let (new_dialer, _) = start_service(SecioKeyPair::secp256k1_generated(), Some(address));
+    assert_eq!(
+        new_dialer.events.recv(),
+        Ok(Event::Open(new_key_pair.peer_id()))
+    );
+
+    let envelope = listener.key_rotations.recv().unwrap().unwrap();
+    let rotation = KeyRotation::from_signed_envelope(&envelope).unwrap();
+    assert_eq!(rotation.old_peer_id(), &old_key_pair.peer_id());
+    assert_eq!(rotation.new_peer_id(), &new_key_pair.peer_id());
+
+    if keep_sessions {
+        // the session established with the old key pair is still alive
+        assert!(dialer
+            .events
+            .recv_timeout(Duration::from_millis(500))
+            .is_err());
+    }
+}
+
+#[test]
+fn test_rotate_key_pair_and_close_sessions() {
+    test_rotate_key_pair(false)
+}
+
+#[test]
+fn test_rotate_key_pair_and_keep_sessions() {
+    test_rotate_key_pair(true)
+}
@@ -0,0 +1,147 @@
+use bytes::Bytes;
+use futures::{channel, StreamExt};
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol, TargetSession},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+pub fn create<F>(secio: bool, meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true);
+
+    if secio {
+        builder
+            .key_pair(SecioKeyPair::secp256k1_generated())
+            .build(shandle)
+    } else {
+        builder.build(shandle)
+    }
+}
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<(usize, usize)>,
+    received: usize,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            for _ in 0..5 {
+                let _res = context.send_message_to_with_ttl(
+                    context.session.id,
+                    context.proto_id,
+                    Bytes::from("stale"),
+                    Duration::from_secs(0),
+                );
+                let _res = context.filter_broadcast_with_ttl(
+                    TargetSession::All,
+                    context.proto_id,
+                    Bytes::from("stale"),
+                    Duration::from_secs(0),
+                );
+            }
+            let _res = context.send_message_to_with_ttl(
+                context.session.id,
+                context.proto_id,
+                Bytes::from("fresh"),
+                Duration::from_secs(60),
+            );
+            let _res = context.send_message(Bytes::from("end"));
+        }
+    }
+
+    fn received(&mut self, context: ProtocolContextMutRef, data: bytes::Bytes) {
+        self.received += 1;
+        if data == Bytes::from("end") {
+            let _res = context.send_message(Bytes::from(self.received.to_string()));
+        } else if context.session.ty.is_outbound() {
+            let _res = self.sender.send((
+                String::from_utf8_lossy(&data).parse().unwrap(),
+                context.session.expired_messages(),
+            ));
+        }
+    }
+}
+
+fn create_meta(id: ProtocolId) -> (ProtocolMeta, crossbeam_channel::Receiver<(usize, usize)>) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    (
+        MetaBuilder::new()
+            .id(id)
+            .service_handle(move || {
+                let handle = Box::new(PHandle {
+                    sender,
+                    received: 0,
+                });
+                ProtocolHandle::Callback(handle)
+            })
+            .build(),
+        receiver,
+    )
+}
+
+fn test_message_expiry(secio: bool) {
+    let (meta, _) = create_meta(1.into());
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, meta, ());
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let (meta, result) = create_meta(1.into());
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, meta, ());
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    // remote only received "fresh" and "end", all stale messages are dropped on local
+    assert_eq!(result.recv().unwrap(), (2, 10));
+}
+
+#[test]
+fn test_message_expiry_with_secio() {
+    test_message_expiry(true)
+}
+
+#[test]
+fn test_message_expiry_with_no_secio() {
+    test_message_expiry(false)
+}
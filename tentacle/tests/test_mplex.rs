@@ -0,0 +1,243 @@
+use bytes::Bytes;
+use futures::{channel, StreamExt};
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef, ServiceContext},
+    error::{DialerErrorKind, HandshakeErrorKind},
+    multiaddr::Multiaddr,
+    muxer::Muxer,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceError, ServiceEvent, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+pub fn create<F>(
+    secio: bool,
+    muxers: Vec<Muxer>,
+    metas: Vec<ProtocolMeta>,
+    shandle: F,
+) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let mut builder = ServiceBuilder::default().muxers(muxers).forever(true);
+    for meta in metas {
+        builder = builder.insert_protocol(meta);
+    }
+
+    if secio {
+        builder
+            .key_pair(SecioKeyPair::secp256k1_generated())
+            .build(shandle)
+    } else {
+        builder.build(shandle)
+    }
+}
+
+/// Sizes of the messages, include one larger than the max mplex message
+const SIZES: [usize; 5] = [1, 1024, 64 * 1024, 1024 * 1024, 3 * 1024 * 1024];
+
+struct PHandle {
+    count: usize,
+    sender: crossbeam_channel::Sender<(ProtocolId, bool)>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            for size in SIZES.iter() {
+                let _res = context.send_message(Bytes::from(vec![*size as u8; *size]));
+            }
+        }
+    }
+
+    fn received(&mut self, context: ProtocolContextMutRef, data: Bytes) {
+        if context.session.ty.is_inbound() {
+            // echo
+            let _res = context.send_message(data);
+            return;
+        }
+        let size = SIZES[self.count];
+        assert_eq!(data, Bytes::from(vec![size as u8; size]));
+        self.count += 1;
+        if self.count == SIZES.len() {
+            // mplex doesn't support ping
+            let session = context.session.clone();
+            let sender = self.sender.clone();
+            let proto_id = context.proto_id;
+            tokio::spawn(async move {
+                let _res = sender.send((proto_id, session.ping().await.is_err()));
+            });
+        }
+    }
+}
+
+fn create_meta(
+    id: ProtocolId,
+    sender: crossbeam_channel::Sender<(ProtocolId, bool)>,
+) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || {
+            ProtocolHandle::Callback(Box::new(PHandle {
+                count: 0,
+                sender: sender.clone(),
+            }))
+        })
+        .build()
+}
+
+fn test_mplex(secio: bool) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    let metas = vec![
+        create_meta(1.into(), sender.clone()),
+        create_meta(2.into(), sender.clone()),
+    ];
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, vec![Muxer::Yamux, Muxer::Mplex], metas, ());
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let metas = vec![
+        create_meta(1.into(), sender.clone()),
+        create_meta(2.into(), sender),
+    ];
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        // The dialer's preference wins
+        let mut service = create(secio, vec![Muxer::Mplex, Muxer::Yamux], metas, ());
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let mut finished = vec![
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap(),
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap(),
+    ];
+    finished.sort();
+    assert_eq!(finished, vec![(1.into(), true), (2.into(), true)]);
+}
+
+#[test]
+fn test_mplex_with_secio() {
+    test_mplex(true)
+}
+
+#[test]
+fn test_mplex_with_no_secio() {
+    test_mplex(false)
+}
+
+#[derive(Debug, PartialEq)]
+enum Notice {
+    SessionOpen,
+    MuxerNegotiationError,
+    OtherError,
+}
+
+struct NoticeHandle {
+    sender: crossbeam_channel::Sender<Notice>,
+}
+
+impl ServiceHandle for NoticeHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::DialerError { error, .. } = error {
+            let notice = match error {
+                DialerErrorKind::HandshakeError(HandshakeErrorKind::MuxerNegotiationError(_)) => {
+                    Notice::MuxerNegotiationError
+                }
+                _ => Notice::OtherError,
+            };
+            let _res = self.sender.send(notice);
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { .. } = event {
+            let _res = self.sender.send(Notice::SessionOpen);
+        }
+    }
+}
+
+/// A node with a muxer list always negotiates, there is no fallback to bare yamux,
+/// so it can't dial a node with the default setting
+#[test]
+fn test_muxer_list_incompatible_with_bare_yamux() {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        // The listener opens the session right after secio and closes it on the
+        // yamux error, only the dialer is checked
+        let mut service = create(true, vec![Muxer::Yamux], Vec::new(), ());
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(
+            true,
+            vec![Muxer::Mplex, Muxer::Yamux],
+            Vec::new(),
+            NoticeHandle { sender },
+        );
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap(),
+        Notice::MuxerNegotiationError
+    );
+    assert!(receiver.recv_timeout(Duration::from_secs(1)).is_err());
+}
@@ -0,0 +1,127 @@
+use futures::{channel, SinkExt, StreamExt};
+use std::thread;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ServiceContext},
+    multiaddr::Multiaddr,
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceError, ServiceEvent},
+    traits::{ServiceHandle, ServiceProtocol},
+    utils::multiaddr_to_socketaddr,
+    yamux::{
+        frame::{Frame, FrameCodec, GoAwayCode},
+        Error as YamuxError,
+    },
+    ProtocolId,
+};
+use tokio_util::codec::Framed;
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    MuxerError(Option<YamuxError>),
+    SessionClose,
+}
+
+pub fn create<F>(meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true)
+        .build(shandle)
+}
+
+struct PHandle;
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Event>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::MuxerError { error, .. } = error {
+            let error = error
+                .into_inner()
+                .and_then(|inner| inner.downcast::<YamuxError>().ok())
+                .map(|inner| *inner);
+            let _res = self.sender.send(Event::MuxerError(error));
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionClose { .. } = event {
+            let _res = self.sender.send(Event::SessionClose);
+        }
+    }
+}
+
+fn create_meta(id: ProtocolId) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle)))
+        .build()
+}
+
+fn test_remote_go_away(code: GoAwayCode) -> crossbeam_channel::Receiver<Event> {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(create_meta(1.into()), SHandle { sender });
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            let socket =
+                tokio::net::TcpStream::connect(multiaddr_to_socketaddr(&listen_addr).unwrap())
+                    .await
+                    .unwrap();
+            // A raw yamux peer, close the session immediately
+            let mut framed = Framed::new(socket, FrameCodec::default());
+            framed.send(Frame::new_go_away(code)).await.unwrap();
+            while let Some(Ok(_)) = framed.next().await {}
+        });
+    });
+
+    receiver
+}
+
+#[test]
+fn test_remote_go_away_with_error() {
+    let result = test_remote_go_away(GoAwayCode::ProtocolError);
+
+    assert_eq!(
+        result.recv().unwrap(),
+        Event::MuxerError(Some(YamuxError::RemoteGoAwayWithError(
+            GoAwayCode::ProtocolError
+        )))
+    );
+    assert_eq!(result.recv().unwrap(), Event::SessionClose);
+}
+
+#[test]
+fn test_remote_go_away_normal() {
+    let result = test_remote_go_away(GoAwayCode::Normal);
+
+    // clean close, no error reported
+    assert_eq!(result.recv().unwrap(), Event::SessionClose);
+}
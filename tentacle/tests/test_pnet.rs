@@ -0,0 +1,163 @@
+use futures::{channel, StreamExt};
+use std::thread;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ServiceContext},
+    error::{DialerErrorKind, HandshakeErrorKind},
+    multiaddr::Multiaddr,
+    secio::{pnet::PreSharedKey, SecioKeyPair},
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceError, ServiceEvent},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    SessionOpen,
+    PreSharedKeyError,
+    SecioError,
+}
+
+pub fn create<F>(key: Option<PreSharedKey>, meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(meta)
+        .key_pair(SecioKeyPair::secp256k1_generated())
+        .forever(true);
+
+    match key {
+        Some(key) => builder.pre_shared_key(key).build(shandle),
+        None => builder.build(shandle),
+    }
+}
+
+struct PHandle;
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Event>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_error(&mut self, _context: &mut ServiceContext, error: ServiceError) {
+        if let ServiceError::DialerError {
+            error: DialerErrorKind::HandshakeError(error),
+            ..
+        } = error
+        {
+            match error {
+                HandshakeErrorKind::PreSharedKeyError(_) => {
+                    let _res = self.sender.send(Event::PreSharedKeyError);
+                }
+                HandshakeErrorKind::SecioError(_) => {
+                    let _res = self.sender.send(Event::SecioError);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { .. } = event {
+            let _res = self.sender.send(Event::SessionOpen);
+        }
+    }
+}
+
+fn create_meta(id: ProtocolId) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle)))
+        .build()
+}
+
+fn test_pnet(
+    listen_key: Option<PreSharedKey>,
+    dial_key: Option<PreSharedKey>,
+) -> crossbeam_channel::Receiver<Event> {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    let (listen_sender, _listen_receiver) = crossbeam_channel::unbounded();
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(
+            listen_key,
+            create_meta(1.into()),
+            SHandle {
+                sender: listen_sender,
+            },
+        );
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(dial_key, create_meta(1.into()), SHandle { sender });
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, tentacle::service::TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    receiver
+}
+
+#[test]
+fn test_pnet_with_same_key() {
+    let result = test_pnet(
+        Some(PreSharedKey::new([1; 32])),
+        Some(PreSharedKey::new([1; 32])),
+    );
+
+    assert_eq!(result.recv().unwrap(), Event::SessionOpen);
+}
+
+#[test]
+fn test_pnet_with_different_key() {
+    let result = test_pnet(
+        Some(PreSharedKey::new([1; 32])),
+        Some(PreSharedKey::new([2; 32])),
+    );
+
+    assert_eq!(result.recv().unwrap(), Event::PreSharedKeyError);
+}
+
+#[test]
+fn test_pnet_without_key() {
+    let result = test_pnet(Some(PreSharedKey::new([1; 32])), None);
+
+    // the dialer without key fails on secio handshake
+    assert_eq!(result.recv().unwrap(), Event::SecioError);
+}
+
+#[test]
+fn test_pnet_dial_without_key() {
+    let result = test_pnet(None, Some(PreSharedKey::new([1; 32])));
+
+    assert_eq!(result.recv().unwrap(), Event::PreSharedKeyError);
+}
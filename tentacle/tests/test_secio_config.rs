@@ -0,0 +1,140 @@
+use futures::{channel, StreamExt};
+use std::thread;
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ServiceContext},
+    multiaddr::Multiaddr,
+    secio::{
+        crypto::cipher::CipherType,
+        handshake::{Config, NegotiatedAlgorithms},
+        Digest, KeyAgreement, SecioKeyPair,
+    },
+    service::{ProtocolHandle, ProtocolMeta, Service, ServiceEvent, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    ProtocolId,
+};
+
+pub fn create<F>(config: Option<Config>, meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true);
+
+    match config {
+        Some(config) => builder.secio_config(config).build(shandle),
+        None => builder.build(shandle),
+    }
+}
+
+struct PHandle;
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+}
+
+struct SHandle {
+    sender: crossbeam_channel::Sender<Option<NegotiatedAlgorithms>>,
+}
+
+impl ServiceHandle for SHandle {
+    fn handle_event(&mut self, _context: &mut ServiceContext, event: ServiceEvent) {
+        if let ServiceEvent::SessionOpen { session_context } = event {
+            let _res = self.sender.send(session_context.negotiated_algorithms);
+        }
+    }
+}
+
+fn create_meta(id: ProtocolId) -> ProtocolMeta {
+    MetaBuilder::new()
+        .id(id)
+        .service_handle(move || ProtocolHandle::Callback(Box::new(PHandle)))
+        .build()
+}
+
+fn test_negotiate(
+    listen_config: Option<Config>,
+    dial_config: Option<Config>,
+) -> (Option<NegotiatedAlgorithms>, Option<NegotiatedAlgorithms>) {
+    let (listen_sender, listen_receiver) = crossbeam_channel::unbounded();
+    let (dial_sender, dial_receiver) = crossbeam_channel::unbounded();
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(
+            listen_config,
+            create_meta(1.into()),
+            SHandle {
+                sender: listen_sender,
+            },
+        );
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(
+            dial_config,
+            create_meta(1.into()),
+            SHandle {
+                sender: dial_sender,
+            },
+        );
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    (
+        listen_receiver.recv().unwrap(),
+        dial_receiver.recv().unwrap(),
+    )
+}
+
+#[test]
+fn test_negotiated_algorithms() {
+    let listen_config = Config::new(SecioKeyPair::secp256k1_generated())
+        .key_agreements(&[KeyAgreement::X25519])
+        .ciphers(&[CipherType::ChaCha20Poly1305]);
+    let dial_config = Config::new(SecioKeyPair::secp256k1_generated()).digests(&[Digest::Sha512]);
+
+    let expected = NegotiatedAlgorithms {
+        key_agreement: KeyAgreement::X25519,
+        cipher: CipherType::ChaCha20Poly1305,
+        digest: Digest::Sha512,
+    };
+    let (listen, dial) = test_negotiate(Some(listen_config), Some(dial_config));
+
+    assert_eq!(listen, Some(expected));
+    assert_eq!(dial, Some(expected));
+}
+
+#[test]
+fn test_no_encryption() {
+    let (listen, dial) = test_negotiate(None, None);
+
+    assert_eq!(listen, None);
+    assert_eq!(dial, None);
+}
@@ -0,0 +1,123 @@
+use futures::{channel, StreamExt};
+use std::{thread, time::Duration};
+use tentacle::{
+    builder::{MetaBuilder, ServiceBuilder},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    multiaddr::Multiaddr,
+    secio::SecioKeyPair,
+    service::{ProtocolHandle, ProtocolMeta, Service, TargetProtocol},
+    traits::{ServiceHandle, ServiceProtocol},
+    yamux::Rtt,
+    ProtocolId,
+};
+
+pub fn create<F>(secio: bool, meta: ProtocolMeta, shandle: F) -> Service<F>
+where
+    F: ServiceHandle + Unpin,
+{
+    let builder = ServiceBuilder::default()
+        .insert_protocol(meta)
+        .forever(true);
+
+    if secio {
+        builder
+            .key_pair(SecioKeyPair::secp256k1_generated())
+            .build(shandle)
+    } else {
+        builder.build(shandle)
+    }
+}
+
+struct PHandle {
+    sender: crossbeam_channel::Sender<(Duration, Option<Rtt>)>,
+}
+
+impl ServiceProtocol for PHandle {
+    fn init(&mut self, _context: &mut ProtocolContext) {}
+
+    fn connected(&mut self, context: ProtocolContextMutRef, _version: &str) {
+        if context.session.ty.is_outbound() {
+            let session = context.session.clone();
+            let sender = self.sender.clone();
+            tokio::spawn(async move {
+                let rtt = session.ping().await.unwrap();
+                let _res = sender.send((rtt, session.rtt()));
+            });
+        }
+    }
+}
+
+fn create_meta(
+    id: ProtocolId,
+) -> (
+    ProtocolMeta,
+    crossbeam_channel::Receiver<(Duration, Option<Rtt>)>,
+) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    (
+        MetaBuilder::new()
+            .id(id)
+            .service_handle(move || {
+                let handle = Box::new(PHandle { sender });
+                ProtocolHandle::Callback(handle)
+            })
+            .build(),
+        receiver,
+    )
+}
+
+fn test_session_rtt(secio: bool) {
+    let (meta, _) = create_meta(1.into());
+    let (addr_sender, addr_receiver) = channel::oneshot::channel::<Multiaddr>();
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, meta, ());
+        rt.block_on(async move {
+            let listen_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .await
+                .unwrap();
+            let _res = addr_sender.send(listen_addr);
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let (meta, result) = create_meta(1.into());
+
+    thread::spawn(move || {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let mut service = create(secio, meta, ());
+        rt.block_on(async move {
+            let listen_addr = addr_receiver.await.unwrap();
+            service
+                .dial(listen_addr, TargetProtocol::All)
+                .await
+                .unwrap();
+            loop {
+                if service.next().await.is_none() {
+                    break;
+                }
+            }
+        });
+    });
+
+    let (rtt, measured) = result.recv().unwrap();
+    let measured = measured.unwrap();
+    assert_eq!(measured.last, rtt);
+    assert!(measured.min <= rtt);
+}
+
+#[test]
+fn test_session_rtt_with_secio() {
+    test_session_rtt(true)
+}
+
+#[test]
+fn test_session_rtt_with_no_secio() {
+    test_session_rtt(false)
+}
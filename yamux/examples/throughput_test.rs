@@ -2,9 +2,9 @@ use bytesize::ByteSize;
 use futures::prelude::*;
 use log::{info, warn};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::{TcpListener, TcpStream},
-    time::delay_for,
+    time::{delay_for, delay_until, Instant},
 };
 use tokio_yamux::stream::StreamHandle;
 use tokio_yamux::{config::Config, session::Session};
@@ -14,6 +14,9 @@ fn main() {
     if std::env::args().nth(1) == Some("server".to_string()) {
         info!("Starting server ......");
         run_server();
+    } else if std::env::args().nth(1) == Some("delayed".to_string()) {
+        info!("Starting delayed loopback ......");
+        run_delayed();
     } else {
         info!("Starting client ......");
         run_client();
@@ -155,3 +158,113 @@ fn run_client() {
         show_metric().await;
     });
 }
+
+/// One way delay of the delayed loopback
+const DELAY: Duration = Duration::from_millis(25);
+const TRANSFER_SIZE: usize = 32 * 1024 * 1024;
+
+/// Compare the throughput of a single stream over a delayed loopback,
+/// with and without receive window auto tuning
+fn run_delayed() {
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        for &auto_tuning in &[false, true] {
+            let config = Config {
+                enable_window_auto_tuning: auto_tuning,
+                ..Config::default()
+            };
+            let elapsed = delayed_transfer(config).await;
+            info!(
+                "auto tuning: {}, transfer {} in {:?}, {}/s",
+                auto_tuning,
+                ByteSize::b(TRANSFER_SIZE as u64).to_string_as(true),
+                elapsed,
+                ByteSize::b((TRANSFER_SIZE as f64 / elapsed.as_secs_f64()) as u64)
+                    .to_string_as(true),
+            );
+        }
+    });
+}
+
+async fn delayed_transfer(config: Config) -> Duration {
+    let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    let mut proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut session = Session::new_server(socket, config);
+        while let Some(Ok(mut stream)) = session.next().await {
+            tokio::spawn(async move {
+                let mut data = vec![0u8; 64 * 1024];
+                let mut received = 0;
+                while received < TRANSFER_SIZE {
+                    received += stream.read(&mut data).await.unwrap();
+                }
+                info!(
+                    "server stream max receive window: {}",
+                    ByteSize::b(u64::from(stream.max_recv_window())).to_string_as(true)
+                );
+                stream.write_all(b"done").await.unwrap();
+            });
+        }
+    });
+
+    tokio::spawn(async move {
+        let (inbound, _) = proxy.accept().await.unwrap();
+        let outbound = TcpStream::connect(server_addr).await.unwrap();
+        let (inbound_read, inbound_write) = tokio::io::split(inbound);
+        let (outbound_read, outbound_write) = tokio::io::split(outbound);
+        tokio::spawn(delayed_copy(inbound_read, outbound_write));
+        tokio::spawn(delayed_copy(outbound_read, inbound_write));
+    });
+
+    let socket = TcpStream::connect(proxy_addr).await.unwrap();
+    let mut session = Session::new_client(socket, config);
+    let stream = session.open_stream().unwrap();
+    tokio::spawn(async move { while let Some(Ok(_)) = session.next().await {} });
+
+    let start = Instant::now();
+    // The window update frames are handled on the read side of stream,
+    // so keep reading while writing
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    tokio::spawn(async move {
+        let data = vec![1u8; 64 * 1024];
+        for _ in 0..TRANSFER_SIZE / data.len() {
+            writer.write_all(&data).await.unwrap();
+        }
+    });
+    let mut done = [0u8; 4];
+    reader.read_exact(&mut done).await.unwrap();
+    start.elapsed()
+}
+
+/// Copy data from reader to writer, every chunk is delayed by `DELAY`
+async fn delayed_copy<R, W>(mut reader: R, mut writer: W)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, mut receiver) = futures::channel::mpsc::unbounded::<(Instant, Vec<u8>)>();
+    tokio::spawn(async move {
+        while let Some((read_at, data)) = receiver.next().await {
+            delay_until(read_at + DELAY).await;
+            if writer.write_all(&data).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut buf = vec![0u8; 64 * 1024];
+    while let Ok(n) = reader.read(&mut buf).await {
+        if n == 0
+            || sender
+                .unbounded_send((Instant::now(), buf[..n].to_vec()))
+                .is_err()
+        {
+            break;
+        }
+    }
+}
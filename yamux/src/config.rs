@@ -12,6 +12,14 @@ pub const DEFAULT_MAX_STREAM_COUNT: usize = 65535;
 pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
 /// Default write timeout duration
 pub const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Default max receive window size of a stream when auto tuning is enabled
+pub const DEFAULT_MAX_AUTO_TUNED_WINDOW: u32 = 16 * 1024 * 1024;
+/// Default total receive window that streams of a session can grow when auto tuning is enabled
+pub const DEFAULT_AUTO_TUNING_SESSION_BUDGET: usize = 64 * 1024 * 1024;
+/// Default total receive buffer size of all streams in a session
+pub const DEFAULT_SESSION_RECV_BUFFER_SIZE: usize = 256 * 1024 * 1024;
+/// Default write priority of stream, lower value is higher priority
+pub const DEFAULT_STREAM_PRIORITY: u8 = 128;
 
 /// Configuration of session and stream
 #[derive(Clone, Copy)]
@@ -20,6 +28,12 @@ pub struct Config {
     /// waiting an accept.
     pub accept_backlog: usize,
 
+    /// AcceptBacklogTimeout is how long the new streams beyond the backlog are held
+    /// without ACK, waiting for the backlog to be available. At most `accept_backlog`
+    /// streams are held, the others and the timed out ones are reset.
+    /// Streams beyond the backlog are reset immediately if it is None, default is None
+    pub accept_backlog_timeout: Option<Duration>,
+
     /// EnableKeepalive is used to do a period keep alive
     /// messages using a ping.
     pub enable_keepalive: bool,
@@ -39,17 +53,39 @@ pub struct Config {
     /// MaxStreamWindowSize is used to control the maximum
     /// window size that we allow for a stream.
     pub max_stream_window_size: u32,
+
+    /// EnableWindowAutoTuning is used to grow the receive window of a stream
+    /// when the window is consumed within a few round trips, default is false
+    pub enable_window_auto_tuning: bool,
+
+    /// MaxAutoTunedWindowSize is the ceiling of the receive window of a stream
+    /// when auto tuning is enabled
+    pub max_auto_tuned_window_size: u32,
+
+    /// AutoTuningSessionBudget is the total receive window that all streams of a session
+    /// can grow beyond `max_stream_window_size` when auto tuning is enabled
+    pub auto_tuning_session_budget: usize,
+
+    /// MaxSessionRecvBufferSize is the total receive window that all streams of a session
+    /// can advertise, windows are shrunk when it is tight, and new streams from remote
+    /// are reset when it is exhausted
+    pub max_session_recv_buffer_size: usize,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
             accept_backlog: DEFAULT_ACCEPT_BACKLOG,
+            accept_backlog_timeout: None,
             enable_keepalive: true,
             keepalive_interval: DEFAULT_KEEPALIVE_INTERVAL,
             connection_write_timeout: DEFAULT_WRITE_TIMEOUT,
             max_stream_count: DEFAULT_MAX_STREAM_COUNT,
             max_stream_window_size: INITIAL_STREAM_WINDOW,
+            enable_window_auto_tuning: false,
+            max_auto_tuned_window_size: DEFAULT_MAX_AUTO_TUNED_WINDOW,
+            auto_tuning_session_budget: DEFAULT_AUTO_TUNING_SESSION_BUDGET,
+            max_session_recv_buffer_size: DEFAULT_SESSION_RECV_BUFFER_SIZE,
         }
     }
 }
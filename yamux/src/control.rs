@@ -1,41 +1,125 @@
+use bytes::Bytes;
 use futures::{
     channel::{mpsc, oneshot},
     sink::SinkExt,
 };
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use crate::{error::Error, stream::StreamHandle};
+use crate::{
+    error::Error,
+    rtt::{Rtt, RttMeter},
+    stream::StreamHandle,
+};
 
 pub(crate) enum Command {
-    OpenStream(oneshot::Sender<Result<StreamHandle, Error>>),
+    OpenStream(Option<Bytes>, oneshot::Sender<Result<StreamHandle, Error>>),
     Shutdown(oneshot::Sender<()>),
+    GoAway(oneshot::Sender<()>),
+    Flush(oneshot::Sender<()>),
+    Ping(oneshot::Sender<Result<Duration, Error>>),
 }
 
 /// A session control is used to open the stream or close the session
-#[derive(Clone)]
-pub struct Control(mpsc::Sender<Command>);
+#[derive(Clone, Debug)]
+pub struct Control {
+    sender: mpsc::Sender<Command>,
+    rtt: Arc<RttMeter>,
+    refused_streams: Arc<AtomicUsize>,
+}
 
 impl Control {
-    pub(crate) fn new(sender: mpsc::Sender<Command>) -> Self {
-        Control(sender)
+    pub(crate) fn new(
+        sender: mpsc::Sender<Command>,
+        rtt: Arc<RttMeter>,
+        refused_streams: Arc<AtomicUsize>,
+    ) -> Self {
+        Control {
+            sender,
+            rtt,
+            refused_streams,
+        }
     }
 
     /// Open a new stream to remote session
     pub async fn open_stream(&mut self) -> Result<StreamHandle, Error> {
         let (tx, rx) = oneshot::channel();
-        self.0
-            .send(Command::OpenStream(tx))
+        self.sender
+            .send(Command::OpenStream(None, tx))
+            .await
+            .map_err(|_| Error::SessionShutdown)?;
+        rx.await.map_err(|_| Error::SessionShutdown)?
+    }
+
+    /// Open a new stream to remote session, the SYN is sent together with
+    /// the first data frame, so remote can handle the data without waiting
+    /// for another round trip.
+    ///
+    /// The data must not be larger than `max_stream_window_size`
+    pub async fn open_stream_with_data(&mut self, data: Bytes) -> Result<StreamHandle, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Command::OpenStream(Some(data), tx))
+            .await
+            .map_err(|_| Error::SessionShutdown)?;
+        rx.await.map_err(|_| Error::SessionShutdown)?
+    }
+
+    /// Send a ping to remote session and return the round trip time
+    pub async fn ping(&mut self) -> Result<Duration, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Command::Ping(tx))
             .await
             .map_err(|_| Error::SessionShutdown)?;
         rx.await.map_err(|_| Error::SessionShutdown)?
     }
 
+    /// The round trip time measured by keepalive and `ping`,
+    /// None if no ping has been acked yet
+    pub fn rtt(&self) -> Option<Rtt> {
+        self.rtt.get()
+    }
+
+    /// The count of streams opened by remote but refused by the session,
+    /// because of the accept backlog, max stream count or receive buffer limit
+    pub fn refused_streams(&self) -> usize {
+        self.refused_streams.load(Ordering::SeqCst)
+    }
+
+    /// Send a GoAway to remote, remote can't open new streams after that,
+    /// the opened streams are not affected
+    pub async fn go_away(&mut self) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Command::GoAway(tx))
+            .await
+            .map_err(|_| Error::SessionShutdown)?;
+        rx.await.map_err(|_| Error::SessionShutdown)
+    }
+
+    /// Wait until all the frames sent by streams have been written to the underlying connection
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .send(Command::Flush(tx))
+            .await
+            .map_err(|_| Error::SessionShutdown)?;
+        rx.await.map_err(|_| Error::SessionShutdown)
+    }
+
     /// shutdown is used to close the session and all streams.
     pub async fn close(&mut self) {
-        if self.0.is_closed() {
+        if self.sender.is_closed() {
             return;
         }
         let (tx, rx) = oneshot::channel();
-        let _ignore = self.0.send(Command::Shutdown(tx)).await;
+        let _ignore = self.sender.send(Command::Shutdown(tx)).await;
         let _ignore = rx.await;
     }
 }
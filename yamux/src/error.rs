@@ -2,6 +2,8 @@
 
 use std::{error, fmt};
 
+use crate::frame::GoAwayCode;
+
 /// The error types
 #[derive(Debug, Eq, PartialEq)]
 pub enum Error {
@@ -56,6 +58,30 @@ pub enum Error {
 
     /// Sub stream send event channel full, block to complete
     WouldBlock,
+
+    /// RecvBufferExhausted is returned if the receive buffer budget
+    /// of session can't afford a new stream
+    RecvBufferExhausted,
+
+    /// RemoteGoAwayWithError is returned when the remote closes
+    /// the session with an error code
+    RemoteGoAwayWithError(GoAwayCode),
+
+    /// UnknownStream is returned if we received a frame of a stream
+    /// that was never opened
+    UnknownStream(u32),
+
+    /// StreamReset is returned when reading a stream reset by remote,
+    /// with the reset code of remote, 0 means no reason
+    StreamReset(u32),
+
+    /// PingTimeout is returned if the ping is never acked, the remote
+    /// acked a later ping instead or the ping id was reused
+    PingTimeout,
+
+    /// InitialDataTooLarge is returned if the data sent with the SYN
+    /// doesn't fit in the initial send window
+    InitialDataTooLarge,
 }
 
 impl error::Error for Error {}
@@ -80,6 +106,14 @@ impl fmt::Display for Error {
             Error::KeepAliveTimeout => write!(f, "Keepalive timeout"),
             Error::SubStreamRemoteClosing => write!(f, "Remote sub stream is closed"),
             Error::WouldBlock => write!(f, "Sub stream send channel full"),
+            Error::RecvBufferExhausted => write!(f, "Session receive buffer exhausted"),
+            Error::RemoteGoAwayWithError(code) => {
+                write!(f, "Go away message from the other side with {:?}", code)
+            }
+            Error::UnknownStream(id) => write!(f, "Received a frame of unknown stream {}", id),
+            Error::StreamReset(code) => write!(f, "Stream is reset by remote with code {}", code),
+            Error::PingTimeout => write!(f, "Ping is not acked by remote"),
+            Error::InitialDataTooLarge => write!(f, "Initial data exceeds the send window"),
         }
     }
 }
@@ -10,17 +10,24 @@ pub mod config;
 pub mod error;
 // Frame module
 pub mod frame;
+// Round trip time module
+pub mod rtt;
 // Session module
 pub mod session;
 // Stream module
 mod control;
 pub mod stream;
+// Receive window auto tuning module
+mod window;
+// Write scheduling module
+mod scheduler;
 
 // Stream ID type
 pub(crate) type StreamId = u32;
 
 pub use crate::{
-    config::Config, control::Control, error::Error, session::Session, stream::StreamHandle,
+    config::Config, control::Control, error::Error, rtt::Rtt, session::Session,
+    stream::StreamHandle,
 };
 
 // Latest Protocol Version
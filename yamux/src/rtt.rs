@@ -0,0 +1,88 @@
+//! Round trip time measured by ping frames
+
+use std::{sync::Mutex, time::Duration};
+
+/// Round trip time of a session, measured by ping frames
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Rtt {
+    /// The latest measurement
+    pub last: Duration,
+    /// Smoothed round trip time, `srtt = 7/8 * srtt + 1/8 * last`
+    pub smoothed: Duration,
+    /// The minimum measurement
+    pub min: Duration,
+}
+
+/// Shared between the session and its controls
+#[derive(Debug, Default)]
+pub(crate) struct RttMeter {
+    inner: Mutex<Option<Rtt>>,
+}
+
+impl RttMeter {
+    /// Record a new measurement
+    pub(crate) fn update(&self, sample: Duration) {
+        if let Ok(mut inner) = self.inner.lock() {
+            let rtt = match *inner {
+                Some(rtt) => Rtt {
+                    last: sample,
+                    smoothed: (rtt.smoothed * 7 + sample) / 8,
+                    min: ::std::cmp::min(rtt.min, sample),
+                },
+                None => Rtt {
+                    last: sample,
+                    smoothed: sample,
+                    min: sample,
+                },
+            };
+            *inner = Some(rtt);
+        }
+    }
+
+    /// Get the measurement, None if no ping has been acked
+    pub(crate) fn get(&self) -> Option<Rtt> {
+        self.inner.lock().ok().and_then(|inner| *inner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Rtt, RttMeter};
+    use std::time::Duration;
+
+    #[test]
+    fn test_rtt_meter() {
+        let meter = RttMeter::default();
+        assert_eq!(meter.get(), None);
+
+        meter.update(Duration::from_millis(80));
+        assert_eq!(
+            meter.get(),
+            Some(Rtt {
+                last: Duration::from_millis(80),
+                smoothed: Duration::from_millis(80),
+                min: Duration::from_millis(80),
+            })
+        );
+
+        meter.update(Duration::from_millis(160));
+        assert_eq!(
+            meter.get(),
+            Some(Rtt {
+                last: Duration::from_millis(160),
+                smoothed: Duration::from_millis(90),
+                min: Duration::from_millis(80),
+            })
+        );
+
+        meter.update(Duration::from_millis(10));
+        assert_eq!(
+            meter.get(),
+            Some(Rtt {
+                last: Duration::from_millis(10),
+                smoothed: Duration::from_millis(80),
+                min: Duration::from_millis(10),
+            })
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Write scheduling across streams
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::{config::DEFAULT_STREAM_PRIORITY, frame::Frame, StreamId, RESERVED_STREAM_ID};
+
+/// Pending frames waiting to be written to the underlying connection.
+///
+/// Session frames are always sent first, then streams with higher priority,
+/// streams with the same priority take turns to send one frame, so a stream
+/// with a large backlog can't delay the others.
+#[derive(Default)]
+pub(crate) struct WriteScheduler {
+    session_frames: VecDeque<Frame>,
+    stream_frames: HashMap<StreamId, VecDeque<Frame>>,
+    // Streams that have pending frames, grouped by priority
+    ready: BTreeMap<u8, VecDeque<StreamId>>,
+    priorities: HashMap<StreamId, u8>,
+    len: usize,
+}
+
+impl WriteScheduler {
+    pub(crate) fn push_back(&mut self, frame: Frame) {
+        self.len += 1;
+        let stream_id = frame.stream_id();
+        if stream_id == RESERVED_STREAM_ID {
+            self.session_frames.push_back(frame);
+            return;
+        }
+        let frames = self.stream_frames.entry(stream_id).or_default();
+        if frames.is_empty() {
+            let priority = self
+                .priorities
+                .get(&stream_id)
+                .unwrap_or(&DEFAULT_STREAM_PRIORITY);
+            self.ready
+                .entry(*priority)
+                .or_default()
+                .push_back(stream_id);
+        }
+        frames.push_back(frame);
+    }
+
+    pub(crate) fn pop_front(&mut self) -> Option<Frame> {
+        if let Some(frame) = self.session_frames.pop_front() {
+            self.len -= 1;
+            return Some(frame);
+        }
+
+        let (&priority, streams) = self.ready.iter_mut().next()?;
+        let stream_id = streams.pop_front()?;
+        if streams.is_empty() {
+            self.ready.remove(&priority);
+        }
+        let frames = self.stream_frames.get_mut(&stream_id)?;
+        let frame = frames.pop_front();
+        if frames.is_empty() {
+            self.stream_frames.remove(&stream_id);
+        } else {
+            // Take turns, the priority may have been changed
+            let priority = self
+                .priorities
+                .get(&stream_id)
+                .unwrap_or(&DEFAULT_STREAM_PRIORITY);
+            self.ready
+                .entry(*priority)
+                .or_default()
+                .push_back(stream_id);
+        }
+        self.len -= 1;
+        frame
+    }
+
+    /// Set the priority of stream, it takes effect on the next turn of stream
+    pub(crate) fn set_priority(&mut self, stream_id: StreamId, priority: u8) {
+        if priority == DEFAULT_STREAM_PRIORITY {
+            self.priorities.remove(&stream_id);
+        } else {
+            self.priorities.insert(stream_id, priority);
+        }
+    }
+
+    /// Forget the priority of a closed stream, its pending frames will still be sent
+    pub(crate) fn remove_stream(&mut self, stream_id: StreamId) {
+        self.priorities.remove(&stream_id);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.session_frames.clear();
+        self.stream_frames.clear();
+        self.ready.clear();
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::WriteScheduler;
+    use crate::frame::{Flag, Flags, Frame, GoAwayCode};
+
+    fn data(stream_id: u32) -> Frame {
+        Frame::new_data(Flags::default(), stream_id, Default::default())
+    }
+
+    fn pop_ids(scheduler: &mut WriteScheduler) -> Vec<u32> {
+        let mut ids = Vec::new();
+        while let Some(frame) = scheduler.pop_front() {
+            ids.push(frame.stream_id());
+        }
+        ids
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut scheduler = WriteScheduler::default();
+        for _ in 0..3 {
+            scheduler.push_back(data(1));
+        }
+        scheduler.push_back(data(3));
+        scheduler.push_back(Frame::new_ping(Flags::from(Flag::Syn), 1));
+        scheduler.push_back(data(5));
+        scheduler.push_back(Frame::new_go_away(GoAwayCode::Normal));
+        assert_eq!(scheduler.len(), 7);
+
+        assert_eq!(pop_ids(&mut scheduler), vec![0, 0, 1, 3, 5, 1, 1]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut scheduler = WriteScheduler::default();
+        scheduler.set_priority(1, 200);
+        scheduler.set_priority(5, 10);
+        for _ in 0..2 {
+            scheduler.push_back(data(1));
+            scheduler.push_back(data(3));
+            scheduler.push_back(data(5));
+        }
+        assert_eq!(pop_ids(&mut scheduler), vec![5, 5, 3, 3, 1, 1]);
+
+        // the priority of closed stream is forgotten
+        scheduler.remove_stream(5);
+        scheduler.push_back(data(3));
+        scheduler.push_back(data(5));
+        assert_eq!(pop_ids(&mut scheduler), vec![3, 5]);
+    }
+}
@@ -3,17 +3,25 @@
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
 use std::{
-    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     io,
     pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
     time::Duration,
 };
 #[cfg(target_arch = "wasm32")]
 use timer::Instant;
 
+use bytes::Bytes;
 use futures::{
-    channel::mpsc::{channel, unbounded, Receiver, Sender, UnboundedReceiver, UnboundedSender},
+    channel::{
+        mpsc::{channel, unbounded, Receiver, Sender, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
     Sink, Stream,
 };
 use log::{debug, log_enabled, trace};
@@ -25,7 +33,10 @@ use crate::{
     control::{Command, Control},
     error::Error,
     frame::{Flag, Flags, Frame, FrameCodec, GoAwayCode, Type},
+    rtt::RttMeter,
+    scheduler::WriteScheduler,
     stream::{StreamEvent, StreamHandle, StreamState},
+    window::{RecvBudget, WindowTuning},
     StreamId,
 };
 
@@ -34,6 +45,9 @@ use timer::{interval, Interval};
 const BUF_SHRINK_THRESHOLD: usize = u8::max_value() as usize;
 const TIMEOUT: Duration = Duration::from_secs(30);
 
+/// The waiter of an on-demand ping, told the round trip time or why the ping is lost
+type PingWaiter = oneshot::Sender<Result<Duration, Error>>;
+
 /// wasm doesn't support time get, must use browser timer instead
 /// But we can simulate it with `futures-timer`.
 /// So, I implemented a global time dependent on `futures-timer`,
@@ -57,26 +71,46 @@ pub struct Session<T> {
     // accepting further connections. Must be first for alignment.
     local_go_away: bool,
 
+    // The session is closing by local or on error, it's dead once the
+    // go away is exchanged, otherwise it waits for the streams to finish
+    closing: bool,
+
     // nextStreamID is the next stream we should
     // send. This depends if we are a client/server.
     next_stream_id: StreamId,
+    // The max stream id opened by remote
+    max_remote_stream_id: StreamId,
     ty: SessionType,
 
     // config holds our configuration
     config: Config,
 
-    // pings is used to track inflight pings
-    pings: BTreeMap<u32, Instant>,
+    // pings is used to track inflight pings, and the waiter of on-demand ping
+    pings: BTreeMap<u32, (Instant, Option<PingWaiter>)>,
     ping_id: u32,
+    // rtt is measured by the acked pings, shared with controls
+    rtt: Arc<RttMeter>,
+    // window_tuning is shared with streams when auto tuning is enabled
+    window_tuning: Option<WindowTuning>,
+    // recv_budget bounds the receive window of all streams
+    recv_budget: RecvBudget,
 
     // streams maps a stream id to a sender of stream,
     streams: HashMap<StreamId, Sender<Frame>>,
     // The StreamHandle not yet been polled
     pending_streams: VecDeque<StreamHandle>,
+    // The streams held without ACK since the accept backlog is full, and when they arrived
+    held_streams: HashMap<StreamId, Instant>,
+    // Wake up the session to check the timeout of held streams
+    accept_timer: Option<Interval>,
+    // The count of streams refused, shared with controls
+    refused_streams: Arc<AtomicUsize>,
     // The buffer which will send to underlying network
-    write_pending_frames: VecDeque<Frame>,
+    write_pending_frames: WriteScheduler,
     // The buffer which will distribute to sub streams
     read_pending_frames: VecDeque<Frame>,
+    // Waiters of flush, notified when all pending frames have been written
+    flush_waiters: Vec<oneshot::Sender<()>>,
 
     // Why can unbound channel be used here?
     //
@@ -147,32 +181,62 @@ where
             raw_stream,
             FrameCodec::default().max_frame_size(config.max_stream_window_size),
         );
+        let rtt = Arc::new(RttMeter::default());
+        let window_tuning = if config.enable_window_auto_tuning {
+            Some(WindowTuning::new(
+                Arc::clone(&rtt),
+                config.auto_tuning_session_budget,
+                config.max_auto_tuned_window_size,
+            ))
+        } else {
+            None
+        };
         let keepalive = if config.enable_keepalive {
             Some(interval(config.keepalive_interval))
         } else {
             None
         };
 
-        Session {
+        let mut session = Session {
             framed_stream,
             eof: false,
             remote_go_away: false,
             local_go_away: false,
+            closing: false,
             next_stream_id,
+            max_remote_stream_id: 0,
             ty,
             config,
             pings: BTreeMap::default(),
             ping_id: 0,
+            rtt,
+            window_tuning,
+            recv_budget: RecvBudget::new(config.max_session_recv_buffer_size),
             streams: HashMap::default(),
             pending_streams: VecDeque::default(),
-            write_pending_frames: VecDeque::default(),
+            held_streams: HashMap::default(),
+            accept_timer: None,
+            refused_streams: Arc::new(AtomicUsize::new(0)),
+            write_pending_frames: WriteScheduler::default(),
             read_pending_frames: VecDeque::default(),
+            flush_waiters: Vec::new(),
             event_sender,
             event_receiver,
             control_sender,
             control_receiver,
             keepalive,
+        };
+        if session.window_tuning.is_some() {
+            // Window auto tuning depends on rtt, measure it as soon as possible
+            session.ping_id = session.ping_id.overflowing_add(1).0;
+            session
+                .pings
+                .insert(session.ping_id, (Instant::now(), None));
+            session
+                .write_pending_frames
+                .push_back(Frame::new_ping(Flags::from(Flag::Syn), session.ping_id));
         }
+        session
     }
 
     /// Create a server session (typical raw_stream is an accepted TcpStream)
@@ -194,7 +258,7 @@ where
 
         // Ignore frames remaining in pending queue
         self.write_pending_frames.clear();
-        self.send_go_away(cx)?;
+        self.send_go_away_with_code(cx, GoAwayCode::Normal)?;
         Ok(())
     }
 
@@ -207,8 +271,18 @@ where
         Ok(())
     }
 
+    // The stream id is neither allocated by local nor opened by remote
+    fn is_never_opened(&self, stream_id: StreamId) -> bool {
+        if stream_id % 2 == self.next_stream_id % 2 {
+            stream_id >= self.next_stream_id
+        } else {
+            stream_id > self.max_remote_stream_id
+        }
+    }
+
     fn is_dead(&self) -> bool {
-        self.remote_go_away && self.local_go_away || self.eof
+        self.remote_go_away && self.local_go_away && (self.closing || self.streams.is_empty())
+            || self.eof
     }
 
     fn send_ping(&mut self, cx: &mut Context, ping_id: Option<u32>) -> Result<u32, io::Error> {
@@ -225,8 +299,16 @@ where
 
     /// GoAway can be used to prevent accepting further
     /// connections. It does not close the underlying conn.
+    ///
+    /// The opened streams can still be used, the pending frames are sent as usual
     pub fn send_go_away(&mut self, cx: &mut Context) -> Result<(), io::Error> {
-        self.send_go_away_with_code(cx, GoAwayCode::Normal)
+        if self.local_go_away {
+            return Ok(());
+        }
+        let frame = Frame::new_go_away(GoAwayCode::Normal);
+        self.send_frame(cx, frame)?;
+        self.local_go_away = true;
+        Ok(())
     }
 
     fn send_go_away_with_code(
@@ -239,6 +321,7 @@ where
         let frame = Frame::new_go_away(code);
         self.send_frame(cx, frame)?;
         self.local_go_away = true;
+        self.closing = true;
         let mut new_timer = interval(self.config.connection_write_timeout);
         // force registration of new timer to driver
         let _ignore = Pin::new(&mut new_timer).as_mut().poll_next(cx);
@@ -255,14 +338,34 @@ where
         } else if self.remote_go_away {
             Err(Error::RemoteGoAway)
         } else {
-            let stream = self.create_stream(None)?;
+            let stream = self.create_stream(None, None)?;
             Ok(stream)
         }
     }
 
+    /// Open a new stream with the first data frame carrying SYN
+    ///
+    /// The data must not be larger than `max_stream_window_size`,
+    /// which is the initial send window of stream
+    pub fn open_stream_with_data(&mut self, data: Bytes) -> Result<StreamHandle, Error> {
+        if data.len() > self.config.max_stream_window_size as usize {
+            Err(Error::InitialDataTooLarge)
+        } else if self.is_dead() {
+            Err(Error::SessionShutdown)
+        } else if self.remote_go_away {
+            Err(Error::RemoteGoAway)
+        } else {
+            self.create_stream(None, Some(data))
+        }
+    }
+
     /// Return a control to async open stream/close session
     pub fn control(&self) -> Control {
-        Control::new(self.control_sender.clone())
+        Control::new(
+            self.control_sender.clone(),
+            Arc::clone(&self.rtt),
+            Arc::clone(&self.refused_streams),
+        )
     }
 
     fn keep_alive(&mut self, cx: &mut Context, ping_at: Instant) -> Result<(), io::Error> {
@@ -274,18 +377,34 @@ where
         if self
             .pings
             .iter()
-            .any(|(_id, time)| time.elapsed() > TIMEOUT)
+            .any(|(_id, (time, _))| time.elapsed() > TIMEOUT)
         {
+            for (_, (_, waiter)) in ::std::mem::take(&mut self.pings) {
+                if let Some(waiter) = waiter {
+                    let _ignore = waiter.send(Err(Error::PingTimeout));
+                }
+            }
             return Err(io::ErrorKind::TimedOut.into());
         }
 
         let ping_id = self.send_ping(cx, None)?;
         debug!("[{:?}] sent keep_alive ping (id={:?})", self.ty, ping_id);
-        self.pings.insert(ping_id, ping_at);
+        self.track_ping(ping_id, ping_at, None);
         Ok(())
     }
 
-    fn create_stream(&mut self, stream_id: Option<StreamId>) -> Result<StreamHandle, Error> {
+    /// Track an inflight ping, the waiter of a reused ping id is told the ping is lost
+    fn track_ping(&mut self, ping_id: u32, ping_at: Instant, waiter: Option<PingWaiter>) {
+        if let Some((_, Some(lost))) = self.pings.insert(ping_id, (ping_at, waiter)) {
+            let _ignore = lost.send(Err(Error::PingTimeout));
+        }
+    }
+
+    fn create_stream(
+        &mut self,
+        stream_id: Option<StreamId>,
+        initial_data: Option<Bytes>,
+    ) -> Result<StreamHandle, Error> {
         let (stream_id, state) = match stream_id {
             Some(stream_id) => (stream_id, StreamState::SynReceived),
             None => {
@@ -297,12 +416,17 @@ where
                 (next_id, StreamState::Init)
             }
         };
+        if self.streams.contains_key(&stream_id) {
+            return Err(Error::DuplicateStream);
+        }
+        if !self
+            .recv_budget
+            .try_reserve(self.config.max_stream_window_size)
+        {
+            return Err(Error::RecvBufferExhausted);
+        }
         let (frame_sender, frame_receiver) = channel(8);
-
-        match self.streams.entry(stream_id) {
-            Entry::Occupied(_) => return Err(Error::DuplicateStream),
-            Entry::Vacant(entry) => entry.insert(frame_sender),
-        };
+        self.streams.insert(stream_id, frame_sender);
         let mut stream = StreamHandle::new(
             stream_id,
             self.event_sender.clone(),
@@ -310,9 +434,19 @@ where
             state,
             self.config.max_stream_window_size,
             self.config.max_stream_window_size,
-        );
-        if let Err(err) = stream.send_window_update() {
-            debug!("[{:?}] stream.send_window_update error={:?}", self.ty, err);
+        )
+        .with_recv_budget(self.recv_budget.clone());
+        if let Some(ref window_tuning) = self.window_tuning {
+            stream = stream.with_window_tuning(window_tuning.clone());
+        }
+        let res = match initial_data {
+            Some(data) => stream.send_initial_data(data),
+            None => stream.send_window_update(),
+        };
+        match res {
+            Err(Error::InitialDataTooLarge) => return Err(Error::InitialDataTooLarge),
+            Err(err) => debug!("[{:?}] stream send SYN error={:?}", self.ty, err),
+            Ok(()) => (),
         }
         Ok(stream)
     }
@@ -321,7 +455,7 @@ where
     /// Sink `start_send` NotReady -> buffer full need poll complete
     #[inline]
     fn send_all(&mut self, cx: &mut Context) -> Result<bool, io::Error> {
-        while let Some(frame) = self.write_pending_frames.pop_front() {
+        while !self.write_pending_frames.is_empty() {
             if self.is_dead() {
                 break;
             }
@@ -330,11 +464,17 @@ where
 
             match sink.as_mut().poll_ready(cx)? {
                 Poll::Ready(()) => {
-                    sink.as_mut().start_send(frame)?;
+                    // The next frame is decided by the scheduler when the sink is ready
+                    if let Some(frame) = self.write_pending_frames.pop_front() {
+                        sink.as_mut().start_send(frame)?;
+                    }
                 }
                 Poll::Pending => {
-                    debug!("[{:?}] framed_stream NotReady, frame: {:?}", self.ty, frame);
-                    self.write_pending_frames.push_front(frame);
+                    debug!(
+                        "[{:?}] framed_stream NotReady, pending frames: {}",
+                        self.ty,
+                        self.write_pending_frames.len()
+                    );
 
                     if self.poll_complete(cx)? {
                         return Ok(true);
@@ -400,6 +540,7 @@ where
                 continue;
             }
             if frame.flags().contains(Flag::Syn) {
+                self.max_remote_stream_id = ::std::cmp::max(self.max_remote_stream_id, stream_id);
                 if self.local_go_away {
                     let flags = Flags::from(Flag::Rst);
                     let frame = Frame::new_window_update(flags, stream_id, 0);
@@ -408,27 +549,43 @@ where
                         "substream({}) local go away send Reset to remote, session.ty={:?}",
                         stream_id, self.ty
                     );
-                    // TODO: should report error?
-                    return Ok(());
+                    continue;
                 }
-                if self.streams.len() < self.config.max_stream_count
+                if self.pending_streams.len() >= self.config.accept_backlog
+                    && self.hold_stream(cx, stream_id)
+                {
+                    // Hold the SYN and the following frames of the stream
+                    // until the backlog is available
+                    trace!("substream({}) held, session.ty={:?}", stream_id, self.ty);
+                    self.read_pending_frames.push_back(frame);
+                    block_substream.insert(stream_id);
+                    continue;
+                }
+                self.held_streams.remove(&stream_id);
+                let stream = if self.streams.len() < self.config.max_stream_count
                     && self.pending_streams.len() < self.config.accept_backlog
                 {
-                    debug!(
-                        "substream({}) accepted, session.ty={:?}",
-                        stream_id, self.ty
-                    );
-                    let stream = match self.create_stream(Some(stream_id)) {
-                        Ok(stream) => stream,
+                    match self.create_stream(Some(stream_id), None) {
+                        Ok(stream) => Some(stream),
+                        Err(Error::RecvBufferExhausted) => None,
                         Err(_) => {
                             self.send_go_away_with_code(cx, GoAwayCode::ProtocolError)?;
                             return Ok(());
                         }
-                    };
+                    }
+                } else {
+                    None
+                };
+                if let Some(stream) = stream {
+                    debug!(
+                        "substream({}) accepted, session.ty={:?}",
+                        stream_id, self.ty
+                    );
                     self.pending_streams.push_back(stream);
                 } else {
                     // close the stream immediately
-                    debug!("substream({}) closed, session.ty={:?}", stream_id, self.ty);
+                    debug!("substream({}) refused, session.ty={:?}", stream_id, self.ty);
+                    self.refused_streams.fetch_add(1, Ordering::SeqCst);
                     let mut flags = Flags::from(Flag::Ack);
                     flags.add(Flag::Rst);
                     let frame = Frame::new_window_update(flags, stream_id, 0);
@@ -463,8 +620,17 @@ where
                             true
                         }
                     }
+                } else if self.is_never_opened(stream_id) {
+                    debug!(
+                        "substream({}) was never opened, session.ty={:?}",
+                        stream_id, self.ty
+                    );
+                    self.send_go_away_with_code(cx, GoAwayCode::ProtocolError)?;
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        Error::UnknownStream(stream_id),
+                    ));
                 } else {
-                    // TODO: stream already closed ?
                     debug!(
                         "substream({}) should exist but not, may drop by self",
                         stream_id
@@ -481,6 +647,29 @@ where
         Ok(())
     }
 
+    // Return true if the stream beyond the accept backlog should be held,
+    // false if it should be reset
+    fn hold_stream(&mut self, cx: &mut Context, stream_id: StreamId) -> bool {
+        let timeout = match self.config.accept_backlog_timeout {
+            Some(timeout) => timeout,
+            None => return false,
+        };
+        if let Some(held_at) = self.held_streams.get(&stream_id) {
+            return held_at.elapsed() < timeout;
+        }
+        if self.held_streams.len() >= self.config.accept_backlog {
+            return false;
+        }
+        if self.accept_timer.is_none() {
+            let mut timer = interval(timeout);
+            // force registration of new timer to driver
+            let _ignore = Pin::new(&mut timer).as_mut().poll_next(cx);
+            self.accept_timer = Some(timer);
+        }
+        self.held_streams.insert(stream_id, Instant::now());
+        true
+    }
+
     // Send message to stream (Data/WindowUpdate)
     fn handle_stream_message(&mut self, cx: &mut Context, frame: Frame) -> Result<(), io::Error> {
         self.read_pending_frames.push_back(frame);
@@ -494,34 +683,47 @@ where
             // Send ping back
             self.send_ping(cx, Some(frame.length()))?;
         } else if flags.contains(Flag::Ack) {
-            self.pings.remove(&frame.length());
+            if let Some((ping_at, waiter)) = self.pings.remove(&frame.length()) {
+                let rtt = ping_at.elapsed();
+                self.rtt.update(rtt);
+                if let Some(waiter) = waiter {
+                    let _ignore = waiter.send(Ok(rtt));
+                }
+            }
             // If the remote peer does not follow the protocol,
             // there may be a memory leak, so here need to discard all ping ids below the ack.
-            self.pings = self.pings.split_off(&frame.length());
+            let pings = self.pings.split_off(&frame.length());
+            for (_, (_, waiter)) in ::std::mem::replace(&mut self.pings, pings) {
+                if let Some(waiter) = waiter {
+                    let _ignore = waiter.send(Err(Error::PingTimeout));
+                }
+            }
         } else {
-            // TODO: unexpected case, send a GoAwayCode::ProtocolError ?
+            self.send_go_away_with_code(cx, GoAwayCode::ProtocolError)?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Error::UnexpectedFlag,
+            ));
         }
         Ok(())
     }
 
     fn handle_go_away(&mut self, cx: &mut Context, frame: &Frame) -> Result<(), io::Error> {
-        let mut close = || -> Result<(), io::Error> {
-            self.remote_go_away = true;
-            self.write_pending_frames.clear();
-            if !self.local_go_away {
-                self.send_go_away(cx)?;
-            }
-            Ok(())
-        };
+        self.remote_go_away = true;
         match GoAwayCode::from(frame.length()) {
-            GoAwayCode::Normal => close(),
-            GoAwayCode::ProtocolError => {
-                // TODO: report error
-                close()
-            }
-            GoAwayCode::InternalError => {
-                // TODO: report error
-                close()
+            // Remote doesn't accept new streams any more, the opened streams are kept
+            // until they finish, the session is closed after that
+            GoAwayCode::Normal => self.send_go_away(cx),
+            code => {
+                self.write_pending_frames.clear();
+                if !self.local_go_away {
+                    self.send_go_away(cx)?;
+                }
+                self.closing = true;
+                Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    Error::RemoteGoAwayWithError(code),
+                ))
             }
         }
     }
@@ -556,10 +758,14 @@ where
             }
             StreamEvent::Closed(stream_id) => {
                 self.streams.remove(&stream_id);
+                self.write_pending_frames.remove_stream(stream_id);
                 if self.streams.capacity() - self.streams.len() > BUF_SHRINK_THRESHOLD {
                     self.streams.shrink_to_fit();
                 }
             }
+            StreamEvent::Priority(stream_id, priority) => {
+                self.write_pending_frames.set_priority(stream_id, priority)
+            }
             StreamEvent::GoAway => self.send_go_away_with_code(cx, GoAwayCode::ProtocolError)?,
         }
         Ok(())
@@ -585,13 +791,27 @@ where
         match Pin::new(&mut self.control_receiver).as_mut().poll_next(cx) {
             Poll::Ready(Some(event)) => {
                 match event {
-                    Command::OpenStream(tx) => {
-                        let _ignore = tx.send(self.open_stream());
+                    Command::OpenStream(data, tx) => {
+                        let res = match data {
+                            Some(data) => self.open_stream_with_data(data),
+                            None => self.open_stream(),
+                        };
+                        let _ignore = tx.send(res);
                     }
                     Command::Shutdown(tx) => {
                         self.shutdown(cx)?;
                         let _ignore = tx.send(());
                     }
+                    Command::GoAway(tx) => {
+                        self.send_go_away(cx)?;
+                        let _ignore = tx.send(());
+                    }
+                    Command::Flush(tx) => self.flush_waiters.push(tx),
+                    Command::Ping(tx) => {
+                        let ping_id = self.send_ping(cx, None)?;
+                        debug!("[{:?}] sent ping (id={:?})", self.ty, ping_id);
+                        self.track_ping(ping_id, Instant::now(), Some(tx));
+                    }
                 }
                 Poll::Ready(Some(Ok(())))
             }
@@ -630,7 +850,7 @@ where
         if let Some(ref mut interval) = self.keepalive {
             match Pin::new(interval).as_mut().poll_next(cx) {
                 Poll::Ready(Some(_)) => {
-                    if self.local_go_away {
+                    if self.closing {
                         // The remote peer has not responded to our sent go away code.
                         // Assume that remote peer has gone away and this session should be closed.
                         self.remote_go_away = true;
@@ -645,7 +865,15 @@ where
             }
         }
 
+        if self.held_streams.is_empty() {
+            self.accept_timer = None;
+        } else if let Some(ref mut timer) = self.accept_timer {
+            // The timed out streams are reset on distributing
+            while let Poll::Ready(Some(_)) = Pin::new(&mut *timer).as_mut().poll_next(cx) {}
+        }
+
         let mut need_wake = false;
+        let mut is_pending = false;
 
         for _ in 0..16 {
             if self.is_dead() {
@@ -665,7 +893,7 @@ where
                 return Poll::Ready(Some(Ok(stream)));
             }
 
-            let mut is_pending = self.control_poll(cx)?.is_pending();
+            is_pending = self.control_poll(cx)?.is_pending();
             is_pending &= self.recv_frames(cx)?.is_pending();
             is_pending &= self.recv_events(cx)?.is_pending();
 
@@ -676,6 +904,17 @@ where
             }
         }
 
+        // Nothing left in the event channel, notify the waiters once all frames are written
+        if is_pending
+            && !self.flush_waiters.is_empty()
+            && self.write_pending_frames.is_empty()
+            && !self.poll_complete(cx)?
+        {
+            for tx in self.flush_waiters.drain(..) {
+                let _ignore = tx.send(());
+            }
+        }
+
         if need_wake {
             // To ensure we do not starve other tasks waiting on the executor,
             // we yield here, but immediately wake ourselves up to continue.
@@ -686,7 +925,7 @@ where
     }
 }
 
-mod timer {
+pub(crate) mod timer {
     #[cfg(feature = "generic-timer")]
     pub use generic_time::{interval, Interval};
     #[cfg(feature = "tokio-timer")]
@@ -847,13 +1086,18 @@ mod timer {
 
 #[cfg(test)]
 mod test {
-    use super::Session;
+    use super::{Instant, Session};
     use crate::{
         config::Config,
+        error::Error,
         frame::{Flag, Flags, Frame, FrameCodec, GoAwayCode, Type},
     };
+    use bytes::Bytes;
     use futures::{
-        channel::mpsc::{channel, Receiver, Sender},
+        channel::{
+            mpsc::{channel, Receiver, Sender},
+            oneshot,
+        },
         stream::FusedStream,
         SinkExt, Stream, StreamExt,
     };
@@ -864,7 +1108,7 @@ mod test {
         time::Duration,
     };
     use tokio::{
-        io::AsyncReadExt,
+        io::{AsyncReadExt, AsyncWriteExt},
         prelude::{AsyncRead, AsyncWrite},
     };
     use tokio_util::codec::Framed;
@@ -1016,6 +1260,148 @@ mod test {
         })
     }
 
+    #[test]
+    fn test_go_away_keeps_opened_streams() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let (remote, local) = MockSocket::new();
+            let mut config = Config::default();
+            config.enable_keepalive = false;
+
+            let mut session = Session::new_client(local, config);
+            let mut control = session.control();
+            let handle =
+                tokio::spawn(async move { while let Some(Ok(_)) = session.next().await {} });
+
+            let mut server = Framed::new(
+                remote,
+                FrameCodec::default().max_frame_size(config.max_stream_window_size),
+            );
+
+            let mut stream = control.open_stream().await.unwrap();
+            let syn = server.next().await.unwrap().unwrap();
+            assert!(syn.flags().contains(Flag::Syn));
+
+            control.go_away().await.unwrap();
+            let go_away = server.next().await.unwrap().unwrap();
+            assert_eq!(go_away.ty(), Type::GoAway);
+            assert_eq!(GoAwayCode::from(go_away.length()), GoAwayCode::Normal);
+
+            // the stream opened by remote is refused after go away
+            let frame = Frame::new_window_update(Flags::from(Flag::Syn), 2, 0);
+            server.send(frame).await.unwrap();
+            assert_eq!(
+                server.next().await.unwrap().unwrap(),
+                Frame::new_window_update(Flags::from(Flag::Rst), 2, 0)
+            );
+
+            // remote responds go away, the opened stream still works
+            server
+                .send(Frame::new_go_away(GoAwayCode::Normal))
+                .await
+                .unwrap();
+            stream.write_all(b"hello").await.unwrap();
+            control.flush().await.unwrap();
+            assert_eq!(
+                server.next().await.unwrap().unwrap(),
+                Frame::new_data(Flags::default(), 1, Bytes::from("hello"))
+            );
+
+            // the session is closed after all streams finished
+            drop(stream);
+            handle.await.unwrap();
+        })
+    }
+
+    #[test]
+    fn test_go_away_keeps_frames_behind_refused_stream() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let (remote, local) = MockSocket::new();
+            let mut config = Config::default();
+            config.enable_keepalive = false;
+
+            let mut session = Session::new_client(local, config);
+            let mut control = session.control();
+            tokio::spawn(async move { while let Some(Ok(_)) = session.next().await {} });
+
+            let mut server = Framed::new(
+                remote,
+                FrameCodec::default().max_frame_size(config.max_stream_window_size),
+            );
+
+            let mut stream = control.open_stream().await.unwrap();
+            let syn = server.next().await.unwrap().unwrap();
+            assert!(syn.flags().contains(Flag::Syn));
+
+            control.go_away().await.unwrap();
+            let go_away = server.next().await.unwrap().unwrap();
+            assert_eq!(go_away.ty(), Type::GoAway);
+
+            // a new stream and the data of the opened stream arrive in the same batch
+            server
+                .feed(Frame::new_window_update(Flags::from(Flag::Syn), 2, 0))
+                .await
+                .unwrap();
+            server
+                .send(Frame::new_data(
+                    Flags::from(Flag::Ack),
+                    1,
+                    Bytes::from("hello"),
+                ))
+                .await
+                .unwrap();
+
+            assert_eq!(
+                server.next().await.unwrap().unwrap(),
+                Frame::new_window_update(Flags::from(Flag::Rst), 2, 0)
+            );
+            // the data behind the refused stream still reaches the opened stream
+            let mut buf = [0; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        })
+    }
+
+    #[test]
+    fn test_open_stream_with_data() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let (remote, local) = MockSocket::new();
+            let mut config = Config::default();
+            config.enable_keepalive = false;
+
+            let mut session = Session::new_client(local, config);
+            let mut control = session.control();
+            tokio::spawn(async move { while let Some(Ok(_)) = session.next().await {} });
+
+            let mut server = Framed::new(
+                remote,
+                FrameCodec::default().max_frame_size(config.max_stream_window_size),
+            );
+
+            let _stream = control
+                .open_stream_with_data(Bytes::from("hello"))
+                .await
+                .unwrap();
+            // SYN is sent with the data frame, no window update first
+            assert_eq!(
+                Frame::new_data(Flags::from(Flag::Syn), 1, Bytes::from("hello")),
+                server.next().await.unwrap().unwrap()
+            );
+
+            // data can't exceed the initial send window
+            let data = Bytes::from(vec![0; config.max_stream_window_size as usize + 1]);
+            assert_eq!(
+                control.open_stream_with_data(data).await.err(),
+                Some(Error::InitialDataTooLarge)
+            );
+        })
+    }
+
     // issue: https://github.com/nervosnetwork/tentacle/issues/259
     // The reason for the problem is that when the session is closed,
     // all stream states are not set to `RemoteClosed`
@@ -1118,6 +1504,116 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_recv_buffer_exhausted() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let (remote, local) = MockSocket::new();
+            let mut config = Config::default();
+            config.enable_keepalive = false;
+            // only enough for one stream
+            config.max_session_recv_buffer_size = config.max_stream_window_size as usize;
+
+            let mut session = Session::new_server(local, config);
+
+            tokio::spawn(async move {
+                while let Some(Ok(mut stream)) = session.next().await {
+                    tokio::spawn(async move {
+                        let mut buf = [0; 100];
+                        let _ignore = stream.read(&mut buf).await;
+                    });
+                }
+            });
+
+            let mut client = Framed::new(
+                remote,
+                FrameCodec::default().max_frame_size(config.max_stream_window_size),
+            );
+
+            let next_stream_id = 3;
+            // open stream
+            let frame = Frame::new_window_update(Flags::from(Flag::Syn), next_stream_id, 0);
+            client.send(frame).await.unwrap();
+            // stream window respond
+            assert_eq!(
+                Frame::new_window_update(Flags::from(Flag::Ack), next_stream_id, 0),
+                client.next().await.unwrap().unwrap()
+            );
+
+            let frame = Frame::new_window_update(Flags::from(Flag::Syn), next_stream_id + 2, 0);
+            client.send(frame).await.unwrap();
+
+            // the budget is exhausted, get reset msg
+            let reset_msg = client.next().await.unwrap().unwrap();
+
+            assert_eq!(reset_msg.ty(), Type::WindowUpdate);
+            assert!(reset_msg.flags().contains(Flag::Rst));
+            assert_eq!(reset_msg.stream_id(), 5)
+        });
+    }
+
+    fn poll_session_error(frame: Frame) -> (Error, Frame) {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let (remote, local) = MockSocket::new();
+            let mut config = Config::default();
+            config.enable_keepalive = false;
+
+            let mut session = Session::new_server(local, config);
+            let mut client = Framed::new(
+                remote,
+                FrameCodec::default().max_frame_size(config.max_stream_window_size),
+            );
+            client.send(frame).await.unwrap();
+
+            let err = match session.next().await {
+                Some(Err(err)) => err,
+                _ => panic!("must be an error"),
+            };
+            let go_away = client.next().await.unwrap().unwrap();
+            (
+                *err.into_inner().unwrap().downcast::<Error>().unwrap(),
+                go_away,
+            )
+        })
+    }
+
+    #[test]
+    fn test_remote_go_away_with_error() {
+        let (err, go_away) = poll_session_error(Frame::new_go_away(GoAwayCode::InternalError));
+
+        assert_eq!(err, Error::RemoteGoAwayWithError(GoAwayCode::InternalError));
+        assert_eq!(go_away.ty(), Type::GoAway);
+        assert_eq!(GoAwayCode::from(go_away.length()), GoAwayCode::Normal);
+    }
+
+    #[test]
+    fn test_unexpected_ping_flag() {
+        let (err, go_away) = poll_session_error(Frame::new_ping(Flags::default(), 1));
+
+        assert_eq!(err, Error::UnexpectedFlag);
+        assert_eq!(go_away.ty(), Type::GoAway);
+        assert_eq!(
+            GoAwayCode::from(go_away.length()),
+            GoAwayCode::ProtocolError
+        );
+    }
+
+    #[test]
+    fn test_data_of_unknown_stream() {
+        let (err, go_away) =
+            poll_session_error(Frame::new_data(Flags::default(), 3, Bytes::from("unknown")));
+
+        assert_eq!(err, Error::UnknownStream(3));
+        assert_eq!(go_away.ty(), Type::GoAway);
+        assert_eq!(
+            GoAwayCode::from(go_away.length()),
+            GoAwayCode::ProtocolError
+        );
+    }
+
     #[test]
     fn test_remote_does_not_respond_go_away() {
         let mut rt = tokio::runtime::Runtime::new().unwrap();
@@ -1146,4 +1642,181 @@ mod test {
             }
         });
     }
+
+    #[test]
+    fn test_ping_rtt() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let (remote, local) = MockSocket::new();
+            let mut config = Config::default();
+            config.enable_keepalive = false;
+
+            let mut server = Session::new_server(local, config);
+            tokio::spawn(async move { while let Some(Ok(_)) = server.next().await {} });
+
+            let mut client = Session::new_client(remote, config);
+            let mut control = client.control();
+            tokio::spawn(async move { while let Some(Ok(_)) = client.next().await {} });
+
+            assert_eq!(control.rtt(), None);
+
+            let rtt = control.ping().await.unwrap();
+            let measured = control.rtt().unwrap();
+            assert_eq!(measured.last, rtt);
+            assert_eq!(measured.min, rtt);
+            assert_eq!(measured.smoothed, rtt);
+
+            let rtt = control.ping().await.unwrap();
+            assert_eq!(control.rtt().unwrap().last, rtt);
+
+            control.close().await;
+        });
+    }
+
+    #[test]
+    fn test_ping_discarded_by_later_ack() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let (_remote, local) = MockSocket::new();
+            let mut config = Config::default();
+            config.enable_keepalive = false;
+
+            let mut session = Session::new_server(local, config);
+            let (lost_tx, lost_rx) = oneshot::channel();
+            let (acked_tx, acked_rx) = oneshot::channel();
+            let (reused_tx, reused_rx) = oneshot::channel();
+            let mut waiters = Some((lost_tx, acked_tx, reused_tx));
+
+            futures::future::poll_fn(|cx| {
+                let (lost_tx, acked_tx, reused_tx) = waiters.take().unwrap();
+                session.track_ping(1, Instant::now(), Some(lost_tx));
+                session.track_ping(2, Instant::now(), Some(acked_tx));
+                session.track_ping(3, Instant::now(), Some(reused_tx));
+                // the id of an inflight ping is reused after overflow
+                session.track_ping(3, Instant::now(), None);
+
+                // remote acks the later ping only
+                session
+                    .handle_ping(cx, &Frame::new_ping(Flags::from(Flag::Ack), 2))
+                    .unwrap();
+                assert_eq!(session.pings.len(), 1);
+                Poll::Ready(())
+            })
+            .await;
+
+            assert_eq!(lost_rx.await.unwrap(), Err(Error::PingTimeout));
+            assert!(acked_rx.await.unwrap().is_ok());
+            assert_eq!(reused_rx.await.unwrap(), Err(Error::PingTimeout));
+        });
+    }
+
+    #[test]
+    fn test_hold_stream_beyond_backlog() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let (_remote, local) = MockSocket::new();
+            let mut config = Config::default();
+            config.enable_keepalive = false;
+            config.accept_backlog = 1;
+            config.accept_backlog_timeout = Some(Duration::from_millis(100));
+
+            let mut session = Session::new_server(local, config);
+            let control = session.control();
+
+            futures::future::poll_fn(|cx| {
+                // the backlog is full
+                let stream = session.create_stream(Some(1), None).unwrap();
+                session.pending_streams.push_back(stream);
+
+                session
+                    .read_pending_frames
+                    .push_back(Frame::new_window_update(Flags::from(Flag::Syn), 3, 0));
+                session.read_pending_frames.push_back(Frame::new_data(
+                    Flags::default(),
+                    3,
+                    Bytes::from("hello"),
+                ));
+                session.distribute_to_substream(cx).unwrap();
+
+                // held without ACK, the frames are kept in order
+                assert!(session.held_streams.contains_key(&3));
+                assert!(!session.streams.contains_key(&3));
+                assert_eq!(session.read_pending_frames.len(), 2);
+                assert_eq!(session.pending_streams.len(), 1);
+
+                // accepted when the backlog is available
+                let _ignore = session.pending_streams.pop_front();
+                session.distribute_to_substream(cx).unwrap();
+                assert!(session.held_streams.is_empty());
+                assert!(session.read_pending_frames.is_empty());
+                assert_eq!(session.pending_streams.len(), 1);
+                assert_eq!(session.pending_streams[0].id(), 3);
+
+                session
+                    .read_pending_frames
+                    .push_back(Frame::new_window_update(Flags::from(Flag::Syn), 5, 0));
+                session.distribute_to_substream(cx).unwrap();
+                assert!(session.held_streams.contains_key(&5));
+                Poll::Ready(())
+            })
+            .await;
+
+            tokio::time::delay_for(Duration::from_millis(200)).await;
+
+            futures::future::poll_fn(|cx| {
+                // reset after timeout
+                session.distribute_to_substream(cx).unwrap();
+                assert!(session.held_streams.is_empty());
+                assert!(session.read_pending_frames.is_empty());
+                let mut flags = Flags::from(Flag::Ack);
+                flags.add(Flag::Rst);
+                assert_eq!(
+                    session.write_pending_frames.pop_front(),
+                    Some(Frame::new_window_update(flags, 5, 0))
+                );
+                Poll::Ready(())
+            })
+            .await;
+
+            assert_eq!(control.refused_streams(), 1);
+        });
+    }
+
+    #[test]
+    fn test_reset_stream_beyond_backlog() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let (_remote, local) = MockSocket::new();
+            let mut config = Config::default();
+            config.enable_keepalive = false;
+            config.accept_backlog = 1;
+
+            let mut session = Session::new_server(local, config);
+            let control = session.control();
+
+            futures::future::poll_fn(|cx| {
+                let stream = session.create_stream(Some(1), None).unwrap();
+                session.pending_streams.push_back(stream);
+
+                session
+                    .read_pending_frames
+                    .push_back(Frame::new_window_update(Flags::from(Flag::Syn), 3, 0));
+                session.distribute_to_substream(cx).unwrap();
+
+                // no timeout, reset immediately
+                assert!(session.held_streams.is_empty());
+                let frame = session.write_pending_frames.pop_front().unwrap();
+                assert_eq!(frame.stream_id(), 3);
+                assert!(frame.flags().contains(Flag::Rst));
+                Poll::Ready(())
+            })
+            .await;
+
+            assert_eq!(control.refused_streams(), 1);
+        });
+    }
 }
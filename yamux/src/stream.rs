@@ -8,6 +8,8 @@ use futures::{
     Stream,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 use std::{
     io,
     pin::Pin,
@@ -17,9 +19,12 @@ use std::{
 use log::{debug, trace};
 use tokio::prelude::{AsyncRead, AsyncWrite};
 
+#[cfg(target_arch = "wasm32")]
+use crate::session::timer::Instant;
 use crate::{
     error::Error,
     frame::{Flag, Flags, Frame, Type},
+    window::{RecvBudget, WindowTuning},
     StreamId,
 };
 
@@ -28,12 +33,24 @@ use crate::{
 pub struct StreamHandle {
     id: StreamId,
     state: StreamState,
+    // The code of RST received from remote
+    reset_code: Option<u32>,
 
+    // The configured window size, also the max size of data frame that we send
+    window_size: u32,
     max_recv_window: u32,
     recv_window: u32,
     send_window: u32,
     read_buf: Bytes,
 
+    // Grow the max receive window when auto tuning is enabled
+    window_tuning: Option<WindowTuning>,
+    last_window_update: Instant,
+
+    // The receive buffer budget of session, and the size reserved from it by this stream
+    recv_budget: Option<RecvBudget>,
+    reserved: u32,
+
     // Send stream event to parent session
     unbound_event_sender: UnboundedSender<StreamEvent>,
 
@@ -59,16 +76,35 @@ impl StreamHandle {
         StreamHandle {
             id,
             state,
+            reset_code: None,
+            window_size: recv_window_size,
             max_recv_window: recv_window_size,
             recv_window: recv_window_size,
             send_window: send_window_size,
             read_buf: Bytes::default(),
+            window_tuning: None,
+            last_window_update: Instant::now(),
+            recv_budget: None,
+            reserved: 0,
             unbound_event_sender,
             frame_receiver,
             writeable_wake: None,
         }
     }
 
+    // Enable receive window auto tuning
+    pub(crate) fn with_window_tuning(mut self, window_tuning: WindowTuning) -> Self {
+        self.window_tuning = Some(window_tuning);
+        self
+    }
+
+    // The initial receive window must have been reserved from the budget
+    pub(crate) fn with_recv_budget(mut self, recv_budget: RecvBudget) -> Self {
+        self.recv_budget = Some(recv_budget);
+        self.reserved = self.max_recv_window;
+        self
+    }
+
     /// Get the stream id
     pub fn id(&self) -> StreamId {
         self.id
@@ -85,6 +121,43 @@ impl StreamHandle {
     pub fn send_window(&self) -> u32 {
         self.send_window
     }
+    /// Get the max receive window size, it may grow when auto tuning is enabled
+    pub fn max_recv_window(&self) -> u32 {
+        self.max_recv_window
+    }
+    /// Set the write priority of stream, lower value is higher priority,
+    /// default is 128.
+    ///
+    /// Frames of streams with the same priority are written in turn,
+    /// streams with higher priority are always written first.
+    pub fn set_priority(&mut self, priority: u8) -> Result<(), Error> {
+        self.unbound_send_event(StreamEvent::Priority(self.id, priority))
+    }
+
+    /// Reset the stream with a code, remote reads it as `Error::StreamReset(code)`,
+    /// the stream can't be read or written after reset
+    pub fn reset(&mut self, code: u32) -> Result<(), Error> {
+        match self.state {
+            StreamState::Closed => return Ok(()),
+            StreamState::Reset => return self.close(),
+            _ => (),
+        }
+        // The length of RST frame is the reset code
+        let mut flags = self.get_flags();
+        flags.add(Flag::Rst);
+        self.state = StreamState::Closed;
+        self.unbound_send_frame(Frame::new_window_update(flags, self.id, code))?;
+        self.unbound_send_event(StreamEvent::Closed(self.id))
+    }
+
+    /// Close the write side of stream by sending FIN,
+    /// the stream can still be read until remote closes its write side
+    pub fn close_write(&mut self) -> Result<(), Error> {
+        match self.state {
+            StreamState::LocalClosing | StreamState::Closed => Ok(()),
+            _ => self.close(),
+        }
+    }
 
     fn close(&mut self) -> Result<(), Error> {
         match self.state {
@@ -143,19 +216,87 @@ impl StreamHandle {
     // Send a window update
     pub(crate) fn send_window_update(&mut self) -> Result<(), Error> {
         let buf_len = self.read_buf.len() as u32;
-        let delta = self.max_recv_window - buf_len - self.recv_window;
+        let mut delta = self.max_recv_window - buf_len - self.recv_window;
 
         // Check if we can omit the update
         let flags = self.get_flags();
         if delta < (self.max_recv_window / 2) && flags.value() == 0 {
             return Ok(());
         }
+        let mut grown = 0;
+        if let Some(ref window_tuning) = self.window_tuning {
+            if flags.value() == 0 {
+                let window =
+                    window_tuning.grow(self.max_recv_window, self.last_window_update.elapsed());
+                grown = window - self.max_recv_window;
+                delta += grown;
+                self.max_recv_window = window;
+            }
+            self.last_window_update = Instant::now();
+        }
+        if let Some(ref recv_budget) = self.recv_budget {
+            // The size that remote can make us hold after the update
+            let mut hold = self.recv_window + buf_len + delta;
+            if recv_budget.is_tight() {
+                hold = ::std::cmp::min(
+                    hold,
+                    ::std::cmp::max(self.max_recv_window / 2, self.recv_window + buf_len),
+                );
+            }
+            if hold > self.reserved {
+                hold = self.reserved + recv_budget.reserve_up_to(hold - self.reserved);
+            } else {
+                recv_budget.release(self.reserved - hold);
+            }
+            self.reserved = hold;
+            // The growth clamped by the budget is not applied, give it back to auto tuning
+            let unapplied = ::std::cmp::min(grown, self.recv_window + buf_len + delta - hold);
+            if unapplied > 0 {
+                if let Some(ref window_tuning) = self.window_tuning {
+                    window_tuning.release(unapplied);
+                }
+                self.max_recv_window -= unapplied;
+            }
+            delta = hold - self.recv_window - buf_len;
+            if delta == 0 && flags.value() == 0 {
+                return Ok(());
+            }
+        }
         // Update our window
         self.recv_window += delta;
-        let frame = Frame::new_window_update(flags, self.id, delta);
-        self.unbound_event_sender
-            .unbounded_send(StreamEvent::Frame(frame))
-            .map_err(|_| Error::SessionShutdown)
+        // The remote may reject the frame whose length is larger than the configured
+        // window size, so split the delta grown by auto tuning into several updates
+        let mut flags = Some(flags);
+        loop {
+            let length = ::std::cmp::min(delta, self.window_size);
+            delta -= length;
+            let frame = Frame::new_window_update(
+                flags.take().unwrap_or_else(Flags::default),
+                self.id,
+                length,
+            );
+            self.unbound_event_sender
+                .unbounded_send(StreamEvent::Frame(frame))
+                .map_err(|_| Error::SessionShutdown)?;
+            if delta == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    // Send the SYN with the first data frame instead of a window update,
+    // the data must fit in the initial send window
+    pub(crate) fn send_initial_data(&mut self, data: Bytes) -> Result<(), Error> {
+        debug_assert!(self.state == StreamState::Init);
+        if data.len() > ::std::cmp::min(self.send_window, self.window_size) as usize {
+            return Err(Error::InitialDataTooLarge);
+        }
+        let flags = self.get_flags();
+        self.send_window -= data.len() as u32;
+        let frame = Frame::new_data(flags, self.id, data);
+        self.unbound_send_frame(frame)?;
+        // The window may still need an update
+        self.send_window_update()
     }
 
     fn send_data(&mut self, data: &[u8]) -> Result<(), Error> {
@@ -238,11 +379,17 @@ impl StreamHandle {
     }
 
     fn handle_window_update(&mut self, frame: &Frame) -> Result<(), Error> {
+        if frame.flags().contains(Flag::Rst) {
+            // The length of RST frame is the reset code
+            self.reset_code = Some(frame.length());
+        }
         self.process_flags(frame.flags())?;
-        self.send_window = self
-            .send_window
-            .checked_add(frame.length())
-            .ok_or(Error::InvalidMsgType)?;
+        if !frame.flags().contains(Flag::Rst) {
+            self.send_window = self
+                .send_window
+                .checked_add(frame.length())
+                .ok_or(Error::InvalidMsgType)?;
+        }
         // wake writer continue
         if let Some(waker) = self.writeable_wake.take() {
             waker.wake()
@@ -251,7 +398,12 @@ impl StreamHandle {
     }
 
     fn handle_data(&mut self, frame: Frame) -> Result<(), Error> {
+        let remote_closed = self.state == StreamState::RemoteClosing;
         self.process_flags(frame.flags())?;
+        if remote_closed {
+            // The data after FIN is ignored
+            return Ok(());
+        }
         let length = frame.length();
         if length > self.recv_window {
             return Err(Error::RecvWindowExceeded);
@@ -269,14 +421,9 @@ impl StreamHandle {
     fn recv_frames(&mut self, cx: &mut Context) -> Result<(), Error> {
         trace!("stream-handle({}) recv_frames", self.id);
         loop {
-            match self.state {
-                StreamState::RemoteClosing => {
-                    return Err(Error::SubStreamRemoteClosing);
-                }
-                StreamState::Reset | StreamState::Closed => {
-                    return Err(Error::SessionShutdown);
-                }
-                _ => {}
+            // Remote closing still sends window updates for the local write side
+            if let StreamState::Reset | StreamState::Closed = self.state {
+                return Err(Error::SessionShutdown);
             }
 
             // After get data, break here
@@ -308,20 +455,27 @@ impl StreamHandle {
         Ok(())
     }
 
+    fn reset_error(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            Error::StreamReset(self.reset_code.unwrap_or(0)),
+        )
+    }
+
     fn check_self_state(&mut self) -> Result<(), io::Error> {
         // if read buf is empty and state is close, return close error
         if self.read_buf.is_empty() {
             match self.state {
                 StreamState::RemoteClosing => {
                     debug!("closed(EOF)");
-                    let _ignore = self.send_close();
                     Err(io::ErrorKind::UnexpectedEof.into())
                 }
                 StreamState::Reset => {
                     debug!("connection reset");
                     let _ignore = self.send_close();
-                    Err(io::ErrorKind::ConnectionReset.into())
+                    Err(self.reset_error())
                 }
+                StreamState::Closed if self.reset_code.is_some() => Err(self.reset_error()),
                 StreamState::Closed => Err(io::ErrorKind::BrokenPipe.into()),
                 _ => Ok(()),
             }
@@ -368,11 +522,7 @@ impl AsyncRead for StreamHandle {
         buf[..n].copy_from_slice(&b);
         match self.state {
             StreamState::RemoteClosing | StreamState::Closed | StreamState::Reset => (),
-            StreamState::LocalClosing => {
-                if self.close().is_err() {
-                    return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
-                }
-            }
+            // Only the write side is closed, keep receiving
             _ => {
                 if self.send_window_update().is_err() {
                     return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
@@ -395,9 +545,8 @@ impl AsyncWrite for StreamHandle {
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
         match self.state {
-            StreamState::RemoteClosing | StreamState::Reset => {
-                return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
-            }
+            // Remote closing only closes the read side, local can still write until close
+            StreamState::Reset => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
             StreamState::LocalClosing | StreamState::Closed => {
                 return Poll::Ready(Err(io::Error::new(
                     io::ErrorKind::BrokenPipe,
@@ -407,6 +556,10 @@ impl AsyncWrite for StreamHandle {
             _ => (),
         }
 
+        if self.send_window == 0 && self.state == StreamState::RemoteClosing {
+            // No one reads after EOF, receive the window updates here
+            let _ignore = self.recv_frames(cx);
+        }
         if self.send_window == 0 {
             // register writer context waker
             // when write buf become empty, it can wake the upper layer to write the message again
@@ -414,7 +567,10 @@ impl AsyncWrite for StreamHandle {
             return Poll::Pending;
         }
         // Allow n = 0, send an empty frame to remote
-        let n = ::std::cmp::min(self.send_window as usize, buf.len());
+        let n = ::std::cmp::min(
+            ::std::cmp::min(self.send_window, self.window_size) as usize,
+            buf.len(),
+        );
         trace!(
             "stream-hanlde({}) poll_write self.send_window={}, buf.len={}, n={}",
             self.id,
@@ -440,7 +596,7 @@ impl AsyncWrite for StreamHandle {
 
     fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
         debug!("[{}] StreamHandle.shutdown()", self.id);
-        match self.close() {
+        match self.close_write() {
             Err(_) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
             Ok(()) => Poll::Ready(Ok(())),
         }
@@ -449,6 +605,12 @@ impl AsyncWrite for StreamHandle {
 
 impl Drop for StreamHandle {
     fn drop(&mut self) {
+        if let Some(ref window_tuning) = self.window_tuning {
+            window_tuning.release(self.max_recv_window - self.window_size);
+        }
+        if let Some(ref recv_budget) = self.recv_budget {
+            recv_budget.release(self.reserved);
+        }
         if !self.unbound_event_sender.is_closed() && self.state != StreamState::Closed {
             let event = StreamEvent::Closed(self.id);
             if self.state == StreamState::LocalClosing {
@@ -474,6 +636,7 @@ impl Drop for StreamHandle {
 pub(crate) enum StreamEvent {
     Frame(Frame),
     Closed(StreamId),
+    Priority(StreamId, u8),
     // Only use on protocol error
     GoAway,
 }
@@ -504,14 +667,17 @@ mod test {
     use super::{StreamEvent, StreamHandle, StreamState};
     use crate::{
         config::INITIAL_STREAM_WINDOW,
+        error::Error,
         frame::{Flag, Flags, Frame, Type},
+        rtt::RttMeter,
+        window::{RecvBudget, WindowTuning},
     };
     use bytes::Bytes;
     use futures::{
         channel::mpsc::{channel, unbounded},
-        SinkExt, StreamExt,
+        FutureExt, SinkExt, StreamExt,
     };
-    use std::io::ErrorKind;
+    use std::{io::ErrorKind, sync::Arc, time::Duration};
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
     #[test]
@@ -567,7 +733,7 @@ mod test {
             // try poll stream handle, then it will recv RST frame and set self state to reset
             assert_eq!(
                 stream.read(&mut b).await.unwrap_err().kind(),
-                ErrorKind::BrokenPipe
+                ErrorKind::ConnectionReset
             );
 
             drop(stream);
@@ -614,6 +780,170 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_reset_with_code() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (mut frame_sender, frame_receiver) = channel(2);
+            let (unbound_sender, mut unbound_receiver) = unbounded();
+            let mut stream = StreamHandle::new(
+                1,
+                unbound_sender,
+                frame_receiver,
+                StreamState::SynReceived,
+                INITIAL_STREAM_WINDOW,
+                INITIAL_STREAM_WINDOW,
+            );
+
+            // remote reset the stream with code 7
+            let frame = Frame::new_window_update(Flags::from(Flag::Rst), 1, 7);
+            frame_sender.send(frame).await.unwrap();
+            let mut b = [0; 1024];
+            let err = stream.read(&mut b).await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+            assert_eq!(
+                err.get_ref().unwrap().downcast_ref::<Error>(),
+                Some(&Error::StreamReset(7))
+            );
+
+            // reset after remote reset only cleanup
+            stream.reset(3).unwrap();
+            drop(stream);
+            assert_eq!(
+                unbound_receiver.next().await.unwrap(),
+                StreamEvent::Closed(1)
+            );
+            assert!(unbound_receiver.next().await.is_none());
+
+            let (_frame_sender, frame_receiver) = channel(2);
+            let (unbound_sender, mut unbound_receiver) = unbounded();
+            let mut stream = StreamHandle::new(
+                3,
+                unbound_sender,
+                frame_receiver,
+                StreamState::SynReceived,
+                INITIAL_STREAM_WINDOW,
+                INITIAL_STREAM_WINDOW,
+            );
+
+            stream.reset(7).unwrap();
+            match unbound_receiver.next().await.unwrap() {
+                StreamEvent::Frame(frame) => {
+                    assert!(frame.flags().contains(Flag::Rst));
+                    assert_eq!(frame.length(), 7);
+                }
+                _ => panic!("must be a frame msg contain RST"),
+            }
+            assert_eq!(
+                unbound_receiver.next().await.unwrap(),
+                StreamEvent::Closed(3)
+            );
+            assert_eq!(
+                stream.write(b"data").await.unwrap_err().kind(),
+                ErrorKind::BrokenPipe
+            );
+
+            // drop after reset don't send anything
+            drop(stream);
+            assert!(unbound_receiver.next().await.is_none());
+        });
+    }
+
+    #[test]
+    fn test_close_write() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (mut frame_sender, frame_receiver) = channel(2);
+            let (unbound_sender, mut unbound_receiver) = unbounded();
+            let mut stream = StreamHandle::new(
+                1,
+                unbound_sender,
+                frame_receiver,
+                StreamState::SynReceived,
+                INITIAL_STREAM_WINDOW,
+                INITIAL_STREAM_WINDOW,
+            );
+
+            stream.close_write().unwrap();
+            match unbound_receiver.next().await.unwrap() {
+                StreamEvent::Frame(frame) => {
+                    assert!(frame.flags().contains(Flag::Fin));
+                    assert_eq!(frame.ty(), Type::WindowUpdate);
+                }
+                _ => panic!("must be fin window update"),
+            }
+            // close write twice is a no-op
+            stream.close_write().unwrap();
+            assert!(stream.write(b"data").await.is_err());
+
+            // still can read after local write side closed
+            let frame = Frame::new_data(Flags::from(Flag::Ack), 1, Bytes::from("hello"));
+            frame_sender.send(frame).await.unwrap();
+            let mut b = [0; 1024];
+            assert_eq!(stream.read(&mut b).await.unwrap(), 5);
+            assert_eq!(&b[..5], b"hello");
+
+            // remote close its write side, the stream is closed
+            let frame = Frame::new_window_update(Flags::from(Flag::Fin), 1, 0);
+            frame_sender.send(frame).await.unwrap();
+            assert!(stream.read(&mut b).await.is_err());
+            assert_eq!(
+                unbound_receiver.next().await.unwrap(),
+                StreamEvent::Closed(1)
+            );
+        });
+    }
+
+    #[test]
+    fn test_write_after_remote_close() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let (mut frame_sender, frame_receiver) = channel(2);
+            let (unbound_sender, mut unbound_receiver) = unbounded();
+            let mut stream = StreamHandle::new(
+                1,
+                unbound_sender,
+                frame_receiver,
+                StreamState::SynReceived,
+                INITIAL_STREAM_WINDOW,
+                4,
+            );
+            stream.state = StreamState::Established;
+
+            // remote close its write side, local gets eof without sending fin
+            let frame = Frame::new_window_update(Flags::from(Flag::Fin), 1, 0);
+            frame_sender.send(frame).await.unwrap();
+            let mut b = [0; 1024];
+            assert_eq!(
+                stream.read(&mut b).await.unwrap_err().kind(),
+                ErrorKind::UnexpectedEof
+            );
+            assert!(unbound_receiver.try_next().is_err());
+
+            // local can still write, the window update is received without reading
+            let frame = Frame::new_window_update(Flags::default(), 1, 4);
+            frame_sender.send(frame).await.unwrap();
+            stream.write_all(b"hello").await.unwrap();
+            for _ in 0..2 {
+                match unbound_receiver.next().await.unwrap() {
+                    StreamEvent::Frame(frame) => assert_eq!(frame.ty(), Type::Data),
+                    _ => panic!("must be a data frame"),
+                }
+            }
+
+            // fin is sent on local close
+            stream.shutdown().await.unwrap();
+            match unbound_receiver.next().await.unwrap() {
+                StreamEvent::Frame(frame) => assert!(frame.flags().contains(Flag::Fin)),
+                _ => panic!("must be fin window update"),
+            }
+            assert_eq!(
+                unbound_receiver.next().await.unwrap(),
+                StreamEvent::Closed(1)
+            );
+        });
+    }
+
     #[test]
     fn test_data_large_than_recv_window() {
         let mut rt = tokio::runtime::Runtime::new().unwrap();
@@ -689,4 +1019,157 @@ mod test {
             }
         });
     }
+
+    #[test]
+    fn test_initial_data_too_large() {
+        let (_frame_sender, frame_receiver) = channel(2);
+        let (unbound_sender, mut unbound_receiver) = unbounded();
+        let mut stream = StreamHandle::new(
+            1,
+            unbound_sender,
+            frame_receiver,
+            StreamState::Init,
+            100,
+            100,
+        );
+
+        assert_eq!(
+            stream.send_initial_data(Bytes::from(vec![0; 101])),
+            Err(Error::InitialDataTooLarge)
+        );
+        assert_eq!(stream.send_window, 100);
+        assert!(unbound_receiver.try_next().is_err());
+    }
+
+    #[test]
+    fn test_split_grown_window_update() {
+        let (_frame_sender, frame_receiver) = channel(2);
+        let (unbound_sender, mut unbound_receiver) = unbounded();
+        let rtt = Arc::new(RttMeter::default());
+        rtt.update(Duration::from_secs(1));
+        let mut stream = StreamHandle::new(
+            1,
+            unbound_sender,
+            frame_receiver,
+            StreamState::Init,
+            100,
+            100,
+        )
+        .with_window_tuning(WindowTuning::new(rtt, 1000, 400));
+        stream.state = StreamState::Established;
+
+        // all the receive window is consumed in a short time, the window grows to 200
+        stream.recv_window = 0;
+        stream.send_window_update().unwrap();
+        assert_eq!(stream.max_recv_window(), 200);
+
+        for _ in 0..2 {
+            match unbound_receiver.try_next() {
+                Ok(Some(StreamEvent::Frame(frame))) => {
+                    assert_eq!(frame.ty(), Type::WindowUpdate);
+                    assert_eq!(frame.length(), 100);
+                }
+                _ => panic!("must be a window update frame"),
+            }
+        }
+        assert!(unbound_receiver.try_next().is_err());
+    }
+
+    #[test]
+    fn test_shrink_window_when_budget_tight() {
+        let (_frame_sender, frame_receiver) = channel(2);
+        let (unbound_sender, mut unbound_receiver) = unbounded();
+        let recv_budget = RecvBudget::new(400);
+        assert!(recv_budget.try_reserve(100));
+        let mut stream = StreamHandle::new(
+            1,
+            unbound_sender,
+            frame_receiver,
+            StreamState::Init,
+            100,
+            100,
+        )
+        .with_recv_budget(recv_budget.clone());
+        stream.state = StreamState::Established;
+
+        // other streams take most of the budget
+        assert_eq!(recv_budget.reserve_up_to(250), 250);
+        assert!(recv_budget.is_tight());
+
+        // only half of the window is granted, the rest is given back to budget
+        stream.recv_window = 0;
+        stream.send_window_update().unwrap();
+        match unbound_receiver.next().now_or_never() {
+            Some(Some(StreamEvent::Frame(frame))) => {
+                assert_eq!(frame.ty(), Type::WindowUpdate);
+                assert_eq!(frame.length(), 50);
+            }
+            _ => panic!("must be a window update frame"),
+        }
+        assert!(!recv_budget.is_tight());
+
+        // all the reserved is given back on drop
+        drop(stream);
+        assert!(recv_budget.try_reserve(150));
+    }
+
+    #[test]
+    fn test_charge_applied_growth_only() {
+        let (_frame_sender, frame_receiver) = channel(2);
+        let (unbound_sender, mut unbound_receiver) = unbounded();
+        let rtt = Arc::new(RttMeter::default());
+        rtt.update(Duration::from_secs(1));
+        let window_tuning = WindowTuning::new(rtt, 100, 400);
+        let recv_budget = RecvBudget::new(150);
+        assert!(recv_budget.try_reserve(100));
+        let mut stream = StreamHandle::new(
+            1,
+            unbound_sender,
+            frame_receiver,
+            StreamState::Init,
+            100,
+            100,
+        )
+        .with_window_tuning(window_tuning.clone())
+        .with_recv_budget(recv_budget);
+        stream.state = StreamState::Established;
+
+        // the window grows to 200, but the receive budget only affords 150
+        stream.recv_window = 0;
+        stream.send_window_update().unwrap();
+        assert_eq!(stream.max_recv_window(), 150);
+
+        let mut granted = 0;
+        while let Some(Some(StreamEvent::Frame(frame))) = unbound_receiver.next().now_or_never() {
+            assert_eq!(frame.ty(), Type::WindowUpdate);
+            granted += frame.length();
+        }
+        assert_eq!(granted, 150);
+
+        // the unapplied growth is given back to auto tuning
+        assert_eq!(window_tuning.grow(50, Duration::from_millis(1)), 100);
+    }
+
+    #[test]
+    fn test_set_priority() {
+        let (_frame_sender, frame_receiver) = channel(2);
+        let (unbound_sender, mut unbound_receiver) = unbounded();
+        let mut stream = StreamHandle::new(
+            1,
+            unbound_sender,
+            frame_receiver,
+            StreamState::Init,
+            INITIAL_STREAM_WINDOW,
+            INITIAL_STREAM_WINDOW,
+        );
+
+        stream.set_priority(10).unwrap();
+        match unbound_receiver.next().now_or_never() {
+            Some(Some(StreamEvent::Priority(id, priority))) => {
+                assert_eq!(id, 1);
+                assert_eq!(priority, 10);
+            }
+            _ => panic!("must be a priority event"),
+        }
+    }
 }
@@ -0,0 +1,162 @@
+//! Receive window auto tuning and session receive buffer budget
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::rtt::RttMeter;
+
+/// Shared by all streams of a session, used to grow the receive window of streams
+#[derive(Clone, Debug)]
+pub(crate) struct WindowTuning {
+    rtt: Arc<RttMeter>,
+    // The remaining memory that streams of the session can grow their receive window with
+    budget: Arc<AtomicUsize>,
+    max_window: u32,
+}
+
+impl WindowTuning {
+    pub(crate) fn new(rtt: Arc<RttMeter>, budget: usize, max_window: u32) -> Self {
+        WindowTuning {
+            rtt,
+            budget: Arc::new(AtomicUsize::new(budget)),
+            max_window,
+        }
+    }
+
+    /// Try to double the window if it is consumed within a few round trips,
+    /// which means that the throughput is limited by the window size.
+    ///
+    /// Return the new window size
+    pub(crate) fn grow(&self, window: u32, consumed_in: Duration) -> u32 {
+        let rtt = match self.rtt.get() {
+            Some(rtt) => rtt.smoothed,
+            None => return window,
+        };
+        if consumed_in >= rtt * 4 {
+            return window;
+        }
+
+        let target = ::std::cmp::min(window.saturating_mul(2), self.max_window);
+        if target <= window {
+            return window;
+        }
+        let extra = (target - window) as usize;
+        let reserved = self
+            .budget
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |remaining| {
+                remaining.checked_sub(extra)
+            })
+            .is_ok();
+        if reserved {
+            target
+        } else {
+            window
+        }
+    }
+
+    /// Give back the window grown by `grow`
+    pub(crate) fn release(&self, extra: u32) {
+        self.budget.fetch_add(extra as usize, Ordering::AcqRel);
+    }
+}
+
+/// The receive buffer budget shared by all streams of a session.
+///
+/// Every stream reserves the receive window it advertised to remote,
+/// so the memory that the remote can make the session hold is bounded.
+#[derive(Clone, Debug)]
+pub(crate) struct RecvBudget {
+    total: usize,
+    remaining: Arc<AtomicUsize>,
+}
+
+impl RecvBudget {
+    pub(crate) fn new(total: usize) -> Self {
+        RecvBudget {
+            total,
+            remaining: Arc::new(AtomicUsize::new(total)),
+        }
+    }
+
+    /// Reserve all of `size` or nothing
+    pub(crate) fn try_reserve(&self, size: u32) -> bool {
+        self.remaining
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |remaining| {
+                remaining.checked_sub(size as usize)
+            })
+            .is_ok()
+    }
+
+    /// Reserve as much as possible up to `size`, return the reserved size
+    pub(crate) fn reserve_up_to(&self, size: u32) -> u32 {
+        let mut reserved = 0;
+        let _ignore =
+            self.remaining
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |remaining| {
+                    reserved = ::std::cmp::min(remaining, size as usize);
+                    Some(remaining - reserved)
+                });
+        reserved as u32
+    }
+
+    /// Give back the reserved size
+    pub(crate) fn release(&self, size: u32) {
+        self.remaining.fetch_add(size as usize, Ordering::AcqRel);
+    }
+
+    /// Less than a quarter of the budget remains, streams should shrink their window
+    pub(crate) fn is_tight(&self) -> bool {
+        self.remaining.load(Ordering::Acquire) < self.total / 4
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RecvBudget, WindowTuning};
+    use crate::rtt::RttMeter;
+    use std::{sync::Arc, time::Duration};
+
+    #[test]
+    fn test_window_grow() {
+        let rtt = Arc::new(RttMeter::default());
+        let tuning = WindowTuning::new(rtt.clone(), 300, 400);
+
+        // no rtt measurement, don't grow
+        assert_eq!(tuning.grow(100, Duration::from_millis(1)), 100);
+
+        rtt.update(Duration::from_millis(10));
+        // consumed slowly, don't grow
+        assert_eq!(tuning.grow(100, Duration::from_millis(40)), 100);
+
+        assert_eq!(tuning.grow(100, Duration::from_millis(39)), 200);
+        // up to the max window
+        assert_eq!(tuning.grow(300, Duration::from_millis(1)), 400);
+        // budget exhausted, 300 - 100 - 100 = 100
+        assert_eq!(tuning.grow(150, Duration::from_millis(1)), 150);
+
+        tuning.release(100);
+        assert_eq!(tuning.grow(150, Duration::from_millis(1)), 300);
+    }
+
+    #[test]
+    fn test_recv_budget() {
+        let budget = RecvBudget::new(400);
+
+        assert!(budget.try_reserve(250));
+        assert!(!budget.is_tight());
+        assert!(!budget.try_reserve(200));
+        assert_eq!(budget.reserve_up_to(100), 100);
+        assert!(budget.is_tight());
+        assert_eq!(budget.reserve_up_to(100), 50);
+        assert_eq!(budget.reserve_up_to(100), 0);
+
+        budget.release(150);
+        assert!(!budget.is_tight());
+        assert!(budget.try_reserve(150));
+    }
+}
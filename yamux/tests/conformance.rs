@@ -0,0 +1,318 @@
+//! Conformance tests of the session against the yamux spec:
+//! https://github.com/hashicorp/yamux/blob/master/spec.md
+//!
+//! Every case drives a server session with a recorded sequence of raw frames,
+//! and asserts the exact bytes responded by the session.
+
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tokio_util::codec::Encoder;
+use tokio_yamux::{
+    config::Config,
+    frame::{Flag, Flags, Frame, FrameCodec, GoAwayCode},
+    session::Session,
+};
+
+// Frame types
+const DATA: u8 = 0;
+const WINDOW_UPDATE: u8 = 1;
+const PING: u8 = 2;
+const GO_AWAY: u8 = 3;
+
+// Flags
+const NONE: u16 = 0;
+const SYN: u16 = 1;
+const ACK: u16 = 2;
+const FIN: u16 = 4;
+const RST: u16 = 8;
+
+// Go away codes
+const NORMAL: u32 = 0;
+const PROTOCOL_ERROR: u32 = 1;
+
+const WINDOW: u32 = 256 * 1024;
+
+/// Raw frame: version(8) | type(8) | flags(16) | stream id(32) | length(32) | body
+fn raw(version: u8, ty: u8, flags: u16, stream_id: u32, length: u32, body: &[u8]) -> Vec<u8> {
+    let mut buf = vec![version, ty];
+    buf.extend_from_slice(&flags.to_be_bytes());
+    buf.extend_from_slice(&stream_id.to_be_bytes());
+    buf.extend_from_slice(&length.to_be_bytes());
+    buf.extend_from_slice(body);
+    buf
+}
+
+fn data(flags: u16, stream_id: u32, body: &[u8]) -> Vec<u8> {
+    raw(0, DATA, flags, stream_id, body.len() as u32, body)
+}
+
+fn window_update(flags: u16, stream_id: u32, delta: u32) -> Vec<u8> {
+    raw(0, WINDOW_UPDATE, flags, stream_id, delta, &[])
+}
+
+fn ping(flags: u16, opaque: u32) -> Vec<u8> {
+    raw(0, PING, flags, 0, opaque, &[])
+}
+
+fn go_away(code: u32) -> Vec<u8> {
+    raw(0, GO_AWAY, NONE, 0, code, &[])
+}
+
+enum Step {
+    /// Write the bytes to session
+    Send(Vec<u8>),
+    /// Session must respond the exact bytes
+    Expect(Vec<u8>),
+    /// Session must not respond anything for a while
+    Silent,
+    /// Session must close the connection without sending anything else
+    Closed,
+}
+
+use Step::*;
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(3);
+const SILENT_DURATION: Duration = Duration::from_millis(200);
+
+fn run(steps: Vec<Step>) {
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async move {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (mut remote, (local, _)) =
+            futures::try_join!(TcpStream::connect(addr), listener.accept()).unwrap();
+
+        let config = Config {
+            enable_keepalive: false,
+            ..Config::default()
+        };
+        let mut session = Session::new_server(local, config);
+        tokio::spawn(async move {
+            // the session is dropped on error, and the connection is closed
+            while let Some(Ok(mut stream)) = session.next().await {
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    while let Ok(n) = stream.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                    }
+                    // close the write side after eof
+                    let _ignore = stream.shutdown().await;
+                });
+            }
+        });
+
+        for (index, step) in steps.into_iter().enumerate() {
+            match step {
+                Send(bytes) => remote.write_all(&bytes).await.unwrap(),
+                Expect(expected) => {
+                    let mut buf = vec![0; expected.len()];
+                    timeout(RESPONSE_TIMEOUT, remote.read_exact(&mut buf))
+                        .await
+                        .unwrap_or_else(|_| panic!("step {}: response timeout", index))
+                        .unwrap_or_else(|err| panic!("step {}: read error {}", index, err));
+                    assert_eq!(buf, expected, "step {}: unexpected response", index);
+                }
+                Silent => {
+                    let mut buf = [0; 1];
+                    if let Ok(res) = timeout(SILENT_DURATION, remote.read(&mut buf)).await {
+                        panic!("step {}: unexpected response {:?}", index, res);
+                    }
+                }
+                Closed => {
+                    let mut buf = Vec::new();
+                    let res = timeout(RESPONSE_TIMEOUT, remote.read_to_end(&mut buf))
+                        .await
+                        .unwrap_or_else(|_| panic!("step {}: connection not closed", index));
+                    if res.is_ok() {
+                        assert!(buf.is_empty(), "step {}: unexpected {:?}", index, buf);
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn test_frame_vectors() {
+    let mut codec = FrameCodec::default();
+    let vectors = vec![
+        (
+            Frame::new_data(Flags::from(Flag::Syn), 1, Bytes::from("hi")),
+            vec![0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 2, b'h', b'i'],
+        ),
+        (
+            Frame::new_window_update(Flags::from(Flag::Ack), 2, WINDOW),
+            vec![0, 1, 0, 2, 0, 0, 0, 2, 0, 4, 0, 0],
+        ),
+        (
+            Frame::new_window_update(Flags::from(Flag::Rst), 3, 0),
+            vec![0, 1, 0, 8, 0, 0, 0, 3, 0, 0, 0, 0],
+        ),
+        (
+            Frame::new_ping(Flags::from(Flag::Syn), 0x0102_0304),
+            vec![0, 2, 0, 1, 0, 0, 0, 0, 1, 2, 3, 4],
+        ),
+        (
+            Frame::new_go_away(GoAwayCode::InternalError),
+            vec![0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2],
+        ),
+    ];
+    for (frame, expected) in vectors {
+        let mut buf = BytesMut::new();
+        codec.encode(frame, &mut buf).unwrap();
+        assert_eq!(&buf[..], &expected[..]);
+    }
+    assert_eq!(
+        data(SYN, 1, b"hi"),
+        vec![0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 2, b'h', b'i']
+    );
+}
+
+#[test]
+fn test_open_stream() {
+    run(vec![
+        Send(window_update(SYN, 1, 0)),
+        Expect(window_update(ACK, 1, 0)),
+        Send(data(NONE, 1, b"hello")),
+        Silent,
+        // open with data
+        Send(data(SYN, 3, b"hello")),
+        Expect(window_update(ACK, 3, 0)),
+        Silent,
+    ]);
+}
+
+#[test]
+fn test_ping() {
+    run(vec![
+        Send(ping(SYN, 0x1234)),
+        Expect(ping(ACK, 0x1234)),
+        // ack of an unknown ping is ignored
+        Send(ping(ACK, 99)),
+        Silent,
+        Send(ping(SYN, 1)),
+        Expect(ping(ACK, 1)),
+    ]);
+}
+
+#[test]
+fn test_ping_without_flag() {
+    run(vec![
+        Send(ping(NONE, 1)),
+        Expect(go_away(PROTOCOL_ERROR)),
+        Closed,
+    ]);
+}
+
+#[test]
+fn test_duplicate_syn() {
+    run(vec![
+        Send(window_update(SYN, 1, 0)),
+        Expect(window_update(ACK, 1, 0)),
+        Send(window_update(SYN, 1, 0)),
+        Expect(go_away(PROTOCOL_ERROR)),
+        // the session waits for remote to go away
+        Send(go_away(NORMAL)),
+        Closed,
+    ]);
+}
+
+#[test]
+fn test_data_of_unknown_stream() {
+    run(vec![
+        Send(data(NONE, 1, b"hello")),
+        Expect(go_away(PROTOCOL_ERROR)),
+        Closed,
+    ]);
+}
+
+#[test]
+fn test_recv_window_exceeded() {
+    let body = vec![0; (WINDOW - 100 + 1) as usize];
+    run(vec![
+        Send(window_update(SYN, 1, 0)),
+        Expect(window_update(ACK, 1, 0)),
+        // consumed 100 bytes, not enough to update the window
+        Send(data(NONE, 1, &[0; 100])),
+        Silent,
+        Send(data(NONE, 1, &body)),
+        Expect(go_away(PROTOCOL_ERROR)),
+        Send(go_away(NORMAL)),
+        Closed,
+    ]);
+}
+
+#[test]
+fn test_window_update_after_consumed() {
+    let body = vec![0; (WINDOW / 2) as usize];
+    run(vec![
+        Send(window_update(SYN, 1, 0)),
+        Expect(window_update(ACK, 1, 0)),
+        Send(data(NONE, 1, &body)),
+        Expect(window_update(NONE, 1, WINDOW / 2)),
+    ]);
+}
+
+#[test]
+fn test_data_after_fin() {
+    run(vec![
+        Send(window_update(SYN, 1, 0)),
+        Expect(window_update(ACK, 1, 0)),
+        Send(window_update(FIN, 1, 0)),
+        // the reader gets eof and closes the stream
+        Expect(window_update(FIN, 1, 0)),
+        // the stream is closed, the data is ignored
+        Send(data(NONE, 1, b"hello")),
+        Silent,
+    ]);
+}
+
+#[test]
+fn test_remote_reset() {
+    run(vec![
+        Send(window_update(SYN, 1, 0)),
+        Expect(window_update(ACK, 1, 0)),
+        Send(window_update(RST, 1, 0)),
+        Silent,
+        // the stream is removed, but session still works
+        Send(ping(SYN, 1)),
+        Expect(ping(ACK, 1)),
+    ]);
+}
+
+#[test]
+fn test_oversized_frame() {
+    run(vec![Send(raw(0, DATA, SYN, 1, WINDOW + 1, &[])), Closed]);
+}
+
+#[test]
+fn test_invalid_version() {
+    run(vec![Send(raw(1, PING, SYN, 0, 1, &[])), Closed]);
+}
+
+#[test]
+fn test_invalid_type() {
+    run(vec![Send(raw(0, 4, NONE, 0, 0, &[])), Closed]);
+}
+
+#[test]
+fn test_remote_go_away() {
+    run(vec![Send(go_away(NORMAL)), Expect(go_away(NORMAL)), Closed]);
+}
+
+#[test]
+fn test_remote_go_away_with_error() {
+    run(vec![
+        Send(go_away(PROTOCOL_ERROR)),
+        Expect(go_away(NORMAL)),
+        Closed,
+    ]);
+}